@@ -0,0 +1,330 @@
+//! On-disk local storage for the client layer
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+
+use core::layer::CacheLayer;
+use core::pattern::glob_matches;
+use core::{Error, Result};
+
+/// Default disk budget for cached entries
+pub const DEFAULT_MAX_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Logs smaller than this are never compacted
+const COMPACT_MIN_BYTES: u64 = 1024 * 1024;
+
+/// Length and checksum preceding each record
+const HEADER: usize = 8;
+
+const SET: u8 = 1;
+const DELETE: u8 = 2;
+
+fn io_error(path: &Path, e: std::io::Error) -> Error {
+    Error::Other(format!("cache log {}: {}", path.display(), e).into())
+}
+
+/// CRC-32 (IEEE) of `bytes`
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+fn frame(kind: u8, key: &str, value: &[u8]) -> Vec<u8> {
+    let mut payload = Vec::with_capacity(5 + key.len() + value.len());
+    payload.push(kind);
+    payload.extend_from_slice(&(key.len() as u32).to_le_bytes());
+    payload.extend_from_slice(key.as_bytes());
+    payload.extend_from_slice(value);
+
+    let mut record = Vec::with_capacity(HEADER + payload.len());
+    record.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    record.extend_from_slice(&crc32(&payload).to_le_bytes());
+    record.extend_from_slice(&payload);
+    record
+}
+
+/// A decoded log record
+struct Record {
+    kind: u8,
+    key: String,
+    value: Vec<u8>,
+}
+
+/// Parse the record at the start of `bytes`, returning it and its length
+fn parse(bytes: &[u8]) -> Option<(Record, usize)> {
+    let len = u32::from_le_bytes(bytes.get(..4)?.try_into().ok()?) as usize;
+    let crc = u32::from_le_bytes(bytes.get(4..HEADER)?.try_into().ok()?);
+    let payload = bytes.get(HEADER..HEADER + len)?;
+    if crc32(payload) != crc {
+        return None;
+    }
+    let kind = *payload.first()?;
+    let key_len = u32::from_le_bytes(payload.get(1..5)?.try_into().ok()?) as usize;
+    let key = String::from_utf8(payload.get(5..5 + key_len)?.to_vec()).ok()?;
+    let value = payload[5 + key_len..].to_vec();
+    Some((Record { kind, key, value }, HEADER + len))
+}
+
+/// Disk space an entry takes in a compacted log
+fn footprint(key: &str, value: &[u8]) -> u64 {
+    (HEADER + 5 + key.len() + value.len()) as u64
+}
+
+/// Storage statistics
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DiskStats {
+    pub entries: usize,
+    /// Bytes the live entries take in a compacted log
+    pub live_bytes: u64,
+    /// Current size of the log
+    pub log_bytes: u64,
+    /// Entries evicted to stay within budget since opening
+    pub evicted: u64,
+    /// Bytes of corrupt or torn records discarded when opening
+    pub discarded: u64,
+}
+
+struct Slot {
+    value: Vec<u8>,
+    /// Position in `recency`
+    used: u64,
+}
+
+struct State {
+    entries: HashMap<String, Slot>,
+    /// Keys by last use, least recent first
+    recency: BTreeMap<u64, String>,
+    tick: u64,
+    log: File,
+    stats: DiskStats,
+}
+
+impl State {
+    fn touch(&mut self, key: &str) {
+        self.tick += 1;
+        let tick = self.tick;
+        if let Some(slot) = self.entries.get_mut(key) {
+            self.recency.remove(&slot.used);
+            slot.used = tick;
+            self.recency.insert(tick, key.to_string());
+        }
+    }
+
+    fn insert(&mut self, key: &str, value: Vec<u8>) {
+        self.remove(key);
+        self.stats.live_bytes += footprint(key, &value);
+        self.tick += 1;
+        self.recency.insert(self.tick, key.to_string());
+        self.entries.insert(key.to_string(), Slot { value, used: self.tick });
+    }
+
+    fn remove(&mut self, key: &str) -> bool {
+        match self.entries.remove(key) {
+            Some(slot) => {
+                self.recency.remove(&slot.used);
+                self.stats.live_bytes -= footprint(key, &slot.value);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Local store persisted to an append-only log
+///
+/// Entries are held in memory and every change is appended to the log, so
+/// a restarted client starts with its warm cache. Each record carries a
+/// checksum; on open, replay stops at the first corrupt or torn record and
+/// the log is truncated there. Least recently used entries are evicted to
+/// keep live entries within the disk budget, and the log is compacted once
+/// it grows to twice their size.
+pub struct DiskStore {
+    path: PathBuf,
+    max_bytes: u64,
+    state: Mutex<State>,
+}
+
+impl DiskStore {
+    /// Open or create the log at `path`, keeping live entries within `max_bytes`
+    pub fn open(path: impl Into<PathBuf>, max_bytes: u64) -> Result<Self> {
+        let path = path.into();
+        let mut bytes = Vec::new();
+        if path.exists() {
+            File::open(&path)
+                .and_then(|mut f| f.read_to_end(&mut bytes))
+                .map_err(|e| io_error(&path, e))?;
+        }
+
+        let log = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|e| io_error(&path, e))?;
+        let mut state = State {
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            tick: 0,
+            log,
+            stats: DiskStats::default(),
+        };
+
+        // Replaying in log order leaves the most recently written entries most recent
+        let mut offset = 0;
+        while offset < bytes.len() {
+            match parse(&bytes[offset..]) {
+                Some((Record { kind: SET, key, value }, len)) => {
+                    state.insert(&key, value);
+                    offset += len;
+                }
+                Some((Record { kind: DELETE, key, .. }, len)) => {
+                    state.remove(&key);
+                    offset += len;
+                }
+                _ => {
+                    tracing::warn!(path = %path.display(), offset, "discarding corrupt cache log tail");
+                    break;
+                }
+            }
+        }
+        state.stats.log_bytes = offset as u64;
+        state.stats.discarded = (bytes.len() - offset) as u64;
+        if state.stats.discarded > 0 {
+            state.log.set_len(offset as u64).map_err(|e| io_error(&path, e))?;
+        }
+
+        let store = Self {
+            path,
+            max_bytes,
+            state: Mutex::new(state),
+        };
+        {
+            let mut state = store.state.lock().unwrap();
+            store.evict(&mut state)?;
+            store.maybe_compact(&mut state)?;
+        }
+        Ok(store)
+    }
+
+    /// Storage statistics
+    pub fn stats(&self) -> DiskStats {
+        let state = self.state.lock().unwrap();
+        DiskStats {
+            entries: state.entries.len(),
+            ..state.stats
+        }
+    }
+
+    /// Number of entries held
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().entries.len()
+    }
+
+    /// Whether the store is empty
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn append(&self, state: &mut State, record: &[u8]) -> Result<()> {
+        state.log.write_all(record).map_err(|e| io_error(&self.path, e))?;
+        state.stats.log_bytes += record.len() as u64;
+        Ok(())
+    }
+
+    /// Drop least recently used entries until live entries fit the budget
+    fn evict(&self, state: &mut State) -> Result<()> {
+        while state.stats.live_bytes > self.max_bytes {
+            let Some((_, key)) = state.recency.pop_first() else { break };
+            if let Some(slot) = state.entries.remove(&key) {
+                state.stats.live_bytes -= footprint(&key, &slot.value);
+            }
+            self.append(state, &frame(DELETE, &key, &[]))?;
+            state.stats.evicted += 1;
+            tracing::debug!(key, "evicted from client cache");
+        }
+        Ok(())
+    }
+
+    /// Rewrite the log with only live entries once it has grown enough
+    fn maybe_compact(&self, state: &mut State) -> Result<()> {
+        let log_bytes = state.stats.log_bytes;
+        if log_bytes < COMPACT_MIN_BYTES.max(state.stats.live_bytes * 2) {
+            return Ok(());
+        }
+        let tmp = self.path.with_extension("tmp");
+        let mut file = File::create(&tmp).map_err(|e| io_error(&tmp, e))?;
+        let mut written = 0;
+        for key in state.recency.values() {
+            let record = frame(SET, key, &state.entries[key].value);
+            file.write_all(&record).map_err(|e| io_error(&tmp, e))?;
+            written += record.len() as u64;
+        }
+        file.sync_all().map_err(|e| io_error(&tmp, e))?;
+        fs::rename(&tmp, &self.path).map_err(|e| io_error(&self.path, e))?;
+        state.log = OpenOptions::new()
+            .append(true)
+            .open(&self.path)
+            .map_err(|e| io_error(&self.path, e))?;
+        state.stats.log_bytes = written;
+        tracing::debug!(path = %self.path.display(), before = log_bytes, after = written, "compacted client cache log");
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl CacheLayer for DiskStore {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let mut state = self.state.lock().unwrap();
+        let value = state.entries.get(key).map(|slot| slot.value.clone());
+        if value.is_some() {
+            state.touch(key);
+        }
+        Ok(value)
+    }
+
+    async fn set(&self, key: &str, value: Vec<u8>) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if footprint(key, &value) > self.max_bytes {
+            tracing::debug!(key, size = value.len(), "value exceeds client cache budget, not cached");
+            if state.remove(key) {
+                self.append(&mut state, &frame(DELETE, key, &[]))?;
+            }
+            return Ok(());
+        }
+        self.append(&mut state, &frame(SET, key, &value))?;
+        state.insert(key, value);
+        self.evict(&mut state)?;
+        self.maybe_compact(&mut state)
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if state.remove(key) {
+            self.append(&mut state, &frame(DELETE, key, &[]))?;
+            self.maybe_compact(&mut state)?;
+        }
+        Ok(())
+    }
+
+    async fn keys(&self, pattern: &str) -> Result<Vec<String>> {
+        Ok(self
+            .state
+            .lock()
+            .unwrap()
+            .entries
+            .keys()
+            .filter(|key| *key == pattern || glob_matches(pattern, key))
+            .cloned()
+            .collect())
+    }
+}
@@ -0,0 +1,421 @@
+//! Offline-first client layer for the stateless caching system
+//!
+//! Reads are served from local storage first. Writes apply locally at once
+//! and are queued in a durable outbox, then replayed to the owning layer in
+//! order whenever the client is online. Writes carry the version they were
+//! made at, so an owner that tracks versions can detect and resolve writes
+//! that raced with changes made while the client was offline.
+//!
+//! `Pool` connects the client to the server over a pool of multiplexed
+//! connections and can be used directly as its upstream layer. With key
+//! tracking enabled, the server pushes invalidations for keys the client
+//! holds, so the local store doubles as a consistent near cache.
+
+mod disk;
+mod outbox;
+mod pool;
+mod store;
+
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use dashmap::DashMap;
+use tokio::sync::broadcast;
+
+use core::layer::CacheLayer;
+use core::pattern::glob_matches;
+use core::{CrdtValue, Error, HybridClock, Invalidation, PNCounter, Result, VersionVector, Versioned};
+
+pub use outbox::{Outbox, OutboxEntry, WriteOp, WriteVersion};
+pub use disk::{DiskStats, DiskStore, DEFAULT_MAX_BYTES};
+pub use store::MemoryStore;
+pub use pool::{Pool, PoolConfig, PoolStats};
+
+/// Client configuration
+#[derive(Debug, Clone, Default)]
+pub struct ClientConfig {
+    /// Directory for the outbox and cache logs; `None` keeps everything in memory
+    pub data_dir: Option<PathBuf>,
+    /// Disk budget for cached entries, `DEFAULT_MAX_BYTES` if unset
+    pub max_cache_bytes: Option<u64>,
+}
+
+/// A queued write the owning layer refused
+#[derive(Debug)]
+pub struct Rejection {
+    pub seq: u64,
+    pub op: WriteOp,
+    pub error: Error,
+}
+
+/// Outcome of replaying the outbox
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SyncReport {
+    /// Writes the owner accepted
+    pub applied: usize,
+    /// Writes the owner refused, see `Client::take_rejections`
+    pub rejected: usize,
+    /// Accepted writes the owner stored a different value for after resolving a conflict
+    pub resolved: usize,
+    /// Writes still queued because the upstream became unreachable
+    pub remaining: usize,
+}
+
+/// Offline-first client
+///
+/// Writes are replayed strictly in the order they were made: a write that
+/// cannot reach upstream holds back every later write, so the owner never
+/// sees them out of order. A write the owner refuses, e.g. because the
+/// client does not own the key, is dropped from the outbox, its local value
+/// is discarded and it is reported as a `Rejection`.
+///
+/// The client tracks the version of each key it last read or wrote and
+/// stamps writes with it and its hybrid logical clock. When the owner
+/// resolves a conflict, the resolved value replaces the local one.
+///
+/// Given a stream of invalidations, e.g. from a tracking `Pool`, the client
+/// applies those received so far before every read, dropping local copies
+/// that no pending write covers. A value fetched while its key was
+/// invalidated is returned but not kept. Copies held before the stream was
+/// attached, such as those recovered from disk, are dropped on the first
+/// read after it, since no invalidation would ever cover them.
+pub struct Client {
+    local: Arc<dyn CacheLayer>,
+    remote: Arc<dyn CacheLayer>,
+    outbox: Outbox,
+    clock: HybridClock,
+    /// Version of each key as last seen from upstream or written here
+    versions: DashMap<String, VersionVector>,
+    online: AtomicBool,
+    /// Serializes outbox replay
+    replay: tokio::sync::Mutex<()>,
+    rejections: Mutex<Vec<Rejection>>,
+    invalidations: Mutex<Option<broadcast::Receiver<Invalidation>>>,
+    /// Local copies predate the invalidation stream
+    unverified: AtomicBool,
+}
+
+impl Client {
+    /// Create a client with in-memory storage and outbox
+    pub fn new(remote: Arc<dyn CacheLayer>) -> Self {
+        Self::with_outbox(remote, Outbox::in_memory())
+    }
+
+    /// Create a client, recovering pending writes and cached entries from `config.data_dir`
+    pub fn open(config: ClientConfig, remote: Arc<dyn CacheLayer>) -> Result<Self> {
+        let Some(dir) = &config.data_dir else {
+            return Ok(Self::new(remote));
+        };
+        std::fs::create_dir_all(dir)
+            .map_err(|e| Error::Other(format!("data dir {}: {}", dir.display(), e).into()))?;
+        let outbox = Outbox::open(dir.join("outbox.log"))?;
+        let cache = DiskStore::open(
+            dir.join("cache.log"),
+            config.max_cache_bytes.unwrap_or(DEFAULT_MAX_BYTES),
+        )?;
+        Ok(Self::with_outbox(remote, outbox).with_local(Arc::new(cache)))
+    }
+
+    fn with_outbox(remote: Arc<dyn CacheLayer>, outbox: Outbox) -> Self {
+        Self {
+            local: Arc::new(MemoryStore::new()),
+            remote,
+            outbox,
+            clock: HybridClock::new(node_name()),
+            versions: DashMap::new(),
+            online: AtomicBool::new(true),
+            replay: tokio::sync::Mutex::new(()),
+            rejections: Mutex::new(Vec::new()),
+            invalidations: Mutex::new(None),
+            unverified: AtomicBool::new(false),
+        }
+    }
+
+    /// Use a different local store
+    pub fn with_local(mut self, local: Arc<dyn CacheLayer>) -> Self {
+        self.local = local;
+        self
+    }
+
+    /// Drop local copies of keys as invalidations arrive
+    ///
+    /// Copies held until now are dropped before the next read.
+    pub fn with_invalidations(self, invalidations: broadcast::Receiver<Invalidation>) -> Self {
+        *self.invalidations.lock().unwrap() = Some(invalidations);
+        self.unverified.store(true, Ordering::SeqCst);
+        self
+    }
+
+    /// Identify this client's writes as `node` in versions
+    ///
+    /// Clients sharing an owner need distinct node names for their
+    /// concurrent writes to be detected. By default each client gets a
+    /// name no other client uses.
+    pub fn with_node(mut self, node: impl Into<String>) -> Self {
+        self.clock = HybridClock::new(node);
+        self
+    }
+
+    /// Whether the client believes upstream is reachable
+    pub fn is_online(&self) -> bool {
+        self.online.load(Ordering::SeqCst)
+    }
+
+    /// Stop contacting upstream; writes queue until `reconnect`
+    pub fn go_offline(&self) {
+        self.online.store(false, Ordering::SeqCst);
+    }
+
+    /// Resume contacting upstream and replay queued writes
+    pub async fn reconnect(&self) -> Result<SyncReport> {
+        self.online.store(true, Ordering::SeqCst);
+        self.sync().await
+    }
+
+    /// Number of writes waiting for upstream
+    pub fn pending(&self) -> usize {
+        self.outbox.len()
+    }
+
+    /// The outbox
+    pub fn outbox(&self) -> &Outbox {
+        &self.outbox
+    }
+
+    /// Writes refused since the last call
+    pub fn take_rejections(&self) -> Vec<Rejection> {
+        std::mem::take(&mut *self.rejections.lock().unwrap())
+    }
+
+    /// Read locally, falling back to upstream when online
+    ///
+    /// A key with a pending write reads as that write even if the local
+    /// store evicted it. Values fetched from upstream are kept locally.
+    pub async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        self.invalidate().await?;
+        if let Some(value) = self.local.get(key).await? {
+            return Ok(Some(value));
+        }
+        if let Some(op) = self.outbox.latest(key) {
+            return Ok(match op {
+                WriteOp::Set { value, .. } => Some(value),
+                WriteOp::Delete { .. } => None,
+            });
+        }
+        if !self.is_online() {
+            return Ok(None);
+        }
+        match self.remote.get_versioned(key).await {
+            Ok(Some(versioned)) => {
+                self.clock.observe(&versioned.stamp);
+                // Invalidations are received before any later reply, so one
+                // raced with this read if it has arrived by now
+                let raced = self.invalidate().await?.iter().any(|target| match target {
+                    Invalidation::Key(k) => k == key,
+                    Invalidation::Pattern(pattern) => glob_matches(pattern, key),
+                });
+                if !raced {
+                    self.local.set(key, versioned.value.clone()).await?;
+                }
+                self.versions.insert(key.to_string(), versioned.vector);
+                Ok(Some(versioned.value))
+            }
+            Ok(None) => Ok(None),
+            Err(e) if e.is_retryable() => {
+                tracing::debug!(key, error = %e, "upstream unreachable, going offline");
+                self.go_offline();
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Write locally and queue the write for upstream
+    pub async fn set(&self, key: &str, value: Vec<u8>) -> Result<()> {
+        self.write(WriteOp::Set {
+            key: key.to_string(),
+            value,
+        })
+        .await
+    }
+
+    /// Delete locally and queue the delete for upstream
+    pub async fn delete(&self, key: &str) -> Result<()> {
+        self.write(WriteOp::Delete {
+            key: key.to_string(),
+        })
+        .await
+    }
+
+    /// Add `delta` to the counter at `key`, returning its new value
+    ///
+    /// Counters are `PNCounter`s counted under this client's node name, so
+    /// increments made offline merge with those made elsewhere on replay.
+    /// Offline, the counter must be held locally: counting from zero could
+    /// replay a smaller count for this node than the owner already has.
+    pub async fn incr(&self, key: &str, delta: i64) -> Result<i64> {
+        let current = self.get(key).await?;
+        if current.is_none() && !self.is_online() && self.outbox.latest(key).is_none() {
+            return Err(Error::Network {
+                layer: None,
+                reason: format!("no local copy of counter {} to increment offline", key),
+                source: None,
+            });
+        }
+        let mut counter = PNCounter::from_stored(key, current.as_deref())?;
+        counter.incr(self.clock.node(), delta);
+        self.set(key, CrdtValue::PNCounter(counter).encode()?).await?;
+        // Replay may have merged in increments made elsewhere
+        let stored = self.local.get(key).await?;
+        Ok(PNCounter::from_stored(key, stored.as_deref())?.value())
+    }
+
+    async fn write(&self, op: WriteOp) -> Result<()> {
+        let version = match &op {
+            WriteOp::Set { key, .. } => {
+                let stamp = self.clock.now();
+                let mut vector = self.versions.entry(key.clone()).or_default();
+                vector.advance(self.clock.node(), stamp.physical);
+                Some(WriteVersion {
+                    stamp,
+                    vector: vector.clone(),
+                })
+            }
+            WriteOp::Delete { key } => {
+                self.versions.remove(key);
+                None
+            }
+        };
+        self.outbox.push_at(op.clone(), version)?;
+        match &op {
+            WriteOp::Set { key, value } => self.local.set(key, value.clone()).await?,
+            WriteOp::Delete { key } => self.local.delete(key).await?,
+        }
+        if self.is_online() {
+            self.sync().await?;
+        }
+        Ok(())
+    }
+
+    /// Replay queued writes in order until the outbox drains or upstream fails
+    pub async fn sync(&self) -> Result<SyncReport> {
+        let _replay = self.replay.lock().await;
+        let mut report = SyncReport::default();
+        while self.is_online() {
+            let Some(entry) = self.outbox.front() else { break };
+            let result = match (&entry.op, &entry.version) {
+                (WriteOp::Set { key, value }, Some(version)) => {
+                    let write = Versioned::new(value.clone(), version.stamp.clone(), version.vector.clone());
+                    self.remote.set_versioned(key, write).await.map(Some)
+                }
+                (WriteOp::Set { key, value }, None) => self.remote.set(key, value.clone()).await.map(|_| None),
+                (WriteOp::Delete { key }, _) => self.remote.delete(key).await.map(|_| None),
+            };
+            match result {
+                Ok(stored) => {
+                    self.outbox.ack(entry.seq)?;
+                    report.applied += 1;
+                    if let (Some(stored), WriteOp::Set { key, value }) = (stored, &entry.op) {
+                        if self.settle(key, value, stored).await? {
+                            report.resolved += 1;
+                        }
+                    }
+                }
+                Err(e) if e.is_retryable() => {
+                    tracing::debug!(seq = entry.seq, error = %e, "upstream unreachable, going offline");
+                    self.go_offline();
+                    break;
+                }
+                Err(error) => {
+                    self.outbox.ack(entry.seq)?;
+                    self.reject(entry, error).await?;
+                    report.rejected += 1;
+                }
+            }
+        }
+        report.remaining = self.outbox.len();
+        Ok(report)
+    }
+
+    /// Adopt the version upstream stored for a write, returning whether its
+    /// value differs from the one written
+    ///
+    /// Later writes to the key still pending take precedence locally.
+    async fn settle(&self, key: &str, written: &[u8], stored: Versioned) -> Result<bool> {
+        self.clock.observe(&stored.stamp);
+        let resolved = stored.value != written;
+        if !self.outbox.has_pending(key) {
+            if resolved {
+                self.local.set(key, stored.value).await?;
+            }
+            self.versions.insert(key.to_string(), stored.vector);
+        }
+        Ok(resolved)
+    }
+
+    /// Apply the invalidations received so far, returning them
+    async fn invalidate(&self) -> Result<Vec<Invalidation>> {
+        let received = {
+            let mut invalidations = self.invalidations.lock().unwrap();
+            let Some(receiver) = invalidations.as_mut() else { return Ok(Vec::new()) };
+            let mut received = Vec::new();
+            if self.unverified.swap(false, Ordering::SeqCst) {
+                received.push(Invalidation::Pattern("*".to_string()));
+            }
+            loop {
+                match receiver.try_recv() {
+                    Ok(target) => received.push(target),
+                    // Missed invalidations could cover anything
+                    Err(broadcast::error::TryRecvError::Lagged(_)) => {
+                        received.push(Invalidation::Pattern("*".to_string()))
+                    }
+                    Err(_) => break,
+                }
+            }
+            received
+        };
+        for target in &received {
+            let keys = match target {
+                Invalidation::Key(key) => vec![key.clone()],
+                Invalidation::Pattern(pattern) => self.local.keys(pattern).await?,
+            };
+            for key in keys {
+                if !self.outbox.has_pending(&key) {
+                    tracing::trace!(key, "dropping invalidated local copy");
+                    self.local.delete(&key).await?;
+                }
+            }
+        }
+        Ok(received)
+    }
+
+    /// Discard the local effect of a refused write and report it
+    async fn reject(&self, entry: OutboxEntry, error: Error) -> Result<()> {
+        tracing::warn!(seq = entry.seq, key = entry.op.key(), error = %error, "write rejected upstream");
+        let key = entry.op.key();
+        if !self.outbox.has_pending(key) {
+            self.local.delete(key).await?;
+            self.versions.remove(key);
+        }
+        self.rejections.lock().unwrap().push(Rejection {
+            seq: entry.seq,
+            op: entry.op,
+            error,
+        });
+        Ok(())
+    }
+}
+
+/// A node name no other client has used
+fn node_name() -> String {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+    format!(
+        "client-{:x}.{:x}.{:x}",
+        nanos,
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::Relaxed)
+    )
+}
@@ -0,0 +1,238 @@
+//! Durable queue of writes waiting to reach the owning layer
+
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use core::{Error, Result, Timestamp, VersionVector};
+
+/// A write made on the client
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum WriteOp {
+    Set { key: String, value: Vec<u8> },
+    Delete { key: String },
+}
+
+impl WriteOp {
+    /// The key written
+    pub fn key(&self) -> &str {
+        match self {
+            WriteOp::Set { key, .. } | WriteOp::Delete { key } => key,
+        }
+    }
+}
+
+/// The version a write was made at
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WriteVersion {
+    pub stamp: Timestamp,
+    pub vector: VersionVector,
+}
+
+/// A queued write and its position in the outbox
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutboxEntry {
+    pub seq: u64,
+    #[serde(flatten)]
+    pub op: WriteOp,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<WriteVersion>,
+}
+
+/// One line of the outbox log
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "record", rename_all = "snake_case")]
+enum Record {
+    Write(OutboxEntry),
+    Ack { seq: u64 },
+}
+
+struct State {
+    pending: VecDeque<OutboxEntry>,
+    next_seq: u64,
+    log: Option<File>,
+}
+
+/// Ordered writes waiting to be replayed upstream
+///
+/// Backed by an append-only log when opened from a path: every write is
+/// synced to disk before `push` returns, and acknowledged writes are
+/// recorded so a restarted client replays only what never reached the
+/// owner. The log is compacted whenever the outbox drains.
+pub struct Outbox {
+    path: Option<PathBuf>,
+    state: Mutex<State>,
+}
+
+fn io_error(path: &Path, e: std::io::Error) -> Error {
+    Error::Other(format!("outbox {}: {}", path.display(), e).into())
+}
+
+impl Outbox {
+    /// An outbox that does not survive restarts
+    pub fn in_memory() -> Self {
+        Self {
+            path: None,
+            state: Mutex::new(State {
+                pending: VecDeque::new(),
+                next_seq: 1,
+                log: None,
+            }),
+        }
+    }
+
+    /// Open or create the outbox log at `path`, recovering unacknowledged writes
+    ///
+    /// A torn final line from a crash mid-write is ignored. An unreadable
+    /// record anywhere else fails the open rather than dropping the writes
+    /// after it when the log is compacted.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let mut pending = VecDeque::new();
+        let mut next_seq = 1;
+        if path.exists() {
+            let file = File::open(&path).map_err(|e| io_error(&path, e))?;
+            let mut lines = BufReader::new(file).lines().enumerate().peekable();
+            while let Some((number, line)) = lines.next() {
+                let line = line.map_err(|e| io_error(&path, e))?;
+                match serde_json::from_str(&line) {
+                    Ok(Record::Write(entry)) => {
+                        next_seq = next_seq.max(entry.seq + 1);
+                        pending.push_back(entry);
+                    }
+                    Ok(Record::Ack { seq }) => pending.retain(|e: &OutboxEntry| e.seq > seq),
+                    Err(e) if lines.peek().is_none() => {
+                        tracing::warn!(path = %path.display(), error = %e, "ignoring torn outbox record");
+                    }
+                    Err(e) => {
+                        return Err(Error::Serialization {
+                            key: None,
+                            reason: format!(
+                                "outbox {}: unreadable record on line {}",
+                                path.display(),
+                                number + 1
+                            ),
+                            source: Some(Box::new(e)),
+                        });
+                    }
+                }
+            }
+        }
+
+        let outbox = Self {
+            path: Some(path),
+            state: Mutex::new(State {
+                pending,
+                next_seq,
+                log: None,
+            }),
+        };
+        outbox.compact(&mut outbox.state.lock().unwrap())?;
+        Ok(outbox)
+    }
+
+    /// Rewrite the log with only the pending writes
+    fn compact(&self, state: &mut State) -> Result<()> {
+        let Some(path) = &self.path else { return Ok(()) };
+        let tmp = path.with_extension("tmp");
+        let mut file = File::create(&tmp).map_err(|e| io_error(&tmp, e))?;
+        for entry in &state.pending {
+            writeln!(file, "{}", serde_json::to_string(&Record::Write(entry.clone()))?)
+                .map_err(|e| io_error(&tmp, e))?;
+        }
+        file.sync_all().map_err(|e| io_error(&tmp, e))?;
+        fs::rename(&tmp, path).map_err(|e| io_error(path, e))?;
+        state.log = Some(
+            OpenOptions::new()
+                .append(true)
+                .open(path)
+                .map_err(|e| io_error(path, e))?,
+        );
+        Ok(())
+    }
+
+    fn append(&self, state: &mut State, record: &Record) -> Result<()> {
+        let (Some(path), Some(log)) = (&self.path, state.log.as_mut()) else {
+            return Ok(());
+        };
+        writeln!(log, "{}", serde_json::to_string(record)?).map_err(|e| io_error(path, e))?;
+        log.sync_data().map_err(|e| io_error(path, e))
+    }
+
+    /// Queue a write, returning its sequence number once it is durable
+    pub fn push(&self, op: WriteOp) -> Result<u64> {
+        self.push_at(op, None)
+    }
+
+    /// Queue a write made at a known version
+    pub fn push_at(&self, op: WriteOp, version: Option<WriteVersion>) -> Result<u64> {
+        let mut state = self.state.lock().unwrap();
+        let entry = OutboxEntry {
+            seq: state.next_seq,
+            op,
+            version,
+        };
+        self.append(&mut state, &Record::Write(entry.clone()))?;
+        state.next_seq += 1;
+        state.pending.push_back(entry.clone());
+        Ok(entry.seq)
+    }
+
+    /// The oldest pending write
+    pub fn front(&self) -> Option<OutboxEntry> {
+        self.state.lock().unwrap().pending.front().cloned()
+    }
+
+    /// Remove every write up to and including `seq`
+    pub fn ack(&self, seq: u64) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.pending.retain(|e| e.seq > seq);
+        if state.pending.is_empty() {
+            self.compact(&mut state)
+        } else {
+            self.append(&mut state, &Record::Ack { seq })
+        }
+    }
+
+    /// Pending writes, oldest first
+    pub fn pending(&self) -> Vec<OutboxEntry> {
+        self.state.lock().unwrap().pending.iter().cloned().collect()
+    }
+
+    /// Whether a write to `key` is still pending
+    pub fn has_pending(&self, key: &str) -> bool {
+        self.state
+            .lock()
+            .unwrap()
+            .pending
+            .iter()
+            .any(|e| e.op.key() == key)
+    }
+
+    /// The most recent pending write to `key`
+    pub fn latest(&self, key: &str) -> Option<WriteOp> {
+        self.state
+            .lock()
+            .unwrap()
+            .pending
+            .iter()
+            .rev()
+            .find(|e| e.op.key() == key)
+            .map(|e| e.op.clone())
+    }
+
+    /// Number of pending writes
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().pending.len()
+    }
+
+    /// Whether every write has been acknowledged
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
@@ -0,0 +1,587 @@
+//! Pooled, multiplexed connections to the server
+
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock, Weak};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use dashmap::DashMap;
+use tokio::io::{AsyncWriteExt, BufWriter};
+use tokio::net::TcpStream;
+use tokio::sync::{broadcast, mpsc, oneshot, Semaphore};
+use tokio::task::JoinHandle;
+
+use core::layer::CacheLayer;
+use core::wire::{self, Frame, Reply, Request, PUSH};
+use core::{Error, Invalidation, Layer, Result, TrackingMode, Versioned};
+
+/// Invalidations buffered for each receiver before it lags
+const INVALIDATION_BUFFER: usize = 4096;
+
+/// Connection pool configuration
+#[derive(Debug, Clone)]
+pub struct PoolConfig {
+    /// Server address as `host:port`
+    pub addr: String,
+    /// Connections kept open even when idle
+    pub min_connections: usize,
+    /// Most connections opened at once
+    pub max_connections: usize,
+    /// Requests a connection carries at once before another is opened
+    pub max_in_flight: usize,
+    pub connect_timeout: Duration,
+    pub request_timeout: Duration,
+    /// How often idle connections are pinged and the pool topped up
+    pub health_interval: Duration,
+    /// Ask the server to report changes to keys in this scope, see `Pool::invalidations`
+    pub tracking: Option<TrackingMode>,
+}
+
+impl PoolConfig {
+    /// Defaults for connecting to `addr`
+    pub fn new(addr: impl Into<String>) -> Self {
+        Self {
+            addr: addr.into(),
+            min_connections: 1,
+            max_connections: 8,
+            max_in_flight: 64,
+            connect_timeout: Duration::from_secs(5),
+            request_timeout: Duration::from_secs(10),
+            health_interval: Duration::from_secs(15),
+            tracking: None,
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.max_connections == 0 || self.max_in_flight == 0 {
+            return Err(Error::InvalidConfig(
+                "pool needs at least one connection and one request in flight".to_string(),
+            ));
+        }
+        if self.min_connections > self.max_connections {
+            return Err(Error::InvalidConfig(format!(
+                "pool minimum of {} connections exceeds its maximum of {}",
+                self.min_connections, self.max_connections
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Pool statistics
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PoolStats {
+    /// Open connections
+    pub connections: usize,
+    /// Requests awaiting a reply
+    pub in_flight: usize,
+    /// Requests queued for a free slot
+    pub waiting: usize,
+    /// Connections opened since the pool was created
+    pub opened: u64,
+    /// Connections dropped after failing or closing
+    pub closed: u64,
+}
+
+fn closed(addr: &str) -> Error {
+    Error::Network {
+        layer: Some(Layer::Server),
+        reason: format!("connection to {} closed", addr),
+        source: None,
+    }
+}
+
+/// Publishes the `*` invalidation for a lost tracking connection, once
+struct TrackingLost {
+    invalidations: broadcast::Sender<Invalidation>,
+    reported: AtomicBool,
+}
+
+impl TrackingLost {
+    fn report(&self) {
+        if !self.reported.swap(true, Ordering::SeqCst) {
+            // Changes made from now until tracking resumes go unreported
+            let _ = self.invalidations.send(Invalidation::Pattern("*".to_string()));
+        }
+    }
+}
+
+/// One multiplexed connection
+///
+/// Requests are written as they are made, without waiting for earlier
+/// replies, and each reply is routed back to its caller by request id.
+/// Invalidations the server pushes are published in the order they arrive
+/// relative to replies, before any reply that follows them.
+struct Connection {
+    id: u64,
+    outgoing: mpsc::UnboundedSender<Frame<Request>>,
+    pending: Arc<DashMap<u64, oneshot::Sender<Reply>>>,
+    alive: Arc<AtomicBool>,
+    in_flight: AtomicUsize,
+    next_request: AtomicU64,
+    tasks: [JoinHandle<()>; 2],
+    /// Set when the connection tracks keys
+    tracking: Option<Arc<TrackingLost>>,
+}
+
+impl Connection {
+    async fn open(id: u64, config: &PoolConfig, invalidations: broadcast::Sender<Invalidation>) -> Result<Self> {
+        let stream = match tokio::time::timeout(config.connect_timeout, TcpStream::connect(&config.addr)).await {
+            Ok(Ok(stream)) => stream,
+            Ok(Err(e)) => {
+                return Err(Error::network(Some(Layer::Server), format!("connecting to {}", config.addr), e))
+            }
+            Err(_) => {
+                return Err(Error::Timeout {
+                    operation: format!("connect to {}", config.addr),
+                    key: None,
+                    layer: Some(Layer::Server),
+                    after: config.connect_timeout,
+                })
+            }
+        };
+        let _ = stream.set_nodelay(true);
+        let (mut reader, writer) = stream.into_split();
+        let (outgoing, mut requests) = mpsc::unbounded_channel::<Frame<Request>>();
+        let pending: Arc<DashMap<u64, oneshot::Sender<Reply>>> = Arc::new(DashMap::new());
+        let alive = Arc::new(AtomicBool::new(true));
+        let tracking = config.tracking.as_ref().map(|_| {
+            Arc::new(TrackingLost {
+                invalidations: invalidations.clone(),
+                reported: AtomicBool::new(false),
+            })
+        });
+
+        let writes = {
+            let alive = alive.clone();
+            let pending = pending.clone();
+            tokio::spawn(async move {
+                let mut writer = BufWriter::new(writer);
+                // Everything queued is written before flushing, so pipelined
+                // requests share packets
+                'conn: while let Some(frame) = requests.recv().await {
+                    let mut next = Some(frame);
+                    while let Some(frame) = next {
+                        if wire::write_frame(&mut writer, &frame).await.is_err() {
+                            break 'conn;
+                        }
+                        next = requests.try_recv().ok();
+                    }
+                    if writer.flush().await.is_err() {
+                        break;
+                    }
+                }
+                alive.store(false, Ordering::SeqCst);
+                pending.clear();
+            })
+        };
+        let reads = {
+            let alive = alive.clone();
+            let pending = pending.clone();
+            let tracking = tracking.clone();
+            tokio::spawn(async move {
+                while let Ok(Some(Frame { id, body })) = wire::read_frame::<_, Frame<Reply>>(&mut reader).await {
+                    match body {
+                        Reply::Invalidate { keys } if id == PUSH => {
+                            for key in keys {
+                                let _ = invalidations.send(Invalidation::Key(key));
+                            }
+                        }
+                        body => {
+                            if let Some((_, reply)) = pending.remove(&id) {
+                                let _ = reply.send(body);
+                            }
+                        }
+                    }
+                }
+                alive.store(false, Ordering::SeqCst);
+                // Dropping the senders fails every request still waiting
+                pending.clear();
+                if let Some(tracking) = tracking {
+                    tracking.report();
+                }
+            })
+        };
+
+        let conn = Self {
+            id,
+            outgoing,
+            pending,
+            alive,
+            in_flight: AtomicUsize::new(0),
+            next_request: AtomicU64::new(PUSH + 1),
+            tasks: [writes, reads],
+            tracking,
+        };
+        if let Some(tracking) = &config.tracking {
+            match conn.call(Request::Track { tracking: tracking.clone() }, config).await? {
+                Reply::Done => {}
+                Reply::Error { error } => return Err(error.into()),
+                reply => return Err(unexpected("track", reply)),
+            }
+        }
+        Ok(conn)
+    }
+
+    fn is_alive(&self) -> bool {
+        self.alive.load(Ordering::SeqCst)
+    }
+
+    async fn call(&self, request: Request, config: &PoolConfig) -> Result<Reply> {
+        let id = self.next_request.fetch_add(1, Ordering::Relaxed);
+        let operation = request.name();
+        let key = request.key().map(str::to_string);
+        let (tx, rx) = oneshot::channel();
+        self.pending.insert(id, tx);
+        // Checked after registering so a connection failing now still fails this request
+        if !self.is_alive() || self.outgoing.send(Frame { id, body: request }).is_err() {
+            self.pending.remove(&id);
+            return Err(closed(&config.addr));
+        }
+        match tokio::time::timeout(config.request_timeout, rx).await {
+            Ok(Ok(reply)) => Ok(reply),
+            Ok(Err(_)) => Err(closed(&config.addr)),
+            Err(_) => {
+                self.pending.remove(&id);
+                Err(Error::Timeout {
+                    operation: operation.to_string(),
+                    key,
+                    layer: Some(Layer::Server),
+                    after: config.request_timeout,
+                })
+            }
+        }
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+        // The read task may be stopped before it reports the loss itself
+        if let Some(tracking) = &self.tracking {
+            tracking.report();
+        }
+    }
+}
+
+/// A connection claimed for one request
+struct Claim(Arc<Connection>);
+
+impl Drop for Claim {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// A request counted as waiting for a slot until dropped
+struct Waiting<'a>(&'a AtomicUsize);
+
+impl<'a> Waiting<'a> {
+    fn new(count: &'a AtomicUsize) -> Self {
+        count.fetch_add(1, Ordering::SeqCst);
+        Self(count)
+    }
+}
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+struct Shared {
+    config: PoolConfig,
+    connections: RwLock<Vec<Arc<Connection>>>,
+    /// One permit per request slot across the largest pool; tokio's
+    /// semaphore is fair, so waiting requests are admitted in arrival order
+    slots: Semaphore,
+    waiting: AtomicUsize,
+    /// Serializes opening connections
+    opening: tokio::sync::Mutex<()>,
+    /// Rotates the starting point when connections are equally loaded
+    cursor: AtomicUsize,
+    next_connection: AtomicU64,
+    opened: AtomicU64,
+    closed: AtomicU64,
+    invalidations: broadcast::Sender<Invalidation>,
+}
+
+impl Shared {
+    /// Claim a slot on the least loaded live connection
+    fn claim(&self) -> Option<Claim> {
+        let connections = self.connections.read().unwrap();
+        let len = connections.len();
+        let start = self.cursor.fetch_add(1, Ordering::Relaxed);
+        let mut candidates: Vec<_> = (0..len)
+            .map(|i| &connections[(start + i) % len.max(1)])
+            .filter(|conn| conn.is_alive())
+            .collect();
+        candidates.sort_by_key(|conn| conn.in_flight.load(Ordering::SeqCst));
+        candidates.into_iter().find_map(|conn| {
+            conn.in_flight
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                    (n < self.config.max_in_flight).then_some(n + 1)
+                })
+                .ok()
+                .map(|_| Claim(conn.clone()))
+        })
+    }
+
+    async fn open(&self) -> Result<Arc<Connection>> {
+        let id = self.next_connection.fetch_add(1, Ordering::Relaxed);
+        let conn = Arc::new(Connection::open(id, &self.config, self.invalidations.clone()).await?);
+        self.opened.fetch_add(1, Ordering::Relaxed);
+        tracing::debug!(addr = %self.config.addr, connection = id, "opened server connection");
+        Ok(conn)
+    }
+
+    /// Claim a slot, opening a connection if every live one is saturated
+    async fn checkout(&self) -> Result<Claim> {
+        if let Some(claim) = self.claim() {
+            return Ok(claim);
+        }
+        let _opening = self.opening.lock().await;
+        // Another request may have opened one, or a slot freed up, while we waited
+        if let Some(claim) = self.claim() {
+            return Ok(claim);
+        }
+        self.prune();
+        let conn = self.open().await?;
+        conn.in_flight.fetch_add(1, Ordering::SeqCst);
+        self.connections.write().unwrap().push(conn.clone());
+        Ok(Claim(conn))
+    }
+
+    /// Drop connections that have failed or closed
+    fn prune(&self) {
+        let mut connections = self.connections.write().unwrap();
+        let before = connections.len();
+        connections.retain(|conn| conn.is_alive());
+        let dropped = before - connections.len();
+        if dropped > 0 {
+            self.closed.fetch_add(dropped as u64, Ordering::Relaxed);
+            tracing::debug!(addr = %self.config.addr, dropped, "dropped dead server connections");
+        }
+    }
+
+    async fn request(&self, request: Request) -> Result<Reply> {
+        let waiting = Waiting::new(&self.waiting);
+        let _slot = self
+            .slots
+            .acquire()
+            .await
+            .map_err(|_| closed(&self.config.addr))?;
+        drop(waiting);
+        let claim = self.checkout().await?;
+        let result = claim.0.call(request, &self.config).await;
+        if !claim.0.is_alive() {
+            self.prune();
+        }
+        match result? {
+            Reply::Error { error } => Err(error.into()),
+            reply => Ok(reply),
+        }
+    }
+
+    /// Ping every connection, drop those that fail and open up to the minimum
+    async fn check(&self) {
+        let connections: Vec<_> = self.connections.read().unwrap().clone();
+        for conn in connections {
+            if !conn.is_alive() {
+                continue;
+            }
+            match conn.call(Request::Ping, &self.config).await {
+                Ok(Reply::Pong) => {}
+                outcome => {
+                    tracing::warn!(addr = %self.config.addr, connection = conn.id, ?outcome, "server connection failed health check");
+                    conn.alive.store(false, Ordering::SeqCst);
+                }
+            }
+        }
+        self.prune();
+
+        let _opening = self.opening.lock().await;
+        while self.connections.read().unwrap().len() < self.config.min_connections {
+            match self.open().await {
+                Ok(conn) => self.connections.write().unwrap().push(conn),
+                Err(e) => {
+                    tracing::warn!(addr = %self.config.addr, error = %e, "could not open server connection");
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Pool of multiplexed connections to the server
+///
+/// Each request goes to the live connection with the fewest requests in
+/// flight. Once every connection carries `max_in_flight` requests another
+/// is opened, up to `max_connections`; after that, requests wait and are
+/// admitted in the order they arrived. A background task pings each
+/// connection every `health_interval`, drops those that fail and keeps
+/// `min_connections` open. Connections that fail mid-request are dropped
+/// at once and the request fails with a retryable network error.
+///
+/// With `tracking` set, every connection asks the server to report changes
+/// to keys in that scope, and the changes are published to
+/// `invalidations` receivers. When a tracking connection is lost, a `*`
+/// pattern invalidation is published, since changes made while it was down
+/// were not reported.
+pub struct Pool {
+    shared: Arc<Shared>,
+    health: JoinHandle<()>,
+}
+
+impl Pool {
+    /// Create a pool that connects on first use
+    ///
+    /// Must be called within a Tokio runtime.
+    pub fn new(config: PoolConfig) -> Result<Self> {
+        config.validate()?;
+        let slots = config.max_connections * config.max_in_flight;
+        let shared = Arc::new(Shared {
+            config,
+            connections: RwLock::new(Vec::new()),
+            slots: Semaphore::new(slots),
+            waiting: AtomicUsize::new(0),
+            opening: tokio::sync::Mutex::new(()),
+            cursor: AtomicUsize::new(0),
+            next_connection: AtomicU64::new(0),
+            opened: AtomicU64::new(0),
+            closed: AtomicU64::new(0),
+            invalidations: broadcast::channel(INVALIDATION_BUFFER).0,
+        });
+        let health = tokio::spawn(health_loop(Arc::downgrade(&shared)));
+        Ok(Self { shared, health })
+    }
+
+    /// Create a pool and open its minimum connections
+    pub async fn connect(config: PoolConfig) -> Result<Self> {
+        let pool = Self::new(config)?;
+        {
+            let shared = &pool.shared;
+            let _opening = shared.opening.lock().await;
+            for _ in 0..shared.config.min_connections {
+                let conn = shared.open().await?;
+                shared.connections.write().unwrap().push(conn);
+            }
+        }
+        Ok(pool)
+    }
+
+    /// The pool's configuration
+    pub fn config(&self) -> &PoolConfig {
+        &self.shared.config
+    }
+
+    /// Receive the invalidations the server pushes from now on
+    ///
+    /// Nothing is published unless `PoolConfig::tracking` is set.
+    pub fn invalidations(&self) -> broadcast::Receiver<Invalidation> {
+        self.shared.invalidations.subscribe()
+    }
+
+    /// Current statistics
+    pub fn stats(&self) -> PoolStats {
+        let connections = self.shared.connections.read().unwrap();
+        let in_flight = connections
+            .iter()
+            .map(|conn| conn.in_flight.load(Ordering::SeqCst))
+            .sum();
+        PoolStats {
+            connections: connections.len(),
+            in_flight,
+            waiting: self.shared.waiting.load(Ordering::SeqCst),
+            opened: self.shared.opened.load(Ordering::Relaxed),
+            closed: self.shared.closed.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Run a health check now rather than waiting for the next interval
+    pub async fn check(&self) {
+        self.shared.check().await
+    }
+
+    /// Round-trip a ping through the pool
+    pub async fn ping(&self) -> Result<()> {
+        match self.shared.request(Request::Ping).await? {
+            Reply::Pong => Ok(()),
+            reply => Err(unexpected("ping", reply)),
+        }
+    }
+}
+
+impl Drop for Pool {
+    fn drop(&mut self) {
+        self.health.abort();
+    }
+}
+
+async fn health_loop(shared: Weak<Shared>) {
+    let period = match shared.upgrade() {
+        Some(shared) => shared.config.health_interval,
+        None => return,
+    };
+    let mut ticks = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+    loop {
+        ticks.tick().await;
+        let Some(shared) = shared.upgrade() else { return };
+        shared.check().await;
+    }
+}
+
+fn unexpected(operation: &str, reply: Reply) -> Error {
+    Error::Serialization {
+        key: None,
+        reason: format!("unexpected reply to {}: {:?}", operation, reply),
+        source: None,
+    }
+}
+
+#[async_trait]
+impl CacheLayer for Pool {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        match self.shared.request(Request::Get { key: key.to_string() }).await? {
+            Reply::Value { value } => Ok(value),
+            reply => Err(unexpected("get", reply)),
+        }
+    }
+
+    async fn set(&self, key: &str, value: Vec<u8>) -> Result<()> {
+        match self.shared.request(Request::Set { key: key.to_string(), value }).await? {
+            Reply::Done => Ok(()),
+            reply => Err(unexpected("set", reply)),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        match self.shared.request(Request::Delete { key: key.to_string() }).await? {
+            Reply::Done => Ok(()),
+            reply => Err(unexpected("delete", reply)),
+        }
+    }
+
+    async fn keys(&self, pattern: &str) -> Result<Vec<String>> {
+        match self.shared.request(Request::Keys { pattern: pattern.to_string() }).await? {
+            Reply::Keys { keys } => Ok(keys),
+            reply => Err(unexpected("keys", reply)),
+        }
+    }
+
+    async fn get_versioned(&self, key: &str) -> Result<Option<Versioned>> {
+        match self.shared.request(Request::GetVersioned { key: key.to_string() }).await? {
+            Reply::Versioned { value } => Ok(value),
+            reply => Err(unexpected("get_versioned", reply)),
+        }
+    }
+
+    async fn set_versioned(&self, key: &str, value: Versioned) -> Result<Versioned> {
+        match self.shared.request(Request::SetVersioned { key: key.to_string(), value }).await? {
+            Reply::Versioned { value: Some(value) } => Ok(value),
+            reply => Err(unexpected("set_versioned", reply)),
+        }
+    }
+}
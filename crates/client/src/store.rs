@@ -0,0 +1,56 @@
+//! Local storage for the client layer
+
+use async_trait::async_trait;
+use dashmap::DashMap;
+
+use core::layer::CacheLayer;
+use core::pattern::glob_matches;
+
+/// In-memory local store, lost when the process exits
+#[derive(Default)]
+pub struct MemoryStore {
+    entries: DashMap<String, Vec<u8>>,
+}
+
+impl MemoryStore {
+    /// Create an empty store
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of entries held
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether the store is empty
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[async_trait]
+impl CacheLayer for MemoryStore {
+    async fn get(&self, key: &str) -> core::Result<Option<Vec<u8>>> {
+        Ok(self.entries.get(key).map(|v| v.clone()))
+    }
+
+    async fn set(&self, key: &str, value: Vec<u8>) -> core::Result<()> {
+        self.entries.insert(key.to_string(), value);
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> core::Result<()> {
+        self.entries.remove(key);
+        Ok(())
+    }
+
+    async fn keys(&self, pattern: &str) -> core::Result<Vec<String>> {
+        Ok(self
+            .entries
+            .iter()
+            .filter(|e| e.key() == pattern || glob_matches(pattern, e.key()))
+            .map(|e| e.key().clone())
+            .collect())
+    }
+}
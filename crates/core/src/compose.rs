@@ -0,0 +1,404 @@
+//! Combinators for composing cache strategies
+
+use std::time::Duration;
+
+use async_trait::async_trait;
+
+use crate::debug::overlaps;
+use crate::pattern::{glob_matches, specificity};
+use crate::strategy::{CacheStrategy, Consistency};
+use crate::{Error, Layer, Result};
+
+/// Append items not already present, keeping first-seen order
+fn merge<T: PartialEq>(into: &mut Vec<T>, items: Vec<T>) {
+    for item in items {
+        if !into.contains(&item) {
+            into.push(item);
+        }
+    }
+}
+
+/// Whether every key `pattern` matches also matches `route`
+///
+/// Matching the pattern as if it were a key is exact when a route `*` takes
+/// the pattern's `*`, since it takes whatever that `*` stands for. A
+/// `{name}` segment only stands for one segment, so it never covers a `*`.
+fn covers(route: &str, pattern: &str) -> bool {
+    route == pattern || (glob_matches(route, pattern) && !(route.contains('{') && pattern.contains('*')))
+}
+
+/// Stable FNV-1a hash, so weighted splits agree across processes
+fn stable_hash(key: &str) -> u64 {
+    key.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Always use one layer, e.g. `#[fallback = "edge"]`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pinned(pub Layer);
+
+#[async_trait]
+impl CacheStrategy for Pinned {
+    async fn determine_location(&self, _key: &str) -> Result<Layer> {
+        Ok(self.0)
+    }
+
+    async fn handle_invalidation(&self, pattern: &str) -> Result<Vec<String>> {
+        Ok(vec![pattern.to_string()])
+    }
+
+    fn name(&self) -> &str {
+        self.0.as_str()
+    }
+}
+
+/// Use `primary`, and `fallback` when it fails or misses
+///
+/// Reads try every layer of the primary before those of the fallback.
+pub struct Fallback {
+    primary: Box<dyn CacheStrategy>,
+    fallback: Box<dyn CacheStrategy>,
+}
+
+impl Fallback {
+    /// Create a fallback chain of two strategies
+    pub fn new(primary: impl CacheStrategy, fallback: impl CacheStrategy) -> Self {
+        Self {
+            primary: Box::new(primary),
+            fallback: Box::new(fallback),
+        }
+    }
+}
+
+#[async_trait]
+impl CacheStrategy for Fallback {
+    async fn determine_location(&self, key: &str) -> Result<Layer> {
+        match self.primary.determine_location(key).await {
+            Ok(layer) => Ok(layer),
+            Err(_) => self.fallback.determine_location(key).await,
+        }
+    }
+
+    async fn determine_location_sized(&self, key: &str, size: usize) -> Result<Layer> {
+        match self.primary.determine_location_sized(key, size).await {
+            Ok(layer) => Ok(layer),
+            Err(_) => self.fallback.determine_location_sized(key, size).await,
+        }
+    }
+
+    async fn locations(&self, key: &str) -> Result<Vec<Layer>> {
+        let mut layers = self.primary.locations(key).await.unwrap_or_default();
+        merge(&mut layers, self.fallback.locations(key).await?);
+        Ok(layers)
+    }
+
+    async fn handle_invalidation(&self, pattern: &str) -> Result<Vec<String>> {
+        let mut patterns = self.primary.handle_invalidation(pattern).await?;
+        merge(&mut patterns, self.fallback.handle_invalidation(pattern).await?);
+        Ok(patterns)
+    }
+
+    fn name(&self) -> &str {
+        "Fallback"
+    }
+
+    fn consistency(&self) -> Consistency {
+        self.primary.consistency().min(self.fallback.consistency())
+    }
+
+    fn ttl(&self, key: &str) -> Option<Duration> {
+        self.primary.ttl(key)
+    }
+
+    fn children(&self) -> Vec<&dyn CacheStrategy> {
+        vec![&*self.primary, &*self.fallback]
+    }
+}
+
+/// Pick a strategy by the most specific matching key pattern
+pub struct Route {
+    routes: Vec<(String, Box<dyn CacheStrategy>)>,
+    default: Box<dyn CacheStrategy>,
+}
+
+impl Route {
+    /// Route unmatched keys to `default`
+    pub fn new(default: impl CacheStrategy) -> Self {
+        Self {
+            routes: Vec::new(),
+            default: Box::new(default),
+        }
+    }
+
+    /// Route keys matching `pattern` to `strategy`
+    pub fn route(mut self, pattern: impl Into<String>, strategy: impl CacheStrategy) -> Self {
+        self.routes.push((pattern.into(), Box::new(strategy)));
+        self.routes
+            .sort_by_key(|(pattern, _)| std::cmp::Reverse(specificity(pattern)));
+        self
+    }
+
+    /// The strategy that handles a key or pattern
+    pub fn strategy_for(&self, key: &str) -> &dyn CacheStrategy {
+        self.routes
+            .iter()
+            .find(|(pattern, _)| pattern == key || glob_matches(pattern, key))
+            .map(|(_, strategy)| &**strategy)
+            .unwrap_or(&*self.default)
+    }
+}
+
+#[async_trait]
+impl CacheStrategy for Route {
+    async fn determine_location(&self, key: &str) -> Result<Layer> {
+        self.strategy_for(key).determine_location(key).await
+    }
+
+    async fn determine_location_sized(&self, key: &str, size: usize) -> Result<Layer> {
+        self.strategy_for(key).determine_location_sized(key, size).await
+    }
+
+    async fn locations(&self, key: &str) -> Result<Vec<Layer>> {
+        self.strategy_for(key).locations(key).await
+    }
+
+    /// A pattern can span several routes, so every route it overlaps
+    /// handles it, and the default does unless one route covers it entirely
+    async fn handle_invalidation(&self, pattern: &str) -> Result<Vec<String>> {
+        let mut patterns = Vec::new();
+        for (route, strategy) in &self.routes {
+            if overlaps(route, pattern) {
+                merge(&mut patterns, strategy.handle_invalidation(pattern).await?);
+            }
+        }
+        if !self.routes.iter().any(|(route, _)| covers(route, pattern)) {
+            merge(&mut patterns, self.default.handle_invalidation(pattern).await?);
+        }
+        Ok(patterns)
+    }
+
+    fn name(&self) -> &str {
+        "Route"
+    }
+
+    fn disjoint_children(&self) -> bool {
+        true
+    }
+
+    fn consistency(&self) -> Consistency {
+        self.children()
+            .iter()
+            .map(|s| s.consistency())
+            .min()
+            .unwrap_or(Consistency::Eventual)
+    }
+
+    fn ttl(&self, key: &str) -> Option<Duration> {
+        self.strategy_for(key).ttl(key)
+    }
+
+    fn children(&self) -> Vec<&dyn CacheStrategy> {
+        let mut children: Vec<&dyn CacheStrategy> =
+            self.routes.iter().map(|(_, s)| &**s).collect();
+        children.push(&*self.default);
+        children
+    }
+
+    fn validate(&self) -> Result<()> {
+        for (i, (pattern, _)) in self.routes.iter().enumerate() {
+            if pattern.is_empty() {
+                return Err(Error::StrategyError {
+                    strategy: self.name().to_string(),
+                    key: None,
+                    pattern: None,
+                    layer: None,
+                    reason: "route pattern is empty".to_string(),
+                });
+            }
+            if self.routes[..i].iter().any(|(other, _)| other == pattern) {
+                return Err(Error::StrategyError {
+                    strategy: self.name().to_string(),
+                    key: None,
+                    pattern: Some(pattern.clone()),
+                    layer: None,
+                    reason: format!("{} is routed more than once", pattern),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Send values larger than a threshold to a different strategy
+///
+/// Without a size, e.g. when reading, both strategies' layers are candidates.
+pub struct BySize {
+    threshold: usize,
+    small: Box<dyn CacheStrategy>,
+    large: Box<dyn CacheStrategy>,
+}
+
+impl BySize {
+    /// Use `large` for values over `threshold` bytes and `small` otherwise
+    pub fn new(threshold: usize, small: impl CacheStrategy, large: impl CacheStrategy) -> Self {
+        Self {
+            threshold,
+            small: Box::new(small),
+            large: Box::new(large),
+        }
+    }
+}
+
+#[async_trait]
+impl CacheStrategy for BySize {
+    async fn determine_location(&self, key: &str) -> Result<Layer> {
+        self.small.determine_location(key).await
+    }
+
+    async fn determine_location_sized(&self, key: &str, size: usize) -> Result<Layer> {
+        if size > self.threshold {
+            self.large.determine_location_sized(key, size).await
+        } else {
+            self.small.determine_location_sized(key, size).await
+        }
+    }
+
+    async fn locations(&self, key: &str) -> Result<Vec<Layer>> {
+        let mut layers = self.small.locations(key).await?;
+        merge(&mut layers, self.large.locations(key).await?);
+        Ok(layers)
+    }
+
+    async fn handle_invalidation(&self, pattern: &str) -> Result<Vec<String>> {
+        let mut patterns = self.small.handle_invalidation(pattern).await?;
+        merge(&mut patterns, self.large.handle_invalidation(pattern).await?);
+        Ok(patterns)
+    }
+
+    fn name(&self) -> &str {
+        "BySize"
+    }
+
+    fn consistency(&self) -> Consistency {
+        self.small.consistency().min(self.large.consistency())
+    }
+
+    fn ttl(&self, key: &str) -> Option<Duration> {
+        self.small.ttl(key).or_else(|| self.large.ttl(key))
+    }
+
+    fn children(&self) -> Vec<&dyn CacheStrategy> {
+        vec![&*self.small, &*self.large]
+    }
+}
+
+/// Split keys between strategies in proportion to their weights
+///
+/// A key always lands on the same strategy.
+#[derive(Default)]
+pub struct Weighted {
+    choices: Vec<(u32, Box<dyn CacheStrategy>)>,
+}
+
+impl Weighted {
+    /// Create an empty split
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a strategy receiving `weight` shares of the keyspace
+    pub fn with(mut self, weight: u32, strategy: impl CacheStrategy) -> Self {
+        self.choices.push((weight, Box::new(strategy)));
+        self
+    }
+
+    /// The strategy a key is assigned to
+    pub fn strategy_for(&self, key: &str) -> Result<&dyn CacheStrategy> {
+        let total: u64 = self.choices.iter().map(|(w, _)| u64::from(*w)).sum();
+        if total == 0 {
+            return Err(Error::StrategyError {
+                strategy: self.name().to_string(),
+                key: Some(key.to_string()),
+                pattern: None,
+                layer: None,
+                reason: "weights sum to zero".to_string(),
+            });
+        }
+        let mut point = stable_hash(key) % total;
+        for (weight, strategy) in &self.choices {
+            let weight = u64::from(*weight);
+            if point < weight {
+                return Ok(&**strategy);
+            }
+            point -= weight;
+        }
+        unreachable!("point is below the total weight")
+    }
+}
+
+#[async_trait]
+impl CacheStrategy for Weighted {
+    async fn determine_location(&self, key: &str) -> Result<Layer> {
+        self.strategy_for(key)?.determine_location(key).await
+    }
+
+    async fn determine_location_sized(&self, key: &str, size: usize) -> Result<Layer> {
+        self.strategy_for(key)?.determine_location_sized(key, size).await
+    }
+
+    async fn locations(&self, key: &str) -> Result<Vec<Layer>> {
+        self.strategy_for(key)?.locations(key).await
+    }
+
+    async fn handle_invalidation(&self, pattern: &str) -> Result<Vec<String>> {
+        let mut patterns = Vec::new();
+        for (_, strategy) in &self.choices {
+            merge(&mut patterns, strategy.handle_invalidation(pattern).await?);
+        }
+        Ok(patterns)
+    }
+
+    fn name(&self) -> &str {
+        "Weighted"
+    }
+
+    fn disjoint_children(&self) -> bool {
+        true
+    }
+
+    fn consistency(&self) -> Consistency {
+        self.choices
+            .iter()
+            .map(|(_, s)| s.consistency())
+            .min()
+            .unwrap_or(Consistency::Eventual)
+    }
+
+    fn ttl(&self, key: &str) -> Option<Duration> {
+        self.strategy_for(key).ok()?.ttl(key)
+    }
+
+    fn children(&self) -> Vec<&dyn CacheStrategy> {
+        self.choices.iter().map(|(_, s)| &**s).collect()
+    }
+
+    fn validate(&self) -> Result<()> {
+        self.strategy_for("").map(|_| ())
+    }
+}
+
+/// Declarative composition for any strategy
+pub trait StrategyExt: CacheStrategy + Sized {
+    /// Fall back to `fallback` when this strategy fails or misses
+    fn or_else(self, fallback: impl CacheStrategy) -> Fallback {
+        Fallback::new(self, fallback)
+    }
+
+    /// Send values over `threshold` bytes to `large`
+    fn above_size(self, threshold: usize, large: impl CacheStrategy) -> BySize {
+        BySize::new(threshold, self, large)
+    }
+}
+
+impl<S: CacheStrategy> StrategyExt for S {}
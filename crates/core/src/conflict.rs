@@ -0,0 +1,539 @@
+//! Detecting and resolving concurrent writes to the same key
+
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::crdt::CrdtValue;
+use crate::layer::CacheLayer;
+use crate::pattern::{glob_matches, specificity};
+use crate::{Error, Result};
+
+/// A hybrid logical clock reading
+///
+/// Orders by wall-clock milliseconds, then by a logical counter that orders
+/// events within one millisecond or under clock skew, then by node so
+/// readings from different nodes never tie.
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Timestamp {
+    pub physical: u64,
+    pub logical: u32,
+    pub node: String,
+}
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}@{}", self.physical, self.logical, self.node)
+    }
+}
+
+/// Hybrid logical clock for one node
+///
+/// Readings never go backwards and always order after every reading the
+/// node has observed from other nodes, even when wall clocks disagree.
+pub struct HybridClock {
+    node: String,
+    last: Mutex<(u64, u32)>,
+}
+
+fn wall_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+impl HybridClock {
+    /// Create a clock for `node`
+    pub fn new(node: impl Into<String>) -> Self {
+        Self {
+            node: node.into(),
+            last: Mutex::new((0, 0)),
+        }
+    }
+
+    /// The node readings are attributed to
+    pub fn node(&self) -> &str {
+        &self.node
+    }
+
+    /// Read the clock for a local event
+    pub fn now(&self) -> Timestamp {
+        let wall = wall_millis();
+        let mut last = self.last.lock().unwrap();
+        *last = if wall > last.0 { (wall, 0) } else { (last.0, last.1 + 1) };
+        Timestamp {
+            physical: last.0,
+            logical: last.1,
+            node: self.node.clone(),
+        }
+    }
+
+    /// Account for a reading from another node
+    pub fn observe(&self, remote: &Timestamp) {
+        let mut last = self.last.lock().unwrap();
+        *last = (*last).max((remote.physical, remote.logical));
+    }
+}
+
+/// How one version relates to another
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Causality {
+    Equal,
+    /// Every change in this version is also in the other
+    Before,
+    /// This version includes every change in the other
+    After,
+    /// Each version has changes the other has not seen
+    Concurrent,
+}
+
+/// Per-node change counters
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct VersionVector(BTreeMap<String, u64>);
+
+impl VersionVector {
+    /// An empty vector
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The counter for `node`
+    pub fn get(&self, node: &str) -> u64 {
+        self.0.get(node).copied().unwrap_or_default()
+    }
+
+    /// Record a change on `node`, returning its new counter
+    pub fn increment(&mut self, node: &str) -> u64 {
+        self.advance(node, 0)
+    }
+
+    /// Record a change on `node`, moving its counter to at least `at`
+    ///
+    /// Nodes that lose their vectors on restart can pass a clock reading to
+    /// keep counters increasing.
+    pub fn advance(&mut self, node: &str, at: u64) -> u64 {
+        let counter = self.0.entry(node.to_string()).or_default();
+        *counter = (*counter + 1).max(at);
+        *counter
+    }
+
+    /// Include every change in `other`
+    pub fn merge(&mut self, other: &VersionVector) {
+        for (node, &counter) in &other.0 {
+            let entry = self.0.entry(node.clone()).or_default();
+            *entry = (*entry).max(counter);
+        }
+    }
+
+    /// Compare against `other`
+    pub fn compare(&self, other: &VersionVector) -> Causality {
+        let mut ordering = Ordering::Equal;
+        for node in self.0.keys().chain(other.0.keys()) {
+            match (self.get(node).cmp(&other.get(node)), ordering) {
+                (Ordering::Equal, _) => {}
+                (o, Ordering::Equal) => ordering = o,
+                (o, current) if o != current => return Causality::Concurrent,
+                _ => {}
+            }
+        }
+        match ordering {
+            Ordering::Equal => Causality::Equal,
+            Ordering::Less => Causality::Before,
+            Ordering::Greater => Causality::After,
+        }
+    }
+
+    /// Whether no changes are recorded
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// A value with the version it was written at
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Versioned {
+    pub value: Vec<u8>,
+    pub stamp: Timestamp,
+    pub vector: VersionVector,
+}
+
+impl Versioned {
+    /// Version a value
+    pub fn new(value: Vec<u8>, stamp: Timestamp, vector: VersionVector) -> Self {
+        Self { value, stamp, vector }
+    }
+
+    /// A value from a layer that does not track versions
+    pub fn unversioned(value: Vec<u8>) -> Self {
+        Self {
+            value,
+            ..Self::default()
+        }
+    }
+
+    /// Serialize for storage
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        Ok(serde_json::to_vec(self)?)
+    }
+
+    /// Deserialize stored bytes
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        Ok(serde_json::from_slice(bytes)?)
+    }
+}
+
+/// Two writes to one key where neither saw the other
+#[derive(Debug, Clone)]
+pub struct Conflict {
+    pub key: String,
+    /// The stored version
+    pub current: Versioned,
+    /// The write being applied
+    pub incoming: Versioned,
+}
+
+/// Which value a resolver keeps
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Resolution {
+    Current,
+    Incoming,
+    Merged(Vec<u8>),
+}
+
+/// Decides the result of a conflict
+///
+/// Returning an error refuses the incoming write and keeps the stored value.
+pub trait ConflictResolver: Send + Sync + 'static {
+    fn resolve(&self, conflict: &Conflict) -> Result<Resolution>;
+}
+
+impl<F> ConflictResolver for F
+where
+    F: Fn(&Conflict) -> Result<Resolution> + Send + Sync + 'static,
+{
+    fn resolve(&self, conflict: &Conflict) -> Result<Resolution> {
+        self(conflict)
+    }
+}
+
+/// Keep the write with the later hybrid logical clock reading
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LastWriterWins;
+
+impl ConflictResolver for LastWriterWins {
+    fn resolve(&self, conflict: &Conflict) -> Result<Resolution> {
+        Ok(if conflict.incoming.stamp > conflict.current.stamp {
+            Resolution::Incoming
+        } else {
+            Resolution::Current
+        })
+    }
+}
+
+/// Combine concurrent writes with a user merge function
+pub struct MergeWith<F> {
+    merge: F,
+}
+
+impl<F> MergeWith<F>
+where
+    F: Fn(&Conflict) -> Vec<u8> + Send + Sync + 'static,
+{
+    /// Merge conflicts with `merge`
+    pub fn new(merge: F) -> Self {
+        Self { merge }
+    }
+}
+
+impl<F> ConflictResolver for MergeWith<F>
+where
+    F: Fn(&Conflict) -> Vec<u8> + Send + Sync + 'static,
+{
+    fn resolve(&self, conflict: &Conflict) -> Result<Resolution> {
+        Ok(Resolution::Merged((self.merge)(conflict)))
+    }
+}
+
+/// Refuse conflicting writes and report them
+pub struct RejectAndNotify<F> {
+    notify: F,
+}
+
+impl<F> RejectAndNotify<F>
+where
+    F: Fn(&Conflict) + Send + Sync + 'static,
+{
+    /// Call `notify` with each refused conflict
+    pub fn new(notify: F) -> Self {
+        Self { notify }
+    }
+}
+
+impl<F> ConflictResolver for RejectAndNotify<F>
+where
+    F: Fn(&Conflict) + Send + Sync + 'static,
+{
+    fn resolve(&self, conflict: &Conflict) -> Result<Resolution> {
+        (self.notify)(conflict);
+        Err(Error::Conflict {
+            key: conflict.key.clone(),
+            reason: format!("concurrent with the write at {}", conflict.current.stamp),
+        })
+    }
+}
+
+/// Conflict resolvers by pattern
+///
+/// Shared between the `LayerCoordinator`, where resolvers are registered
+/// with `on_conflict`, and the `ConflictLayer`s that apply them. Keys with
+/// no matching resolver use `LastWriterWins`.
+#[derive(Default)]
+pub struct Conflicts {
+    resolvers: DashMap<String, Arc<dyn ConflictResolver>>,
+}
+
+impl Conflicts {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Resolve conflicts on keys matching `pattern` with `resolver`
+    pub fn on_conflict(&self, pattern: impl Into<String>, resolver: impl ConflictResolver) {
+        self.resolvers.insert(pattern.into(), Arc::new(resolver));
+    }
+
+    /// Remove the resolver for `pattern`
+    pub fn remove(&self, pattern: &str) -> bool {
+        self.resolvers.remove(pattern).is_some()
+    }
+
+    /// The resolver for a key, by the most specific matching pattern
+    pub fn resolver_for(&self, key: &str) -> Arc<dyn ConflictResolver> {
+        self.resolvers
+            .iter()
+            .filter(|r| r.key() == key || glob_matches(r.key(), key))
+            .max_by_key(|r| specificity(r.key()))
+            .map(|r| r.value().clone())
+            .unwrap_or_else(|| Arc::new(LastWriterWins))
+    }
+
+    /// The version to store when `incoming` is written over `current`
+    ///
+    /// CRDT values of the same type are always merged. Otherwise writes
+    /// that include the stored version replace it, and writes the stored
+    /// version already includes are dropped. Anything else is a conflict for
+    /// the key's resolver. Merged and resolved results include both versions.
+    pub fn reconcile(&self, key: &str, current: Option<Versioned>, incoming: Versioned) -> Result<Versioned> {
+        let Some(current) = current else { return Ok(incoming) };
+        let value = match CrdtValue::merge_bytes(&current.value, &incoming.value) {
+            Some(merged) => merged,
+            None => {
+                match incoming.vector.compare(&current.vector) {
+                    Causality::After => return Ok(incoming),
+                    Causality::Before => return Ok(current),
+                    Causality::Equal if incoming.stamp == current.stamp => return Ok(incoming),
+                    Causality::Equal | Causality::Concurrent => {}
+                }
+                let conflict = Conflict {
+                    key: key.to_string(),
+                    current,
+                    incoming,
+                };
+                let resolution = self.resolver_for(key).resolve(&conflict)?;
+                tracing::debug!(key, ?resolution, current = %conflict.current.stamp, incoming = %conflict.incoming.stamp, "resolved write conflict");
+                return Ok(Self::combine(conflict.current, conflict.incoming, resolution));
+            }
+        };
+        Ok(Self::combine(current, incoming, Resolution::Merged(value)))
+    }
+
+    /// A version including both `current` and `incoming`, holding the resolved value
+    fn combine(current: Versioned, incoming: Versioned, resolution: Resolution) -> Versioned {
+        let mut vector = current.vector.clone();
+        vector.merge(&incoming.vector);
+        let stamp = current.stamp.clone().max(incoming.stamp.clone());
+        let value = match resolution {
+            Resolution::Current => current.value,
+            Resolution::Incoming => incoming.value,
+            Resolution::Merged(value) => value,
+        };
+        Versioned { value, stamp, vector }
+    }
+}
+
+/// What a `ConflictLayer` stores for a key
+#[derive(Serialize, Deserialize)]
+struct Record {
+    #[serde(flatten)]
+    version: Versioned,
+    /// The key was deleted at `version`, whose value is empty
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    deleted: bool,
+}
+
+impl Record {
+    fn live(version: Versioned) -> Self {
+        Self { version, deleted: false }
+    }
+}
+
+/// A `CacheLayer` that versions every value and resolves concurrent writes
+///
+/// Values are stored as `Versioned` records. Plain writes, such as those
+/// from the coordinator, are versioned on this layer's node and always win;
+/// writers that track versions use `set_versioned`, and their writes are
+/// checked against the stored version and resolved by the shared
+/// `Conflicts` registry. Writes of a CRDT value merge with the stored copy
+/// either way, so replicas written on different layers converge.
+///
+/// Deletes are versioned too and leave a tombstone. A versioned write the
+/// delete saw, or one concurrent with it and stamped earlier, is refused
+/// with `Error::Conflict` instead of bringing the value back.
+pub struct ConflictLayer<L> {
+    inner: L,
+    clock: HybridClock,
+    conflicts: Arc<Conflicts>,
+    writes: DashMap<String, Arc<tokio::sync::Mutex<()>>>,
+}
+
+impl<L: CacheLayer> ConflictLayer<L> {
+    /// Version writes to `inner` as `node`, resolving with `conflicts`
+    pub fn new(inner: L, node: impl Into<String>, conflicts: Arc<Conflicts>) -> Self {
+        Self {
+            inner,
+            clock: HybridClock::new(node),
+            conflicts,
+            writes: DashMap::new(),
+        }
+    }
+
+    /// The clock versioning plain writes
+    pub fn clock(&self) -> &HybridClock {
+        &self.clock
+    }
+
+    /// The resolvers applied to conflicting writes
+    pub fn conflicts(&self) -> &Arc<Conflicts> {
+        &self.conflicts
+    }
+
+    async fn record(&self, key: &str) -> Result<Option<Record>> {
+        let Some(bytes) = self.inner.get(key).await? else { return Ok(None) };
+        serde_json::from_slice(&bytes).map(Some).map_err(|e| Error::Serialization {
+            key: Some(key.to_string()),
+            reason: e.to_string(),
+            source: Some(Box::new(e)),
+        })
+    }
+
+    /// The stored version of a key that is not deleted
+    async fn current(&self, key: &str) -> Result<Option<Versioned>> {
+        Ok(self.record(key).await?.filter(|r| !r.deleted).map(|r| r.version))
+    }
+
+    /// Read, resolve and store a write while no other write of `key` runs
+    async fn apply(&self, key: &str, write: impl FnOnce(Option<Record>) -> Result<Record>) -> Result<Versioned> {
+        let gate = self.writes.entry(key.to_string()).or_default().clone();
+        let result = {
+            let _guard = gate.lock().await;
+            async {
+                let stored = write(self.record(key).await?)?;
+                self.inner.set(key, serde_json::to_vec(&stored)?).await?;
+                Ok(stored.version)
+            }
+            .await
+        };
+        drop(gate);
+        self.writes.remove_if(key, |_, gate| Arc::strong_count(gate) == 1);
+        result
+    }
+}
+
+#[async_trait]
+impl<L: CacheLayer> CacheLayer for ConflictLayer<L> {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self.current(key).await?.map(|v| v.value))
+    }
+
+    async fn set(&self, key: &str, value: Vec<u8>) -> Result<()> {
+        self.apply(key, |record| {
+            let stamp = self.clock.now();
+            let (value, mut vector) = match record {
+                Some(Record { version, deleted: false }) => {
+                    let value = CrdtValue::merge_bytes(&version.value, &value).unwrap_or(value);
+                    (value, version.vector)
+                }
+                Some(Record { version, deleted: true }) => (value, version.vector),
+                None => (value, VersionVector::new()),
+            };
+            vector.advance(self.clock.node(), stamp.physical);
+            Ok(Record::live(Versioned { value, stamp, vector }))
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        self.apply(key, |record| {
+            let stamp = self.clock.now();
+            let mut vector = record.map(|r| r.version.vector).unwrap_or_default();
+            vector.advance(self.clock.node(), stamp.physical);
+            Ok(Record {
+                version: Versioned { value: Vec::new(), stamp, vector },
+                deleted: true,
+            })
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn keys(&self, pattern: &str) -> Result<Vec<String>> {
+        let mut keys = Vec::new();
+        for key in self.inner.keys(pattern).await? {
+            if self.current(&key).await?.is_some() {
+                keys.push(key);
+            }
+        }
+        Ok(keys)
+    }
+
+    async fn get_versioned(&self, key: &str) -> Result<Option<Versioned>> {
+        self.current(key).await
+    }
+
+    async fn set_versioned(&self, key: &str, incoming: Versioned) -> Result<Versioned> {
+        self.clock.observe(&incoming.stamp);
+        self.apply(key, |record| match record {
+            Some(Record { version: tombstone, deleted: true }) => {
+                let kept = match incoming.vector.compare(&tombstone.vector) {
+                    Causality::After => true,
+                    Causality::Concurrent => incoming.stamp > tombstone.stamp,
+                    Causality::Before | Causality::Equal => false,
+                };
+                if !kept {
+                    return Err(Error::Conflict {
+                        key: key.to_string(),
+                        reason: format!("deleted at {}", tombstone.stamp),
+                    });
+                }
+                let mut vector = tombstone.vector;
+                vector.merge(&incoming.vector);
+                Ok(Record::live(Versioned { vector, ..incoming }))
+            }
+            record => {
+                let current = record.map(|r| r.version);
+                self.conflicts.reconcile(key, current, incoming).map(Record::live)
+            }
+        })
+        .await
+    }
+}
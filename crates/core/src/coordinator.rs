@@ -0,0 +1,653 @@
+//! Coordinated reads and writes across the client, edge and server layers
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use dashmap::DashMap;
+
+use crate::conflict::{ConflictResolver, Conflicts};
+use crate::crdt::{CrdtValue, PNCounter};
+use crate::handoff::{Borrow, Handoffs};
+use crate::invalidation::{CatchUp, Invalidation, InvalidationBus};
+use crate::layer::CacheLayer;
+use crate::metrics::Metrics;
+use crate::pins::Pins;
+use crate::strategy::CacheStrategy;
+use crate::validate::StrategyValidator;
+use crate::{EdgeType, Error, Layer, Ownership, OwnershipGraph, Result, Strategy};
+
+/// A value found by a tiered read
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lookup {
+    pub value: Vec<u8>,
+    /// The layer that held the value
+    pub layer: Layer,
+    /// Faster layers the value was copied into
+    pub backfilled: Vec<Layer>,
+}
+
+struct Tier {
+    layer: Layer,
+    backend: Arc<dyn CacheLayer>,
+    /// Serializes invalidation delivery so events apply in sequence order
+    delivery: tokio::sync::Mutex<()>,
+}
+
+/// Coordinates operations across layers
+///
+/// Reads walk the layers from client to server and backfill faster layers
+/// the key's strategy places it on. Writes go to the owning layer, and the
+/// invalidation bus removes copies everywhere else. Layers that miss
+/// invalidations during a partition are skipped by reads until they catch up.
+///
+/// Ownership can move between components and layers at runtime with
+/// `transfer`, which drains in-flight writes to the pattern first.
+pub struct LayerCoordinator {
+    layers: Vec<Tier>,
+    ownership_graph: Arc<OwnershipGraph>,
+    strategy: Box<dyn CacheStrategy>,
+    bus: Arc<InvalidationBus>,
+    pins: Arc<Pins>,
+    conflicts: Arc<Conflicts>,
+    /// Layers chosen with `set_layer` for keys the manifest does not own
+    placements: DashMap<String, Layer>,
+    handoffs: Handoffs,
+    /// Outstanding runtime borrows per (borrower, pattern), and whether the
+    /// manifest already declared the borrow
+    borrows: DashMap<(String, String), (usize, bool)>,
+    in_flight: DashMap<String, Arc<tokio::sync::Mutex<()>>>,
+    metrics: Option<Arc<Metrics>>,
+}
+
+/// Position of a layer in the read path, fastest first
+fn tier(layer: Layer) -> u8 {
+    match layer {
+        Layer::Client => 0,
+        Layer::Edge => 1,
+        Layer::Server => 2,
+    }
+}
+
+impl LayerCoordinator {
+    /// Create a coordinator with no layers, using `GlobalConsistent` placement
+    pub fn new(ownership_graph: Arc<OwnershipGraph>) -> Self {
+        Self {
+            layers: Vec::new(),
+            ownership_graph,
+            strategy: Box::new(Strategy::GlobalConsistent),
+            bus: Arc::new(InvalidationBus::new()),
+            pins: Arc::new(Pins::new()),
+            conflicts: Arc::new(Conflicts::new()),
+            placements: DashMap::new(),
+            handoffs: Handoffs::new(),
+            borrows: DashMap::new(),
+            in_flight: DashMap::new(),
+            metrics: None,
+        }
+    }
+
+    /// Add or replace the backend for a layer
+    pub fn with_layer(mut self, layer: Layer, backend: Arc<dyn CacheLayer>) -> Self {
+        self.layers.retain(|t| t.layer != layer);
+        self.layers.push(Tier {
+            layer,
+            backend,
+            delivery: tokio::sync::Mutex::new(()),
+        });
+        self.layers.sort_by_key(|t| tier(t.layer));
+        self.bus.subscribe(layer);
+        self
+    }
+
+    /// Retain up to `capacity` invalidations for layers that fall behind
+    pub fn with_invalidation_log(mut self, capacity: usize) -> Self {
+        self.bus = Arc::new(InvalidationBus::with_capacity(capacity));
+        for tier in &self.layers {
+            self.bus.subscribe(tier.layer);
+        }
+        self
+    }
+
+    /// Set the strategy that places unowned keys and decides backfills
+    ///
+    /// The strategy and the manifest are checked against the layers added so
+    /// far, so call this after `with_layer`. Fails with
+    /// `Error::StrategyError` on an invalid composition, a layer that is not
+    /// configured, or a circular invalidation chain.
+    pub async fn with_strategy(mut self, strategy: impl CacheStrategy) -> Result<Self> {
+        StrategyValidator::new(&self.ownership_graph)
+            .with_layers(&self.layers())
+            .strategy("*", &strategy)
+            .validate()
+            .await?;
+        self.strategy = Box::new(strategy);
+        Ok(self)
+    }
+
+    /// Wait up to `timeout` for in-flight writes when transferring ownership
+    pub fn with_drain_timeout(mut self, timeout: Duration) -> Self {
+        self.handoffs = Handoffs::with_drain_timeout(timeout);
+        self
+    }
+
+    /// Share pins with other components, e.g. a `QuotaManager`
+    pub fn with_pins(mut self, pins: Arc<Pins>) -> Self {
+        self.pins = pins;
+        self
+    }
+
+    /// Keys pinned to a layer
+    pub fn pins(&self) -> &Arc<Pins> {
+        &self.pins
+    }
+
+    /// Share conflict resolvers with the `ConflictLayer`s that apply them
+    pub fn with_conflicts(mut self, conflicts: Arc<Conflicts>) -> Self {
+        self.conflicts = conflicts;
+        self
+    }
+
+    /// Conflict resolvers by pattern
+    pub fn conflicts(&self) -> &Arc<Conflicts> {
+        &self.conflicts
+    }
+
+    /// Record the keys each layer drops for invalidations in a metrics registry
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Resolve concurrent writes to keys matching `pattern` with `resolver`
+    ///
+    /// Applies to layers wrapped in a `ConflictLayer` sharing this
+    /// coordinator's `conflicts()`.
+    pub fn on_conflict(&self, pattern: &str, resolver: impl ConflictResolver) {
+        self.conflicts.on_conflict(pattern, resolver);
+    }
+
+    /// The manifest writes are checked against
+    pub fn ownership(&self) -> &Arc<OwnershipGraph> {
+        &self.ownership_graph
+    }
+
+    /// The invalidation bus shared by all layers
+    pub fn bus(&self) -> &Arc<InvalidationBus> {
+        &self.bus
+    }
+
+    /// The backend for a layer, if configured
+    pub fn layer(&self, layer: Layer) -> Option<&Arc<dyn CacheLayer>> {
+        self.tier(layer).map(|t| &t.backend)
+    }
+
+    /// Configured layers, fastest first
+    pub fn layers(&self) -> Vec<Layer> {
+        self.layers.iter().map(|t| t.layer).collect()
+    }
+
+    fn tier(&self, layer: Layer) -> Option<&Tier> {
+        self.layers.iter().find(|t| t.layer == layer)
+    }
+
+    fn configured(&self, key: &str, layer: Layer) -> Result<&Tier> {
+        self.tier(layer).ok_or_else(|| Error::LayerViolation {
+            key: key.to_string(),
+            layer,
+            reason: "layer is not configured".to_string(),
+        })
+    }
+
+    /// Reject placing `key` on `layer` if the manifest or a pin puts it elsewhere
+    fn check_placement(&self, key: &str, layer: Layer) -> Result<()> {
+        let owner = self
+            .ownership_graph
+            .get(key)
+            .or_else(|| self.ownership_graph.owner_of(key));
+        if let Some(owner) = owner {
+            if owner.layer() != layer {
+                return Err(Error::LayerViolation {
+                    key: key.to_string(),
+                    layer,
+                    reason: format!("{} is owned by {} on the {} layer", owner.pattern(), owner.owner(), owner.layer()),
+                });
+            }
+        }
+        if let Some(pinned) = self.pins.pinned(key) {
+            if pinned != layer {
+                return Err(Error::LayerViolation {
+                    key: key.to_string(),
+                    layer,
+                    reason: format!("pinned to the {} layer", pinned),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Get a value, walking the layers from fastest to slowest
+    pub async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self.lookup(key).await?.map(|hit| hit.value))
+    }
+
+    /// Get a value and report where it was found and which layers were backfilled
+    ///
+    /// Concurrent lookups of the same key are serialized, so a cold key
+    /// causes one walk down the layers and later callers hit the backfill.
+    pub async fn lookup(&self, key: &str) -> Result<Option<Lookup>> {
+        self.exclusive(key, self.walk(key)).await
+    }
+
+    /// Run `op` while no other read walk or write of `key` is in progress
+    ///
+    /// Writes of `key` take the same gate as reads. Pattern and dependent
+    /// invalidations do not, so walks check the bus before keeping a backfill.
+    async fn exclusive<T>(&self, key: &str, op: impl std::future::Future<Output = T>) -> T {
+        let gate = self
+            .in_flight
+            .entry(key.to_string())
+            .or_default()
+            .clone();
+        let result = {
+            let _guard = gate.lock().await;
+            op.await
+        };
+        drop(gate);
+        self.in_flight
+            .remove_if(key, |_, gate| Arc::strong_count(gate) == 1);
+        result
+    }
+
+    async fn walk(&self, key: &str) -> Result<Option<Lookup>> {
+        let seen = self.bus.latest();
+        let mut missed = Vec::new();
+        for tier in &self.layers {
+            let layer = tier.layer;
+            if !self.bus.is_connected(layer) {
+                continue;
+            }
+            let value = match tier.backend.get(key).await {
+                Ok(Some(value)) => value,
+                Ok(None) => {
+                    missed.push(layer);
+                    continue;
+                }
+                Err(e) if e.is_retryable() => {
+                    tracing::debug!(key, layer = %layer, error = %e, "layer unavailable, trying next");
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+
+            let placement = self.strategy.locations(key).await?;
+            let mut backfilled = Vec::new();
+            for faster in missed.into_iter().filter(|l| placement.contains(l)) {
+                if let Some(target) = self.layer(faster) {
+                    match target.set(key, value.clone()).await {
+                        Ok(()) => backfilled.push(faster),
+                        Err(e) => tracing::debug!(key, layer = %faster, error = %e, "backfill failed"),
+                    }
+                }
+            }
+            // An invalidation published before a backfill landed may have
+            // been delivered before it too, so take the copies back out
+            if !backfilled.is_empty() && self.bus.invalidated_since(seen, key) {
+                tracing::debug!(key, "value invalidated during read, dropping backfill");
+                for faster in backfilled.drain(..) {
+                    if let Some(target) = self.layer(faster) {
+                        target.delete(key).await?;
+                    }
+                }
+            }
+            return Ok(Some(Lookup {
+                value,
+                layer,
+                backfilled,
+            }));
+        }
+        Ok(None)
+    }
+
+    /// The layer a key is written to
+    ///
+    /// The manifest owner's layer wins, then pins, then `set_layer`
+    /// placements; anything else is placed by the strategy.
+    pub async fn owning_layer(&self, key: &str, size: usize) -> Result<Layer> {
+        if let Some(ownership) = self.ownership_graph.owner_of(key) {
+            return Ok(ownership.layer());
+        }
+        if let Some(layer) = self.pins.pinned(key) {
+            return Ok(layer);
+        }
+        if let Some(layer) = self.placements.get(key) {
+            return Ok(*layer);
+        }
+        self.strategy.determine_location_sized(key, size).await
+    }
+
+    /// Write a value to its owning layer and invalidate copies on every other layer
+    ///
+    /// Patterns the manifest says depend on the key's owner are invalidated too.
+    pub async fn set(&self, key: &str, value: Vec<u8>) -> Result<()> {
+        let _permit = self.write_permit(key).await;
+        self.exclusive(key, self.write(key, value)).await
+    }
+
+    async fn write(&self, key: &str, value: Vec<u8>) -> Result<()> {
+        let layer = self.owning_layer(key, value.len()).await?;
+        let backend = self.layer(layer).ok_or_else(|| Error::LayerViolation {
+            key: key.to_string(),
+            layer,
+            reason: "owning layer is not configured".to_string(),
+        })?;
+        backend.set(key, value).await?;
+
+        self.bus.publish(Invalidation::Key(key.to_string()), Some(layer));
+        if let Some(owner) = self.ownership_graph.owner_of(key) {
+            for pattern in self.strategy.handle_invalidation(owner.pattern()).await? {
+                if pattern != owner.pattern() {
+                    self.bus.publish(Invalidation::Pattern(pattern), None);
+                }
+            }
+        }
+        self.deliver().await
+    }
+
+    /// Add `delta` to the counter at `key` on behalf of `node`, returning its new value
+    ///
+    /// The key holds a `PNCounter` in which each node changes only its own
+    /// count, so increments made through coordinators on different layers
+    /// converge when the owning layer merges CRDTs, as `ConflictLayer` does.
+    pub async fn incr(&self, key: &str, node: &str, delta: i64) -> Result<i64> {
+        let _permit = self.write_permit(key).await;
+        self.exclusive(key, async {
+            let layer = self.owning_layer(key, 0).await?;
+            let current = self.configured(key, layer)?.backend.get(key).await?;
+            let mut counter = PNCounter::from_stored(key, current.as_deref())?;
+            counter.incr(node, delta);
+            let value = counter.value();
+            self.write(key, CrdtValue::PNCounter(counter).encode()?).await?;
+            Ok(value)
+        })
+        .await
+    }
+
+    /// Delete a key from every layer
+    pub async fn delete(&self, key: &str) -> Result<()> {
+        let _permit = self.write_permit(key).await;
+        self.exclusive(key, async {
+            self.placements.remove(key);
+            self.bus.publish(Invalidation::Key(key.to_string()), None);
+            self.deliver().await
+        })
+        .await
+    }
+
+    /// Write a key to a specific layer and keep later writes there
+    ///
+    /// Fails if the manifest owns the key on, or a pin holds it to, another layer.
+    /// Owned keys already follow their owner, so only unowned keys are placed.
+    pub async fn set_layer(&self, key: &str, value: Vec<u8>, layer: Layer) -> Result<()> {
+        let _permit = self.write_permit(key).await;
+        self.check_placement(key, layer)?;
+        let tier = self.configured(key, layer)?;
+        self.exclusive(key, async {
+            tier.backend.set(key, value).await?;
+            if self.ownership_graph.owner_of(key).is_none() {
+                self.placements.insert(key.to_string(), layer);
+            }
+            self.bus.publish(Invalidation::Key(key.to_string()), Some(layer));
+            self.deliver().await
+        })
+        .await
+    }
+
+    /// Layer an unowned key was placed on with `set_layer`
+    pub fn placement(&self, key: &str) -> Option<Layer> {
+        self.placements.get(key).map(|layer| *layer)
+    }
+
+    /// Read a key from one layer, without falling through or backfilling
+    pub async fn get_from(&self, key: &str, layer: Layer) -> Result<Option<Vec<u8>>> {
+        self.configured(key, layer)?.backend.get(key).await
+    }
+
+    /// Copy every entry matching `pattern` from its owning layer to `layer`
+    ///
+    /// Copies are read replicas: writes through the coordinator invalidate
+    /// them like any other copy. Returns the number of entries copied.
+    pub async fn replicate_to(&self, pattern: &str, layer: Layer) -> Result<usize> {
+        let target = self.configured(pattern, layer)?;
+        let source_layer = match self
+            .ownership_graph
+            .get(pattern)
+            .or_else(|| self.ownership_graph.owner_of(pattern))
+        {
+            Some(owner) => owner.layer(),
+            None => self.owning_layer(pattern, 0).await?,
+        };
+        if source_layer == layer {
+            return Ok(0);
+        }
+        let source = self.configured(pattern, source_layer)?;
+
+        let mut copied = 0;
+        for key in source.backend.keys(pattern).await? {
+            if let Some(value) = source.backend.get(&key).await? {
+                target.backend.set(&key, value).await?;
+                copied += 1;
+            }
+        }
+        Ok(copied)
+    }
+
+    /// Pin keys matching `pattern` to `layer`
+    ///
+    /// Pinned keys are always written to that layer, and quota managers
+    /// sharing the coordinator's pins never evict them.
+    pub fn pin_to(&self, pattern: &str, layer: Layer) -> Result<()> {
+        self.check_placement(pattern, layer)?;
+        self.configured(pattern, layer)?;
+        self.pins.pin(pattern, layer);
+        Ok(())
+    }
+
+    /// Remove a pin
+    pub fn unpin(&self, pattern: &str) -> Option<Layer> {
+        self.pins.unpin(pattern)
+    }
+
+    /// Permit to write a key, held until the write and its invalidations finish
+    async fn write_permit(&self, key: &str) -> Option<tokio::sync::OwnedRwLockReadGuard<()>> {
+        let owner = self.ownership_graph.owner_of(key)?;
+        Some(self.handoffs.write(owner.pattern()).await)
+    }
+
+    /// The current owner of a pattern or key
+    pub fn check_owner(&self, pattern: &str) -> Option<Arc<Ownership>> {
+        self.ownership_graph
+            .get(pattern)
+            .or_else(|| self.ownership_graph.owner_of(pattern))
+    }
+
+    /// Hand a pattern from the component `from` to a new owner
+    ///
+    /// New writes to the pattern wait while in-flight writes drain, then the
+    /// current owner is checked against `from`. If the owning layer changes,
+    /// entries are copied to the new layer and every other copy is
+    /// invalidated before writes resume. Returns the number of entries migrated.
+    pub async fn transfer(&self, from: &str, to: Ownership) -> Result<usize> {
+        let pattern = to.pattern().to_string();
+        let _quiesced = self.handoffs.quiesce(&pattern).await?;
+        let current = self.ownership_graph.get(&pattern).ok_or_else(|| {
+            Error::InvalidConfig(format!("{} has no owner to transfer from", pattern))
+        })?;
+        if current.owner() != from {
+            return Err(Error::PatternConflict {
+                pattern,
+                owner: current.owner().to_string(),
+                requested_by: from.to_string(),
+            });
+        }
+        if let Some(pinned) = self.pins.pinned(&pattern).filter(|l| *l != to.layer()) {
+            return Err(Error::LayerViolation {
+                key: pattern,
+                layer: to.layer(),
+                reason: format!("pinned to the {} layer", pinned),
+            });
+        }
+        let target = self.configured(&pattern, to.layer())?;
+
+        let mut migrated = 0;
+        if current.layer() != to.layer() {
+            let source = self.configured(&pattern, current.layer())?;
+            for key in source.backend.keys(&pattern).await? {
+                if let Some(value) = source.backend.get(&key).await? {
+                    target.backend.set(&key, value).await?;
+                    migrated += 1;
+                }
+            }
+            self.bus.publish(Invalidation::Pattern(pattern.clone()), Some(to.layer()));
+        }
+        self.ownership_graph.reassign(to);
+        self.deliver().await?;
+        Ok(migrated)
+    }
+
+    /// Borrow read access to a pattern from its owner
+    ///
+    /// Records the borrow in the ownership graph until `release_borrow`.
+    pub fn borrow_from(&self, owner: &str, pattern: &str, borrower: &str) -> Result<Borrow> {
+        let current = self.check_owner(pattern).ok_or_else(|| {
+            Error::InvalidConfig(format!("{} has no owner to borrow from", pattern))
+        })?;
+        if current.owner() != owner {
+            return Err(Error::PatternConflict {
+                pattern: pattern.to_string(),
+                owner: current.owner().to_string(),
+                requested_by: borrower.to_string(),
+            });
+        }
+        if borrower == owner {
+            return Err(Error::InvalidBorrowing {
+                key: pattern.to_string(),
+                pattern: pattern.to_string(),
+                owner: owner.to_string(),
+                borrower: borrower.to_string(),
+            });
+        }
+
+        let declared = self.ownership_graph.has_edge(borrower, pattern, EdgeType::Borrows);
+        self.borrows
+            .entry((borrower.to_string(), pattern.to_string()))
+            .or_insert((0, declared))
+            .0 += 1;
+        self.ownership_graph.add_borrow(borrower, pattern);
+        Ok(Borrow::new(pattern, owner, borrower))
+    }
+
+    /// Give back a borrow
+    ///
+    /// The graph keeps borrows the manifest declared and borrows still held
+    /// elsewhere.
+    pub fn release_borrow(&self, borrow: Borrow) {
+        let id = (borrow.borrower().to_string(), borrow.pattern().to_string());
+        let released = self.borrows.remove_if_mut(&id, |_, (count, _)| {
+            *count -= 1;
+            *count == 0
+        });
+        if let Some((_, (_, false))) = released {
+            self.ownership_graph
+                .remove_edge(borrow.borrower(), borrow.pattern(), EdgeType::Borrows);
+        }
+    }
+
+    /// Invalidate the patterns the strategy derives from `pattern` on every layer
+    pub async fn invalidate_pattern(&self, pattern: &str) -> Result<()> {
+        for pattern in self.strategy.handle_invalidation(pattern).await? {
+            self.bus.publish(Invalidation::Pattern(pattern), None);
+        }
+        self.deliver().await
+    }
+
+    /// Reconnect a layer and replay the invalidations it missed
+    ///
+    /// A layer that fell behind the retained log drops all of its copies.
+    /// Returns the number of invalidations applied.
+    pub async fn catch_up(&self, layer: Layer) -> Result<usize> {
+        let tier = self
+            .tier(layer)
+            .ok_or_else(|| Error::InvalidConfig(format!("the {} layer is not configured", layer)))?;
+        let applied = self.deliver_to(tier).await?;
+        self.bus.reconnect(layer);
+        Ok(applied)
+    }
+
+    /// Deliver pending invalidations to every connected layer
+    ///
+    /// Layers that fail with a retryable error are disconnected and catch up
+    /// later. Other failures do not stop delivery to the remaining layers;
+    /// a single one is returned as is, several as `Error::Undelivered`.
+    async fn deliver(&self) -> Result<()> {
+        let mut errors = Vec::new();
+        for tier in &self.layers {
+            if !self.bus.is_connected(tier.layer) {
+                continue;
+            }
+            match self.deliver_to(tier).await {
+                Ok(_) => {}
+                Err(e) if e.is_retryable() => {
+                    tracing::warn!(layer = %tier.layer, error = %e, "layer missed invalidations, disconnecting");
+                    self.bus.disconnect(tier.layer);
+                }
+                Err(e) => errors.push((tier.layer, e)),
+            }
+        }
+        match errors.len() {
+            0 => Ok(()),
+            1 => Err(errors.remove(0).1),
+            _ => Err(Error::Undelivered { errors }),
+        }
+    }
+
+    async fn deliver_to(&self, tier: &Tier) -> Result<usize> {
+        let _guard = tier.delivery.lock().await;
+        match self.bus.pending(tier.layer) {
+            CatchUp::Replay(events) => {
+                let mut applied = 0;
+                for event in events {
+                    if event.origin != Some(tier.layer) {
+                        match &event.target {
+                            Invalidation::Key(key) => self.drop_copy(tier, key).await?,
+                            Invalidation::Pattern(pattern) => {
+                                for key in tier.backend.keys(pattern).await? {
+                                    self.drop_copy(tier, &key).await?;
+                                }
+                            }
+                        }
+                        applied += 1;
+                    }
+                    self.bus.ack(tier.layer, event.seq);
+                }
+                Ok(applied)
+            }
+            CatchUp::Resync { latest } => {
+                let keys = tier.backend.keys("*").await?;
+                for key in &keys {
+                    self.drop_copy(tier, key).await?;
+                }
+                self.bus.ack(tier.layer, latest);
+                Ok(keys.len())
+            }
+        }
+    }
+
+    /// Delete an invalidated key from a layer
+    async fn drop_copy(&self, tier: &Tier, key: &str) -> Result<()> {
+        tier.backend.delete(key).await?;
+        if let Some(metrics) = &self.metrics {
+            metrics.record_invalidation(key, tier.layer);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,403 @@
+//! Conflict-free replicated value types
+//!
+//! Each type merges concurrent edits deterministically, so copies edited on
+//! different layers converge without coordination. `ConflictLayer` merges
+//! stored CRDT values with incoming ones on every write.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use bytes::Bytes;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::cache::CacheEntry;
+use crate::conflict::Timestamp;
+use crate::{Error, Result};
+
+/// A value that merges with concurrent copies of itself
+///
+/// Merging is commutative, associative and idempotent.
+pub trait Crdt: Clone + Default + Serialize + DeserializeOwned {
+    fn merge(&mut self, other: &Self);
+}
+
+/// Counter that only grows
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GCounter {
+    counts: BTreeMap<String, u64>,
+}
+
+impl GCounter {
+    /// A counter at zero
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `by` on behalf of `node`
+    pub fn incr(&mut self, node: &str, by: u64) {
+        *self.counts.entry(node.to_string()).or_default() += by;
+    }
+
+    /// Sum of every node's count
+    pub fn value(&self) -> u64 {
+        self.counts.values().sum()
+    }
+}
+
+impl Crdt for GCounter {
+    fn merge(&mut self, other: &Self) {
+        for (node, &count) in &other.counts {
+            let entry = self.counts.entry(node.clone()).or_default();
+            *entry = (*entry).max(count);
+        }
+    }
+}
+
+/// Counter that can be incremented and decremented
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PNCounter {
+    incr: GCounter,
+    decr: GCounter,
+}
+
+impl PNCounter {
+    /// A counter at zero
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `delta`, which may be negative, on behalf of `node`
+    pub fn incr(&mut self, node: &str, delta: i64) {
+        if delta >= 0 {
+            self.incr.incr(node, delta as u64);
+        } else {
+            self.decr.incr(node, delta.unsigned_abs());
+        }
+    }
+
+    /// Increments minus decrements across every node
+    pub fn value(&self) -> i64 {
+        self.incr.value() as i64 - self.decr.value() as i64
+    }
+
+    /// Read the counter stored at `key`, starting from zero if there is none
+    pub fn from_stored(key: &str, value: Option<&[u8]>) -> Result<Self> {
+        match value.map(CrdtValue::decode) {
+            None => Ok(Self::new()),
+            Some(Some(CrdtValue::PNCounter(counter))) => Ok(counter),
+            Some(other) => Err(Error::Serialization {
+                key: Some(key.to_string()),
+                reason: match other {
+                    Some(value) => format!("holds a {}, not a counter", value.kind()),
+                    None => "holds a plain value, not a counter".to_string(),
+                },
+                source: None,
+            }),
+        }
+    }
+}
+
+impl Crdt for PNCounter {
+    fn merge(&mut self, other: &Self) {
+        self.incr.merge(&other.incr);
+        self.decr.merge(&other.decr);
+    }
+}
+
+/// Unique tag for one add to an `OrSet`
+type Dot = (String, u64);
+
+/// Observed-remove set
+///
+/// A remove only cancels the adds it has seen, so an add concurrent with a
+/// remove of the same item wins.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(bound(serialize = "T: Serialize + Ord", deserialize = "T: DeserializeOwned + Ord"))]
+pub struct OrSet<T> {
+    adds: BTreeMap<T, BTreeSet<Dot>>,
+    removed: BTreeSet<Dot>,
+    /// Adds made by each node
+    seq: BTreeMap<String, u64>,
+}
+
+impl<T> Default for OrSet<T> {
+    fn default() -> Self {
+        Self {
+            adds: BTreeMap::new(),
+            removed: BTreeSet::new(),
+            seq: BTreeMap::new(),
+        }
+    }
+}
+
+impl<T: Ord + Clone> OrSet<T> {
+    /// An empty set
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `item` on behalf of `node`
+    pub fn add(&mut self, node: &str, item: T) {
+        let seq = self.seq.entry(node.to_string()).or_default();
+        *seq += 1;
+        self.adds.entry(item).or_default().insert((node.to_string(), *seq));
+    }
+
+    /// Remove `item`, cancelling every add of it seen so far
+    pub fn remove(&mut self, item: &T) -> bool {
+        match self.adds.remove(item) {
+            Some(dots) => {
+                self.removed.extend(dots);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Whether `item` is in the set
+    pub fn contains(&self, item: &T) -> bool {
+        self.adds.contains_key(item)
+    }
+
+    /// Items in the set, in order
+    pub fn items(&self) -> impl Iterator<Item = &T> {
+        self.adds.keys()
+    }
+
+    /// Number of items
+    pub fn len(&self) -> usize {
+        self.adds.len()
+    }
+
+    /// Whether the set is empty
+    pub fn is_empty(&self) -> bool {
+        self.adds.is_empty()
+    }
+}
+
+impl<T: Ord + Clone + Serialize + DeserializeOwned> Crdt for OrSet<T> {
+    fn merge(&mut self, other: &Self) {
+        self.removed.extend(other.removed.iter().cloned());
+        for (item, dots) in &other.adds {
+            self.adds.entry(item.clone()).or_default().extend(dots.iter().cloned());
+        }
+        let removed = &self.removed;
+        self.adds.retain(|_, dots| {
+            dots.retain(|dot| !removed.contains(dot));
+            !dots.is_empty()
+        });
+        for (node, &seq) in &other.seq {
+            let entry = self.seq.entry(node.clone()).or_default();
+            *entry = (*entry).max(seq);
+        }
+    }
+}
+
+/// Register holding the value with the latest timestamp
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LwwRegister<T> {
+    value: T,
+    stamp: Timestamp,
+}
+
+impl<T: Clone> LwwRegister<T> {
+    /// A register holding `value` as of `stamp`
+    pub fn new(value: T, stamp: Timestamp) -> Self {
+        Self { value, stamp }
+    }
+
+    /// Set the value if `stamp` is later than the current one
+    pub fn set(&mut self, value: T, stamp: Timestamp) -> bool {
+        if stamp > self.stamp {
+            self.value = value;
+            self.stamp = stamp;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// The current value
+    pub fn get(&self) -> &T {
+        &self.value
+    }
+
+    /// When the current value was set
+    pub fn stamp(&self) -> &Timestamp {
+        &self.stamp
+    }
+}
+
+impl<T: Clone + Default + Serialize + DeserializeOwned> Crdt for LwwRegister<T> {
+    fn merge(&mut self, other: &Self) {
+        self.set(other.value.clone(), other.stamp.clone());
+    }
+}
+
+/// Map whose entries are last-writer-wins registers
+///
+/// Removes are kept as timestamped tombstones so a stale copy cannot bring
+/// an entry back.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(bound(
+    serialize = "K: Serialize + Ord, V: Serialize",
+    deserialize = "K: DeserializeOwned + Ord, V: DeserializeOwned"
+))]
+pub struct LwwMap<K, V> {
+    entries: BTreeMap<K, LwwRegister<Option<V>>>,
+}
+
+impl<K, V> Default for LwwMap<K, V> {
+    fn default() -> Self {
+        Self {
+            entries: BTreeMap::new(),
+        }
+    }
+}
+
+impl<K: Ord + Clone, V: Clone> LwwMap<K, V> {
+    /// An empty map
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set `key` to `value` if `stamp` is later than its last change
+    pub fn set(&mut self, key: K, value: V, stamp: Timestamp) -> bool {
+        self.write(key, Some(value), stamp)
+    }
+
+    /// Remove `key` if `stamp` is later than its last change
+    pub fn remove(&mut self, key: K, stamp: Timestamp) -> bool {
+        self.write(key, None, stamp)
+    }
+
+    fn write(&mut self, key: K, value: Option<V>, stamp: Timestamp) -> bool {
+        match self.entries.get_mut(&key) {
+            Some(register) => register.set(value, stamp),
+            None => {
+                self.entries.insert(key, LwwRegister::new(value, stamp));
+                true
+            }
+        }
+    }
+
+    /// The value at `key`, unless it was removed
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.entries.get(key).and_then(|r| r.get().as_ref())
+    }
+
+    /// Live entries, in key order
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.entries
+            .iter()
+            .filter_map(|(k, r)| r.get().as_ref().map(|v| (k, v)))
+    }
+
+    /// Number of live entries
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    /// Whether the map has no live entries
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<K, V> Crdt for LwwMap<K, V>
+where
+    K: Ord + Clone + Serialize + DeserializeOwned,
+    V: Clone + Serialize + DeserializeOwned,
+{
+    fn merge(&mut self, other: &Self) {
+        for (key, register) in &other.entries {
+            match self.entries.get_mut(key) {
+                Some(mine) => {
+                    mine.set(register.get().clone(), register.stamp().clone());
+                }
+                None => {
+                    self.entries.insert(key.clone(), register.clone());
+                }
+            }
+        }
+    }
+}
+
+/// A CRDT stored as a cache value
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "crdt")]
+pub enum CrdtValue {
+    #[serde(rename = "g_counter")]
+    GCounter(GCounter),
+    #[serde(rename = "pn_counter")]
+    PNCounter(PNCounter),
+    #[serde(rename = "or_set")]
+    OrSet(OrSet<String>),
+    #[serde(rename = "lww_register")]
+    LwwRegister(LwwRegister<Vec<u8>>),
+    #[serde(rename = "lww_map")]
+    LwwMap(LwwMap<String, Vec<u8>>),
+}
+
+impl CrdtValue {
+    /// The type's name, as stored
+    pub fn kind(&self) -> &'static str {
+        match self {
+            CrdtValue::GCounter(_) => "g_counter",
+            CrdtValue::PNCounter(_) => "pn_counter",
+            CrdtValue::OrSet(_) => "or_set",
+            CrdtValue::LwwRegister(_) => "lww_register",
+            CrdtValue::LwwMap(_) => "lww_map",
+        }
+    }
+
+    /// Merge a copy of the same type, returning false if the types differ
+    pub fn merge(&mut self, other: &CrdtValue) -> bool {
+        match (self, other) {
+            (CrdtValue::GCounter(a), CrdtValue::GCounter(b)) => a.merge(b),
+            (CrdtValue::PNCounter(a), CrdtValue::PNCounter(b)) => a.merge(b),
+            (CrdtValue::OrSet(a), CrdtValue::OrSet(b)) => a.merge(b),
+            (CrdtValue::LwwRegister(a), CrdtValue::LwwRegister(b)) => a.merge(b),
+            (CrdtValue::LwwMap(a), CrdtValue::LwwMap(b)) => a.merge(b),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Serialize as a cache value
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        Ok(serde_json::to_vec(self)?)
+    }
+
+    /// Read a cache value, if it holds a CRDT
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        serde_json::from_slice(bytes).ok()
+    }
+
+    /// Merge two stored values, if both hold CRDTs of the same type
+    pub fn merge_bytes(current: &[u8], incoming: &[u8]) -> Option<Vec<u8>> {
+        let mut merged = Self::decode(current)?;
+        if !merged.merge(&Self::decode(incoming)?) {
+            return None;
+        }
+        merged.encode().ok()
+    }
+}
+
+impl CacheEntry {
+    /// An entry holding a CRDT
+    pub fn crdt(value: &CrdtValue) -> Result<Self> {
+        let mut metadata = std::collections::HashMap::new();
+        metadata.insert("crdt".to_string(), value.kind().to_string());
+        Ok(Self {
+            value: Bytes::from(value.encode()?),
+            ttl: None,
+            metadata,
+        })
+    }
+
+    /// The CRDT this entry holds, if any
+    pub fn as_crdt(&self) -> Option<CrdtValue> {
+        CrdtValue::decode(&self.value)
+    }
+}
@@ -0,0 +1,189 @@
+//! Ownership debugging and graph export
+
+use std::collections::BTreeSet;
+use std::fmt;
+
+use serde_json::{json, Value};
+
+use crate::ownership::{DependencyEdge, EdgeType};
+use crate::pattern::glob_matches;
+use crate::{Layer, OwnershipGraph};
+
+/// Explanation of how a key or pattern is governed by the manifest
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OwnershipReport {
+    /// The key or pattern that was asked about
+    pub query: String,
+    /// The owned pattern covering the query
+    pub pattern: Option<String>,
+    /// The owning component
+    pub owner: Option<String>,
+    /// The layer holding the data
+    pub layer: Option<Layer>,
+    /// Components borrowing overlapping patterns
+    pub borrowers: Vec<String>,
+    /// Patterns invalidated (transitively) by writes to the query
+    pub invalidates: Vec<String>,
+    /// Patterns whose writes invalidate the query
+    pub invalidated_by: Vec<String>,
+}
+
+impl fmt::Display for OwnershipReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn list(items: &[String]) -> String {
+            if items.is_empty() {
+                "-".to_string()
+            } else {
+                items.join(", ")
+            }
+        }
+
+        writeln!(f, "{}", self.query)?;
+        match (&self.owner, &self.pattern, self.layer) {
+            (Some(owner), Some(pattern), Some(layer)) => {
+                writeln!(f, "  owner: {} ({}) on {}", owner, pattern, layer)?
+            }
+            _ => writeln!(f, "  owner: -")?,
+        }
+        writeln!(f, "  borrowed by: {}", list(&self.borrowers))?;
+        writeln!(f, "  invalidates: {}", list(&self.invalidates))?;
+        write!(f, "  invalidated by: {}", list(&self.invalidated_by))
+    }
+}
+
+/// Whether two patterns (or a pattern and a key) can refer to the same key
+pub(crate) fn overlaps(a: &str, b: &str) -> bool {
+    a == b || glob_matches(a, b) || glob_matches(b, a)
+}
+
+impl OwnershipGraph {
+    /// Explain who owns, borrows and invalidates a key or pattern
+    pub fn debug_ownership(&self, query: &str) -> OwnershipReport {
+        let ownership = self.get(query).or_else(|| self.owner_of(query));
+        let edges = self.edges();
+
+        let borrowers: BTreeSet<String> = edges
+            .iter()
+            .filter(|e| e.edge_type == EdgeType::Borrows && overlaps(&e.to, query))
+            .map(|e| e.from.clone())
+            .collect();
+
+        let mut sources = vec![query.to_string()];
+        if let Some(ownership) = &ownership {
+            sources.push(ownership.pattern().to_string());
+        }
+        let invalidates = self.invalidation_closure(&edges, sources);
+
+        let invalidated_by: BTreeSet<String> = edges
+            .iter()
+            .filter(|e| e.edge_type == EdgeType::Invalidates && overlaps(&e.to, query))
+            .map(|e| e.from.clone())
+            .collect();
+
+        OwnershipReport {
+            query: query.to_string(),
+            pattern: ownership.as_ref().map(|o| o.pattern().to_string()),
+            owner: ownership.as_ref().map(|o| o.owner().to_string()),
+            layer: ownership.as_ref().map(|o| o.layer()),
+            borrowers: borrowers.into_iter().collect(),
+            invalidates,
+            invalidated_by: invalidated_by.into_iter().collect(),
+        }
+    }
+
+    /// Render the whole graph as Graphviz DOT
+    pub fn to_dot(&self) -> String {
+        let quote = |s: &str| format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""));
+        let nodes = self.nodes();
+        let mut out = String::from("digraph ownership {\n    rankdir=LR;\n");
+
+        for layer in [Layer::Client, Layer::Edge, Layer::Server] {
+            let owned: Vec<_> = nodes.iter().filter(|n| n.layer() == layer).collect();
+            if owned.is_empty() {
+                continue;
+            }
+            out.push_str(&format!("    subgraph cluster_{} {{\n", layer));
+            out.push_str(&format!("        label={};\n", quote(layer.as_str())));
+            for node in owned {
+                out.push_str(&format!("        {} [shape=box];\n", quote(node.pattern())));
+            }
+            out.push_str("    }\n");
+        }
+
+        for node in &nodes {
+            out.push_str(&format!(
+                "    {} [shape=ellipse];\n    {} -> {} [label=\"owns\"];\n",
+                quote(node.owner()),
+                quote(node.owner()),
+                quote(node.pattern())
+            ));
+        }
+        for edge in self.edges() {
+            let style = match edge.edge_type {
+                EdgeType::Owns => "",
+                EdgeType::Borrows => ", style=dashed",
+                EdgeType::Invalidates => ", color=red",
+                EdgeType::Derives => ", style=dotted",
+            };
+            out.push_str(&format!(
+                "    {} -> {} [label=\"{}\"{}];\n",
+                quote(&edge.from),
+                quote(&edge.to),
+                edge.edge_type.as_str(),
+                style
+            ));
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    /// Render the whole graph as JSON
+    pub fn to_json(&self) -> Value {
+        let nodes: Vec<Value> = self
+            .nodes()
+            .iter()
+            .map(|n| {
+                json!({
+                    "pattern": n.pattern(),
+                    "owner": n.owner(),
+                    "layer": n.layer().as_str(),
+                })
+            })
+            .collect();
+        let edges: Vec<Value> = self
+            .edges()
+            .iter()
+            .map(|e| {
+                json!({
+                    "from": e.from,
+                    "to": e.to,
+                    "type": e.edge_type.as_str(),
+                })
+            })
+            .collect();
+        json!({ "nodes": nodes, "edges": edges })
+    }
+
+    /// Patterns invalidated, directly or transitively, by writes to `pattern`
+    pub fn invalidation_targets(&self, pattern: &str) -> Vec<String> {
+        self.invalidation_closure(&self.edges(), vec![pattern.to_string()])
+    }
+
+    /// Follow invalidation edges transitively from a set of patterns
+    fn invalidation_closure(&self, edges: &[DependencyEdge], sources: Vec<String>) -> Vec<String> {
+        let mut seen = BTreeSet::new();
+        let mut frontier = sources.clone();
+        while let Some(pattern) = frontier.pop() {
+            for edge in edges {
+                if edge.edge_type == EdgeType::Invalidates
+                    && overlaps(&edge.from, &pattern)
+                    && !sources.contains(&edge.to)
+                    && seen.insert(edge.to.clone())
+                {
+                    frontier.push(edge.to.clone());
+                }
+            }
+        }
+        seen.into_iter().collect()
+    }
+}
@@ -0,0 +1,119 @@
+//! `Cache` entries with TTLs over any `CacheLayer`
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+
+use crate::cache::{Cache, CacheEntry};
+use crate::layer::CacheLayer;
+use crate::metrics::Metrics;
+use crate::{Layer, Result};
+
+/// An entry as written to the layer
+#[derive(Serialize, Deserialize)]
+struct Stored {
+    value: Vec<u8>,
+    /// Wall-clock expiry in milliseconds since the Unix epoch
+    expires_at: Option<u64>,
+    metadata: HashMap<String, String>,
+}
+
+/// A `Cache` keeping entries in a `CacheLayer` until their TTL runs out
+///
+/// Entries are stored with the wall-clock time they expire at, so a layer
+/// that persists them keeps their TTLs across restarts. An expired entry
+/// reads as missing and is deleted from the layer on the read that finds
+/// it. Values written to the layer directly read as entries without a TTL.
+pub struct LayerCache<L> {
+    inner: L,
+    metrics: Option<(Arc<Metrics>, Layer)>,
+}
+
+impl<L: CacheLayer> LayerCache<L> {
+    /// Keep entries in `inner`
+    pub fn new(inner: L) -> Self {
+        Self { inner, metrics: None }
+    }
+
+    /// Record expirations against `layer` in a metrics registry
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>, layer: Layer) -> Self {
+        self.metrics = Some((metrics, layer));
+        self
+    }
+
+    /// The layer entries are kept in
+    pub fn inner(&self) -> &L {
+        &self.inner
+    }
+
+    /// The stored entry for a key, dropping it if it has expired
+    async fn live(&self, key: &str) -> Result<Option<Stored>> {
+        let Some(bytes) = self.inner.get(key).await? else { return Ok(None) };
+        let stored = serde_json::from_slice(&bytes).unwrap_or(Stored {
+            value: bytes,
+            expires_at: None,
+            metadata: HashMap::new(),
+        });
+        if stored.expires_at.is_some_and(|at| at <= now_millis()) {
+            self.inner.delete(key).await?;
+            if let Some((metrics, layer)) = &self.metrics {
+                metrics.record_expiration(key, *layer);
+            }
+            return Ok(None);
+        }
+        Ok(Some(stored))
+    }
+
+    async fn store(&self, key: &str, stored: &Stored) -> Result<()> {
+        self.inner.set(key, serde_json::to_vec(stored)?).await
+    }
+}
+
+#[async_trait]
+impl<L: CacheLayer> Cache for LayerCache<L> {
+    async fn get(&self, key: &str) -> Result<Option<CacheEntry>> {
+        Ok(self.live(key).await?.map(|stored| CacheEntry {
+            value: Bytes::from(stored.value),
+            ttl: stored
+                .expires_at
+                .map(|at| Duration::from_millis(at.saturating_sub(now_millis()))),
+            metadata: stored.metadata,
+        }))
+    }
+
+    async fn set(&self, key: &str, value: CacheEntry) -> Result<()> {
+        let stored = Stored {
+            value: value.value.to_vec(),
+            expires_at: value.ttl.map(expires_at),
+            metadata: value.metadata,
+        };
+        self.store(key, &stored).await
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        self.inner.delete(key).await
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool> {
+        Ok(self.live(key).await?.is_some())
+    }
+
+    async fn expire(&self, key: &str, ttl: Duration) -> Result<bool> {
+        let Some(mut stored) = self.live(key).await? else { return Ok(false) };
+        stored.expires_at = Some(expires_at(ttl));
+        self.store(key, &stored).await?;
+        Ok(true)
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64
+}
+
+fn expires_at(ttl: Duration) -> u64 {
+    now_millis().saturating_add(ttl.as_millis() as u64)
+}
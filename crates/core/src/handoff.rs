@@ -0,0 +1,102 @@
+//! Draining writes to a pattern while its ownership changes hands
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use dashmap::DashMap;
+use tokio::sync::{OwnedRwLockReadGuard, OwnedRwLockWriteGuard, RwLock};
+
+use crate::{Error, Result};
+
+/// How long a transfer waits for in-flight writes by default
+const DEFAULT_DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Write gates for owned patterns
+///
+/// Every write to an owned key holds a shared permit for the owning pattern.
+/// A handoff takes the gate exclusively: it waits for in-flight writes to
+/// finish, and writes arriving meanwhile queue until the new owner is in
+/// place.
+pub(crate) struct Handoffs {
+    gates: DashMap<String, Arc<RwLock<()>>>,
+    drain_timeout: Duration,
+}
+
+impl Handoffs {
+    /// Create gates that wait up to 5 seconds for writes to drain
+    pub fn new() -> Self {
+        Self::with_drain_timeout(DEFAULT_DRAIN_TIMEOUT)
+    }
+
+    /// Create gates that wait up to `drain_timeout` for writes to drain
+    pub fn with_drain_timeout(drain_timeout: Duration) -> Self {
+        Self {
+            gates: DashMap::new(),
+            drain_timeout,
+        }
+    }
+
+    fn gate(&self, pattern: &str) -> Arc<RwLock<()>> {
+        self.gates.entry(pattern.to_string()).or_default().clone()
+    }
+
+    /// Permit to write a key owned by `pattern`, waiting out any handoff
+    pub async fn write(&self, pattern: &str) -> OwnedRwLockReadGuard<()> {
+        self.gate(pattern).read_owned().await
+    }
+
+    /// Block new writes to `pattern` once in-flight writes have drained
+    pub async fn quiesce(&self, pattern: &str) -> Result<OwnedRwLockWriteGuard<()>> {
+        tokio::time::timeout(self.drain_timeout, self.gate(pattern).write_owned())
+            .await
+            .map_err(|_| Error::Timeout {
+                operation: "ownership transfer".to_string(),
+                key: Some(pattern.to_string()),
+                layer: None,
+                after: self.drain_timeout,
+            })
+    }
+}
+
+impl Default for Handoffs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A component's read access to a pattern it does not own
+///
+/// Returned by `LayerCoordinator::borrow_from` and given back with
+/// `release_borrow`. It cannot be cloned, so releasing it consumes the only
+/// handle and each borrow is released at most once.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Borrow {
+    pattern: String,
+    owner: String,
+    borrower: String,
+}
+
+impl Borrow {
+    pub(crate) fn new(pattern: &str, owner: &str, borrower: &str) -> Self {
+        Self {
+            pattern: pattern.to_string(),
+            owner: owner.to_string(),
+            borrower: borrower.to_string(),
+        }
+    }
+
+    /// The borrowed pattern
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+
+    /// The owner at the time of borrowing
+    pub fn owner(&self) -> &str {
+        &self.owner
+    }
+
+    /// The borrowing component
+    pub fn borrower(&self) -> &str {
+        &self.borrower
+    }
+}
@@ -0,0 +1,264 @@
+//! Health checks for cache layers and subsystems
+
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use serde_json::{json, Value};
+
+use crate::layer::CacheLayer;
+use crate::Layer;
+
+/// Health of a single component
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum HealthStatus {
+    Healthy,
+    Degraded,
+    Unhealthy,
+}
+
+impl HealthStatus {
+    /// Lowercase name, as used in health endpoints
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HealthStatus::Healthy => "healthy",
+            HealthStatus::Degraded => "degraded",
+            HealthStatus::Unhealthy => "unhealthy",
+        }
+    }
+}
+
+/// Result of probing one component
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ComponentHealth {
+    pub name: String,
+    pub status: HealthStatus,
+    /// Critical components failing means the process is not live
+    pub critical: bool,
+    pub latency: Duration,
+    pub replication_lag: Option<Duration>,
+    pub message: Option<String>,
+}
+
+impl ComponentHealth {
+    /// A healthy component
+    pub fn healthy(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            status: HealthStatus::Healthy,
+            critical: false,
+            latency: Duration::ZERO,
+            replication_lag: None,
+            message: None,
+        }
+    }
+
+    /// A degraded component with a reason
+    pub fn degraded(name: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            status: HealthStatus::Degraded,
+            message: Some(message.into()),
+            ..Self::healthy(name)
+        }
+    }
+
+    /// An unhealthy component with a reason
+    pub fn unhealthy(name: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            status: HealthStatus::Unhealthy,
+            message: Some(message.into()),
+            ..Self::healthy(name)
+        }
+    }
+
+    /// Mark the component as critical for liveness
+    pub fn critical(mut self) -> Self {
+        self.critical = true;
+        self
+    }
+}
+
+/// Aggregated health of all registered components
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HealthReport {
+    pub components: Vec<ComponentHealth>,
+}
+
+impl HealthReport {
+    /// Worst status across all components
+    pub fn status(&self) -> HealthStatus {
+        self.components
+            .iter()
+            .map(|c| c.status)
+            .max()
+            .unwrap_or(HealthStatus::Healthy)
+    }
+
+    /// Liveness: no critical component is unhealthy
+    pub fn is_live(&self) -> bool {
+        !self
+            .components
+            .iter()
+            .any(|c| c.critical && c.status == HealthStatus::Unhealthy)
+    }
+
+    /// Readiness: no component is unhealthy, so traffic can be served
+    pub fn is_ready(&self) -> bool {
+        self.components
+            .iter()
+            .all(|c| c.status != HealthStatus::Unhealthy)
+    }
+
+    /// Names of degraded components
+    pub fn degraded(&self) -> Vec<&str> {
+        self.components
+            .iter()
+            .filter(|c| c.status == HealthStatus::Degraded)
+            .map(|c| c.name.as_str())
+            .collect()
+    }
+
+    /// Look up a component by name
+    pub fn component(&self, name: &str) -> Option<&ComponentHealth> {
+        self.components.iter().find(|c| c.name == name)
+    }
+
+    /// Render the report as JSON
+    pub fn to_json(&self) -> Value {
+        let components: Vec<Value> = self
+            .components
+            .iter()
+            .map(|c| {
+                json!({
+                    "name": c.name,
+                    "status": c.status.as_str(),
+                    "critical": c.critical,
+                    "latency_ms": c.latency.as_secs_f64() * 1000.0,
+                    "replication_lag_ms": c.replication_lag.map(|lag| lag.as_secs_f64() * 1000.0),
+                    "message": c.message,
+                })
+            })
+            .collect();
+        json!({
+            "status": self.status().as_str(),
+            "live": self.is_live(),
+            "ready": self.is_ready(),
+            "degraded": self.degraded(),
+            "components": components,
+        })
+    }
+}
+
+/// A probe for one component
+#[async_trait]
+pub trait HealthCheck: Send + Sync + 'static {
+    async fn check(&self) -> ComponentHealth;
+}
+
+/// Source of a layer's replication lag behind its upstream
+pub type LagSource = Arc<dyn Fn() -> Option<Duration> + Send + Sync>;
+
+/// Probes a `CacheLayer` by reading a sentinel key
+///
+/// The probe only reads, so it never adds keys to the layer or counts
+/// against its quotas.
+pub struct LayerProbe {
+    layer: Layer,
+    inner: Arc<dyn CacheLayer>,
+    timeout: Duration,
+    slow_after: Duration,
+    lag: Option<(LagSource, Duration)>,
+}
+
+impl LayerProbe {
+    /// Create a probe for a layer
+    pub fn new(layer: Layer, inner: Arc<dyn CacheLayer>) -> Self {
+        Self {
+            layer,
+            inner,
+            timeout: Duration::from_secs(1),
+            slow_after: Duration::from_millis(100),
+            lag: None,
+        }
+    }
+
+    /// Fail the probe if the round trip takes longer than `timeout`
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Report the layer degraded if the round trip takes longer than `slow_after`
+    pub fn with_latency_threshold(mut self, slow_after: Duration) -> Self {
+        self.slow_after = slow_after;
+        self
+    }
+
+    /// Report the layer degraded if replication lags more than `max_lag`
+    pub fn with_replication_lag(mut self, lag: LagSource, max_lag: Duration) -> Self {
+        self.lag = Some((lag, max_lag));
+        self
+    }
+
+    async fn round_trip(&self) -> crate::Result<()> {
+        self.inner.get(&format!("__health__:{}", self.layer)).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl HealthCheck for LayerProbe {
+    async fn check(&self) -> ComponentHealth {
+        let name = format!("layer:{}", self.layer);
+        let start = Instant::now();
+        let result = tokio::time::timeout(self.timeout, self.round_trip()).await;
+        let latency = start.elapsed();
+
+        let mut health = match result {
+            Err(_) => ComponentHealth::unhealthy(&name, format!("probe timed out after {:?}", self.timeout)),
+            Ok(Err(e)) => ComponentHealth::unhealthy(&name, e.to_string()),
+            Ok(Ok(())) if latency > self.slow_after => {
+                ComponentHealth::degraded(&name, format!("probe took {:?}", latency))
+            }
+            Ok(Ok(())) => ComponentHealth::healthy(&name),
+        };
+        health.latency = latency;
+
+        if let Some((lag, max_lag)) = &self.lag {
+            health.replication_lag = lag();
+            if health.status == HealthStatus::Healthy
+                && health.replication_lag.is_some_and(|lag| lag > *max_lag)
+            {
+                health.status = HealthStatus::Degraded;
+                health.message = Some(format!("replication lag exceeds {:?}", max_lag));
+            }
+        }
+        health
+    }
+}
+
+/// Runs all registered health checks
+#[derive(Default)]
+pub struct HealthChecker {
+    checks: RwLock<Vec<Arc<dyn HealthCheck>>>,
+}
+
+impl HealthChecker {
+    /// Create a checker with no probes
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a probe
+    pub fn register(&self, check: impl HealthCheck) -> &Self {
+        self.checks.write().unwrap().push(Arc::new(check));
+        self
+    }
+
+    /// Probe every component concurrently
+    pub async fn check(&self) -> HealthReport {
+        let checks = self.checks.read().unwrap().clone();
+        let components = futures::future::join_all(checks.iter().map(|c| c.check())).await;
+        HealthReport { components }
+    }
+}
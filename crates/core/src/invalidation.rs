@@ -0,0 +1,222 @@
+//! Sequenced invalidation log with per-layer acknowledgements
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use dashmap::DashMap;
+
+use crate::pattern::glob_matches;
+use crate::Layer;
+
+/// Invalidations kept for lagging layers before they must resync
+const DEFAULT_CAPACITY: usize = 10_000;
+
+/// Invalidations kept after every layer applied them, see `invalidated_since`
+const RECENT: usize = 1024;
+
+/// What to invalidate
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Invalidation {
+    Key(String),
+    Pattern(String),
+}
+
+impl Invalidation {
+    /// Whether this invalidation removes `key`
+    pub fn covers(&self, key: &str) -> bool {
+        match self {
+            Invalidation::Key(k) => k == key,
+            Invalidation::Pattern(pattern) => pattern == key || glob_matches(pattern, key),
+        }
+    }
+}
+
+/// An invalidation with its position in the log
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidationEvent {
+    pub seq: u64,
+    pub target: Invalidation,
+    /// The layer that made the change, which needs no invalidation
+    pub origin: Option<Layer>,
+}
+
+/// What a layer must do to catch up with the log
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CatchUp {
+    /// Apply these invalidations in order
+    Replay(Vec<InvalidationEvent>),
+    /// The layer fell behind the retained log and must drop its copies
+    Resync { latest: u64 },
+}
+
+struct Subscriber {
+    acked: AtomicU64,
+    connected: AtomicBool,
+}
+
+/// Fans invalidations out to every subscribed layer
+///
+/// Each invalidation gets a sequence number. Layers acknowledge the last
+/// sequence they applied, and the log keeps everything a layer has not yet
+/// acknowledged (up to a capacity), so a layer returning from a partition
+/// replays exactly what it missed.
+pub struct InvalidationBus {
+    log: Mutex<VecDeque<InvalidationEvent>>,
+    /// The latest invalidations, acknowledged or not
+    recent: Mutex<VecDeque<InvalidationEvent>>,
+    next_seq: AtomicU64,
+    capacity: usize,
+    subscribers: DashMap<Layer, Subscriber>,
+}
+
+impl InvalidationBus {
+    /// Create a bus retaining up to 10,000 unacknowledged invalidations
+    pub fn new() -> Self {
+        Self::with_capacity(DEFAULT_CAPACITY)
+    }
+
+    /// Create a bus retaining up to `capacity` unacknowledged invalidations
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            log: Mutex::new(VecDeque::new()),
+            recent: Mutex::new(VecDeque::new()),
+            next_seq: AtomicU64::new(1),
+            capacity,
+            subscribers: DashMap::new(),
+        }
+    }
+
+    /// Subscribe a layer, starting after the latest invalidation
+    pub fn subscribe(&self, layer: Layer) {
+        let latest = self.latest();
+        self.subscribers.entry(layer).or_insert_with(|| Subscriber {
+            acked: AtomicU64::new(latest),
+            connected: AtomicBool::new(true),
+        });
+    }
+
+    /// Record an invalidation and return it with its sequence number
+    pub fn publish(&self, target: Invalidation, origin: Option<Layer>) -> InvalidationEvent {
+        let mut log = self.log.lock().unwrap();
+        let event = InvalidationEvent {
+            seq: self.next_seq.fetch_add(1, Ordering::SeqCst),
+            target,
+            origin,
+        };
+        log.push_back(event.clone());
+        if log.len() > self.capacity {
+            log.pop_front();
+        }
+        let mut recent = self.recent.lock().unwrap();
+        recent.push_back(event.clone());
+        if recent.len() > RECENT {
+            recent.pop_front();
+        }
+        event
+    }
+
+    /// Whether an invalidation published after `seq` may have removed `key`
+    ///
+    /// Only the latest invalidations are remembered, so once more than that
+    /// have been published since `seq` the answer is always yes.
+    pub fn invalidated_since(&self, seq: u64, key: &str) -> bool {
+        let _log = self.log.lock().unwrap();
+        if self.latest() <= seq {
+            return false;
+        }
+        let recent = self.recent.lock().unwrap();
+        if recent.front().is_none_or(|e| e.seq > seq + 1) {
+            return true;
+        }
+        recent.iter().filter(|e| e.seq > seq).any(|e| e.target.covers(key))
+    }
+
+    /// Sequence number of the latest invalidation, or 0
+    pub fn latest(&self) -> u64 {
+        self.next_seq.load(Ordering::SeqCst) - 1
+    }
+
+    /// Last sequence number a layer acknowledged
+    pub fn acked(&self, layer: Layer) -> Option<u64> {
+        self.subscribers
+            .get(&layer)
+            .map(|s| s.acked.load(Ordering::SeqCst))
+    }
+
+    /// Invalidations a layer has not yet acknowledged
+    pub fn lag(&self, layer: Layer) -> Option<u64> {
+        self.acked(layer).map(|acked| self.latest() - acked)
+    }
+
+    /// Acknowledge every invalidation up to `seq` and trim the log
+    pub fn ack(&self, layer: Layer, seq: u64) {
+        if let Some(subscriber) = self.subscribers.get(&layer) {
+            subscriber.acked.fetch_max(seq, Ordering::SeqCst);
+        }
+        let floor = self
+            .subscribers
+            .iter()
+            .map(|s| s.acked.load(Ordering::SeqCst))
+            .min()
+            .unwrap_or(u64::MAX);
+        let mut log = self.log.lock().unwrap();
+        while log.front().is_some_and(|e| e.seq <= floor) {
+            log.pop_front();
+        }
+    }
+
+    /// Invalidations a layer must apply, oldest first
+    pub fn pending(&self, layer: Layer) -> CatchUp {
+        let acked = self.acked(layer).unwrap_or(0);
+        let log = self.log.lock().unwrap();
+        let latest = self.latest();
+        let retained_from = log.front().map(|e| e.seq).unwrap_or(latest + 1);
+        if acked + 1 < retained_from {
+            return CatchUp::Resync { latest };
+        }
+        CatchUp::Replay(log.iter().filter(|e| e.seq > acked).cloned().collect())
+    }
+
+    /// Stop delivering to a layer until it reconnects
+    pub fn disconnect(&self, layer: Layer) {
+        if let Some(subscriber) = self.subscribers.get(&layer) {
+            subscriber.connected.store(false, Ordering::SeqCst);
+        }
+    }
+
+    /// Resume delivering to a layer
+    pub fn reconnect(&self, layer: Layer) {
+        if let Some(subscriber) = self.subscribers.get(&layer) {
+            subscriber.connected.store(true, Ordering::SeqCst);
+        }
+    }
+
+    /// Whether invalidations are being delivered to a layer
+    pub fn is_connected(&self, layer: Layer) -> bool {
+        self.subscribers
+            .get(&layer)
+            .is_some_and(|s| s.connected.load(Ordering::SeqCst))
+    }
+
+    /// Number of invalidations retained for lagging layers
+    pub fn retained(&self) -> usize {
+        self.log.lock().unwrap().len()
+    }
+
+    /// Sequence numbers of the retained invalidations, oldest first
+    pub(crate) fn retained_seqs(&self) -> Vec<u64> {
+        self.log.lock().unwrap().iter().map(|e| e.seq).collect()
+    }
+
+    /// Subscribed layers
+    pub(crate) fn subscribers(&self) -> Vec<Layer> {
+        self.subscribers.iter().map(|s| *s.key()).collect()
+    }
+}
+
+impl Default for InvalidationBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -6,22 +6,72 @@ mod strategy;
 mod layer;
 mod error;
 mod cache;
+mod expiry;
+mod quota;
+mod namespace;
+mod metrics;
+mod trace;
+mod debug;
+mod health;
+mod strategies;
+mod compose;
+mod validate;
+mod coordinator;
+mod invalidation;
+mod pins;
+mod handoff;
+mod conflict;
+mod crdt;
+mod tracking;
+mod sim;
+mod simulation;
+pub mod otlp;
+pub mod typed;
+pub mod wire;
 
 pub use pattern::{Pattern, PatternMatcher};
-pub use ownership::{Ownership, OwnershipGraph};
-pub use strategy::{Strategy, CacheStrategy};
+pub use ownership::{Ownership, OwnershipGraph, DependencyEdge, EdgeType};
+pub use strategy::{Strategy, CacheStrategy, Consistency};
+pub use strategies::{ClientFirst, EdgeOptimized, GlobalConsistent, RedisCompatible};
+pub use compose::{Fallback, Route, BySize, Weighted, Pinned, StrategyExt};
+pub use validate::StrategyValidator;
 pub use layer::{Layer, LayerCoordinator};
-pub use error::{Error, Result};
+pub use coordinator::Lookup;
+pub use invalidation::{InvalidationBus, Invalidation, InvalidationEvent, CatchUp};
+pub use pins::Pins;
+pub use handoff::Borrow;
+pub use crdt::{Crdt, CrdtValue, GCounter, PNCounter, OrSet, LwwRegister, LwwMap};
+pub use tracking::{Tracking, TrackingLayer, TrackingMode, Subscription};
+pub use conflict::{Conflicts, ConflictLayer, ConflictResolver, Conflict, Resolution, LastWriterWins, MergeWith, RejectAndNotify, Versioned, VersionVector, Causality, HybridClock, Timestamp};
+pub use sim::{SimNetwork, SimLayer, LinkConditions};
+pub use simulation::{Simulation, SimClock, SimReport, SimFailure};
+pub use typed::{ClientLayer, EdgeLayer, ServerLayer, LayerHandle, Key, PatternToken};
+pub use error::{Error, ErrorKind, Result};
 pub use cache::{Cache, CacheEntry};
+pub use expiry::LayerCache;
+pub use quota::{parse_size, QuotaPolicy, QuotaManager, QuotaUsage, QuotaLayer, OverflowAction, Admission};
+pub use namespace::{Namespace, NamespaceConfig, NamespaceStats, Namespaces};
+pub use metrics::{Metrics, MetricsLayer, PatternStats, Histogram, HistogramSnapshot, Op};
+pub use trace::{Traced, Tracer, PatternTrace, TraceConfig, AccessRecord};
+pub use debug::OwnershipReport;
+pub use health::{HealthCheck, HealthChecker, HealthReport, HealthStatus, ComponentHealth, LayerProbe};
 
 /// Re-exports of common traits
 pub mod prelude {
     pub use super::{Pattern, PatternMatcher};
     pub use super::{Ownership, OwnershipGraph};
-    pub use super::{Strategy, CacheStrategy};
+    pub use super::{Strategy, CacheStrategy, Consistency, StrategyExt};
     pub use super::{Layer, LayerCoordinator};
+    pub use super::{ClientLayer, EdgeLayer, ServerLayer, Key, PatternToken};
     pub use super::{Cache, CacheEntry};
-    pub use super::{Error, Result};
+    pub use super::{QuotaPolicy, QuotaManager, OverflowAction, Pins};
+    pub use super::{Conflicts, ConflictResolver, Resolution, Versioned};
+    pub use super::{Crdt, CrdtValue};
+    pub use super::{Namespace, NamespaceConfig, Namespaces};
+    pub use super::{Metrics, MetricsLayer, PatternStats};
+    pub use super::{Traced, Tracer};
+    pub use super::{HealthCheck, HealthChecker, HealthReport, HealthStatus};
+    pub use super::{Error, ErrorKind, Result};
 }
 
 // Core pattern system
@@ -43,6 +93,45 @@ pub mod pattern {
     pub struct PatternMatcher {
         // Implementation will use a trie-based system
     }
+    
+    /// Check if a glob-style pattern string matches a key
+    ///
+    /// `*` matches any run of characters and `{name}` matches a single
+    /// non-empty `:`-delimited segment.
+    pub fn glob_matches(pattern: &str, key: &str) -> bool {
+        let mut chars = pattern.chars();
+        match chars.next() {
+            None => key.is_empty(),
+            Some('*') => {
+                let rest = chars.as_str();
+                key.char_indices()
+                    .map(|(i, _)| i)
+                    .chain(std::iter::once(key.len()))
+                    .any(|i| glob_matches(rest, &key[i..]))
+            }
+            Some('{') if pattern.contains('}') => {
+                let rest = &pattern[pattern.find('}').unwrap() + 1..];
+                let segment = key.find(':').unwrap_or(key.len());
+                segment > 0 && glob_matches(rest, &key[segment..])
+            }
+            Some(c) => key.starts_with(c) && glob_matches(chars.as_str(), &key[c.len_utf8()..]),
+        }
+    }
+    
+    /// Specificity of a pattern string; more literal characters rank higher
+    pub fn specificity(pattern: &str) -> usize {
+        let mut depth = 0usize;
+        pattern.chars().filter(|c| {
+            match c {
+                '{' => depth += 1,
+                '}' => depth = depth.saturating_sub(1),
+                '*' => {}
+                _ if depth == 0 => return true,
+                _ => {}
+            }
+            false
+        }).count()
+    }
 }
 
 // Ownership tracking
@@ -53,79 +142,690 @@ pub mod ownership {
     /// Represents ownership of cache patterns
     pub struct Ownership {
         pattern: String,
+        owner: String,
         layer: crate::Layer,
         constraints: Vec<Constraint>,
     }
     
+    impl Ownership {
+        /// Declare that `owner` owns `pattern` on `layer`
+        pub fn new(pattern: impl Into<String>, owner: impl Into<String>, layer: crate::Layer) -> Self {
+            Self {
+                pattern: pattern.into(),
+                owner: owner.into(),
+                layer,
+                constraints: Vec::new(),
+            }
+        }
+        
+        /// The owned pattern
+        pub fn pattern(&self) -> &str {
+            &self.pattern
+        }
+        
+        /// The owning component
+        pub fn owner(&self) -> &str {
+            &self.owner
+        }
+        
+        /// The layer holding the pattern
+        pub fn layer(&self) -> crate::Layer {
+            self.layer
+        }
+    }
+    
+    /// Types of pattern relationships
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub enum EdgeType {
+        /// Full ownership
+        Owns,
+        /// Read-only access
+        Borrows,
+        /// Invalidation relationship
+        Invalidates,
+        /// Derived data relationship
+        Derives,
+    }
+    
+    impl EdgeType {
+        /// Lowercase name, as used in manifests and exports
+        pub fn as_str(&self) -> &'static str {
+            match self {
+                EdgeType::Owns => "owns",
+                EdgeType::Borrows => "borrows",
+                EdgeType::Invalidates => "invalidates",
+                EdgeType::Derives => "derives",
+            }
+        }
+    }
+    
+    /// Represents a dependency between a component or pattern and a pattern
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct DependencyEdge {
+        pub from: String,
+        pub to: String,
+        pub edge_type: EdgeType,
+    }
+    
     /// Graph of ownership relationships
     pub struct OwnershipGraph {
         nodes: DashMap<String, Arc<Ownership>>,
         edges: DashMap<String, Vec<DependencyEdge>>,
     }
+    
+    impl OwnershipGraph {
+        /// Create an empty ownership graph
+        pub fn new() -> Self {
+            Self {
+                nodes: DashMap::new(),
+                edges: DashMap::new(),
+            }
+        }
+        
+        /// Register ownership of a pattern
+        pub fn add(&self, ownership: Ownership) -> crate::Result<()> {
+            match self.nodes.entry(ownership.pattern.clone()) {
+                dashmap::mapref::entry::Entry::Occupied(existing) => {
+                    Err(crate::Error::PatternConflict {
+                        pattern: ownership.pattern,
+                        owner: existing.get().owner.clone(),
+                        requested_by: ownership.owner,
+                    })
+                }
+                dashmap::mapref::entry::Entry::Vacant(entry) => {
+                    entry.insert(Arc::new(ownership));
+                    Ok(())
+                }
+            }
+        }
+        
+        /// Ownership declared for exactly this pattern
+        pub fn get(&self, pattern: &str) -> Option<Arc<Ownership>> {
+            self.nodes.get(pattern).map(|node| node.clone())
+        }
+        
+        /// Most specific ownership whose pattern matches a key
+        pub fn owner_of(&self, key: &str) -> Option<Arc<Ownership>> {
+            self.nodes
+                .iter()
+                .filter(|node| crate::pattern::glob_matches(node.key(), key))
+                .max_by_key(|node| crate::pattern::specificity(node.key()))
+                .map(|node| node.value().clone())
+        }
+        
+        /// Record a relationship; ownership itself is recorded with `add`
+        pub fn add_edge(&self, from: impl Into<String>, to: impl Into<String>, edge_type: EdgeType) {
+            let edge = DependencyEdge {
+                from: from.into(),
+                to: to.into(),
+                edge_type,
+            };
+            let mut edges = self.edges.entry(edge.from.clone()).or_default();
+            if !edges.contains(&edge) {
+                edges.push(edge);
+            }
+        }
+        
+        /// Hand a pattern to a new owner, returning the previous ownership
+        pub fn reassign(&self, ownership: Ownership) -> Option<Arc<Ownership>> {
+            self.nodes.insert(ownership.pattern.clone(), Arc::new(ownership))
+        }
+        
+        /// Remove a relationship, returning whether it existed
+        pub fn remove_edge(&self, from: &str, to: &str, edge_type: EdgeType) -> bool {
+            let Some(mut edges) = self.edges.get_mut(from) else {
+                return false;
+            };
+            let before = edges.len();
+            edges.retain(|e| !(e.to == to && e.edge_type == edge_type));
+            before != edges.len()
+        }
+        
+        /// Whether a relationship is recorded
+        pub fn has_edge(&self, from: &str, to: &str, edge_type: EdgeType) -> bool {
+            self.edges
+                .get(from)
+                .is_some_and(|edges| edges.iter().any(|e| e.to == to && e.edge_type == edge_type))
+        }
+        
+        /// Record that `component` borrows `pattern`
+        pub fn add_borrow(&self, component: &str, pattern: &str) {
+            self.add_edge(component, pattern, EdgeType::Borrows);
+        }
+        
+        /// Record that writes to `pattern` invalidate `target`
+        pub fn add_invalidation(&self, pattern: &str, target: &str) {
+            self.add_edge(pattern, target, EdgeType::Invalidates);
+        }
+        
+        /// All ownership nodes, ordered by pattern
+        pub fn nodes(&self) -> Vec<Arc<Ownership>> {
+            let mut nodes: Vec<_> = self.nodes.iter().map(|n| n.value().clone()).collect();
+            nodes.sort_by(|a, b| a.pattern.cmp(&b.pattern));
+            nodes
+        }
+        
+        /// All dependency edges, ordered by source
+        pub fn edges(&self) -> Vec<DependencyEdge> {
+            let mut edges: Vec<_> = self.edges.iter().flat_map(|e| e.value().clone()).collect();
+            edges.sort_by(|a, b| (&a.from, &a.to).cmp(&(&b.from, &b.to)));
+            edges
+        }
+    }
+    
+    impl Default for OwnershipGraph {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
 }
 
 // Strategy system
 pub mod strategy {
+    use std::time::Duration;
     use async_trait::async_trait;
     
+    /// Consistency guarantee a strategy provides for reads
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+    pub enum Consistency {
+        /// Reads may be stale until the next sync
+        Eventual,
+        /// Reads are consistent within a region
+        Regional,
+        /// Reads always observe the latest write
+        Strong,
+    }
+    
     /// Core strategy trait
     #[async_trait]
     pub trait CacheStrategy: Send + Sync + 'static {
         /// Determine cache location for a key
         async fn determine_location(&self, key: &str) -> crate::Result<crate::Layer>;
         
-        /// Handle invalidation for a pattern
+        /// Handle invalidation for a pattern, returning the patterns to purge
         async fn handle_invalidation(&self, pattern: &str) -> crate::Result<Vec<String>>;
+        
+        /// Name used in errors and diagnostics
+        fn name(&self) -> &str {
+            "custom"
+        }
+        
+        /// Consistency guarantee of reads under this strategy
+        fn consistency(&self) -> Consistency {
+            Consistency::Eventual
+        }
+        
+        /// Time-to-live for entries written under this strategy
+        fn ttl(&self, _key: &str) -> Option<Duration> {
+            None
+        }
+        
+        /// Determine cache location for a value of `size` bytes
+        async fn determine_location_sized(&self, key: &str, _size: usize) -> crate::Result<crate::Layer> {
+            self.determine_location(key).await
+        }
+        
+        /// Layers to try, in order, when reading a key
+        async fn locations(&self, key: &str) -> crate::Result<Vec<crate::Layer>> {
+            Ok(vec![self.determine_location(key).await?])
+        }
+        
+        /// Strategies this one is composed of
+        fn children(&self) -> Vec<&dyn CacheStrategy> {
+            Vec::new()
+        }
+        
+        /// Check this strategy's own configuration
+        fn validate(&self) -> crate::Result<()> {
+            Ok(())
+        }
+        
+        /// Whether data stays usable while upstream layers are unreachable
+        fn offline_capable(&self) -> bool {
+            false
+        }
+        
+        /// Whether every key is handled by exactly one child
+        fn disjoint_children(&self) -> bool {
+            false
+        }
+    }
+    
+    #[async_trait]
+    impl CacheStrategy for Box<dyn CacheStrategy> {
+        async fn determine_location(&self, key: &str) -> crate::Result<crate::Layer> {
+            (**self).determine_location(key).await
+        }
+        
+        async fn handle_invalidation(&self, pattern: &str) -> crate::Result<Vec<String>> {
+            (**self).handle_invalidation(pattern).await
+        }
+        
+        fn name(&self) -> &str {
+            (**self).name()
+        }
+        
+        fn consistency(&self) -> Consistency {
+            (**self).consistency()
+        }
+        
+        fn ttl(&self, key: &str) -> Option<Duration> {
+            (**self).ttl(key)
+        }
+        
+        async fn determine_location_sized(&self, key: &str, size: usize) -> crate::Result<crate::Layer> {
+            (**self).determine_location_sized(key, size).await
+        }
+        
+        async fn locations(&self, key: &str) -> crate::Result<Vec<crate::Layer>> {
+            (**self).locations(key).await
+        }
+        
+        fn children(&self) -> Vec<&dyn CacheStrategy> {
+            (**self).children()
+        }
+        
+        fn validate(&self) -> crate::Result<()> {
+            (**self).validate()
+        }
+        
+        fn offline_capable(&self) -> bool {
+            (**self).offline_capable()
+        }
+        
+        fn disjoint_children(&self) -> bool {
+            (**self).disjoint_children()
+        }
+    }
+    
+    /// Built-in strategies with their default settings
+    ///
+    /// Use the concrete types (e.g. [`crate::EdgeOptimized`]) to tune them.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub enum Strategy {
+        ClientFirst,
+        EdgeOptimized,
+        GlobalConsistent,
+        RedisCompatible,
+    }
+    
+    impl Strategy {
+        /// Look up a built-in strategy by its manifest name
+        ///
+        /// Accepts snake_case names and the aliases `offline_first` and
+        /// `real_time` used in manifests.
+        pub fn from_name(name: &str) -> Option<Self> {
+            match name {
+                "client_first" | "offline_first" => Some(Strategy::ClientFirst),
+                "edge_optimized" => Some(Strategy::EdgeOptimized),
+                "global_consistent" | "real_time" => Some(Strategy::GlobalConsistent),
+                "redis_compatible" => Some(Strategy::RedisCompatible),
+                _ => None,
+            }
+        }
+        
+        /// Whether the two strategies may be composed for the same keys
+        ///
+        /// Offline-capable client storage cannot provide strong consistency.
+        pub fn compatible_with(self, other: Strategy) -> bool {
+            let offline = |s: Strategy| s == Strategy::ClientFirst;
+            let strong = |s: Strategy| matches!(s, Strategy::GlobalConsistent | Strategy::RedisCompatible);
+            !(offline(self) && strong(other) || offline(other) && strong(self))
+        }
+        
+        /// The concrete strategy with default settings
+        pub fn build(self) -> Box<dyn CacheStrategy> {
+            match self {
+                Strategy::ClientFirst => Box::new(crate::ClientFirst::new()),
+                Strategy::EdgeOptimized => Box::new(crate::EdgeOptimized::new()),
+                Strategy::GlobalConsistent => Box::new(crate::GlobalConsistent::new()),
+                Strategy::RedisCompatible => Box::new(crate::RedisCompatible::new()),
+            }
+        }
+    }
+    
+    #[async_trait]
+    impl CacheStrategy for Strategy {
+        async fn determine_location(&self, key: &str) -> crate::Result<crate::Layer> {
+            self.build().determine_location(key).await
+        }
+        
+        async fn handle_invalidation(&self, pattern: &str) -> crate::Result<Vec<String>> {
+            self.build().handle_invalidation(pattern).await
+        }
+        
+        fn name(&self) -> &str {
+            match self {
+                Strategy::ClientFirst => "ClientFirst",
+                Strategy::EdgeOptimized => "EdgeOptimized",
+                Strategy::GlobalConsistent => "GlobalConsistent",
+                Strategy::RedisCompatible => "RedisCompatible",
+            }
+        }
+        
+        fn consistency(&self) -> Consistency {
+            self.build().consistency()
+        }
+        
+        fn ttl(&self, key: &str) -> Option<Duration> {
+            self.build().ttl(key)
+        }
+        
+        fn offline_capable(&self) -> bool {
+            *self == Strategy::ClientFirst
+        }
     }
 }
 
 // Layer coordination
 pub mod layer {
     use async_trait::async_trait;
+    use serde::{Deserialize, Serialize};
+    use crate::conflict::Versioned;
     
     /// Available cache layers
-    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+    #[serde(rename_all = "lowercase")]
     pub enum Layer {
         Client,
         Edge,
         Server,
     }
     
-    /// Coordinates operations across layers
-    pub struct LayerCoordinator {
-        layers: Vec<Box<dyn CacheLayer>>,
-        ownership_graph: Arc<crate::OwnershipGraph>,
+    impl Layer {
+        /// Lowercase name, as used in manifests and metric labels
+        pub fn as_str(&self) -> &'static str {
+            match self {
+                Layer::Client => "client",
+                Layer::Edge => "edge",
+                Layer::Server => "server",
+            }
+        }
+        
+        /// Parse a lowercase layer name
+        pub fn from_name(name: &str) -> Option<Self> {
+            match name {
+                "client" => Some(Layer::Client),
+                "edge" => Some(Layer::Edge),
+                "server" => Some(Layer::Server),
+                _ => None,
+            }
+        }
+    }
+    
+    impl std::fmt::Display for Layer {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.write_str(self.as_str())
+        }
     }
     
+    pub use crate::coordinator::LayerCoordinator;
+    
     /// Interface for a cache layer
     #[async_trait]
     pub trait CacheLayer: Send + Sync + 'static {
         async fn get(&self, key: &str) -> crate::Result<Option<Vec<u8>>>;
         async fn set(&self, key: &str, value: Vec<u8>) -> crate::Result<()>;
         async fn delete(&self, key: &str) -> crate::Result<()>;
+        
+        /// List keys held by this layer that match a glob pattern
+        async fn keys(&self, pattern: &str) -> crate::Result<Vec<String>>;
+        
+        /// Read a value with its version
+        ///
+        /// Layers that do not track versions return the value unversioned.
+        async fn get_versioned(&self, key: &str) -> crate::Result<Option<Versioned>> {
+            Ok(self.get(key).await?.map(Versioned::unversioned))
+        }
+        
+        /// Write a value written at a known version, returning what was stored
+        ///
+        /// Layers that track versions resolve conflicts with the stored
+        /// version; others store the value as is.
+        async fn set_versioned(&self, key: &str, value: Versioned) -> crate::Result<Versioned> {
+            self.set(key, value.value.clone()).await?;
+            Ok(value)
+        }
     }
 }
 
 // Error handling
 pub mod error {
+    use std::time::Duration;
     use thiserror::Error;
+    use crate::Layer;
+    
+    /// Boxed underlying cause of an error
+    pub type Source = Box<dyn std::error::Error + Send + Sync>;
     
     #[derive(Error, Debug)]
     pub enum Error {
-        #[error("Pattern conflict: {0}")]
-        PatternConflict(String),
+        #[error("Pattern conflict: {pattern} is owned by {owner}, requested by {requested_by}")]
+        PatternConflict {
+            pattern: String,
+            owner: String,
+            requested_by: String,
+        },
+        
+        #[error("Invalid borrowing: {borrower} borrows {pattern} from {owner} and cannot write {key}")]
+        InvalidBorrowing {
+            key: String,
+            pattern: String,
+            owner: String,
+            borrower: String,
+        },
+        
+        #[error("Layer violation: {key} on {layer} layer: {reason}")]
+        LayerViolation {
+            key: String,
+            layer: Layer,
+            reason: String,
+        },
+        
+        #[error("Strategy error: {strategy}: {reason}")]
+        StrategyError {
+            strategy: String,
+            /// The key being placed, if the error came from placing one
+            key: Option<String>,
+            /// The pattern the strategy serves, if the error is about one
+            pattern: Option<String>,
+            /// The layer the strategy could not use, if any
+            layer: Option<Layer>,
+            reason: String,
+        },
+        
+        #[error("Quota exceeded: {key} exceeds quota for {pattern}: {reason}")]
+        QuotaExceeded {
+            key: String,
+            pattern: String,
+            reason: String,
+        },
+        
+        #[error("Capacity exceeded on {layer} layer: {reason}")]
+        Capacity {
+            layer: Layer,
+            reason: String,
+        },
+        
+        #[error("Namespace error: {namespace}: {reason}")]
+        Namespace {
+            namespace: String,
+            reason: String,
+        },
+        
+        #[error(
+            "Timeout: {operation}{} did not complete within {after:?}",
+            .key.as_ref().map(|key| format!(" on {}", key)).unwrap_or_default()
+        )]
+        Timeout {
+            operation: String,
+            key: Option<String>,
+            layer: Option<Layer>,
+            after: Duration,
+        },
+        
+        #[error("Version conflict: {key} expected version {expected}, found {actual}")]
+        VersionConflict {
+            key: String,
+            expected: u64,
+            actual: u64,
+        },
+        
+        #[error("Write conflict: {key}: {reason}")]
+        Conflict {
+            key: String,
+            reason: String,
+        },
+        
+        #[error("Network error: {reason}")]
+        Network {
+            layer: Option<Layer>,
+            reason: String,
+            #[source]
+            source: Option<Source>,
+        },
         
-        #[error("Invalid borrowing: {0}")]
-        InvalidBorrowing(String),
+        #[error("Serialization error: {reason}")]
+        Serialization {
+            key: Option<String>,
+            reason: String,
+            #[source]
+            source: Option<Source>,
+        },
         
-        #[error("Layer violation: {0}")]
-        LayerViolation(String),
+        #[error("Invalid configuration: {0}")]
+        InvalidConfig(String),
         
-        #[error("Strategy error: {0}")]
-        StrategyError(String),
+        /// Invalidations failed on more than one layer
+        #[error(
+            "Invalidations not delivered: {}",
+            .errors.iter().map(|(layer, e)| format!("{} layer: {}", layer, e)).collect::<Vec<_>>().join("; ")
+        )]
+        Undelivered {
+            errors: Vec<(Layer, Error)>,
+        },
         
         #[error(transparent)]
-        Other(#[from] Box<dyn std::error::Error + Send + Sync>),
+        Other(#[from] Source),
+    }
+    
+    /// Broad classification of errors for retry and reporting logic
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub enum ErrorKind {
+        /// Ownership, borrowing or layer rules were violated
+        Ownership,
+        /// A strategy is invalid or failed
+        Strategy,
+        /// A quota or layer capacity was exhausted
+        Capacity,
+        /// A namespace was missing, duplicated or misnamed
+        Namespace,
+        Timeout,
+        /// A concurrent write won or could not be reconciled
+        Conflict,
+        Network,
+        Serialization,
+        Config,
+        Other,
+    }
+    
+    impl Error {
+        /// Classify the error
+        pub fn kind(&self) -> ErrorKind {
+            match self {
+                Error::PatternConflict { .. }
+                | Error::InvalidBorrowing { .. }
+                | Error::LayerViolation { .. } => ErrorKind::Ownership,
+                Error::StrategyError { .. } => ErrorKind::Strategy,
+                Error::QuotaExceeded { .. } | Error::Capacity { .. } => ErrorKind::Capacity,
+                Error::Namespace { .. } => ErrorKind::Namespace,
+                Error::Timeout { .. } => ErrorKind::Timeout,
+                Error::VersionConflict { .. } | Error::Conflict { .. } => ErrorKind::Conflict,
+                Error::Network { .. } => ErrorKind::Network,
+                Error::Serialization { .. } => ErrorKind::Serialization,
+                Error::InvalidConfig(_) => ErrorKind::Config,
+                Error::Undelivered { .. } | Error::Other(_) => ErrorKind::Other,
+            }
+        }
+        
+        /// Whether repeating the same operation may succeed
+        ///
+        /// Timeouts and network failures are transient, a version conflict can
+        /// be retried after re-reading, and layer capacity frees up as entries
+        /// are evicted. Rule violations and per-pattern quotas are permanent.
+        pub fn is_retryable(&self) -> bool {
+            matches!(
+                self,
+                Error::Timeout { .. }
+                    | Error::Network { .. }
+                    | Error::VersionConflict { .. }
+                    | Error::Capacity { .. }
+            )
+        }
+        
+        /// The key involved, if any
+        pub fn key(&self) -> Option<&str> {
+            match self {
+                Error::InvalidBorrowing { key, .. }
+                | Error::LayerViolation { key, .. }
+                | Error::QuotaExceeded { key, .. }
+                | Error::VersionConflict { key, .. }
+                | Error::Conflict { key, .. } => Some(key),
+                Error::Timeout { key, .. }
+                | Error::Serialization { key, .. }
+                | Error::StrategyError { key, .. } => key.as_deref(),
+                _ => None,
+            }
+        }
+        
+        /// The pattern involved, if any
+        pub fn pattern(&self) -> Option<&str> {
+            match self {
+                Error::PatternConflict { pattern, .. }
+                | Error::InvalidBorrowing { pattern, .. }
+                | Error::QuotaExceeded { pattern, .. } => Some(pattern),
+                Error::StrategyError { pattern, .. } => pattern.as_deref(),
+                _ => None,
+            }
+        }
+        
+        /// The owner involved, if any
+        pub fn owner(&self) -> Option<&str> {
+            match self {
+                Error::PatternConflict { owner, .. } | Error::InvalidBorrowing { owner, .. } => Some(owner),
+                _ => None,
+            }
+        }
+        
+        /// The layer involved, if any
+        pub fn layer(&self) -> Option<Layer> {
+            match self {
+                Error::LayerViolation { layer, .. } | Error::Capacity { layer, .. } => Some(*layer),
+                Error::Timeout { layer, .. }
+                | Error::Network { layer, .. }
+                | Error::StrategyError { layer, .. } => *layer,
+                _ => None,
+            }
+        }
+        
+        /// A network failure caused by an I/O error
+        pub fn network(layer: Option<Layer>, reason: impl Into<String>, source: std::io::Error) -> Self {
+            Error::Network {
+                layer,
+                reason: reason.into(),
+                source: Some(Box::new(source)),
+            }
+        }
+    }
+    
+    impl From<serde_json::Error> for Error {
+        fn from(e: serde_json::Error) -> Self {
+            Error::Serialization {
+                key: None,
+                reason: e.to_string(),
+                source: Some(Box::new(e)),
+            }
+        }
     }
     
     pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,450 @@
+//! Per-pattern, per-layer cache metrics with a Prometheus exporter
+
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use dashmap::DashMap;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+use crate::layer::CacheLayer;
+use crate::pattern::{glob_matches, specificity};
+use crate::{Error, Layer, Result, Versioned};
+
+/// Pattern label for keys not covered by any manifest pattern
+pub const UNMATCHED: &str = "*";
+
+/// Upper bounds of the latency histogram buckets, in microseconds
+const BUCKETS_MICROS: [u64; 14] = [
+    50, 100, 250, 500, 1_000, 2_500, 5_000, 10_000, 25_000, 50_000, 100_000, 250_000, 500_000,
+    1_000_000,
+];
+
+/// Cache operations with a latency histogram
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Op {
+    Get,
+    Set,
+    Delete,
+}
+
+impl Op {
+    const ALL: [Op; 3] = [Op::Get, Op::Set, Op::Delete];
+
+    /// Label value used by the exporter
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Op::Get => "get",
+            Op::Set => "set",
+            Op::Delete => "delete",
+        }
+    }
+}
+
+/// Lock-free latency histogram with fixed buckets
+pub struct Histogram {
+    buckets: [AtomicU64; BUCKETS_MICROS.len()],
+    overflow: AtomicU64,
+    sum_micros: AtomicU64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self {
+            buckets: Default::default(),
+            overflow: AtomicU64::new(0),
+            sum_micros: AtomicU64::new(0),
+        }
+    }
+}
+
+impl Histogram {
+    /// Record one observation
+    pub fn observe(&self, latency: Duration) {
+        let micros = latency.as_micros().min(u64::MAX as u128) as u64;
+        match BUCKETS_MICROS.iter().position(|bound| micros <= *bound) {
+            Some(i) => self.buckets[i].fetch_add(1, Ordering::Relaxed),
+            None => self.overflow.fetch_add(1, Ordering::Relaxed),
+        };
+        self.sum_micros.fetch_add(micros, Ordering::Relaxed);
+    }
+
+    /// Take a consistent-enough copy of the histogram
+    pub fn snapshot(&self) -> HistogramSnapshot {
+        let mut counts: Vec<u64> = self
+            .buckets
+            .iter()
+            .map(|b| b.load(Ordering::Relaxed))
+            .collect();
+        counts.push(self.overflow.load(Ordering::Relaxed));
+        HistogramSnapshot {
+            counts,
+            sum: Duration::from_micros(self.sum_micros.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// Point-in-time copy of a histogram
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HistogramSnapshot {
+    /// Per-bucket (non-cumulative) counts; the last bucket is `+Inf`
+    counts: Vec<u64>,
+    sum: Duration,
+}
+
+impl HistogramSnapshot {
+    /// Number of observations
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Sum of all observations
+    pub fn sum(&self) -> Duration {
+        self.sum
+    }
+
+    /// Cumulative counts paired with bucket upper bounds (`None` is `+Inf`)
+    pub fn buckets(&self) -> Vec<(Option<Duration>, u64)> {
+        let mut total = 0;
+        self.counts
+            .iter()
+            .enumerate()
+            .map(|(i, count)| {
+                total += count;
+                (BUCKETS_MICROS.get(i).map(|b| Duration::from_micros(*b)), total)
+            })
+            .collect()
+    }
+
+    /// Upper bound of the bucket containing quantile `q` (0.0..=1.0)
+    pub fn quantile(&self, q: f64) -> Option<Duration> {
+        let count = self.count();
+        if count == 0 {
+            return None;
+        }
+        let rank = ((q.clamp(0.0, 1.0) * count as f64).ceil() as u64).max(1);
+        self.buckets()
+            .into_iter()
+            .find(|(_, cumulative)| *cumulative >= rank)
+            .map(|(bound, _)| bound.unwrap_or(Duration::MAX))
+    }
+
+    fn merge(&mut self, other: &HistogramSnapshot) {
+        if self.counts.is_empty() {
+            self.counts = vec![0; other.counts.len()];
+        }
+        for (a, b) in self.counts.iter_mut().zip(&other.counts) {
+            *a += b;
+        }
+        self.sum += other.sum;
+    }
+}
+
+/// Accessor for one of a series' counters
+type CounterField = fn(&Series) -> &AtomicU64;
+
+/// Counters and histograms for one (pattern, layer) series
+#[derive(Default)]
+struct Series {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+    expirations: AtomicU64,
+    invalidations: AtomicU64,
+    latency: [Histogram; 3],
+}
+
+/// Aggregated statistics for a pattern
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PatternStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    pub expirations: u64,
+    pub invalidations: u64,
+    pub get_latency: HistogramSnapshot,
+    pub set_latency: HistogramSnapshot,
+    pub delete_latency: HistogramSnapshot,
+}
+
+impl PatternStats {
+    /// Fraction of reads that were hits
+    pub fn hit_ratio(&self) -> f64 {
+        let reads = self.hits + self.misses;
+        if reads == 0 {
+            0.0
+        } else {
+            self.hits as f64 / reads as f64
+        }
+    }
+
+    fn add(&mut self, series: &Series) {
+        self.hits += series.hits.load(Ordering::Relaxed);
+        self.misses += series.misses.load(Ordering::Relaxed);
+        self.evictions += series.evictions.load(Ordering::Relaxed);
+        self.expirations += series.expirations.load(Ordering::Relaxed);
+        self.invalidations += series.invalidations.load(Ordering::Relaxed);
+        self.get_latency.merge(&series.latency[0].snapshot());
+        self.set_latency.merge(&series.latency[1].snapshot());
+        self.delete_latency.merge(&series.latency[2].snapshot());
+    }
+}
+
+/// Metrics registry keyed by manifest pattern and layer
+pub struct Metrics {
+    patterns: Vec<String>,
+    series: DashMap<(String, Layer), Arc<Series>>,
+}
+
+impl Metrics {
+    /// Create a registry for the manifest's patterns
+    pub fn new<I, S>(patterns: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let mut patterns: Vec<String> = patterns.into_iter().map(Into::into).collect();
+        patterns.sort_by_key(|p| std::cmp::Reverse(specificity(p)));
+        Self {
+            patterns,
+            series: DashMap::new(),
+        }
+    }
+
+    /// Most specific manifest pattern covering a key
+    pub fn pattern_for(&self, key: &str) -> &str {
+        self.patterns
+            .iter()
+            .find(|p| glob_matches(p, key))
+            .map(String::as_str)
+            .unwrap_or(UNMATCHED)
+    }
+
+    /// Record a read hit
+    pub fn record_hit(&self, key: &str, layer: Layer, latency: Duration) {
+        let series = self.series(key, layer);
+        series.hits.fetch_add(1, Ordering::Relaxed);
+        series.latency[0].observe(latency);
+    }
+
+    /// Record a read miss
+    pub fn record_miss(&self, key: &str, layer: Layer, latency: Duration) {
+        let series = self.series(key, layer);
+        series.misses.fetch_add(1, Ordering::Relaxed);
+        series.latency[0].observe(latency);
+    }
+
+    /// Record the latency of a write or delete
+    pub fn record_latency(&self, key: &str, layer: Layer, op: Op, latency: Duration) {
+        let index = Op::ALL.iter().position(|o| *o == op).unwrap();
+        self.series(key, layer).latency[index].observe(latency);
+    }
+
+    /// Record an eviction
+    pub fn record_eviction(&self, key: &str, layer: Layer) {
+        self.series(key, layer).evictions.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record a TTL expiration
+    pub fn record_expiration(&self, key: &str, layer: Layer) {
+        self.series(key, layer).expirations.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record an invalidation
+    pub fn record_invalidation(&self, key: &str, layer: Layer) {
+        self.series(key, layer).invalidations.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Statistics for a pattern across all layers
+    pub fn stats(&self, pattern: &str) -> PatternStats {
+        let mut stats = PatternStats::default();
+        for series in self.series.iter().filter(|s| s.key().0 == pattern) {
+            stats.add(series.value());
+        }
+        stats
+    }
+
+    /// Statistics for a pattern on one layer
+    pub fn stats_for(&self, pattern: &str, layer: Layer) -> PatternStats {
+        let mut stats = PatternStats::default();
+        if let Some(series) = self.series.get(&(pattern.to_string(), layer)) {
+            stats.add(series.value());
+        }
+        stats
+    }
+
+    /// Render all series in the Prometheus text exposition format
+    pub fn to_prometheus(&self) -> String {
+        let mut series: Vec<_> = self
+            .series
+            .iter()
+            .map(|s| (s.key().clone(), s.value().clone()))
+            .collect();
+        series.sort_by(|a, b| (&a.0 .0, a.0 .1.as_str()).cmp(&(&b.0 .0, b.0 .1.as_str())));
+
+        let mut out = String::new();
+        let counters: [(&str, &str, CounterField); 5] = [
+            ("hits", "Cache hits", |s| &s.hits),
+            ("misses", "Cache misses", |s| &s.misses),
+            ("evictions", "Entries evicted", |s| &s.evictions),
+            ("expirations", "Entries expired", |s| &s.expirations),
+            ("invalidations", "Entries invalidated", |s| &s.invalidations),
+        ];
+        for (name, help, field) in counters {
+            let _ = writeln!(out, "# HELP stateless_cache_{}_total {}", name, help);
+            let _ = writeln!(out, "# TYPE stateless_cache_{}_total counter", name);
+            for ((pattern, layer), s) in &series {
+                let _ = writeln!(
+                    out,
+                    "stateless_cache_{}_total{{{}}} {}",
+                    name,
+                    labels(pattern, *layer),
+                    field(s).load(Ordering::Relaxed)
+                );
+            }
+        }
+
+        let _ = writeln!(out, "# HELP stateless_cache_latency_seconds Operation latency");
+        let _ = writeln!(out, "# TYPE stateless_cache_latency_seconds histogram");
+        for ((pattern, layer), s) in &series {
+            for (op, histogram) in Op::ALL.iter().zip(&s.latency) {
+                let snapshot = histogram.snapshot();
+                let labels = format!("{},op=\"{}\"", labels(pattern, *layer), op.as_str());
+                for (bound, cumulative) in snapshot.buckets() {
+                    let le = match bound {
+                        Some(bound) => bound.as_secs_f64().to_string(),
+                        None => "+Inf".to_string(),
+                    };
+                    let _ = writeln!(
+                        out,
+                        "stateless_cache_latency_seconds_bucket{{{},le=\"{}\"}} {}",
+                        labels, le, cumulative
+                    );
+                }
+                let _ = writeln!(
+                    out,
+                    "stateless_cache_latency_seconds_sum{{{}}} {}",
+                    labels,
+                    snapshot.sum().as_secs_f64()
+                );
+                let _ = writeln!(
+                    out,
+                    "stateless_cache_latency_seconds_count{{{}}} {}",
+                    labels,
+                    snapshot.count()
+                );
+            }
+        }
+        out
+    }
+
+    /// Serve the Prometheus exposition over HTTP until the listener fails
+    pub async fn serve(self: Arc<Self>, listener: TcpListener) -> Result<()> {
+        loop {
+            let (mut stream, _) = listener
+                .accept()
+                .await
+                .map_err(|e| Error::network(None, "accepting metrics scrape", e))?;
+            let metrics = self.clone();
+            tokio::spawn(async move {
+                // The request itself is ignored; every path serves the metrics
+                let mut request = [0u8; 1024];
+                let _ = stream.read(&mut request).await;
+                let body = metrics.to_prometheus();
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+            });
+        }
+    }
+
+    fn series(&self, key: &str, layer: Layer) -> Arc<Series> {
+        let pattern = self.pattern_for(key).to_string();
+        self.series.entry((pattern, layer)).or_default().clone()
+    }
+}
+
+fn labels(pattern: &str, layer: Layer) -> String {
+    let escaped = pattern
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n");
+    format!("pattern=\"{}\",layer=\"{}\"", escaped, layer.as_str())
+}
+
+/// A `CacheLayer` that records metrics for every operation
+pub struct MetricsLayer<L> {
+    inner: L,
+    layer: Layer,
+    metrics: Arc<Metrics>,
+}
+
+impl<L: CacheLayer> MetricsLayer<L> {
+    /// Wrap a layer, attributing its metrics to `layer`
+    pub fn new(inner: L, layer: Layer, metrics: Arc<Metrics>) -> Self {
+        Self {
+            inner,
+            layer,
+            metrics,
+        }
+    }
+
+    /// Access the metrics registry
+    pub fn metrics(&self) -> &Arc<Metrics> {
+        &self.metrics
+    }
+}
+
+#[async_trait]
+impl<L: CacheLayer> CacheLayer for MetricsLayer<L> {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let start = Instant::now();
+        let value = self.inner.get(key).await?;
+        match value {
+            Some(_) => self.metrics.record_hit(key, self.layer, start.elapsed()),
+            None => self.metrics.record_miss(key, self.layer, start.elapsed()),
+        }
+        Ok(value)
+    }
+
+    async fn set(&self, key: &str, value: Vec<u8>) -> Result<()> {
+        let start = Instant::now();
+        let result = self.inner.set(key, value).await;
+        self.metrics.record_latency(key, self.layer, Op::Set, start.elapsed());
+        result
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        let start = Instant::now();
+        let result = self.inner.delete(key).await;
+        self.metrics.record_latency(key, self.layer, Op::Delete, start.elapsed());
+        result
+    }
+
+    async fn keys(&self, pattern: &str) -> Result<Vec<String>> {
+        self.inner.keys(pattern).await
+    }
+
+    async fn get_versioned(&self, key: &str) -> Result<Option<Versioned>> {
+        let start = Instant::now();
+        let value = self.inner.get_versioned(key).await?;
+        match value {
+            Some(_) => self.metrics.record_hit(key, self.layer, start.elapsed()),
+            None => self.metrics.record_miss(key, self.layer, start.elapsed()),
+        }
+        Ok(value)
+    }
+
+    async fn set_versioned(&self, key: &str, value: Versioned) -> Result<Versioned> {
+        let start = Instant::now();
+        let result = self.inner.set_versioned(key, value).await;
+        self.metrics.record_latency(key, self.layer, Op::Set, start.elapsed());
+        result
+    }
+}
@@ -0,0 +1,316 @@
+//! Multi-tenant namespaces over a shared cache layer
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use dashmap::DashMap;
+
+use crate::layer::CacheLayer;
+use crate::metrics::Metrics;
+use crate::quota::{Admission, QuotaManager, QuotaPolicy, QuotaUsage};
+use crate::{Error, Layer, OwnershipGraph, Result};
+
+/// Separator between a namespace name and its keys in the backing layer
+const SEPARATOR: char = '/';
+
+/// Configuration for a tenant namespace
+pub struct NamespaceConfig {
+    name: String,
+    ownership: OwnershipGraph,
+    quotas: Vec<QuotaPolicy>,
+    max_bytes: Option<usize>,
+    metrics: Option<(Arc<Metrics>, Layer)>,
+}
+
+impl NamespaceConfig {
+    /// Create a configuration for a namespace
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            ownership: OwnershipGraph::new(),
+            quotas: Vec::new(),
+            max_bytes: None,
+            metrics: None,
+        }
+    }
+
+    /// Set the tenant's ownership manifest
+    pub fn ownership(mut self, ownership: OwnershipGraph) -> Self {
+        self.ownership = ownership;
+        self
+    }
+
+    /// Add a per-pattern quota inside the namespace
+    pub fn quota(mut self, policy: QuotaPolicy) -> Self {
+        self.quotas.push(policy);
+        self
+    }
+
+    /// Limit the total value bytes stored by the tenant
+    pub fn max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Record invalidations against `layer` in a metrics registry, by tenant key
+    pub fn metrics(mut self, metrics: Arc<Metrics>, layer: Layer) -> Self {
+        self.metrics = Some((metrics, layer));
+        self
+    }
+}
+
+/// Snapshot of a namespace's operation counters and memory usage
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NamespaceStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub writes: u64,
+    pub deletes: u64,
+    pub invalidations: u64,
+    pub bytes: usize,
+    pub keys: usize,
+}
+
+#[derive(Default)]
+struct Counters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    writes: AtomicU64,
+    deletes: AtomicU64,
+    invalidations: AtomicU64,
+}
+
+/// An isolated tenant keyspace
+///
+/// Keys and patterns are scoped under the namespace name before they reach
+/// the backing layer, so pattern operations never see other tenants' keys.
+pub struct Namespace {
+    name: String,
+    prefix: String,
+    layer: Arc<dyn CacheLayer>,
+    ownership: Arc<OwnershipGraph>,
+    quotas: QuotaManager,
+    limit: QuotaManager,
+    counters: Counters,
+    metrics: Option<(Arc<Metrics>, Layer)>,
+}
+
+impl Namespace {
+    fn new(config: NamespaceConfig, layer: Arc<dyn CacheLayer>) -> Self {
+        let mut limit = QuotaPolicy::new("*").on_overflow(crate::OverflowAction::Reject);
+        limit.max_bytes = config.max_bytes;
+        Self {
+            prefix: format!("{}{}", config.name, SEPARATOR),
+            name: config.name,
+            layer,
+            ownership: Arc::new(config.ownership),
+            quotas: QuotaManager::new(config.quotas),
+            limit: QuotaManager::new(vec![limit]),
+            counters: Counters::default(),
+            metrics: config.metrics,
+        }
+    }
+
+    /// Name of the namespace
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The tenant's ownership manifest
+    pub fn ownership(&self) -> &Arc<OwnershipGraph> {
+        &self.ownership
+    }
+
+    /// Get a value from the namespace
+    pub async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let value = self.layer.get(&self.scoped(key)).await?;
+        let counter = match value {
+            Some(_) => &self.counters.hits,
+            None => &self.counters.misses,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        Ok(value)
+    }
+
+    /// Set a value, enforcing the namespace quotas
+    ///
+    /// Keys a pattern quota evicts are only deleted once the tenant limit
+    /// has admitted the write too.
+    pub async fn set(&self, key: &str, value: Vec<u8>) -> Result<()> {
+        let size = value.len();
+        let previous = self.quotas.size_of(key);
+        let total = self.limit.size_of(key);
+        let evicted = match self.quotas.admit(key, size)? {
+            Admission::Admit { evicted } => evicted,
+            Admission::Spill => {
+                return Err(Error::QuotaExceeded {
+                    key: key.to_string(),
+                    pattern: self.quotas.policy_for(key).map(|p| p.pattern.clone()).unwrap_or_default(),
+                    reason: format!("namespace {} cannot spill", self.name),
+                });
+            }
+        };
+        // Evicted keys free their bytes for the tenant limit, unless it rejects the write anyway
+        let victims: Vec<_> = evicted
+            .into_iter()
+            .map(|victim| {
+                let size = self.limit.size_of(&victim);
+                self.limit.release(&victim);
+                (victim, size)
+            })
+            .collect();
+        if let Err(e) = self.limit.admit(key, size) {
+            for (victim, size) in &victims {
+                self.quotas.restore(victim, *size);
+                self.limit.restore(victim, *size);
+            }
+            self.quotas.restore(key, previous);
+            return Err(e);
+        }
+        for (victim, _) in &victims {
+            self.layer.delete(&self.scoped(victim)).await?;
+        }
+        if let Err(e) = self.layer.set(&self.scoped(key), value).await {
+            self.quotas.restore(key, previous);
+            self.limit.restore(key, total);
+            return Err(e);
+        }
+        self.counters.writes.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Delete a key from the namespace
+    pub async fn delete(&self, key: &str) -> Result<()> {
+        self.quotas.release(key);
+        self.limit.release(key);
+        self.layer.delete(&self.scoped(key)).await?;
+        self.counters.deletes.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Get all keys in the namespace matching a pattern
+    pub async fn get_pattern(&self, pattern: &str) -> Result<Vec<String>> {
+        let keys = self.layer.keys(&self.scoped(pattern)).await?;
+        Ok(keys
+            .into_iter()
+            .filter_map(|key| key.strip_prefix(&self.prefix).map(str::to_string))
+            .collect())
+    }
+
+    /// Invalidate all keys in the namespace matching a pattern
+    pub async fn invalidate_pattern(&self, pattern: &str) -> Result<usize> {
+        let keys = self.get_pattern(pattern).await?;
+        for key in &keys {
+            self.quotas.release(key);
+            self.limit.release(key);
+            self.layer.delete(&self.scoped(key)).await?;
+            if let Some((metrics, layer)) = &self.metrics {
+                metrics.record_invalidation(key, *layer);
+            }
+        }
+        self.counters
+            .invalidations
+            .fetch_add(keys.len() as u64, Ordering::Relaxed);
+        Ok(keys.len())
+    }
+
+    /// Current usage of each pattern quota in the namespace
+    pub fn usage(&self) -> Vec<QuotaUsage> {
+        self.quotas.usage_all()
+    }
+
+    /// Snapshot of the namespace counters and total memory usage
+    pub fn stats(&self) -> NamespaceStats {
+        let total = self.limit.usage("*").unwrap_or_else(|| QuotaUsage {
+            pattern: "*".to_string(),
+            bytes: 0,
+            keys: 0,
+            max_bytes: None,
+            max_keys: None,
+        });
+        NamespaceStats {
+            hits: self.counters.hits.load(Ordering::Relaxed),
+            misses: self.counters.misses.load(Ordering::Relaxed),
+            writes: self.counters.writes.load(Ordering::Relaxed),
+            deletes: self.counters.deletes.load(Ordering::Relaxed),
+            invalidations: self.counters.invalidations.load(Ordering::Relaxed),
+            bytes: total.bytes,
+            keys: total.keys,
+        }
+    }
+
+    fn scoped(&self, key: &str) -> String {
+        format!("{}{}", self.prefix, key)
+    }
+}
+
+/// Registry of tenant namespaces sharing one backing layer
+pub struct Namespaces {
+    layer: Arc<dyn CacheLayer>,
+    namespaces: DashMap<String, Arc<Namespace>>,
+}
+
+impl Namespaces {
+    /// Create a registry over a backing layer
+    pub fn new(layer: Arc<dyn CacheLayer>) -> Self {
+        Self {
+            layer,
+            namespaces: DashMap::new(),
+        }
+    }
+
+    /// Create a namespace
+    pub fn create(&self, config: NamespaceConfig) -> Result<Arc<Namespace>> {
+        validate_name(&config.name)?;
+        match self.namespaces.entry(config.name.clone()) {
+            dashmap::mapref::entry::Entry::Occupied(_) => Err(Error::Namespace {
+                namespace: config.name,
+                reason: "already exists".to_string(),
+            }),
+            dashmap::mapref::entry::Entry::Vacant(entry) => {
+                let namespace = Arc::new(Namespace::new(config, self.layer.clone()));
+                entry.insert(namespace.clone());
+                Ok(namespace)
+            }
+        }
+    }
+
+    /// Look up a namespace by name
+    pub fn get(&self, name: &str) -> Option<Arc<Namespace>> {
+        self.namespaces.get(name).map(|ns| ns.clone())
+    }
+
+    /// Names of all namespaces
+    pub fn names(&self) -> Vec<String> {
+        self.namespaces.iter().map(|ns| ns.key().clone()).collect()
+    }
+
+    /// Remove a namespace and invalidate all of its keys
+    pub async fn remove(&self, name: &str) -> Result<()> {
+        let (_, namespace) = self
+            .namespaces
+            .remove(name)
+            .ok_or_else(|| Error::Namespace {
+                namespace: name.to_string(),
+                reason: "does not exist".to_string(),
+            })?;
+        namespace.invalidate_pattern("*").await?;
+        Ok(())
+    }
+}
+
+/// Namespace names must not contain the separator or pattern syntax
+fn validate_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(Error::Namespace {
+            namespace: name.to_string(),
+            reason: "names may only contain ASCII letters, digits, '-' and '_'".to_string(),
+        })
+    }
+}
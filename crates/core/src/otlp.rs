@@ -0,0 +1,300 @@
+//! OpenTelemetry OTLP/HTTP (JSON) exporter for cache spans
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde_json::{json, Value};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::{Error, Result};
+
+/// Spans buffered before the exporter drops the oldest
+const MAX_PENDING: usize = 8192;
+
+/// Attribute value captured from span fields
+#[derive(Debug, Clone, PartialEq)]
+pub enum AttributeValue {
+    String(String),
+    Int(i64),
+    Bool(bool),
+    Double(f64),
+}
+
+/// A finished span waiting to be exported
+#[derive(Debug, Clone)]
+pub struct SpanData {
+    pub trace_id: u128,
+    pub span_id: u64,
+    pub parent_span_id: Option<u64>,
+    pub name: &'static str,
+    pub start: SystemTime,
+    pub end: SystemTime,
+    pub attributes: Vec<(String, AttributeValue)>,
+    pub error: bool,
+}
+
+struct AttributeVisitor<'a>(&'a mut Vec<(String, AttributeValue)>);
+
+impl AttributeVisitor<'_> {
+    fn set(&mut self, field: &Field, value: AttributeValue) {
+        let name = field.name();
+        match self.0.iter_mut().find(|(key, _)| key == name) {
+            Some(entry) => entry.1 = value,
+            None => self.0.push((name.to_string(), value)),
+        }
+    }
+}
+
+impl Visit for AttributeVisitor<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.set(field, AttributeValue::String(value.to_string()));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.set(field, AttributeValue::Int(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.set(field, AttributeValue::Int(value as i64));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.set(field, AttributeValue::Bool(value));
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.set(field, AttributeValue::Double(value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.set(field, AttributeValue::String(format!("{:?}", value)));
+    }
+}
+
+/// `tracing_subscriber` layer that collects closed spans for OTLP export
+#[derive(Clone, Default)]
+pub struct OtlpLayer {
+    pending: Arc<Mutex<Vec<SpanData>>>,
+}
+
+impl OtlpLayer {
+    /// Create a layer with an empty buffer
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create an exporter that ships this layer's spans to a collector
+    pub fn exporter(&self, endpoint: SocketAddr, service_name: impl Into<String>) -> OtlpExporter {
+        OtlpExporter {
+            pending: self.pending.clone(),
+            endpoint,
+            service_name: service_name.into(),
+        }
+    }
+}
+
+impl<S> tracing_subscriber::Layer<S> for OtlpLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let span = match ctx.span(id) {
+            Some(span) => span,
+            None => return,
+        };
+        let parent = span
+            .parent()
+            .and_then(|p| p.extensions().get::<SpanData>().map(|d| (d.trace_id, d.span_id)));
+        let mut data = SpanData {
+            trace_id: parent
+                .map(|(trace, _)| trace)
+                .unwrap_or_else(|| (u128::from(random_u64()) << 64) | u128::from(random_u64())),
+            span_id: random_u64(),
+            parent_span_id: parent.map(|(_, span)| span),
+            name: attrs.metadata().name(),
+            start: SystemTime::now(),
+            end: SystemTime::now(),
+            attributes: Vec::new(),
+            error: false,
+        };
+        attrs.record(&mut AttributeVisitor(&mut data.attributes));
+        span.extensions_mut().insert(data);
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(data) = span.extensions_mut().get_mut::<SpanData>() {
+                values.record(&mut AttributeVisitor(&mut data.attributes));
+            }
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        if *event.metadata().level() > Level::WARN {
+            return;
+        }
+        if let Some(span) = ctx.event_span(event) {
+            if let Some(data) = span.extensions_mut().get_mut::<SpanData>() {
+                data.error = true;
+            }
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let data = ctx
+            .span(&id)
+            .and_then(|span| span.extensions_mut().remove::<SpanData>());
+        if let Some(mut data) = data {
+            data.end = SystemTime::now();
+            let mut pending = self.pending.lock().unwrap();
+            if pending.len() == MAX_PENDING {
+                pending.remove(0);
+            }
+            pending.push(data);
+        }
+    }
+}
+
+/// Ships buffered spans to an OTLP/HTTP collector as JSON
+pub struct OtlpExporter {
+    pending: Arc<Mutex<Vec<SpanData>>>,
+    endpoint: SocketAddr,
+    service_name: String,
+}
+
+impl OtlpExporter {
+    /// Number of spans waiting to be exported
+    pub fn pending(&self) -> usize {
+        self.pending.lock().unwrap().len()
+    }
+
+    /// Export all buffered spans, returning how many were sent
+    ///
+    /// Spans are put back into the buffer if the collector rejects them.
+    pub async fn flush(&self) -> Result<usize> {
+        let spans = std::mem::take(&mut *self.pending.lock().unwrap());
+        if spans.is_empty() {
+            return Ok(0);
+        }
+        let body = encode(&self.service_name, &spans).to_string();
+        match post(self.endpoint, "/v1/traces", &body).await {
+            Ok(()) => Ok(spans.len()),
+            Err(e) => {
+                let mut pending = self.pending.lock().unwrap();
+                let newer = std::mem::replace(&mut *pending, spans);
+                pending.extend(newer);
+                let excess = pending.len().saturating_sub(MAX_PENDING);
+                pending.drain(..excess);
+                Err(e)
+            }
+        }
+    }
+
+    /// Flush on a fixed interval in a background task
+    pub fn spawn(self, interval: Duration) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                if let Err(e) = self.flush().await {
+                    tracing::debug!(error = %e, "otlp export failed");
+                }
+            }
+        })
+    }
+}
+
+/// Encode spans as an OTLP `ExportTraceServiceRequest` in proto3 JSON
+pub fn encode(service_name: &str, spans: &[SpanData]) -> Value {
+    let spans: Vec<Value> = spans
+        .iter()
+        .map(|span| {
+            json!({
+                "traceId": format!("{:032x}", span.trace_id),
+                "spanId": format!("{:016x}", span.span_id),
+                "parentSpanId": span.parent_span_id.map(|id| format!("{:016x}", id)).unwrap_or_default(),
+                "name": span.name,
+                "kind": 1,
+                "startTimeUnixNano": unix_nanos(span.start).to_string(),
+                "endTimeUnixNano": unix_nanos(span.end).to_string(),
+                "attributes": span.attributes.iter().map(|(k, v)| attribute(k, v)).collect::<Vec<_>>(),
+                "status": { "code": if span.error { 2 } else { 1 } },
+            })
+        })
+        .collect();
+    json!({
+        "resourceSpans": [{
+            "resource": {
+                "attributes": [attribute("service.name", &AttributeValue::String(service_name.to_string()))],
+            },
+            "scopeSpans": [{
+                "scope": { "name": "stateless" },
+                "spans": spans,
+            }],
+        }],
+    })
+}
+
+fn attribute(key: &str, value: &AttributeValue) -> Value {
+    let value = match value {
+        AttributeValue::String(s) => json!({ "stringValue": s }),
+        AttributeValue::Int(i) => json!({ "intValue": i.to_string() }),
+        AttributeValue::Bool(b) => json!({ "boolValue": b }),
+        AttributeValue::Double(d) => json!({ "doubleValue": d }),
+    };
+    json!({ "key": key, "value": value })
+}
+
+fn unix_nanos(time: SystemTime) -> u128 {
+    time.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos()
+}
+
+fn random_u64() -> u64 {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+    hasher.finish()
+}
+
+async fn post(endpoint: SocketAddr, path: &str, body: &str) -> Result<()> {
+    let io = |e: std::io::Error| Error::network(None, format!("exporting to {}", endpoint), e);
+    let mut stream = TcpStream::connect(endpoint).await.map_err(io)?;
+    let request = format!(
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        path,
+        endpoint,
+        body.len(),
+        body
+    );
+    stream.write_all(request.as_bytes()).await.map_err(io)?;
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.map_err(io)?;
+
+    let status_line = String::from_utf8_lossy(&response);
+    let status = status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse::<u16>().ok());
+    match status {
+        Some(code) if (200..300).contains(&code) => Ok(()),
+        _ => Err(Error::Network {
+            layer: None,
+            reason: format!(
+                "collector at {} rejected export: {}",
+                endpoint,
+                status_line.lines().next().unwrap_or_default()
+            ),
+            source: None,
+        }),
+    }
+}
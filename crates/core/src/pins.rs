@@ -0,0 +1,57 @@
+//! Keys pinned to a layer
+
+use dashmap::DashMap;
+
+use crate::pattern::{glob_matches, specificity};
+use crate::Layer;
+
+/// Patterns whose keys stay on one layer and are never evicted
+///
+/// Shared between the `LayerCoordinator`, which keeps pinned keys on their
+/// layer, and `QuotaManager`s, which skip pinned keys when evicting.
+#[derive(Default)]
+pub struct Pins {
+    patterns: DashMap<String, Layer>,
+}
+
+impl Pins {
+    /// Create an empty pin set
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pin keys matching `pattern` to `layer`
+    pub fn pin(&self, pattern: impl Into<String>, layer: Layer) {
+        self.patterns.insert(pattern.into(), layer);
+    }
+
+    /// Remove a pin, returning the layer it pinned to
+    pub fn unpin(&self, pattern: &str) -> Option<Layer> {
+        self.patterns.remove(pattern).map(|(_, layer)| layer)
+    }
+
+    /// The layer a key is pinned to, by the most specific matching pin
+    pub fn pinned(&self, key: &str) -> Option<Layer> {
+        self.patterns
+            .iter()
+            .filter(|p| p.key() == key || glob_matches(p.key(), key))
+            .max_by_key(|p| specificity(p.key()))
+            .map(|p| *p.value())
+    }
+
+    /// Whether a key is pinned to any layer
+    pub fn is_pinned(&self, key: &str) -> bool {
+        self.pinned(key).is_some()
+    }
+
+    /// All pins, sorted by pattern
+    pub fn all(&self) -> Vec<(String, Layer)> {
+        let mut pins: Vec<_> = self
+            .patterns
+            .iter()
+            .map(|p| (p.key().clone(), *p.value()))
+            .collect();
+        pins.sort_by(|a, b| a.0.cmp(&b.0));
+        pins
+    }
+}
@@ -0,0 +1,440 @@
+//! Per-pattern memory and key-count quotas
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use dashmap::DashMap;
+
+use crate::layer::CacheLayer;
+use crate::metrics::Metrics;
+use crate::pattern::{glob_matches, specificity};
+use crate::pins::Pins;
+use crate::{Error, Layer, Result, Versioned};
+
+/// Behavior when a write would push a pattern over its quota
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverflowAction {
+    /// Evict the least recently written keys of the same pattern
+    #[default]
+    Evict,
+    /// Reject the write with `Error::QuotaExceeded`
+    Reject,
+    /// Write the entry to a lower tier instead
+    Spill,
+}
+
+/// Quota declared for a pattern, e.g. `#[max_size = "50MB"]`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuotaPolicy {
+    pub pattern: String,
+    pub max_bytes: Option<usize>,
+    pub max_keys: Option<usize>,
+    pub on_overflow: OverflowAction,
+}
+
+impl QuotaPolicy {
+    /// Create an unbounded policy for a pattern
+    pub fn new(pattern: impl Into<String>) -> Self {
+        Self {
+            pattern: pattern.into(),
+            max_bytes: None,
+            max_keys: None,
+            on_overflow: OverflowAction::default(),
+        }
+    }
+
+    /// Limit total value bytes stored under the pattern
+    pub fn max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Limit total value bytes using a manifest size string such as `"50MB"`
+    pub fn max_size(self, size: &str) -> Result<Self> {
+        Ok(self.max_bytes(parse_size(size)?))
+    }
+
+    /// Limit the number of keys stored under the pattern
+    pub fn max_keys(mut self, max_keys: usize) -> Self {
+        self.max_keys = Some(max_keys);
+        self
+    }
+
+    /// Set the overflow behavior
+    pub fn on_overflow(mut self, action: OverflowAction) -> Self {
+        self.on_overflow = action;
+        self
+    }
+}
+
+/// Parse a manifest size string (`"512"`, `"64KB"`, `"50MB"`, `"1GB"`) into bytes
+pub fn parse_size(size: &str) -> Result<usize> {
+    let size = size.trim();
+    let split = size.find(|c: char| !c.is_ascii_digit()).unwrap_or(size.len());
+    let (digits, unit) = size.split_at(split);
+    let multiplier = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "KB" => 1 << 10,
+        "MB" => 1 << 20,
+        "GB" => 1 << 30,
+        _ => return Err(Error::InvalidConfig(format!("invalid size unit in {:?}", size))),
+    };
+    digits
+        .parse::<usize>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+        .ok_or_else(|| Error::InvalidConfig(format!("invalid size {:?}", size)))
+}
+
+/// Current usage of a pattern quota
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuotaUsage {
+    pub pattern: String,
+    pub bytes: usize,
+    pub keys: usize,
+    pub max_bytes: Option<usize>,
+    pub max_keys: Option<usize>,
+}
+
+/// Outcome of admitting a write against the quotas
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Admission {
+    /// Store the entry; the listed keys were evicted to make room
+    Admit { evicted: Vec<String> },
+    /// Store the entry in the spill tier
+    Spill,
+}
+
+/// Tracked keys of one pattern, in write order
+#[derive(Default)]
+struct PatternState {
+    sizes: HashMap<String, usize>,
+    order: VecDeque<String>,
+    bytes: usize,
+}
+
+impl PatternState {
+    fn remove(&mut self, key: &str) -> Option<usize> {
+        let size = self.sizes.remove(key)?;
+        self.bytes -= size;
+        if let Some(pos) = self.order.iter().position(|k| k == key) {
+            self.order.remove(pos);
+        }
+        Some(size)
+    }
+}
+
+/// Tracks and enforces per-pattern quotas
+///
+/// A key is accounted to the most specific policy whose pattern matches it.
+/// Pinned keys are never chosen for eviction.
+pub struct QuotaManager {
+    policies: Vec<QuotaPolicy>,
+    state: DashMap<String, Mutex<PatternState>>,
+    pins: Option<Arc<Pins>>,
+}
+
+impl QuotaManager {
+    /// Create a manager for a set of policies
+    pub fn new(mut policies: Vec<QuotaPolicy>) -> Self {
+        policies.sort_by_key(|p| std::cmp::Reverse(specificity(&p.pattern)));
+        Self {
+            policies,
+            state: DashMap::new(),
+            pins: None,
+        }
+    }
+
+    /// Never evict keys pinned in `pins`
+    pub fn with_pins(mut self, pins: Arc<Pins>) -> Self {
+        self.pins = Some(pins);
+        self
+    }
+
+    /// Find the policy governing a key
+    pub fn policy_for(&self, key: &str) -> Option<&QuotaPolicy> {
+        self.policies.iter().find(|p| glob_matches(&p.pattern, key))
+    }
+
+    /// Account for a write of `size` bytes to `key`
+    pub fn admit(&self, key: &str, size: usize) -> Result<Admission> {
+        let policy = match self.policy_for(key) {
+            Some(policy) => policy,
+            None => return Ok(Admission::Admit { evicted: Vec::new() }),
+        };
+        let entry = self.state.entry(policy.pattern.clone()).or_default();
+        let mut state = entry.lock().unwrap();
+
+        let previous = state.sizes.get(key).copied();
+        let fits = |bytes: usize, keys: usize| {
+            policy.max_bytes.is_none_or(|max| bytes <= max) && policy.max_keys.is_none_or(|max| keys <= max)
+        };
+        let mut bytes = state.bytes - previous.unwrap_or(0) + size;
+        let mut keys = state.sizes.len() + usize::from(previous.is_none());
+
+        let mut evicted = Vec::new();
+        if !fits(bytes, keys) {
+            match policy.on_overflow {
+                OverflowAction::Spill => return Ok(Admission::Spill),
+                OverflowAction::Reject => return Err(self.exceeded(policy, key, size)),
+                OverflowAction::Evict => {
+                    // Pick every victim before evicting any, so a write that
+                    // cannot fit leaves the accounting untouched
+                    let candidates = state
+                        .order
+                        .iter()
+                        .filter(|k| *k != key && !self.pins.as_ref().is_some_and(|p| p.is_pinned(k)));
+                    for victim in candidates {
+                        if fits(bytes, keys) {
+                            break;
+                        }
+                        bytes -= state.sizes[victim];
+                        keys -= 1;
+                        evicted.push(victim.clone());
+                    }
+                    if !fits(bytes, keys) {
+                        return Err(self.exceeded(policy, key, size));
+                    }
+                    for victim in &evicted {
+                        state.remove(victim);
+                    }
+                }
+            }
+        }
+
+        state.remove(key);
+        state.sizes.insert(key.to_string(), size);
+        state.order.push_back(key.to_string());
+        state.bytes += size;
+        Ok(Admission::Admit { evicted })
+    }
+
+    /// Bytes currently accounted to a key
+    pub fn size_of(&self, key: &str) -> Option<usize> {
+        let policy = self.policy_for(key)?;
+        let entry = self.state.get(&policy.pattern)?;
+        let size = entry.lock().unwrap().sizes.get(key).copied();
+        size
+    }
+
+    /// Account `size` bytes to a key again, or stop accounting for it
+    ///
+    /// Undoes an admission whose write failed, given the key's size from
+    /// `size_of` beforehand. Limits are not checked, and a key that was no
+    /// longer tracked comes back as the most recently written.
+    pub fn restore(&self, key: &str, size: Option<usize>) {
+        let Some(policy) = self.policy_for(key) else { return };
+        let entry = self.state.entry(policy.pattern.clone()).or_default();
+        let mut state = entry.lock().unwrap();
+        let Some(size) = size else {
+            state.remove(key);
+            return;
+        };
+        match state.sizes.insert(key.to_string(), size) {
+            Some(previous) => state.bytes -= previous,
+            None => state.order.push_back(key.to_string()),
+        }
+        state.bytes += size;
+    }
+
+    /// Stop accounting for a key
+    pub fn release(&self, key: &str) {
+        if let Some(policy) = self.policy_for(key) {
+            if let Some(entry) = self.state.get(&policy.pattern) {
+                entry.lock().unwrap().remove(key);
+            }
+        }
+    }
+
+    /// Current usage of a pattern quota
+    pub fn usage(&self, pattern: &str) -> Option<QuotaUsage> {
+        let policy = self.policies.iter().find(|p| p.pattern == pattern)?;
+        let (bytes, keys) = self
+            .state
+            .get(pattern)
+            .map(|entry| {
+                let state = entry.lock().unwrap();
+                (state.bytes, state.sizes.len())
+            })
+            .unwrap_or_default();
+        Some(QuotaUsage {
+            pattern: policy.pattern.clone(),
+            bytes,
+            keys,
+            max_bytes: policy.max_bytes,
+            max_keys: policy.max_keys,
+        })
+    }
+
+    /// Current usage of every pattern quota
+    pub fn usage_all(&self) -> Vec<QuotaUsage> {
+        self.policies
+            .iter()
+            .filter_map(|p| self.usage(&p.pattern))
+            .collect()
+    }
+
+    fn exceeded(&self, policy: &QuotaPolicy, key: &str, size: usize) -> Error {
+        Error::QuotaExceeded {
+            key: key.to_string(),
+            pattern: policy.pattern.clone(),
+            reason: format!("{} bytes do not fit", size),
+        }
+    }
+}
+
+/// A `CacheLayer` that enforces pattern quotas on writes
+pub struct QuotaLayer<L> {
+    inner: L,
+    spill: Option<Box<dyn CacheLayer>>,
+    quotas: Arc<QuotaManager>,
+    metrics: Option<(Arc<Metrics>, Layer)>,
+}
+
+impl<L: CacheLayer> QuotaLayer<L> {
+    /// Wrap a layer with quota enforcement
+    pub fn new(inner: L, quotas: Arc<QuotaManager>) -> Self {
+        Self {
+            inner,
+            spill: None,
+            quotas,
+            metrics: None,
+        }
+    }
+
+    /// Set the lower tier used by `OverflowAction::Spill`
+    pub fn with_spill(mut self, spill: impl CacheLayer) -> Self {
+        self.spill = Some(Box::new(spill));
+        self
+    }
+
+    /// Record quota evictions against `layer` in a metrics registry
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>, layer: Layer) -> Self {
+        self.metrics = Some((metrics, layer));
+        self
+    }
+
+    /// Access the quota manager
+    pub fn quotas(&self) -> &Arc<QuotaManager> {
+        &self.quotas
+    }
+
+    /// Delete keys evicted to admit a write
+    async fn evict(&self, evicted: Vec<String>) -> Result<()> {
+        for victim in evicted {
+            self.inner.delete(&victim).await?;
+            if let Some((metrics, layer)) = &self.metrics {
+                metrics.record_eviction(&victim, *layer);
+            }
+        }
+        Ok(())
+    }
+
+    /// The spill tier for a write to `key` the quota sent there
+    fn spill_tier(&self, key: &str) -> Result<&dyn CacheLayer> {
+        self.spill.as_deref().ok_or_else(|| Error::QuotaExceeded {
+            key: key.to_string(),
+            pattern: self.quotas.policy_for(key).map(|p| p.pattern.clone()).unwrap_or_default(),
+            reason: "no spill layer is configured".to_string(),
+        })
+    }
+}
+
+#[async_trait]
+impl<L: CacheLayer> CacheLayer for QuotaLayer<L> {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        match self.inner.get(key).await? {
+            Some(value) => Ok(Some(value)),
+            None => match &self.spill {
+                Some(spill) => spill.get(key).await,
+                None => Ok(None),
+            },
+        }
+    }
+
+    async fn set(&self, key: &str, value: Vec<u8>) -> Result<()> {
+        let previous = self.quotas.size_of(key);
+        match self.quotas.admit(key, value.len())? {
+            Admission::Admit { evicted } => {
+                self.evict(evicted).await?;
+                if let Err(e) = self.inner.set(key, value).await {
+                    // The previous value is still stored, so keep accounting for it
+                    self.quotas.restore(key, previous);
+                    return Err(e);
+                }
+                if let Some(spill) = &self.spill {
+                    spill.delete(key).await?;
+                }
+                Ok(())
+            }
+            Admission::Spill => {
+                let spill = self.spill_tier(key)?;
+                self.quotas.release(key);
+                self.inner.delete(key).await?;
+                spill.set(key, value).await
+            }
+        }
+    }
+
+    async fn get_versioned(&self, key: &str) -> Result<Option<Versioned>> {
+        match self.inner.get_versioned(key).await? {
+            Some(value) => Ok(Some(value)),
+            None => match &self.spill {
+                Some(spill) => spill.get_versioned(key).await,
+                None => Ok(None),
+            },
+        }
+    }
+
+    async fn set_versioned(&self, key: &str, value: Versioned) -> Result<Versioned> {
+        let previous = self.quotas.size_of(key);
+        let size = value.value.len();
+        match self.quotas.admit(key, size)? {
+            Admission::Admit { evicted } => {
+                self.evict(evicted).await?;
+                let stored = match self.inner.set_versioned(key, value).await {
+                    Ok(stored) => stored,
+                    Err(e) => {
+                        self.quotas.restore(key, previous);
+                        return Err(e);
+                    }
+                };
+                // Resolving a conflict may have stored a different value
+                if stored.value.len() != size {
+                    self.quotas.restore(key, Some(stored.value.len()));
+                }
+                if let Some(spill) = &self.spill {
+                    spill.delete(key).await?;
+                }
+                Ok(stored)
+            }
+            Admission::Spill => {
+                let spill = self.spill_tier(key)?;
+                self.quotas.release(key);
+                self.inner.delete(key).await?;
+                spill.set_versioned(key, value).await
+            }
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        self.quotas.release(key);
+        self.inner.delete(key).await?;
+        if let Some(spill) = &self.spill {
+            spill.delete(key).await?;
+        }
+        Ok(())
+    }
+    async fn keys(&self, pattern: &str) -> Result<Vec<String>> {
+        let mut keys = self.inner.keys(pattern).await?;
+        if let Some(spill) = &self.spill {
+            for key in spill.keys(pattern).await? {
+                if !keys.contains(&key) {
+                    keys.push(key);
+                }
+            }
+        }
+        Ok(keys)
+    }
+}
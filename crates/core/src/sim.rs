@@ -0,0 +1,358 @@
+//! In-process simulated layers with injectable network faults
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use dashmap::{DashMap, DashSet};
+
+use crate::layer::CacheLayer;
+use crate::pattern::glob_matches;
+use crate::simulation::SimClock;
+use crate::{Error, Layer, Result};
+
+/// Seeded xorshift generator, so simulated faults are reproducible
+pub(crate) struct SimRng(u64);
+
+impl SimRng {
+    pub(crate) fn new(seed: u64) -> Self {
+        // xorshift must not start from zero
+        Self(seed.max(1))
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Uniform in `[0, 1)`
+    pub(crate) fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Latency and loss on a link between two layers
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct LinkConditions {
+    pub latency: Duration,
+    /// Extra delay, uniformly distributed up to this much
+    pub jitter: Duration,
+    /// Probability in `[0, 1]` that a request is lost
+    pub loss: f64,
+}
+
+impl LinkConditions {
+    /// A perfect link
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Delay every request by `latency`
+    pub fn latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    /// Add up to `jitter` of random delay
+    pub fn jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Lose requests with probability `loss`
+    pub fn loss(mut self, loss: f64) -> Self {
+        self.loss = loss.clamp(0.0, 1.0);
+        self
+    }
+}
+
+/// A simulated network between client, edge and server layers
+///
+/// Each layer's data lives in the network, and `SimLayer`s reach it over a
+/// link from another layer. Links can be slowed down, made lossy or
+/// partitioned, and layers can crash, losing their data. Randomness comes
+/// from a seeded generator, so runs with the same seed drop the same
+/// requests.
+pub struct SimNetwork {
+    local: Layer,
+    default_link: LinkConditions,
+    links: DashMap<(Layer, Layer), LinkConditions>,
+    partitions: DashSet<(Layer, Layer)>,
+    offline: DashSet<Layer>,
+    crashed: DashSet<Layer>,
+    stores: DashMap<Layer, Arc<DashMap<String, Vec<u8>>>>,
+    rng: Mutex<SimRng>,
+    clock: Option<SimClock>,
+    delivered: AtomicU64,
+    dropped: AtomicU64,
+}
+
+impl SimNetwork {
+    /// Create a perfect network, reached from the server
+    pub fn new() -> Self {
+        Self {
+            local: Layer::Server,
+            default_link: LinkConditions::default(),
+            links: DashMap::new(),
+            partitions: DashSet::new(),
+            offline: DashSet::new(),
+            crashed: DashSet::new(),
+            stores: DashMap::new(),
+            rng: Mutex::new(SimRng::new(0x2545_f491_4f6c_dd1d)),
+            clock: None,
+            delivered: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    /// The layer `layer()` handles are reached from
+    pub fn with_local(mut self, local: Layer) -> Self {
+        self.local = local;
+        self
+    }
+
+    /// Seed fault injection
+    pub fn with_seed(self, seed: u64) -> Self {
+        *self.rng.lock().unwrap() = SimRng::new(seed);
+        self
+    }
+
+    /// Delay requests on a virtual clock instead of tokio's timer
+    pub fn with_clock(mut self, clock: SimClock) -> Self {
+        self.clock = Some(clock);
+        self
+    }
+
+    /// Conditions for links without their own
+    pub fn with_default_link(mut self, conditions: LinkConditions) -> Self {
+        self.default_link = conditions;
+        self
+    }
+
+    /// Conditions for the link between `a` and `b`
+    pub fn with_link(self, a: Layer, b: Layer, conditions: LinkConditions) -> Self {
+        self.set_link(a, b, conditions);
+        self
+    }
+
+    /// Change the conditions between `a` and `b`, in both directions
+    pub fn set_link(&self, a: Layer, b: Layer, conditions: LinkConditions) {
+        self.links.insert((a, b), conditions);
+        self.links.insert((b, a), conditions);
+    }
+
+    /// Handle to `layer`, reached from the local layer
+    pub fn layer(self: &Arc<Self>, layer: Layer) -> SimLayer {
+        self.link(self.local, layer)
+    }
+
+    /// Handle to `to`, reached over the link from `from`
+    pub fn link(self: &Arc<Self>, from: Layer, to: Layer) -> SimLayer {
+        SimLayer {
+            network: self.clone(),
+            from,
+            to,
+            store: self.store(to),
+        }
+    }
+
+    fn store(&self, layer: Layer) -> Arc<DashMap<String, Vec<u8>>> {
+        self.stores.entry(layer).or_default().clone()
+    }
+
+    /// Cut the link between `a` and `b`
+    pub fn partition(&self, a: Layer, b: Layer) {
+        self.partitions.insert((a, b));
+        self.partitions.insert((b, a));
+    }
+
+    /// Restore the link between `a` and `b`
+    pub fn heal(&self, a: Layer, b: Layer) {
+        self.partitions.remove(&(a, b));
+        self.partitions.remove(&(b, a));
+    }
+
+    /// Cut `layer` off from every other layer
+    pub fn simulate_offline(&self, layer: Layer) {
+        self.offline.insert(layer);
+    }
+
+    /// Reconnect a layer cut off by `simulate_offline`
+    pub fn simulate_online(&self, layer: Layer) {
+        self.offline.remove(&layer);
+    }
+
+    /// Restore every link
+    pub fn heal_all(&self) {
+        self.partitions.clear();
+        self.offline.clear();
+    }
+
+    /// Stop a layer and lose its data
+    pub fn crash(&self, layer: Layer) {
+        self.crashed.insert(layer);
+        self.store(layer).clear();
+    }
+
+    /// Bring a crashed layer back, empty
+    pub fn restart(&self, layer: Layer) {
+        self.crashed.remove(&layer);
+    }
+
+    /// Whether a layer is running
+    pub fn is_up(&self, layer: Layer) -> bool {
+        !self.crashed.contains(&layer)
+    }
+
+    /// Whether requests can flow between two layers
+    pub fn is_reachable(&self, from: Layer, to: Layer) -> bool {
+        from == to
+            || !(self.partitions.contains(&(from, to))
+                || self.offline.contains(&from)
+                || self.offline.contains(&to))
+    }
+
+    /// Requests that reached their layer
+    pub fn delivered(&self) -> u64 {
+        self.delivered.load(Ordering::Relaxed)
+    }
+
+    /// Requests lost to partitions, crashes or packet loss
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    fn random(&self) -> f64 {
+        self.rng.lock().unwrap().next_f64()
+    }
+
+    /// Carry one request from `from` to `to`, applying link conditions
+    async fn transmit(&self, from: Layer, to: Layer) -> Result<()> {
+        let fail = |reason: String| {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            Err(Error::Network {
+                layer: Some(to),
+                reason,
+                source: None,
+            })
+        };
+        if !self.is_up(to) {
+            return fail(format!("{} layer is down", to));
+        }
+        if !self.is_reachable(from, to) {
+            return fail(format!("{} is partitioned from {}", to, from));
+        }
+        let link = if from == to {
+            LinkConditions::default()
+        } else {
+            self.links
+                .get(&(from, to))
+                .map(|l| *l)
+                .unwrap_or(self.default_link)
+        };
+        let delay = link.latency + link.jitter.mul_f64(self.random());
+        match &self.clock {
+            // Every request is a scheduling point in a simulation, even without latency
+            Some(clock) => clock.sleep(delay).await,
+            None if !delay.is_zero() => tokio::time::sleep(delay).await,
+            None => {}
+        }
+        if link.loss > 0.0 && self.random() < link.loss {
+            return fail(format!("request from {} to {} was lost", from, to));
+        }
+        self.delivered.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+impl Default for SimNetwork {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A layer of a `SimNetwork`, as seen from another layer
+///
+/// Faults surface as retryable `Error::Network`s, the way a real remote
+/// layer reports them.
+#[derive(Clone)]
+pub struct SimLayer {
+    network: Arc<SimNetwork>,
+    from: Layer,
+    to: Layer,
+    store: Arc<DashMap<String, Vec<u8>>>,
+}
+
+impl SimLayer {
+    /// The layer holding the data
+    pub fn layer(&self) -> Layer {
+        self.to
+    }
+
+    /// The network this layer belongs to
+    pub fn network(&self) -> &Arc<SimNetwork> {
+        &self.network
+    }
+
+    /// Number of entries held, read without going over the network
+    pub fn len(&self) -> usize {
+        self.store.len()
+    }
+
+    /// Whether the layer holds no entries
+    pub fn is_empty(&self) -> bool {
+        self.store.is_empty()
+    }
+
+    /// Read an entry without going over the network
+    pub fn peek(&self, key: &str) -> Option<Vec<u8>> {
+        self.store.get(key).map(|v| v.clone())
+    }
+
+    /// Every entry held, sorted by key, read without going over the network
+    pub fn entries(&self) -> Vec<(String, Vec<u8>)> {
+        let mut entries: Vec<_> = self
+            .store
+            .iter()
+            .map(|e| (e.key().clone(), e.value().clone()))
+            .collect();
+        entries.sort();
+        entries
+    }
+}
+
+#[async_trait]
+impl CacheLayer for SimLayer {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        self.network.transmit(self.from, self.to).await?;
+        Ok(self.store.get(key).map(|v| v.clone()))
+    }
+
+    async fn set(&self, key: &str, value: Vec<u8>) -> Result<()> {
+        self.network.transmit(self.from, self.to).await?;
+        self.store.insert(key.to_string(), value);
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        self.network.transmit(self.from, self.to).await?;
+        self.store.remove(key);
+        Ok(())
+    }
+
+    async fn keys(&self, pattern: &str) -> Result<Vec<String>> {
+        self.network.transmit(self.from, self.to).await?;
+        // Sorted, so a replayed seed walks keys in the same order
+        let mut keys: Vec<_> = self
+            .store
+            .iter()
+            .filter(|e| e.key() == pattern || glob_matches(pattern, e.key()))
+            .map(|e| e.key().clone())
+            .collect();
+        keys.sort();
+        Ok(keys)
+    }
+}
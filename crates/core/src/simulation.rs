@@ -0,0 +1,438 @@
+//! Deterministic simulation of a whole topology on one thread
+//!
+//! A `Simulation` runs client, edge and server layers over a `SimNetwork`
+//! with a seeded scheduler and a virtual clock. Every network request is a
+//! scheduling point, and the scheduler picks which ready task runs next from
+//! the seed, so a failing seed replays the exact same interleaving.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+use std::time::Duration;
+
+use crate::sim::SimRng;
+use crate::{InvalidationBus, Layer, LayerCoordinator, OwnershipGraph, Result, SimNetwork};
+
+/// Environment variable that replays a single seed in `Simulation::explore`
+pub const SEED_VAR: &str = "STATELESS_SIM_SEED";
+
+const DEFAULT_MAX_STEPS: usize = 100_000;
+
+/// Virtual time, advanced by the scheduler when every task is waiting
+#[derive(Clone, Default)]
+pub struct SimClock {
+    state: Arc<Mutex<ClockState>>,
+}
+
+#[derive(Default)]
+struct ClockState {
+    now: Duration,
+    timers: BTreeMap<(Duration, u64), Waker>,
+    next_id: u64,
+}
+
+impl SimClock {
+    /// Create a clock at zero
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Virtual time since the simulation started
+    pub fn now(&self) -> Duration {
+        self.state.lock().unwrap().now
+    }
+
+    /// Wait for `duration` of virtual time
+    ///
+    /// A zero duration yields to the scheduler once.
+    pub fn sleep(&self, duration: Duration) -> Sleep {
+        Sleep {
+            clock: self.clone(),
+            duration,
+            timer: None,
+            yielded: false,
+        }
+    }
+
+    /// Jump to the next timer and wake every sleeper due by then
+    ///
+    /// Returns false if nothing is sleeping.
+    pub fn advance(&self) -> bool {
+        let due = {
+            let mut state = self.state.lock().unwrap();
+            let Some(&(deadline, _)) = state.timers.keys().next() else {
+                return false;
+            };
+            state.now = state.now.max(deadline);
+            let later = state.timers.split_off(&(deadline, u64::MAX));
+            std::mem::replace(&mut state.timers, later)
+        };
+        for waker in due.into_values() {
+            waker.wake();
+        }
+        true
+    }
+}
+
+/// Future returned by `SimClock::sleep`
+pub struct Sleep {
+    clock: SimClock,
+    duration: Duration,
+    timer: Option<(Duration, u64)>,
+    yielded: bool,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.duration.is_zero() {
+            if self.yielded {
+                return Poll::Ready(());
+            }
+            self.yielded = true;
+            cx.waker().wake_by_ref();
+            return Poll::Pending;
+        }
+
+        let clock = self.clock.clone();
+        let mut state = clock.state.lock().unwrap();
+        let timer = match self.timer {
+            Some(timer) if state.now >= timer.0 => {
+                state.timers.remove(&timer);
+                self.timer = None;
+                return Poll::Ready(());
+            }
+            Some(timer) => timer,
+            None => {
+                let timer = (state.now + self.duration, state.next_id);
+                state.next_id += 1;
+                self.timer = Some(timer);
+                timer
+            }
+        };
+        state.timers.insert(timer, cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        if let Some(timer) = self.timer {
+            self.clock.state.lock().unwrap().timers.remove(&timer);
+        }
+    }
+}
+
+type Task = Pin<Box<dyn Future<Output = Result<()>>>>;
+type Check = Box<dyn Fn() -> std::result::Result<(), String>>;
+
+struct TaskWaker {
+    id: usize,
+    ready: Arc<Mutex<BTreeSet<usize>>>,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.ready.lock().unwrap().insert(self.id);
+    }
+}
+
+/// Outcome of a successful run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimReport {
+    pub seed: u64,
+    /// Task polls so far
+    pub steps: usize,
+    /// Virtual time so far
+    pub elapsed: Duration,
+}
+
+/// A failed run, with the seed that reproduces it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimFailure {
+    pub seed: u64,
+    pub step: usize,
+    pub elapsed: Duration,
+    pub reason: String,
+}
+
+impl fmt::Display for SimFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "simulation failed at step {} ({:?} virtual): {}; replay with {}={}",
+            self.step, self.elapsed, self.reason, SEED_VAR, self.seed
+        )
+    }
+}
+
+impl std::error::Error for SimFailure {}
+
+/// A seeded, single-threaded run of a client/edge/server topology
+///
+/// Spawn tasks that drive the topology, register invariants, then `run`.
+/// Invariants are checked after every step and end checks once all tasks
+/// have finished. Call `run` from a plain `#[test]`, not from inside a
+/// tokio runtime.
+pub struct Simulation {
+    seed: u64,
+    rng: SimRng,
+    clock: SimClock,
+    network: Arc<SimNetwork>,
+    tasks: Vec<Option<(String, Task)>>,
+    ready: Arc<Mutex<BTreeSet<usize>>>,
+    invariants: Vec<(String, Check)>,
+    end_checks: Vec<(String, Check)>,
+    max_steps: usize,
+    steps: usize,
+}
+
+impl Simulation {
+    /// Create a simulation whose scheduling and faults follow `seed`
+    pub fn new(seed: u64) -> Self {
+        let clock = SimClock::new();
+        let network = SimNetwork::new()
+            .with_seed(seed.rotate_left(32) ^ 0x9e37_79b9_7f4a_7c15)
+            .with_clock(clock.clone());
+        Self {
+            seed,
+            rng: SimRng::new(seed),
+            clock,
+            network: Arc::new(network),
+            tasks: Vec::new(),
+            ready: Arc::new(Mutex::new(BTreeSet::new())),
+            invariants: Vec::new(),
+            end_checks: Vec::new(),
+            max_steps: DEFAULT_MAX_STEPS,
+            steps: 0,
+        }
+    }
+
+    /// Fail runs that take more than `max_steps` task polls
+    pub fn with_max_steps(mut self, max_steps: usize) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+
+    /// The seed this simulation replays
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// The virtual clock
+    pub fn clock(&self) -> &SimClock {
+        &self.clock
+    }
+
+    /// The simulated network, for injecting faults
+    pub fn network(&self) -> &Arc<SimNetwork> {
+        &self.network
+    }
+
+    /// A coordinator over the client, edge and server layers of the network
+    pub fn coordinator(&self, ownership: Arc<OwnershipGraph>) -> LayerCoordinator {
+        [Layer::Client, Layer::Edge, Layer::Server]
+            .into_iter()
+            .fold(LayerCoordinator::new(ownership), |coordinator, layer| {
+                coordinator.with_layer(layer, Arc::new(self.network.layer(layer)))
+            })
+    }
+
+    /// Add a task; an error or panic fails the run
+    pub fn spawn(&mut self, name: impl Into<String>, task: impl Future<Output = Result<()>> + 'static) {
+        let id = self.tasks.len();
+        self.tasks.push(Some((name.into(), Box::pin(task))));
+        self.ready.lock().unwrap().insert(id);
+    }
+
+    /// Check a condition after every step
+    pub fn invariant(
+        &mut self,
+        name: impl Into<String>,
+        check: impl Fn() -> std::result::Result<(), String> + 'static,
+    ) {
+        self.invariants.push((name.into(), Box::new(check)));
+    }
+
+    /// Check a condition once every task has finished
+    pub fn eventually(
+        &mut self,
+        name: impl Into<String>,
+        check: impl Fn() -> std::result::Result<(), String> + 'static,
+    ) {
+        self.end_checks.push((name.into(), Box::new(check)));
+    }
+
+    /// Invalidations are sequenced and layers acknowledge them in order
+    pub fn check_invalidation_order(&mut self, bus: Arc<InvalidationBus>) {
+        let seen = Mutex::new((0u64, HashMap::<Layer, u64>::new()));
+        self.invariant("invalidation order", move || {
+            let mut seen = seen.lock().unwrap();
+            let latest = bus.latest();
+            if latest < seen.0 {
+                return Err(format!("latest sequence went back from {} to {}", seen.0, latest));
+            }
+            seen.0 = latest;
+
+            let retained = bus.retained_seqs();
+            if retained.windows(2).any(|w| w[1] != w[0] + 1) {
+                return Err(format!("retained log has gaps or reordering: {:?}", retained));
+            }
+            for layer in bus.subscribers() {
+                let acked = bus.acked(layer).unwrap_or(0);
+                if acked > latest {
+                    return Err(format!("{} acked {} beyond latest {}", layer, acked, latest));
+                }
+                let previous = seen.1.insert(layer, acked).unwrap_or(0);
+                if acked < previous {
+                    return Err(format!("{} ack went back from {} to {}", layer, previous, acked));
+                }
+            }
+            Ok(())
+        });
+    }
+
+    /// Once quiet, every connected layer's copy of an owned key matches the owner's
+    pub fn check_convergence(&mut self, coordinator: Arc<LayerCoordinator>) {
+        let network = self.network.clone();
+        self.eventually("ownership convergence", move || {
+            let graph = coordinator.ownership();
+            for layer in coordinator.layers() {
+                if !coordinator.bus().is_connected(layer) || !network.is_up(layer) {
+                    continue;
+                }
+                for (key, value) in network.layer(layer).entries() {
+                    let Some(owner) = graph.owner_of(&key) else { continue };
+                    if owner.layer() == layer || !network.is_up(owner.layer()) {
+                        continue;
+                    }
+                    let expected = network.layer(owner.layer()).peek(&key);
+                    if expected.as_ref() != Some(&value) {
+                        return Err(format!(
+                            "{} holds {} = {:?} but its owner on {} holds {:?}",
+                            layer,
+                            key,
+                            String::from_utf8_lossy(&value),
+                            owner.layer(),
+                            expected.map(|v| String::from_utf8_lossy(&v).into_owned())
+                        ));
+                    }
+                }
+            }
+            Ok(())
+        });
+    }
+
+    fn fail(&self, reason: String) -> SimFailure {
+        SimFailure {
+            seed: self.seed,
+            step: self.steps,
+            elapsed: self.clock.now(),
+            reason,
+        }
+    }
+
+    fn check(&self, checks: &[(String, Check)]) -> std::result::Result<(), SimFailure> {
+        for (name, check) in checks {
+            if let Err(reason) = check() {
+                return Err(self.fail(format!("{} violated: {}", name, reason)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Run until every spawned task finishes
+    ///
+    /// Can be called again after spawning more tasks, e.g. to heal the
+    /// network and catch layers up before the end checks.
+    pub fn run(&mut self) -> std::result::Result<SimReport, SimFailure> {
+        // Layers may use tokio timers and primitives, which need a runtime
+        // context even though this scheduler polls every task itself
+        let runtime = match tokio::runtime::Handle::try_current() {
+            Ok(_) => None,
+            Err(_) => Some(
+                tokio::runtime::Builder::new_current_thread()
+                    .enable_time()
+                    .build()
+                    .map_err(|e| self.fail(format!("cannot start runtime: {}", e)))?,
+            ),
+        };
+        let _context = runtime.as_ref().map(|r| r.enter());
+
+        while self.tasks.iter().any(Option::is_some) {
+            let next = {
+                let ready = self.ready.lock().unwrap();
+                match ready.len() {
+                    0 => None,
+                    n => ready.iter().nth((self.rng.next_u64() % n as u64) as usize).copied(),
+                }
+            };
+            let Some(id) = next else {
+                if self.clock.advance() {
+                    continue;
+                }
+                let blocked: Vec<_> = self.tasks.iter().flatten().map(|(name, _)| name.as_str()).collect();
+                return Err(self.fail(format!("deadlock, blocked tasks: {}", blocked.join(", "))));
+            };
+            self.ready.lock().unwrap().remove(&id);
+            self.steps += 1;
+            if self.steps > self.max_steps {
+                return Err(self.fail(format!("exceeded {} steps", self.max_steps)));
+            }
+            let Some((name, task)) = self.tasks[id].as_mut() else { continue };
+            let waker = Waker::from(Arc::new(TaskWaker {
+                id,
+                ready: self.ready.clone(),
+            }));
+            let mut cx = Context::from_waker(&waker);
+            match panic::catch_unwind(AssertUnwindSafe(|| task.as_mut().poll(&mut cx))) {
+                Ok(Poll::Pending) => {}
+                Ok(Poll::Ready(Ok(()))) => self.tasks[id] = None,
+                Ok(Poll::Ready(Err(e))) => {
+                    let reason = format!("task {} failed: {}", name, e);
+                    return Err(self.fail(reason));
+                }
+                Err(panic) => {
+                    let message = panic
+                        .downcast_ref::<&str>()
+                        .map(|s| s.to_string())
+                        .or_else(|| panic.downcast_ref::<String>().cloned())
+                        .unwrap_or_default();
+                    let reason = format!("task {} panicked: {}", name, message);
+                    return Err(self.fail(reason));
+                }
+            }
+            self.check(&self.invariants)?;
+        }
+        self.tasks.clear();
+        self.check(&self.end_checks)?;
+        Ok(SimReport {
+            seed: self.seed,
+            steps: self.steps,
+            elapsed: self.clock.now(),
+        })
+    }
+
+    /// Run a scenario for every seed, stopping at the first failure
+    ///
+    /// Setting `STATELESS_SIM_SEED` runs only that seed, to replay a failure.
+    pub fn explore(
+        seeds: impl IntoIterator<Item = u64>,
+        scenario: impl Fn(&mut Simulation) -> std::result::Result<SimReport, SimFailure>,
+    ) -> std::result::Result<Vec<SimReport>, SimFailure> {
+        let replay = std::env::var(SEED_VAR).ok().and_then(|s| s.parse().ok());
+        let seeds: Vec<u64> = match replay {
+            Some(seed) => vec![seed],
+            None => seeds.into_iter().collect(),
+        };
+        seeds
+            .into_iter()
+            .map(|seed| scenario(&mut Simulation::new(seed)))
+            .collect()
+    }
+}
@@ -0,0 +1,189 @@
+//! Built-in cache strategies
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+
+use crate::strategy::{CacheStrategy, Consistency};
+use crate::{Layer, OwnershipGraph, Result};
+
+/// The pattern followed by everything its manifest says it invalidates
+fn with_dependents(ownership: Option<&OwnershipGraph>, pattern: &str) -> Vec<String> {
+    let mut patterns = vec![pattern.to_string()];
+    if let Some(graph) = ownership {
+        patterns.extend(graph.invalidation_targets(pattern));
+    }
+    patterns
+}
+
+/// Keep data in client storage so it stays available offline
+///
+/// Writes land on the client and reach upstream as the client replays its
+/// outbox, so reads are only eventually consistent with other clients.
+#[derive(Clone)]
+pub struct ClientFirst {
+    ownership: Option<Arc<OwnershipGraph>>,
+}
+
+impl ClientFirst {
+    /// Create the strategy
+    pub fn new() -> Self {
+        Self { ownership: None }
+    }
+
+    /// Follow the manifest's invalidation edges when invalidating
+    pub fn with_ownership(mut self, ownership: Arc<OwnershipGraph>) -> Self {
+        self.ownership = Some(ownership);
+        self
+    }
+}
+
+impl Default for ClientFirst {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl CacheStrategy for ClientFirst {
+    async fn determine_location(&self, _key: &str) -> Result<Layer> {
+        Ok(Layer::Client)
+    }
+
+    async fn handle_invalidation(&self, pattern: &str) -> Result<Vec<String>> {
+        Ok(with_dependents(self.ownership.as_deref(), pattern))
+    }
+
+    fn name(&self) -> &str {
+        "ClientFirst"
+    }
+
+    fn offline_capable(&self) -> bool {
+        true
+    }
+}
+
+/// Serve data from the edge and let it expire instead of purging it
+///
+/// Entries live for a fixed TTL, so invalidations are not propagated and a
+/// region may serve stale data for at most that long.
+#[derive(Clone)]
+pub struct EdgeOptimized {
+    ttl: Duration,
+}
+
+impl EdgeOptimized {
+    /// Create the strategy with a 60 second TTL
+    pub fn new() -> Self {
+        Self {
+            ttl: Duration::from_secs(60),
+        }
+    }
+
+    /// Bound on how long the edge may serve an entry
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+}
+
+impl Default for EdgeOptimized {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl CacheStrategy for EdgeOptimized {
+    async fn determine_location(&self, _key: &str) -> Result<Layer> {
+        Ok(Layer::Edge)
+    }
+
+    async fn handle_invalidation(&self, _pattern: &str) -> Result<Vec<String>> {
+        Ok(Vec::new())
+    }
+
+    fn name(&self) -> &str {
+        "EdgeOptimized"
+    }
+
+    fn consistency(&self) -> Consistency {
+        Consistency::Regional
+    }
+
+    fn ttl(&self, _key: &str) -> Option<Duration> {
+        Some(self.ttl)
+    }
+}
+
+/// Keep the server authoritative and invalidate everywhere on write
+///
+/// Every read observes the latest write, and invalidations reach every
+/// pattern that depends on the written one.
+#[derive(Clone, Default)]
+pub struct GlobalConsistent {
+    ownership: Option<Arc<OwnershipGraph>>,
+}
+
+impl GlobalConsistent {
+    /// Create the strategy
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Follow the manifest's invalidation edges when invalidating
+    pub fn with_ownership(mut self, ownership: Arc<OwnershipGraph>) -> Self {
+        self.ownership = Some(ownership);
+        self
+    }
+}
+
+#[async_trait]
+impl CacheStrategy for GlobalConsistent {
+    async fn determine_location(&self, _key: &str) -> Result<Layer> {
+        Ok(Layer::Server)
+    }
+
+    async fn handle_invalidation(&self, pattern: &str) -> Result<Vec<String>> {
+        Ok(with_dependents(self.ownership.as_deref(), pattern))
+    }
+
+    fn name(&self) -> &str {
+        "GlobalConsistent"
+    }
+
+    fn consistency(&self) -> Consistency {
+        Consistency::Strong
+    }
+}
+
+/// Plain Redis semantics: one server, no dependency tracking
+#[derive(Clone, Default)]
+pub struct RedisCompatible;
+
+impl RedisCompatible {
+    /// Create the strategy
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+#[async_trait]
+impl CacheStrategy for RedisCompatible {
+    async fn determine_location(&self, _key: &str) -> Result<Layer> {
+        Ok(Layer::Server)
+    }
+
+    async fn handle_invalidation(&self, pattern: &str) -> Result<Vec<String>> {
+        Ok(vec![pattern.to_string()])
+    }
+
+    fn name(&self) -> &str {
+        "RedisCompatible"
+    }
+
+    fn consistency(&self) -> Consistency {
+        Consistency::Strong
+    }
+}
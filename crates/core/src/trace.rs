@@ -0,0 +1,332 @@
+//! Tracing spans for cache operations and on-demand pattern access logs
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime};
+
+use async_trait::async_trait;
+use tracing::field::Empty;
+use tracing::{Instrument, Span};
+
+use crate::cache::{Cache, CacheEntry};
+use crate::layer::CacheLayer;
+use crate::pattern::glob_matches;
+use crate::{Layer, OwnershipGraph, Result, Versioned};
+
+macro_rules! op_span {
+    ($name:literal, $key:expr) => {
+        tracing::info_span!($name, key = $key, pattern = Empty, owner = Empty, layer = Empty)
+    };
+}
+
+/// One sampled access captured by `Tracer::trace_pattern`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccessRecord {
+    pub key: String,
+    pub op: &'static str,
+    pub layer: Option<Layer>,
+    pub owner: Option<String>,
+    /// `Some(hit)` for reads
+    pub hit: Option<bool>,
+    pub ok: bool,
+    pub latency: Duration,
+    pub at: SystemTime,
+}
+
+/// Sampling and retention for a pattern trace
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceConfig {
+    /// Capture one of every `sample_every` matching accesses
+    pub sample_every: u64,
+    /// Maximum records kept; older records are dropped first
+    pub capacity: usize,
+}
+
+impl Default for TraceConfig {
+    fn default() -> Self {
+        Self {
+            sample_every: 1,
+            capacity: 1024,
+        }
+    }
+}
+
+struct TraceBuffer {
+    pattern: String,
+    config: TraceConfig,
+    seen: AtomicU64,
+    records: Mutex<VecDeque<AccessRecord>>,
+}
+
+/// Handle to an active pattern trace; tracing stops when it is dropped
+pub struct PatternTrace {
+    buffer: Arc<TraceBuffer>,
+    tracer: Arc<Tracer>,
+}
+
+impl PatternTrace {
+    /// The traced pattern
+    pub fn pattern(&self) -> &str {
+        &self.buffer.pattern
+    }
+
+    /// Matching accesses seen so far, including unsampled ones
+    pub fn seen(&self) -> u64 {
+        self.buffer.seen.load(Ordering::Relaxed)
+    }
+
+    /// Captured records, oldest first
+    pub fn records(&self) -> Vec<AccessRecord> {
+        self.buffer.records.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Stop tracing and return the captured records
+    pub fn stop(self) -> Vec<AccessRecord> {
+        self.records()
+    }
+}
+
+impl Drop for PatternTrace {
+    fn drop(&mut self) {
+        self.tracer
+            .active
+            .write()
+            .unwrap()
+            .retain(|b| !Arc::ptr_eq(b, &self.buffer));
+    }
+}
+
+/// Registry of active pattern traces
+#[derive(Default)]
+pub struct Tracer {
+    active: RwLock<Vec<Arc<TraceBuffer>>>,
+}
+
+impl Tracer {
+    /// Create a tracer with no active traces
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    /// Start capturing every access to keys matching `pattern`
+    pub fn trace_pattern(self: &Arc<Self>, pattern: &str) -> PatternTrace {
+        self.trace_pattern_with(pattern, TraceConfig::default())
+    }
+
+    /// Start capturing a sampled access log for `pattern`
+    pub fn trace_pattern_with(self: &Arc<Self>, pattern: &str, config: TraceConfig) -> PatternTrace {
+        let buffer = Arc::new(TraceBuffer {
+            pattern: pattern.to_string(),
+            config: TraceConfig {
+                sample_every: config.sample_every.max(1),
+                capacity: config.capacity,
+            },
+            seen: AtomicU64::new(0),
+            records: Mutex::new(VecDeque::new()),
+        });
+        self.active.write().unwrap().push(buffer.clone());
+        PatternTrace {
+            buffer,
+            tracer: self.clone(),
+        }
+    }
+
+    /// Patterns with an active trace, in the order tracing started
+    pub fn patterns(&self) -> Vec<String> {
+        self.active.read().unwrap().iter().map(|b| b.pattern.clone()).collect()
+    }
+
+    fn is_active(&self) -> bool {
+        !self.active.read().unwrap().is_empty()
+    }
+
+    fn record(&self, record: AccessRecord) {
+        for buffer in self.active.read().unwrap().iter() {
+            if !glob_matches(&buffer.pattern, &record.key) {
+                continue;
+            }
+            let seen = buffer.seen.fetch_add(1, Ordering::Relaxed);
+            if seen % buffer.config.sample_every != 0 {
+                continue;
+            }
+            let mut records = buffer.records.lock().unwrap();
+            if records.len() == buffer.config.capacity {
+                records.pop_front();
+            }
+            if buffer.config.capacity > 0 {
+                records.push_back(record.clone());
+            }
+        }
+    }
+}
+
+/// Wraps a `Cache` or `CacheLayer` with a span per operation
+///
+/// Spans are named `cache.<op>` and carry the key, the matched manifest
+/// pattern, its owner and the layer.
+pub struct Traced<T> {
+    inner: T,
+    layer: Option<Layer>,
+    ownership: Option<Arc<OwnershipGraph>>,
+    tracer: Option<Arc<Tracer>>,
+}
+
+impl<T> Traced<T> {
+    /// Trace a top-level cache
+    pub fn cache(inner: T) -> Self {
+        Self {
+            inner,
+            layer: None,
+            ownership: None,
+            tracer: None,
+        }
+    }
+
+    /// Trace a single cache layer
+    pub fn layer(inner: T, layer: Layer) -> Self {
+        Self {
+            layer: Some(layer),
+            ..Self::cache(inner)
+        }
+    }
+
+    /// Resolve patterns and owners from an ownership graph
+    pub fn with_ownership(mut self, ownership: Arc<OwnershipGraph>) -> Self {
+        self.ownership = Some(ownership);
+        self
+    }
+
+    /// Feed accesses to a tracer for `trace_pattern`
+    pub fn with_tracer(mut self, tracer: Arc<Tracer>) -> Self {
+        self.tracer = Some(tracer);
+        self
+    }
+
+    /// Access the wrapped value
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    fn owner(&self, key: &str) -> Option<Arc<crate::Ownership>> {
+        self.ownership.as_ref().and_then(|graph| graph.owner_of(key))
+    }
+
+    fn annotate(&self, span: &Span, owner: Option<&crate::Ownership>) {
+        if let Some(ownership) = owner {
+            span.record("pattern", ownership.pattern());
+            span.record("owner", ownership.owner());
+        }
+        if let Some(layer) = self.layer {
+            span.record("layer", layer.as_str());
+        }
+    }
+
+    async fn run<F, R>(
+        &self,
+        span: Span,
+        op: &'static str,
+        key: &str,
+        hit: fn(&R) -> Option<bool>,
+        fut: F,
+    ) -> Result<R>
+    where
+        F: std::future::Future<Output = Result<R>> + Send,
+        R: Send,
+    {
+        let owner = self.owner(key);
+        self.annotate(&span, owner.as_deref());
+        let start = Instant::now();
+        let result = fut.instrument(span.clone()).await;
+        let latency = start.elapsed();
+
+        span.in_scope(|| match &result {
+            Ok(_) => tracing::debug!(latency_us = latency.as_micros() as u64, "ok"),
+            Err(e) => tracing::warn!(error = %e, "failed"),
+        });
+        if let Some(tracer) = self.tracer.as_ref().filter(|t| t.is_active()) {
+            tracer.record(AccessRecord {
+                key: key.to_string(),
+                op,
+                layer: self.layer,
+                owner: owner.map(|o| o.owner().to_string()),
+                hit: result.as_ref().ok().and_then(hit),
+                ok: result.is_ok(),
+                latency,
+                at: SystemTime::now(),
+            });
+        }
+        result
+    }
+}
+
+fn no_hit<R>(_: &R) -> Option<bool> {
+    None
+}
+
+#[async_trait]
+impl<L: CacheLayer> CacheLayer for Traced<L> {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let span = op_span!("cache.get", key);
+        self.run(span, "get", key, |v: &Option<Vec<u8>>| Some(v.is_some()), self.inner.get(key))
+            .await
+    }
+
+    async fn set(&self, key: &str, value: Vec<u8>) -> Result<()> {
+        let span = op_span!("cache.set", key);
+        self.run(span, "set", key, no_hit, self.inner.set(key, value)).await
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        let span = op_span!("cache.delete", key);
+        self.run(span, "delete", key, no_hit, self.inner.delete(key)).await
+    }
+
+    async fn keys(&self, pattern: &str) -> Result<Vec<String>> {
+        let span = tracing::info_span!("cache.keys", pattern = pattern, layer = Empty);
+        if let Some(layer) = self.layer {
+            span.record("layer", layer.as_str());
+        }
+        self.inner.keys(pattern).instrument(span).await
+    }
+
+    async fn get_versioned(&self, key: &str) -> Result<Option<Versioned>> {
+        let span = op_span!("cache.get", key);
+        self.run(span, "get", key, |v: &Option<Versioned>| Some(v.is_some()), self.inner.get_versioned(key))
+            .await
+    }
+
+    async fn set_versioned(&self, key: &str, value: Versioned) -> Result<Versioned> {
+        let span = op_span!("cache.set", key);
+        self.run(span, "set", key, no_hit, self.inner.set_versioned(key, value)).await
+    }
+}
+
+#[async_trait]
+impl<C: Cache> Cache for Traced<C> {
+    async fn get(&self, key: &str) -> Result<Option<CacheEntry>> {
+        let span = op_span!("cache.get", key);
+        self.run(span, "get", key, |v: &Option<CacheEntry>| Some(v.is_some()), self.inner.get(key))
+            .await
+    }
+
+    async fn set(&self, key: &str, value: CacheEntry) -> Result<()> {
+        let span = op_span!("cache.set", key);
+        self.run(span, "set", key, no_hit, self.inner.set(key, value)).await
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        let span = op_span!("cache.delete", key);
+        self.run(span, "delete", key, no_hit, self.inner.delete(key)).await
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool> {
+        let span = op_span!("cache.exists", key);
+        self.run(span, "exists", key, |v: &bool| Some(*v), self.inner.exists(key)).await
+    }
+
+    async fn expire(&self, key: &str, ttl: Duration) -> Result<bool> {
+        let span = op_span!("cache.expire", key);
+        self.run(span, "expire", key, no_hit, self.inner.expire(key, ttl)).await
+    }
+}
@@ -0,0 +1,218 @@
+//! Server-assisted client-side caching
+//!
+//! Clients that keep near copies of server-owned keys register here and are
+//! pushed the keys that change, so they can drop stale copies instead of
+//! re-reading on every access.
+
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+use crate::conflict::Versioned;
+use crate::layer::CacheLayer;
+use crate::pattern::glob_matches;
+use crate::Result;
+
+/// Keys remembered for default-mode clients before the excess are invalidated
+const DEFAULT_MAX_KEYS: usize = 1_000_000;
+
+/// Which changes a client is told about
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum TrackingMode {
+    /// Keys the client has read since it was last told they changed
+    Default,
+    /// Every key matching one of the patterns, read or not
+    Broadcast { patterns: Vec<String> },
+}
+
+struct Tracked {
+    mode: TrackingMode,
+    sender: mpsc::UnboundedSender<Vec<String>>,
+}
+
+/// Table of clients tracking keys
+///
+/// In default mode the table remembers which clients read each key and
+/// tells each of them once when it next changes; a client that reads the
+/// key again is told about the following change. Broadcast clients are
+/// told about every change matching their patterns and cost nothing per
+/// read. Once more than `max_keys` keys are remembered, the excess are
+/// invalidated early so the table stays bounded.
+pub struct Tracking {
+    clients: DashMap<u64, Tracked>,
+    /// Default-mode clients that read each key
+    readers: DashMap<String, HashSet<u64>>,
+    next_id: AtomicU64,
+    max_keys: usize,
+}
+
+impl Tracking {
+    /// Create a table remembering up to 1,000,000 keys
+    pub fn new() -> Self {
+        Self::with_max_keys(DEFAULT_MAX_KEYS)
+    }
+
+    /// Create a table remembering up to `max_keys` keys
+    pub fn with_max_keys(max_keys: usize) -> Self {
+        Self {
+            clients: DashMap::new(),
+            readers: DashMap::new(),
+            next_id: AtomicU64::new(1),
+            max_keys: max_keys.max(1),
+        }
+    }
+
+    /// Register a client; it is unregistered when the subscription drops
+    pub fn subscribe(self: &Arc<Self>, mode: TrackingMode) -> Subscription {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = mpsc::unbounded_channel();
+        self.clients.insert(id, Tracked { mode, sender });
+        Subscription {
+            id,
+            tracking: self.clone(),
+            receiver,
+        }
+    }
+
+    fn unsubscribe(&self, id: u64) {
+        if let Some((_, tracked)) = self.clients.remove(&id) {
+            if tracked.mode == TrackingMode::Default {
+                self.readers.retain(|_, readers| {
+                    readers.remove(&id);
+                    !readers.is_empty()
+                });
+            }
+        }
+    }
+
+    /// Note that client `id` is about to read `key`
+    ///
+    /// Call this before reading so a change racing with the read is still
+    /// reported. Broadcast clients need no per-key state and are ignored.
+    pub fn remember(&self, id: u64, key: &str) {
+        match self.clients.get(&id) {
+            Some(tracked) if tracked.mode == TrackingMode::Default => {}
+            _ => return,
+        }
+        self.readers.entry(key.to_string()).or_default().insert(id);
+        while self.readers.len() > self.max_keys {
+            let Some(evicted) = self.readers.iter().next().map(|e| e.key().clone()) else { break };
+            tracing::debug!(key = %evicted, "tracking table full, invalidating early");
+            self.invalidate(&evicted);
+        }
+    }
+
+    /// Tell every client tracking `key` that it changed
+    pub fn invalidate(&self, key: &str) {
+        let readers = self.readers.remove(key).map(|(_, readers)| readers).unwrap_or_default();
+        for tracked in self.clients.iter() {
+            let notify = match &tracked.mode {
+                TrackingMode::Default => readers.contains(tracked.key()),
+                TrackingMode::Broadcast { patterns } => patterns
+                    .iter()
+                    .any(|pattern| pattern == key || glob_matches(pattern, key)),
+            };
+            if notify {
+                let _ = tracked.sender.send(vec![key.to_string()]);
+            }
+        }
+    }
+
+    /// Number of keys remembered for default-mode clients
+    pub fn tracked_keys(&self) -> usize {
+        self.readers.len()
+    }
+
+    /// Number of registered clients
+    pub fn subscribers(&self) -> usize {
+        self.clients.len()
+    }
+}
+
+impl Default for Tracking {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A client's registration with a `Tracking` table
+pub struct Subscription {
+    id: u64,
+    tracking: Arc<Tracking>,
+    receiver: mpsc::UnboundedReceiver<Vec<String>>,
+}
+
+impl Subscription {
+    /// The client's id in the table
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Wait for the next batch of changed keys
+    pub async fn recv(&mut self) -> Option<Vec<String>> {
+        self.receiver.recv().await
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.tracking.unsubscribe(self.id);
+    }
+}
+
+/// A `CacheLayer` that reports every write to a tracking table
+pub struct TrackingLayer<L> {
+    inner: L,
+    tracking: Arc<Tracking>,
+}
+
+impl<L: CacheLayer> TrackingLayer<L> {
+    /// Wrap a layer, invalidating tracked keys it writes
+    pub fn new(inner: L, tracking: Arc<Tracking>) -> Self {
+        Self { inner, tracking }
+    }
+
+    /// Access the tracking table
+    pub fn tracking(&self) -> &Arc<Tracking> {
+        &self.tracking
+    }
+}
+
+#[async_trait]
+impl<L: CacheLayer> CacheLayer for TrackingLayer<L> {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        self.inner.get(key).await
+    }
+
+    async fn set(&self, key: &str, value: Vec<u8>) -> Result<()> {
+        self.inner.set(key, value).await?;
+        self.tracking.invalidate(key);
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        self.inner.delete(key).await?;
+        self.tracking.invalidate(key);
+        Ok(())
+    }
+
+    async fn keys(&self, pattern: &str) -> Result<Vec<String>> {
+        self.inner.keys(pattern).await
+    }
+
+    async fn get_versioned(&self, key: &str) -> Result<Option<Versioned>> {
+        self.inner.get_versioned(key).await
+    }
+
+    async fn set_versioned(&self, key: &str, value: Versioned) -> Result<Versioned> {
+        let stored = self.inner.set_versioned(key, value).await?;
+        self.tracking.invalidate(key);
+        Ok(stored)
+    }
+}
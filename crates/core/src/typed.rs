@@ -0,0 +1,203 @@
+//! Layer handles that check ownership at compile time
+//!
+//! `#[cache_manifest]` generates a `PatternToken` for every owned pattern.
+//! Keys carry their token, and a `LayerHandle` only accepts writes for
+//! tokens its layer owns, so writing a client-owned key through the server
+//! handle is a type error rather than a runtime `LayerViolation`.
+
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use crate::pattern::glob_matches;
+use crate::{Error, Layer, LayerCoordinator, Result};
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// Used by `#[cache_manifest]`; not part of the public API
+#[doc(hidden)]
+pub mod __private {
+    /// Seals `PatternToken` to the tokens a manifest generates
+    pub trait Generated {}
+}
+
+/// A cache layer known at compile time
+pub trait LayerKind: sealed::Sealed + Send + Sync + 'static {
+    const LAYER: Layer;
+}
+
+/// The client layer, as a type
+pub enum Client {}
+
+/// The edge layer, as a type
+pub enum Edge {}
+
+/// The server layer, as a type
+pub enum Server {}
+
+impl sealed::Sealed for Client {}
+impl sealed::Sealed for Edge {}
+impl sealed::Sealed for Server {}
+
+impl LayerKind for Client {
+    const LAYER: Layer = Layer::Client;
+}
+
+impl LayerKind for Edge {
+    const LAYER: Layer = Layer::Edge;
+}
+
+impl LayerKind for Server {
+    const LAYER: Layer = Layer::Server;
+}
+
+/// A pattern owned by one layer, generated by `#[cache_manifest]`
+///
+/// The trait is sealed: only manifest tokens implement it, so a token always
+/// names the layer the manifest gives its pattern.
+pub trait PatternToken: __private::Generated + Send + Sync + 'static {
+    /// The layer that owns the pattern
+    type Owner: LayerKind;
+    /// The owned pattern
+    const PATTERN: &'static str;
+    /// The owning component
+    const OWNER: &'static str;
+
+    /// A key of this pattern
+    fn key(key: impl Into<String>) -> Result<Key<Self>>
+    where
+        Self: Sized,
+    {
+        Key::new(key)
+    }
+}
+
+/// A key checked against the pattern of its token
+pub struct Key<P> {
+    key: String,
+    _pattern: PhantomData<fn() -> P>,
+}
+
+impl<P: PatternToken> Key<P> {
+    /// Check that `key` belongs to the token's pattern
+    pub fn new(key: impl Into<String>) -> Result<Self> {
+        let key = key.into();
+        if key != P::PATTERN && !glob_matches(P::PATTERN, &key) {
+            return Err(Error::LayerViolation {
+                key,
+                layer: P::Owner::LAYER,
+                reason: format!("does not match {}", P::PATTERN),
+            });
+        }
+        Ok(Self {
+            key,
+            _pattern: PhantomData,
+        })
+    }
+
+    /// The key as a string
+    pub fn as_str(&self) -> &str {
+        &self.key
+    }
+}
+
+impl<P> Clone for Key<P> {
+    fn clone(&self) -> Self {
+        Self {
+            key: self.key.clone(),
+            _pattern: PhantomData,
+        }
+    }
+}
+
+impl<P> fmt::Debug for Key<P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Key").field(&self.key).finish()
+    }
+}
+
+impl<P> fmt::Display for Key<P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.key)
+    }
+}
+
+/// One layer of a coordinator, typed by the layer
+///
+/// Any handle can read, but only the owning layer's handle can write.
+pub struct LayerHandle<L: LayerKind> {
+    coordinator: Arc<LayerCoordinator>,
+    _layer: PhantomData<fn() -> L>,
+}
+
+/// Typed handle to the client layer
+pub type ClientLayer = LayerHandle<Client>;
+
+/// Typed handle to the edge layer
+pub type EdgeLayer = LayerHandle<Edge>;
+
+/// Typed handle to the server layer
+pub type ServerLayer = LayerHandle<Server>;
+
+impl<L: LayerKind> LayerHandle<L> {
+    /// Wrap a coordinator
+    pub fn new(coordinator: Arc<LayerCoordinator>) -> Self {
+        Self {
+            coordinator,
+            _layer: PhantomData,
+        }
+    }
+
+    /// The layer this handle writes to
+    pub fn layer(&self) -> Layer {
+        L::LAYER
+    }
+
+    /// The coordinator behind the handle
+    pub fn coordinator(&self) -> &Arc<LayerCoordinator> {
+        &self.coordinator
+    }
+
+    /// Write a key owned by this layer
+    ///
+    /// Ownership moved at runtime with `transfer` is still checked, and
+    /// surfaces as a `LayerViolation`.
+    pub async fn set<P: PatternToken<Owner = L>>(&self, key: &Key<P>, value: Vec<u8>) -> Result<()> {
+        self.coordinator.set_layer(key.as_str(), value, L::LAYER).await
+    }
+
+    /// Delete a key owned by this layer from every layer
+    pub async fn delete<P: PatternToken<Owner = L>>(&self, key: &Key<P>) -> Result<()> {
+        self.coordinator.delete(key.as_str()).await
+    }
+
+    /// Read a key held by this layer, whoever owns it
+    pub async fn get<P: PatternToken>(&self, key: &Key<P>) -> Result<Option<Vec<u8>>> {
+        self.coordinator.get_from(key.as_str(), L::LAYER).await
+    }
+}
+
+impl<L: LayerKind> Clone for LayerHandle<L> {
+    fn clone(&self) -> Self {
+        Self::new(self.coordinator.clone())
+    }
+}
+
+impl LayerCoordinator {
+    /// Typed handle to the client layer
+    pub fn client(self: &Arc<Self>) -> ClientLayer {
+        LayerHandle::new(self.clone())
+    }
+
+    /// Typed handle to the edge layer
+    pub fn edge(self: &Arc<Self>) -> EdgeLayer {
+        LayerHandle::new(self.clone())
+    }
+
+    /// Typed handle to the server layer
+    pub fn server(self: &Arc<Self>) -> ServerLayer {
+        LayerHandle::new(self.clone())
+    }
+}
@@ -0,0 +1,216 @@
+//! Startup validation of strategies against the ownership manifest
+
+use std::collections::BTreeSet;
+
+use crate::ownership::{DependencyEdge, EdgeType};
+use crate::strategy::{CacheStrategy, Consistency};
+use crate::{Error, Layer, OwnershipGraph, Result};
+
+/// Checks strategy compositions, layer reachability and manifest cycles
+///
+/// Reports invalid compositions (offline-capable storage mixed with strong
+/// consistency for the same keys), strategies placing data on layers that
+/// are not deployed or that contradict the manifest, and circular
+/// invalidation or derivation chains.
+pub struct StrategyValidator<'a> {
+    ownership: &'a OwnershipGraph,
+    layers: Vec<Layer>,
+    strategies: Vec<(String, &'a dyn CacheStrategy)>,
+}
+
+impl<'a> StrategyValidator<'a> {
+    /// Validate against a manifest with every layer deployed
+    pub fn new(ownership: &'a OwnershipGraph) -> Self {
+        Self {
+            ownership,
+            layers: vec![Layer::Client, Layer::Edge, Layer::Server],
+            strategies: Vec::new(),
+        }
+    }
+
+    /// Restrict the deployed layers
+    pub fn with_layers(mut self, layers: &[Layer]) -> Self {
+        self.layers = layers.to_vec();
+        self
+    }
+
+    /// Add the strategy that serves a pattern
+    pub fn strategy(mut self, pattern: impl Into<String>, strategy: &'a dyn CacheStrategy) -> Self {
+        self.strategies.push((pattern.into(), strategy));
+        self
+    }
+
+    /// Fail with the first problem found
+    pub async fn validate(&self) -> Result<()> {
+        match self.problems().await.into_iter().next() {
+            Some(problem) => Err(problem),
+            None => Ok(()),
+        }
+    }
+
+    /// Every problem found, as `Error::StrategyError`s
+    pub async fn problems(&self) -> Vec<Error> {
+        let mut problems = Vec::new();
+        for (pattern, strategy) in &self.strategies {
+            if let Err(e) = check_composition(*strategy) {
+                problems.push(e);
+            }
+            self.check_layers(pattern, *strategy, &mut problems).await;
+        }
+        for node in self.ownership.nodes() {
+            if !self.layers.contains(&node.layer()) {
+                problems.push(Error::StrategyError {
+                    strategy: node.owner().to_string(),
+                    key: None,
+                    pattern: Some(node.pattern().to_string()),
+                    layer: Some(node.layer()),
+                    reason: format!(
+                        "{} is owned on the {} layer, which is not deployed",
+                        node.pattern(),
+                        node.layer()
+                    ),
+                });
+            }
+        }
+        if let Some(cycle) = self.ownership.dependency_cycle() {
+            problems.push(Error::StrategyError {
+                strategy: "manifest".to_string(),
+                key: None,
+                pattern: None,
+                layer: None,
+                reason: format!("circular dependency: {}", cycle.join(" -> ")),
+            });
+        }
+        problems
+    }
+
+    async fn check_layers(&self, pattern: &str, strategy: &dyn CacheStrategy, problems: &mut Vec<Error>) {
+        let locations = match strategy.locations(pattern).await {
+            Ok(locations) => locations,
+            Err(e) => {
+                problems.push(e);
+                return;
+            }
+        };
+        for layer in &locations {
+            if !self.layers.contains(layer) {
+                problems.push(Error::StrategyError {
+                    strategy: strategy.name().to_string(),
+                    key: None,
+                    pattern: Some(pattern.to_string()),
+                    layer: Some(*layer),
+                    reason: format!("{} is placed on the {} layer, which is not deployed", pattern, layer),
+                });
+            }
+        }
+        let owned = self.ownership.get(pattern).or_else(|| self.ownership.owner_of(pattern));
+        if let Some(ownership) = owned {
+            if !locations.contains(&ownership.layer()) {
+                problems.push(Error::StrategyError {
+                    strategy: strategy.name().to_string(),
+                    key: None,
+                    pattern: Some(pattern.to_string()),
+                    layer: Some(ownership.layer()),
+                    reason: format!(
+                        "{} is owned by {} on the {} layer, which the strategy never uses",
+                        pattern,
+                        ownership.owner(),
+                        ownership.layer()
+                    ),
+                });
+            }
+        }
+    }
+}
+
+impl OwnershipGraph {
+    /// A chain of invalidation or derivation edges leading back to its start
+    pub fn dependency_cycle(&self) -> Option<Vec<String>> {
+        let edges: Vec<_> = self
+            .edges()
+            .into_iter()
+            .filter(|e| matches!(e.edge_type, EdgeType::Invalidates | EdgeType::Derives))
+            .collect();
+        let sources: BTreeSet<&str> = edges.iter().map(|e| e.from.as_str()).collect();
+
+        for start in sources {
+            let mut path = vec![start.to_string()];
+            let mut visited = BTreeSet::new();
+            if walk(&edges, start, &mut path, &mut visited) {
+                return Some(path);
+            }
+        }
+        None
+    }
+}
+
+fn walk(
+    edges: &[DependencyEdge],
+    start: &str,
+    path: &mut Vec<String>,
+    visited: &mut BTreeSet<String>,
+) -> bool {
+    // Nodes are patterns, matched exactly: `user:*` -> `user:*:sessions`
+    // overlaps but does not lead back to `user:*`
+    let current = path.last().cloned().unwrap_or_default();
+    for edge in edges.iter().filter(|e| e.from == current) {
+        if edge.to == start {
+            path.push(edge.to.clone());
+            return true;
+        }
+        if visited.insert(edge.to.clone()) {
+            path.push(edge.to.clone());
+            if walk(edges, start, path, visited) {
+                return true;
+            }
+            path.pop();
+        }
+    }
+    false
+}
+
+/// Offline-capable storage cannot back a strong guarantee for the same keys
+///
+/// Returns the `(offline, strong)` combinations a single key can see. Children
+/// of routing or weighted splits handle disjoint keys, so their combinations
+/// are alternatives; other children all serve the same key, so theirs merge.
+fn check_composition(strategy: &dyn CacheStrategy) -> Result<BTreeSet<(bool, bool)>> {
+    strategy.validate()?;
+    let children = strategy.children();
+    if children.is_empty() {
+        let leaf = (
+            strategy.offline_capable(),
+            strategy.consistency() == Consistency::Strong,
+        );
+        return Ok(BTreeSet::from([leaf]));
+    }
+
+    let disjoint = strategy.disjoint_children();
+    let mut variants = if disjoint {
+        BTreeSet::new()
+    } else {
+        BTreeSet::from([(false, false)])
+    };
+    for child in children {
+        let child = check_composition(child)?;
+        variants = if disjoint {
+            variants.union(&child).copied().collect()
+        } else {
+            variants
+                .iter()
+                .flat_map(|(o, s)| child.iter().map(move |(co, cs)| (o | co, s | cs)))
+                .collect()
+        };
+    }
+    if !disjoint && variants.contains(&(true, true)) {
+        return Err(Error::StrategyError {
+            strategy: strategy.name().to_string(),
+            key: None,
+            pattern: None,
+            layer: None,
+            reason: "offline-capable and strongly consistent strategies cannot serve the same keys"
+                .to_string(),
+        });
+    }
+    Ok(variants)
+}
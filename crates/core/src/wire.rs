@@ -0,0 +1,457 @@
+//! Framed request/response protocol between clients and the server
+//!
+//! Every message is a big-endian `u32` length followed by a JSON body.
+//! Requests carry an id that their reply echoes. A connection can therefore
+//! carry many requests at once, and replies may arrive in any order. A
+//! connection that asks to track keys is also sent invalidations, in frames
+//! with the reserved id `PUSH`.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+
+use crate::conflict::Versioned;
+use crate::layer::CacheLayer;
+use crate::tracking::{Tracking, TrackingMode};
+use crate::{Error, Layer, Result};
+
+/// Largest frame either side accepts
+pub const MAX_FRAME: usize = 64 * 1024 * 1024;
+
+/// Id of frames the server sends unprompted; requests never use it
+pub const PUSH: u64 = 0;
+
+/// A message tagged with its request id
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Frame<T> {
+    pub id: u64,
+    #[serde(flatten)]
+    pub body: T,
+}
+
+/// An operation sent to the server
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Request {
+    Ping,
+    Get { key: String },
+    Set { key: String, value: Vec<u8> },
+    Delete { key: String },
+    Keys { pattern: String },
+    GetVersioned { key: String },
+    SetVersioned { key: String, value: Versioned },
+    /// Be sent invalidations for keys in `tracking`'s scope
+    Track { tracking: TrackingMode },
+    /// Stop being sent invalidations
+    Untrack,
+}
+
+impl Request {
+    /// Operation name, as used in errors
+    pub fn name(&self) -> &'static str {
+        match self {
+            Request::Ping => "ping",
+            Request::Get { .. } => "get",
+            Request::Set { .. } => "set",
+            Request::Delete { .. } => "delete",
+            Request::Keys { .. } => "keys",
+            Request::GetVersioned { .. } => "get_versioned",
+            Request::SetVersioned { .. } => "set_versioned",
+            Request::Track { .. } => "track",
+            Request::Untrack => "untrack",
+        }
+    }
+
+    /// The key the request reads or writes, if any
+    pub fn key(&self) -> Option<&str> {
+        match self {
+            Request::Get { key }
+            | Request::Set { key, .. }
+            | Request::Delete { key }
+            | Request::GetVersioned { key }
+            | Request::SetVersioned { key, .. } => Some(key),
+            Request::Ping | Request::Keys { .. } | Request::Track { .. } | Request::Untrack => None,
+        }
+    }
+}
+
+/// The server's answer to a request
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "reply", rename_all = "snake_case")]
+pub enum Reply {
+    Pong,
+    Done,
+    Value { value: Option<Vec<u8>> },
+    Versioned { value: Option<Versioned> },
+    Keys { keys: Vec<String> },
+    Error { error: RemoteError },
+    /// Pushed to tracking connections when keys change
+    Invalidate { keys: Vec<String> },
+}
+
+/// An `Error` as sent over the wire
+///
+/// Underlying causes are flattened into the reason, so the error's kind,
+/// key and retryability survive the trip.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum RemoteError {
+    PatternConflict { pattern: String, owner: String, requested_by: String },
+    InvalidBorrowing { key: String, pattern: String, owner: String, borrower: String },
+    LayerViolation { key: String, layer: Layer, reason: String },
+    Strategy {
+        strategy: String,
+        key: Option<String>,
+        pattern: Option<String>,
+        layer: Option<Layer>,
+        reason: String,
+    },
+    QuotaExceeded { key: String, pattern: String, reason: String },
+    Capacity { layer: Layer, reason: String },
+    Namespace { namespace: String, reason: String },
+    Timeout { operation: String, key: Option<String>, layer: Option<Layer>, after: Duration },
+    VersionConflict { key: String, expected: u64, actual: u64 },
+    Conflict { key: String, reason: String },
+    Network { layer: Option<Layer>, reason: String },
+    Serialization { key: Option<String>, reason: String },
+    InvalidConfig { reason: String },
+    Other { reason: String },
+}
+
+impl From<&Error> for RemoteError {
+    fn from(error: &Error) -> Self {
+        match error {
+            Error::PatternConflict { pattern, owner, requested_by } => RemoteError::PatternConflict {
+                pattern: pattern.clone(),
+                owner: owner.clone(),
+                requested_by: requested_by.clone(),
+            },
+            Error::InvalidBorrowing { key, pattern, owner, borrower } => RemoteError::InvalidBorrowing {
+                key: key.clone(),
+                pattern: pattern.clone(),
+                owner: owner.clone(),
+                borrower: borrower.clone(),
+            },
+            Error::LayerViolation { key, layer, reason } => RemoteError::LayerViolation {
+                key: key.clone(),
+                layer: *layer,
+                reason: reason.clone(),
+            },
+            Error::StrategyError { strategy, key, pattern, layer, reason } => RemoteError::Strategy {
+                strategy: strategy.clone(),
+                key: key.clone(),
+                pattern: pattern.clone(),
+                layer: *layer,
+                reason: reason.clone(),
+            },
+            Error::QuotaExceeded { key, pattern, reason } => RemoteError::QuotaExceeded {
+                key: key.clone(),
+                pattern: pattern.clone(),
+                reason: reason.clone(),
+            },
+            Error::Capacity { layer, reason } => RemoteError::Capacity {
+                layer: *layer,
+                reason: reason.clone(),
+            },
+            Error::Namespace { namespace, reason } => RemoteError::Namespace {
+                namespace: namespace.clone(),
+                reason: reason.clone(),
+            },
+            Error::Timeout { operation, key, layer, after } => RemoteError::Timeout {
+                operation: operation.clone(),
+                key: key.clone(),
+                layer: *layer,
+                after: *after,
+            },
+            Error::VersionConflict { key, expected, actual } => RemoteError::VersionConflict {
+                key: key.clone(),
+                expected: *expected,
+                actual: *actual,
+            },
+            Error::Conflict { key, reason } => RemoteError::Conflict {
+                key: key.clone(),
+                reason: reason.clone(),
+            },
+            Error::Network { layer, .. } => RemoteError::Network {
+                layer: *layer,
+                reason: error.to_string(),
+            },
+            Error::Serialization { key, .. } => RemoteError::Serialization {
+                key: key.clone(),
+                reason: error.to_string(),
+            },
+            Error::InvalidConfig(reason) => RemoteError::InvalidConfig { reason: reason.clone() },
+            Error::Undelivered { .. } => RemoteError::Other { reason: error.to_string() },
+            Error::Other(source) => RemoteError::Other { reason: source.to_string() },
+        }
+    }
+}
+
+impl From<RemoteError> for Error {
+    fn from(error: RemoteError) -> Self {
+        match error {
+            RemoteError::PatternConflict { pattern, owner, requested_by } => Error::PatternConflict {
+                pattern,
+                owner,
+                requested_by,
+            },
+            RemoteError::InvalidBorrowing { key, pattern, owner, borrower } => Error::InvalidBorrowing {
+                key,
+                pattern,
+                owner,
+                borrower,
+            },
+            RemoteError::LayerViolation { key, layer, reason } => Error::LayerViolation { key, layer, reason },
+            RemoteError::Strategy { strategy, key, pattern, layer, reason } => Error::StrategyError {
+                strategy,
+                key,
+                pattern,
+                layer,
+                reason,
+            },
+            RemoteError::QuotaExceeded { key, pattern, reason } => Error::QuotaExceeded { key, pattern, reason },
+            RemoteError::Capacity { layer, reason } => Error::Capacity { layer, reason },
+            RemoteError::Namespace { namespace, reason } => Error::Namespace { namespace, reason },
+            RemoteError::Timeout { operation, key, layer, after } => Error::Timeout {
+                operation,
+                key,
+                layer,
+                after,
+            },
+            RemoteError::VersionConflict { key, expected, actual } => Error::VersionConflict { key, expected, actual },
+            RemoteError::Conflict { key, reason } => Error::Conflict { key, reason },
+            RemoteError::Network { layer, reason } => Error::Network {
+                layer,
+                reason,
+                source: None,
+            },
+            RemoteError::Serialization { key, reason } => Error::Serialization {
+                key,
+                reason,
+                source: None,
+            },
+            RemoteError::InvalidConfig { reason } => Error::InvalidConfig(reason),
+            RemoteError::Other { reason } => Error::Other(reason.into()),
+        }
+    }
+}
+
+/// Write one frame
+pub async fn write_frame<W, T>(writer: &mut W, message: &T) -> Result<()>
+where
+    W: AsyncWrite + Unpin,
+    T: Serialize,
+{
+    let body = serde_json::to_vec(message)?;
+    if body.len() > MAX_FRAME {
+        return Err(Error::Network {
+            layer: None,
+            reason: format!("frame of {} bytes exceeds the {} byte limit", body.len(), MAX_FRAME),
+            source: None,
+        });
+    }
+    writer
+        .write_u32(body.len() as u32)
+        .await
+        .map_err(|e| Error::network(None, "writing frame", e))?;
+    writer
+        .write_all(&body)
+        .await
+        .map_err(|e| Error::network(None, "writing frame", e))
+}
+
+/// Read one frame, or `None` if the peer closed the connection
+pub async fn read_frame<R, T>(reader: &mut R) -> Result<Option<T>>
+where
+    R: AsyncRead + Unpin,
+    T: DeserializeOwned,
+{
+    let len = match reader.read_u32().await {
+        Ok(len) => len as usize,
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(Error::network(None, "reading frame", e)),
+    };
+    if len > MAX_FRAME {
+        return Err(Error::Network {
+            layer: None,
+            reason: format!("frame of {} bytes exceeds the {} byte limit", len, MAX_FRAME),
+            source: None,
+        });
+    }
+    let mut body = vec![0; len];
+    reader
+        .read_exact(&mut body)
+        .await
+        .map_err(|e| Error::network(None, "reading frame", e))?;
+    Ok(Some(serde_json::from_slice(&body)?))
+}
+
+/// Run a request against a layer
+pub async fn handle(layer: &dyn CacheLayer, request: Request) -> Reply {
+    let result = match request {
+        Request::Ping => Ok(Reply::Pong),
+        Request::Get { key } => layer.get(&key).await.map(|value| Reply::Value { value }),
+        Request::Set { key, value } => layer.set(&key, value).await.map(|_| Reply::Done),
+        Request::Delete { key } => layer.delete(&key).await.map(|_| Reply::Done),
+        Request::Keys { pattern } => layer.keys(&pattern).await.map(|keys| Reply::Keys { keys }),
+        Request::GetVersioned { key } => layer
+            .get_versioned(&key)
+            .await
+            .map(|value| Reply::Versioned { value }),
+        Request::SetVersioned { key, value } => layer
+            .set_versioned(&key, value)
+            .await
+            .map(|value| Reply::Versioned { value: Some(value) }),
+        Request::Track { .. } | Request::Untrack => Err(Error::InvalidConfig(
+            "this server does not track keys".to_string(),
+        )),
+    };
+    result.unwrap_or_else(|e| Reply::Error { error: RemoteError::from(&e) })
+}
+
+/// Task pushing a connection's invalidations, stopped with the connection
+struct Pushes(tokio::task::JoinHandle<()>);
+
+impl Drop for Pushes {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Serves a layer over the wire protocol
+///
+/// With a tracking table, connections may ask to be told when keys change.
+/// Writes must reach the table for that to work, typically by serving a
+/// `TrackingLayer`.
+pub struct Server {
+    layer: Arc<dyn CacheLayer>,
+    tracking: Option<Arc<Tracking>>,
+}
+
+impl Server {
+    /// Serve `layer`
+    pub fn new(layer: Arc<dyn CacheLayer>) -> Self {
+        Self { layer, tracking: None }
+    }
+
+    /// Accept tracking requests, registering connections with `tracking`
+    pub fn with_tracking(mut self, tracking: Arc<Tracking>) -> Self {
+        self.tracking = Some(tracking);
+        self
+    }
+
+    /// Serve one connection until the peer closes it
+    ///
+    /// Requests run concurrently as they arrive and each reply is written as
+    /// soon as it is ready, so a slow request does not hold up the others.
+    /// Tracking requests take effect before any later request is read.
+    pub async fn serve_connection<S>(&self, stream: S) -> Result<()>
+    where
+        S: AsyncRead + AsyncWrite + Send + 'static,
+    {
+        let (mut reader, mut writer) = tokio::io::split(stream);
+        let (replies, mut outgoing) = mpsc::unbounded_channel::<Frame<Reply>>();
+        let writes = tokio::spawn(async move {
+            while let Some(frame) = outgoing.recv().await {
+                write_frame(&mut writer, &frame).await?;
+            }
+            Ok::<_, Error>(())
+        });
+        // This connection's id in the tracking table and the task pushing its invalidations
+        let mut tracked: Option<(u64, Pushes)> = None;
+
+        let result = loop {
+            let Frame { id, body } = match read_frame::<_, Frame<Request>>(&mut reader).await {
+                Ok(Some(frame)) => frame,
+                Ok(None) => break Ok(()),
+                Err(e) => break Err(e),
+            };
+            match (body, &self.tracking) {
+                (Request::Track { tracking: mode }, Some(tracking)) => {
+                    let mut subscription = tracking.subscribe(mode);
+                    let pushes = replies.clone();
+                    let client = subscription.id();
+                    let task = tokio::spawn(async move {
+                        while let Some(keys) = subscription.recv().await {
+                            let body = Reply::Invalidate { keys };
+                            if pushes.send(Frame { id: PUSH, body }).is_err() {
+                                break;
+                            }
+                        }
+                    });
+                    tracked = Some((client, Pushes(task)));
+                    let _ = replies.send(Frame { id, body: Reply::Done });
+                }
+                (Request::Untrack, Some(_)) => {
+                    tracked = None;
+                    let _ = replies.send(Frame { id, body: Reply::Done });
+                }
+                (body, tracking) => {
+                    let layer = self.layer.clone();
+                    let replies = replies.clone();
+                    let reader = tracking.clone().zip(tracked.as_ref().map(|(client, _)| *client));
+                    tokio::spawn(async move {
+                        let key = body.key().map(str::to_string);
+                        let read = matches!(body, Request::Get { .. } | Request::GetVersioned { .. });
+                        let tracked = reader.as_ref().zip(key.as_deref());
+                        // Reads are remembered before reading so a racing write is
+                        // still reported; the client's own writes are remembered
+                        // after they are made, since the client keeps what it wrote
+                        if let Some(((tracking, client), key)) = tracked.filter(|_| read) {
+                            tracking.remember(*client, key);
+                        }
+                        let body = handle(layer.as_ref(), body).await;
+                        if let Some(((tracking, client), key)) = tracked.filter(|_| !read) {
+                            if matches!(body, Reply::Done | Reply::Versioned { .. }) {
+                                tracking.remember(*client, key);
+                            }
+                        }
+                        let _ = replies.send(Frame { id, body });
+                    });
+                }
+            }
+        };
+        drop(tracked);
+        // Requests already running still get their replies
+        drop(replies);
+        match writes.await {
+            Ok(written) => result.and(written),
+            Err(e) => Err(Error::Other(Box::new(e))),
+        }
+    }
+
+    /// Accept connections and serve each until the listener fails
+    pub async fn serve(self: Arc<Self>, listener: TcpListener) -> Result<()> {
+        loop {
+            let (stream, peer) = listener
+                .accept()
+                .await
+                .map_err(|e| Error::network(None, "accepting connection", e))?;
+            let _ = stream.set_nodelay(true);
+            let server = self.clone();
+            tokio::spawn(async move {
+                if let Err(e) = server.serve_connection(stream).await {
+                    tracing::debug!(%peer, error = %e, "connection closed with error");
+                }
+            });
+        }
+    }
+}
+
+/// Serve one connection of `layer` until the peer closes it
+pub async fn serve_connection<S>(stream: S, layer: Arc<dyn CacheLayer>) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    Server::new(layer).serve_connection(stream).await
+}
+
+/// Serve `layer` on every accepted connection until the listener fails
+pub async fn serve(listener: TcpListener, layer: Arc<dyn CacheLayer>) -> Result<()> {
+    Arc::new(Server::new(layer)).serve(listener).await
+}
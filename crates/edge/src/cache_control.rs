@@ -0,0 +1,101 @@
+//! Cache-Control directives (RFC 9111 §5.2)
+
+use std::time::Duration;
+
+use crate::http::Headers;
+
+/// The directives of a request's or response's `Cache-Control` fields
+///
+/// Unknown directives are ignored. Qualified `no-cache` and `private` are
+/// treated as if unqualified, and a `max-age` or `s-maxage` that is not a
+/// valid number of seconds as zero, so the response is treated as stale.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CacheControl {
+    pub max_age: Option<Duration>,
+    pub s_maxage: Option<Duration>,
+    /// `max-stale`, with `Some(None)` accepting any staleness
+    pub max_stale: Option<Option<Duration>>,
+    pub min_fresh: Option<Duration>,
+    pub no_cache: bool,
+    pub no_store: bool,
+    pub private: bool,
+    pub public: bool,
+    pub must_revalidate: bool,
+    pub proxy_revalidate: bool,
+    pub only_if_cached: bool,
+}
+
+impl CacheControl {
+    /// Parse one `Cache-Control` value
+    pub fn parse(value: &str) -> Self {
+        let mut directives = Self::default();
+        for directive in split(value) {
+            let (name, argument) = match directive.split_once('=') {
+                Some((name, argument)) => (name.trim(), Some(argument.trim().trim_matches('"'))),
+                None => (directive.trim(), None),
+            };
+            let seconds = || Some(argument.and_then(delta_seconds).unwrap_or(Duration::ZERO));
+            match name.to_ascii_lowercase().as_str() {
+                "max-age" => directives.max_age = seconds(),
+                "s-maxage" => directives.s_maxage = seconds(),
+                "max-stale" => directives.max_stale = Some(argument.and_then(delta_seconds)),
+                "min-fresh" => directives.min_fresh = seconds(),
+                "no-cache" => directives.no_cache = true,
+                "no-store" => directives.no_store = true,
+                "private" => directives.private = true,
+                "public" => directives.public = true,
+                "must-revalidate" => directives.must_revalidate = true,
+                "proxy-revalidate" => directives.proxy_revalidate = true,
+                "only-if-cached" => directives.only_if_cached = true,
+                _ => {}
+            }
+        }
+        directives
+    }
+
+    /// The directives of every `Cache-Control` field in `headers`
+    ///
+    /// A request without `Cache-Control` but with `Pragma: no-cache` is
+    /// treated as `no-cache`.
+    pub fn from_headers(headers: &Headers) -> Self {
+        match headers.get_all("cache-control") {
+            Some(value) => Self::parse(&value),
+            None => Self {
+                no_cache: headers.list("pragma").iter().any(|p| p.eq_ignore_ascii_case("no-cache")),
+                ..Self::default()
+            },
+        }
+    }
+
+    /// Whether a shared cache must revalidate the response once stale
+    pub fn revalidate_when_stale(&self) -> bool {
+        self.must_revalidate || self.proxy_revalidate || self.s_maxage.is_some()
+    }
+}
+
+/// Split on commas outside quoted strings
+fn split(value: &str) -> Vec<&str> {
+    let mut directives = Vec::new();
+    let (mut start, mut quoted) = (0, false);
+    for (i, c) in value.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                directives.push(&value[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    directives.push(&value[start..]);
+    directives.into_iter().filter(|d| !d.trim().is_empty()).collect()
+}
+
+/// Parse delta-seconds, capped at 2^31 as RFC 9111 §1.2.2 suggests
+pub(crate) fn delta_seconds(value: &str) -> Option<Duration> {
+    if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let seconds: u64 = value.parse().unwrap_or(u64::MAX);
+    Some(Duration::from_secs(seconds.min(1 << 31)))
+}
@@ -0,0 +1,475 @@
+//! HTTP/1.1 messages and their framing
+//!
+//! Just enough of RFC 9112 for the edge proxy: heads are parsed with
+//! `httparse`, bodies are delimited by `Content-Length` or chunked transfer
+//! coding, and a response without either runs until the connection closes.
+//! Messages are always written with a `Content-Length`.
+
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use core::{Error, Layer, Result};
+
+/// Largest message head either side accepts
+pub const MAX_HEAD: usize = 64 * 1024;
+
+/// Largest message body either side accepts
+pub const MAX_BODY: usize = 64 * 1024 * 1024;
+
+const MAX_HEADERS: usize = 100;
+
+/// Headers removed when a message is forwarded (RFC 9110 §7.6.1)
+const HOP_BY_HOP: &[&str] = &[
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// Header fields in the order received, names compared case-insensitively
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Headers(Vec<(String, String)>);
+
+impl Headers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The first value of `name`
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// All values of `name` combined into one list, as RFC 9110 §5.3 allows
+    pub fn get_all(&self, name: &str) -> Option<String> {
+        let values: Vec<_> = self
+            .0
+            .iter()
+            .filter(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+            .collect();
+        (!values.is_empty()).then(|| values.join(", "))
+    }
+
+    /// The comma-separated elements of every `name` field, trimmed
+    pub fn list(&self, name: &str) -> Vec<String> {
+        self.get_all(name)
+            .map(|all| {
+                all.split(',')
+                    .map(str::trim)
+                    .filter(|v| !v.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// Replace every `name` field with one holding `value`
+    pub fn insert(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        let name = name.into();
+        self.remove(&name);
+        self.0.push((name, value.into()));
+    }
+
+    /// Add a `name` field, keeping any already present
+    pub fn append(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        self.0.push((name.into(), value.into()));
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.0.retain(|(n, _)| !n.eq_ignore_ascii_case(name));
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(n, v)| (n.as_str(), v.as_str()))
+    }
+
+    /// Drop hop-by-hop fields, including those `Connection` names
+    pub fn strip_hop_by_hop(&mut self) {
+        let named = self.list("connection");
+        self.0.retain(|(n, _)| {
+            !HOP_BY_HOP.iter().any(|h| n.eq_ignore_ascii_case(h))
+                && !named.iter().any(|c| n.eq_ignore_ascii_case(c))
+        });
+    }
+}
+
+impl<N: Into<String>, V: Into<String>> FromIterator<(N, V)> for Headers {
+    fn from_iter<I: IntoIterator<Item = (N, V)>>(fields: I) -> Self {
+        Self(fields.into_iter().map(|(n, v)| (n.into(), v.into())).collect())
+    }
+}
+
+/// An HTTP request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpRequest {
+    pub method: String,
+    /// Request target in origin form, e.g. `/pages/1?lang=en`
+    pub target: String,
+    pub headers: Headers,
+    pub body: Bytes,
+}
+
+impl HttpRequest {
+    /// A `GET` of `target` without headers
+    pub fn get(target: impl Into<String>) -> Self {
+        Self {
+            method: "GET".to_string(),
+            target: target.into(),
+            headers: Headers::new(),
+            body: Bytes::new(),
+        }
+    }
+
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.append(name, value);
+        self
+    }
+
+    pub fn with_body(mut self, body: impl Into<Bytes>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// Whether the method is safe (RFC 9110 §9.2.1), so never changes the resource
+    pub fn is_safe(&self) -> bool {
+        matches!(self.method.as_str(), "GET" | "HEAD" | "OPTIONS" | "TRACE")
+    }
+
+    /// Whether the connection should be closed after the response
+    pub fn wants_close(&self) -> bool {
+        self.headers.list("connection").iter().any(|c| c.eq_ignore_ascii_case("close"))
+    }
+}
+
+/// An HTTP response
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub headers: Headers,
+    pub body: Bytes,
+}
+
+impl HttpResponse {
+    pub fn new(status: u16) -> Self {
+        Self {
+            status,
+            headers: Headers::new(),
+            body: Bytes::new(),
+        }
+    }
+
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.append(name, value);
+        self
+    }
+
+    pub fn with_body(mut self, body: impl Into<Bytes>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// Whether the status is a final, non-error one
+    pub fn is_success(&self) -> bool {
+        (200..400).contains(&self.status)
+    }
+}
+
+/// Read a request, or `None` if the peer closed the connection between requests
+pub async fn read_request<R>(reader: &mut R) -> Result<Option<HttpRequest>>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let Some(head) = read_head(reader).await? else { return Ok(None) };
+    let mut fields = [httparse::EMPTY_HEADER; MAX_HEADERS];
+    let mut parsed = httparse::Request::new(&mut fields);
+    match parsed.parse(&head) {
+        Ok(httparse::Status::Complete(_)) => {}
+        Ok(httparse::Status::Partial) => return Err(malformed("incomplete request head")),
+        Err(e) => return Err(malformed(format!("bad request head: {}", e))),
+    }
+    let method = parsed.method.unwrap_or_default().to_string();
+    let target = parsed.path.unwrap_or_default().to_string();
+    let headers = collect(parsed.headers)?;
+    let body = match body_length(&headers)? {
+        Length::Chunked => read_chunked(reader).await?,
+        Length::Exactly(len) => read_exact(reader, len).await?,
+        Length::UntilClose => Bytes::new(),
+    };
+    Ok(Some(HttpRequest { method, target, headers, body }))
+}
+
+/// Read the response to a request made with `method`
+pub async fn read_response<R>(reader: &mut R, method: &str) -> Result<HttpResponse>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let head = read_head(reader)
+        .await?
+        .ok_or_else(|| malformed("connection closed before the response"))?;
+    let mut fields = [httparse::EMPTY_HEADER; MAX_HEADERS];
+    let mut parsed = httparse::Response::new(&mut fields);
+    match parsed.parse(&head) {
+        Ok(httparse::Status::Complete(_)) => {}
+        Ok(httparse::Status::Partial) => return Err(malformed("incomplete response head")),
+        Err(e) => return Err(malformed(format!("bad response head: {}", e))),
+    }
+    let status = parsed.code.unwrap_or_default();
+    let headers = collect(parsed.headers)?;
+    let bodiless = method == "HEAD" || (100..200).contains(&status) || status == 204 || status == 304;
+    let body = match body_length(&headers)? {
+        _ if bodiless => Bytes::new(),
+        Length::Chunked => read_chunked(reader).await?,
+        Length::Exactly(len) => read_exact(reader, len).await?,
+        Length::UntilClose => {
+            let mut body = Vec::new();
+            reader
+                .take(MAX_BODY as u64 + 1)
+                .read_to_end(&mut body)
+                .await
+                .map_err(|e| Error::network(Some(Layer::Edge), "reading response body", e))?;
+            check_body(body.len())?;
+            Bytes::from(body)
+        }
+    };
+    Ok(HttpResponse { status, headers, body })
+}
+
+/// Write a request with a `Content-Length` body
+pub async fn write_request<W>(writer: &mut W, request: &HttpRequest) -> Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    let mut head = format!("{} {} HTTP/1.1\r\n", request.method, request.target);
+    push_fields(&mut head, &request.headers, request.body.len(), !request.body.is_empty());
+    write_message(writer, head, &request.body).await
+}
+
+/// Write a response with a `Content-Length` body
+///
+/// A response to `HEAD` keeps its `Content-Length` but sends no body.
+pub async fn write_response<W>(writer: &mut W, response: &HttpResponse, method: &str) -> Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    let mut head = format!("HTTP/1.1 {} {}\r\n", response.status, reason(response.status));
+    let bodiless = (100..200).contains(&response.status) || response.status == 204 || response.status == 304;
+    if bodiless {
+        push_fields(&mut head, &response.headers, 0, false);
+        return write_message(writer, head, &[]).await;
+    }
+    let length = if method == "HEAD" && response.body.is_empty() {
+        response
+            .headers
+            .get("content-length")
+            .and_then(|l| l.parse().ok())
+            .unwrap_or(0)
+    } else {
+        response.body.len()
+    };
+    push_fields(&mut head, &response.headers, length, true);
+    let body: &[u8] = if method == "HEAD" { &[] } else { &response.body };
+    write_message(writer, head, body).await
+}
+
+fn push_fields(head: &mut String, headers: &Headers, length: usize, with_length: bool) {
+    for (name, value) in headers.iter() {
+        if name.eq_ignore_ascii_case("content-length") || name.eq_ignore_ascii_case("transfer-encoding") {
+            continue;
+        }
+        head.push_str(name);
+        head.push_str(": ");
+        head.push_str(value);
+        head.push_str("\r\n");
+    }
+    if with_length {
+        head.push_str(&format!("Content-Length: {}\r\n", length));
+    }
+    head.push_str("\r\n");
+}
+
+async fn write_message<W>(writer: &mut W, head: String, body: &[u8]) -> Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    let write = async {
+        writer.write_all(head.as_bytes()).await?;
+        writer.write_all(body).await?;
+        writer.flush().await
+    };
+    write
+        .await
+        .map_err(|e| Error::network(Some(Layer::Edge), "writing http message", e))
+}
+
+/// Read lines up to and including the blank line ending a message head
+async fn read_head<R>(reader: &mut R) -> Result<Option<Vec<u8>>>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let mut head = Vec::new();
+    loop {
+        let start = head.len();
+        let read = reader
+            .read_until(b'\n', &mut head)
+            .await
+            .map_err(|e| Error::network(Some(Layer::Edge), "reading http head", e))?;
+        if read == 0 {
+            if head.iter().all(u8::is_ascii_whitespace) {
+                return Ok(None);
+            }
+            return Err(malformed("connection closed mid head"));
+        }
+        if head.len() > MAX_HEAD {
+            return Err(malformed(format!("head exceeds the {} byte limit", MAX_HEAD)));
+        }
+        let line = &head[start..];
+        if line == b"\r\n" || line == b"\n" {
+            // Blank lines before a message are ignored (RFC 9112 §2.2)
+            if start == 0 {
+                head.clear();
+                continue;
+            }
+            return Ok(Some(head));
+        }
+    }
+}
+
+fn collect(fields: &[httparse::Header<'_>]) -> Result<Headers> {
+    fields
+        .iter()
+        .map(|f| {
+            let value = std::str::from_utf8(f.value)
+                .map_err(|_| malformed(format!("header {} is not valid UTF-8", f.name)))?;
+            Ok((f.name.to_string(), value.trim().to_string()))
+        })
+        .collect()
+}
+
+enum Length {
+    Chunked,
+    Exactly(usize),
+    UntilClose,
+}
+
+fn body_length(headers: &Headers) -> Result<Length> {
+    if let Some(last) = headers.list("transfer-encoding").last() {
+        if last.eq_ignore_ascii_case("chunked") {
+            return Ok(Length::Chunked);
+        }
+        return Ok(Length::UntilClose);
+    }
+    match headers.get("content-length") {
+        Some(len) => {
+            let len = len
+                .parse()
+                .map_err(|_| malformed(format!("bad Content-Length {:?}", len)))?;
+            check_body(len)?;
+            Ok(Length::Exactly(len))
+        }
+        None => Ok(Length::UntilClose),
+    }
+}
+
+async fn read_exact<R>(reader: &mut R, len: usize) -> Result<Bytes>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let mut body = vec![0; len];
+    reader
+        .read_exact(&mut body)
+        .await
+        .map_err(|e| Error::network(Some(Layer::Edge), "reading http body", e))?;
+    Ok(Bytes::from(body))
+}
+
+async fn read_chunked<R>(reader: &mut R) -> Result<Bytes>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let mut body = Vec::new();
+    let mut line = String::new();
+    loop {
+        line.clear();
+        reader
+            .read_line(&mut line)
+            .await
+            .map_err(|e| Error::network(Some(Layer::Edge), "reading chunk size", e))?;
+        let size = line.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size, 16)
+            .map_err(|_| malformed(format!("bad chunk size {:?}", size)))?;
+        if size == 0 {
+            break;
+        }
+        check_body(body.len() + size)?;
+        body.extend_from_slice(&read_exact(reader, size).await?);
+        line.clear();
+        reader
+            .read_line(&mut line)
+            .await
+            .map_err(|e| Error::network(Some(Layer::Edge), "reading chunk", e))?;
+    }
+    // Trailer fields are read and discarded
+    loop {
+        line.clear();
+        let read = reader
+            .read_line(&mut line)
+            .await
+            .map_err(|e| Error::network(Some(Layer::Edge), "reading trailers", e))?;
+        if read == 0 || line.trim().is_empty() {
+            return Ok(Bytes::from(body));
+        }
+    }
+}
+
+fn check_body(len: usize) -> Result<()> {
+    if len > MAX_BODY {
+        return Err(malformed(format!("body exceeds the {} byte limit", MAX_BODY)));
+    }
+    Ok(())
+}
+
+fn malformed(reason: impl Into<String>) -> Error {
+    Error::Serialization {
+        key: None,
+        reason: reason.into(),
+        source: None,
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "Created",
+        204 => "No Content",
+        206 => "Partial Content",
+        301 => "Moved Permanently",
+        302 => "Found",
+        304 => "Not Modified",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        410 => "Gone",
+        412 => "Precondition Failed",
+        500 => "Internal Server Error",
+        502 => "Bad Gateway",
+        503 => "Service Unavailable",
+        504 => "Gateway Timeout",
+        _ => "",
+    }
+}
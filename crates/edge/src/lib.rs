@@ -0,0 +1,338 @@
+//! Region-aware edge layer for the stateless caching system
+//!
+//! An `EdgeCluster` spreads keys over `EdgeNode`s, one per region. Writes
+//! go to the regions the key's `Placement` names and invalidate copies held
+//! anywhere else. Reads are served by the nearest region holding the key,
+//! falling back to farther regions in order of latency and finally to an
+//! upstream layer. Invalidations can be scoped to a single region.
+//!
+//! `HttpProxy` puts a shared cache in front of an HTTP origin, honoring the
+//! origin's caching headers. A node serves as that cache through
+//! `core::LayerCache`, which keeps the entries' TTLs alongside their values:
+//! `HttpProxy::new(Arc::new(LayerCache::new(node)), config)`.
+
+mod cache_control;
+mod node;
+mod placement;
+mod proxy;
+mod region;
+pub mod http;
+
+use std::collections::BTreeSet;
+use std::future::{Future, IntoFuture};
+use std::pin::Pin;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+use core::layer::CacheLayer;
+use core::{Error, Invalidation, InvalidationBus, Result, Versioned};
+
+pub use cache_control::CacheControl;
+pub use http::{Headers, HttpRequest, HttpResponse};
+pub use node::EdgeNode;
+pub use placement::{Placement, Placements};
+pub use proxy::{HttpProxy, ProxyConfig};
+pub use region::{Region, Topology};
+
+/// A value read through the cluster and where it came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Served {
+    pub value: Vec<u8>,
+    /// The region that held it, or `None` if it came from upstream
+    pub region: Option<Region>,
+}
+
+/// Edge nodes across regions
+pub struct EdgeCluster {
+    local: Region,
+    nodes: Vec<Arc<EdgeNode>>,
+    topology: Topology,
+    placements: Arc<Placements>,
+    upstream: Option<Arc<dyn CacheLayer>>,
+    /// Writes and invalidations, so reads can tell when a fill went stale.
+    /// Nothing subscribes, so nothing is kept for replay.
+    invalidations: InvalidationBus,
+}
+
+impl EdgeCluster {
+    /// An empty cluster serving `local` by default
+    pub fn new(local: impl Into<Region>) -> Self {
+        Self {
+            local: local.into(),
+            nodes: Vec::new(),
+            topology: Topology::new(),
+            placements: Arc::new(Placements::new()),
+            upstream: None,
+            invalidations: InvalidationBus::with_capacity(0),
+        }
+    }
+
+    /// Add a region's node, replacing any node already serving it
+    pub fn with_node(mut self, node: Arc<EdgeNode>) -> Self {
+        self.nodes.retain(|n| n.region() != node.region());
+        self.nodes.push(node);
+        self
+    }
+
+    /// Use `topology` to order regions by distance
+    pub fn with_topology(mut self, topology: Topology) -> Self {
+        self.topology = topology;
+        self
+    }
+
+    /// Share a placement registry
+    pub fn with_placements(mut self, placements: Arc<Placements>) -> Self {
+        self.placements = placements;
+        self
+    }
+
+    /// Fall back to `upstream` when no region holds a key
+    pub fn with_upstream(mut self, upstream: Arc<dyn CacheLayer>) -> Self {
+        self.upstream = Some(upstream);
+        self
+    }
+
+    /// Region reads and writes come from unless told otherwise
+    pub fn local(&self) -> &Region {
+        &self.local
+    }
+
+    /// Regions with a node, in name order
+    pub fn regions(&self) -> Vec<Region> {
+        let regions: BTreeSet<_> = self.nodes.iter().map(|n| n.region().clone()).collect();
+        regions.into_iter().collect()
+    }
+
+    /// The node serving `region`
+    pub fn node(&self, region: &Region) -> Option<&Arc<EdgeNode>> {
+        self.nodes.iter().find(|n| n.region() == region)
+    }
+
+    /// Latencies used to order regions
+    pub fn topology(&self) -> &Topology {
+        &self.topology
+    }
+
+    /// Access the placement registry
+    pub fn placements(&self) -> &Arc<Placements> {
+        &self.placements
+    }
+
+    /// Place keys matching `pattern`
+    pub fn place(&self, pattern: impl Into<String>, placement: Placement) {
+        self.placements.place(pattern, placement);
+    }
+
+    /// Read `key`, from the local region unless `Read::from_region` says otherwise
+    pub fn get(&self, key: &str) -> Read<'_> {
+        Read {
+            cluster: self,
+            key: key.to_string(),
+            region: None,
+        }
+    }
+
+    /// Regions with a node, nearest to `region` first
+    pub fn nearest(&self, region: &Region) -> Vec<Region> {
+        self.topology.nearest(region, &self.regions())
+    }
+
+    /// Read `key` with its version and the region that held it
+    async fn read(&self, key: &str, origin: &Region) -> Result<Option<(Versioned, Option<Region>)>> {
+        let seen = self.invalidations.latest();
+        for region in self.nearest(origin) {
+            let node = self.node(&region).expect("region has a node");
+            match node.get_versioned(key).await {
+                Ok(Some(value)) => {
+                    if &region != origin {
+                        tracing::debug!(key, %origin, served_by = %region, "edge read served by farther region");
+                        self.fill(key, origin, &value, seen).await;
+                    }
+                    return Ok(Some((value, Some(region))));
+                }
+                Ok(None) => {}
+                Err(e) if e.is_retryable() => {
+                    tracing::debug!(key, %region, error = %e, "edge region unavailable, trying the next");
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        let Some(upstream) = &self.upstream else { return Ok(None) };
+        let Some(value) = upstream.get_versioned(key).await? else { return Ok(None) };
+        self.fill(key, origin, &value, seen).await;
+        Ok(Some((value, None)))
+    }
+
+    /// Keep a copy in the reading region if its placement allows it
+    ///
+    /// A write or invalidation of `key` after `seen` may have reached the
+    /// region before the copy did, so the copy is taken back out.
+    async fn fill(&self, key: &str, origin: &Region, value: &Versioned, seen: u64) {
+        let Some(node) = self.node(origin) else { return };
+        if !self.placements.placement_for(key).fills(origin) {
+            return;
+        }
+        if let Err(e) = node.set_versioned(key, value.clone()).await {
+            tracing::debug!(key, region = %origin, error = %e, "could not keep edge copy");
+            return;
+        }
+        if self.invalidations.invalidated_since(seen, key) {
+            tracing::debug!(key, region = %origin, "value invalidated during read, dropping edge copy");
+            if let Err(e) = node.invalidate(Invalidation::Key(key.to_string())).await {
+                tracing::debug!(key, region = %origin, error = %e, "could not drop edge copy");
+            }
+        }
+    }
+
+    /// Write `key` as if from `region`
+    ///
+    /// The key is written to the regions its placement names, taking the
+    /// nearest region with a node as home, and invalidated everywhere else.
+    /// Target regions that are down are invalidated instead of written; the
+    /// write fails only if no target accepted it.
+    pub async fn set_from(&self, region: impl Into<Region>, key: &str, value: Vec<u8>) -> Result<()> {
+        self.write(region.into(), key, Versioned::unversioned(value), false).await.map(|_| ())
+    }
+
+    /// Write `key` as `set_from` does, returning what its home region stored
+    ///
+    /// Versioned writes let the nodes' stores resolve them against the
+    /// versions they hold.
+    async fn write(&self, region: Region, key: &str, value: Versioned, versioned: bool) -> Result<Versioned> {
+        let Some(home) = self.nearest(&region).into_iter().next() else {
+            return Err(Error::InvalidConfig("edge cluster has no nodes".to_string()));
+        };
+        let placement = self.placements.placement_for(key);
+        let targets = placement.targets(&home, &self.regions(), &self.topology);
+        if targets.is_empty() {
+            return Err(Error::InvalidConfig(format!(
+                "placement {:?} of {} names no region with a node",
+                placement, key
+            )));
+        }
+
+        self.invalidations.publish(Invalidation::Key(key.to_string()), None);
+        let mut written = 0;
+        let mut stored = None;
+        let mut failure = None;
+        for node in &self.nodes {
+            if !targets.contains(node.region()) {
+                node.invalidate(Invalidation::Key(key.to_string())).await?;
+                continue;
+            }
+            let result = if versioned {
+                node.set_versioned(key, value.clone()).await
+            } else {
+                node.set(key, value.value.clone()).await.map(|()| value.clone())
+            };
+            match result {
+                Ok(value) => {
+                    written += 1;
+                    if stored.is_none() || node.region() == &home {
+                        stored = Some(value);
+                    }
+                }
+                Err(e) if e.is_retryable() => {
+                    tracing::debug!(key, region = %node.region(), error = %e, "edge write missed a region");
+                    node.invalidate(Invalidation::Key(key.to_string())).await?;
+                    failure = Some(e);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        match failure {
+            Some(e) if written == 0 => Err(e),
+            _ => Ok(stored.unwrap_or(value)),
+        }
+    }
+
+    /// Drop every copy of keys matching `pattern`, returning how many were dropped
+    pub async fn invalidate(&self, pattern: &str) -> Result<usize> {
+        self.invalidations.publish(Invalidation::Pattern(pattern.to_string()), None);
+        let mut dropped = 0;
+        for node in &self.nodes {
+            dropped += node.invalidate(Invalidation::Pattern(pattern.to_string())).await?;
+        }
+        Ok(dropped)
+    }
+
+    /// Drop copies of keys matching `pattern` held in `region` only
+    pub async fn invalidate_in(&self, region: impl Into<Region>, pattern: &str) -> Result<usize> {
+        let region = region.into();
+        let node = self
+            .node(&region)
+            .ok_or_else(|| Error::InvalidConfig(format!("no edge node in region {}", region)))?;
+        self.invalidations.publish(Invalidation::Pattern(pattern.to_string()), None);
+        node.invalidate(Invalidation::Pattern(pattern.to_string())).await
+    }
+}
+
+/// A pending read, awaited for the value
+pub struct Read<'a> {
+    cluster: &'a EdgeCluster,
+    key: String,
+    region: Option<Region>,
+}
+
+impl<'a> Read<'a> {
+    /// Read as a client in `region` would, nearest region first
+    pub fn from_region(mut self, region: impl Into<Region>) -> Self {
+        self.region = Some(region.into());
+        self
+    }
+
+    /// The value with the region that served it
+    pub async fn served(self) -> Result<Option<Served>> {
+        let origin = self.region.unwrap_or_else(|| self.cluster.local.clone());
+        let read = self.cluster.read(&self.key, &origin).await?;
+        Ok(read.map(|(value, region)| Served {
+            value: value.value,
+            region,
+        }))
+    }
+}
+
+impl<'a> IntoFuture for Read<'a> {
+    type Output = Result<Option<Vec<u8>>>;
+    type IntoFuture = Pin<Box<dyn Future<Output = Self::Output> + Send + 'a>>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        Box::pin(async move { Ok(self.served().await?.map(|served| served.value)) })
+    }
+}
+
+#[async_trait]
+impl CacheLayer for EdgeCluster {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        EdgeCluster::get(self, key).await
+    }
+
+    async fn set(&self, key: &str, value: Vec<u8>) -> Result<()> {
+        self.set_from(self.local.clone(), key, value).await
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        self.invalidations.publish(Invalidation::Key(key.to_string()), None);
+        for node in &self.nodes {
+            node.invalidate(Invalidation::Key(key.to_string())).await?;
+        }
+        Ok(())
+    }
+
+    async fn keys(&self, pattern: &str) -> Result<Vec<String>> {
+        let mut keys = BTreeSet::new();
+        for node in self.nodes.iter().filter(|n| n.is_up()) {
+            keys.extend(node.keys(pattern).await?);
+        }
+        Ok(keys.into_iter().collect())
+    }
+
+    async fn get_versioned(&self, key: &str) -> Result<Option<Versioned>> {
+        Ok(self.read(key, &self.local).await?.map(|(value, _)| value))
+    }
+
+    async fn set_versioned(&self, key: &str, value: Versioned) -> Result<Versioned> {
+        self.write(self.local.clone(), key, value, true).await
+    }
+}
@@ -0,0 +1,188 @@
+//! A single region's edge node
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use dashmap::DashMap;
+use tokio::net::TcpListener;
+
+use core::layer::CacheLayer;
+use core::pattern::glob_matches;
+use core::{wire, Error, Invalidation, Layer, Result, Versioned};
+
+use crate::region::Region;
+
+/// In-memory store backing nodes created without one
+#[derive(Default)]
+struct MemoryStore {
+    entries: DashMap<String, Vec<u8>>,
+}
+
+#[async_trait]
+impl CacheLayer for MemoryStore {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self.entries.get(key).map(|v| v.clone()))
+    }
+
+    async fn set(&self, key: &str, value: Vec<u8>) -> Result<()> {
+        self.entries.insert(key.to_string(), value);
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        self.entries.remove(key);
+        Ok(())
+    }
+
+    async fn keys(&self, pattern: &str) -> Result<Vec<String>> {
+        Ok(self
+            .entries
+            .iter()
+            .filter(|e| e.key() == pattern || glob_matches(pattern, e.key()))
+            .map(|e| e.key().clone())
+            .collect())
+    }
+}
+
+/// Edge cache for one region
+///
+/// A node that is down refuses reads and writes with a retryable network
+/// error. Invalidations sent while it is down are kept and applied before
+/// it serves again, so it never serves a copy invalidated in the meantime.
+pub struct EdgeNode {
+    region: Region,
+    store: Arc<dyn CacheLayer>,
+    up: AtomicBool,
+    /// Invalidations received while down
+    missed: Mutex<Vec<Invalidation>>,
+}
+
+impl EdgeNode {
+    /// A node keeping its entries in memory
+    pub fn new(region: impl Into<Region>) -> Self {
+        Self::with_store(region, Arc::new(MemoryStore::default()))
+    }
+
+    /// A node keeping its entries in `store`
+    pub fn with_store(region: impl Into<Region>, store: Arc<dyn CacheLayer>) -> Self {
+        Self {
+            region: region.into(),
+            store,
+            up: AtomicBool::new(true),
+            missed: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn region(&self) -> &Region {
+        &self.region
+    }
+
+    /// Whether the node is serving
+    pub fn is_up(&self) -> bool {
+        self.up.load(Ordering::SeqCst)
+    }
+
+    /// Stop serving, e.g. while the region is partitioned
+    pub fn go_down(&self) {
+        let _missed = self.missed.lock().unwrap();
+        self.up.store(false, Ordering::SeqCst);
+        tracing::info!(region = %self.region, "edge node down");
+    }
+
+    /// Apply the invalidations missed while down, then serve again
+    pub async fn come_up(&self) -> Result<()> {
+        loop {
+            let missed = {
+                let mut missed = self.missed.lock().unwrap();
+                if missed.is_empty() {
+                    self.up.store(true, Ordering::SeqCst);
+                    tracing::info!(region = %self.region, "edge node up");
+                    return Ok(());
+                }
+                std::mem::take(&mut *missed)
+            };
+            for target in &missed {
+                self.apply(target).await?;
+            }
+        }
+    }
+
+    /// Drop the entries `target` covers, returning how many were dropped
+    ///
+    /// A node that is down keeps the invalidation until it comes up.
+    pub async fn invalidate(&self, target: Invalidation) -> Result<usize> {
+        {
+            let mut missed = self.missed.lock().unwrap();
+            if !self.is_up() {
+                missed.push(target);
+                return Ok(0);
+            }
+        }
+        self.apply(&target).await
+    }
+
+    async fn apply(&self, target: &Invalidation) -> Result<usize> {
+        let keys = match target {
+            Invalidation::Key(key) => match self.store.get(key).await? {
+                Some(_) => vec![key.clone()],
+                None => return Ok(0),
+            },
+            Invalidation::Pattern(pattern) => self.store.keys(pattern).await?,
+        };
+        for key in &keys {
+            self.store.delete(key).await?;
+        }
+        tracing::debug!(region = %self.region, ?target, dropped = keys.len(), "edge invalidation applied");
+        Ok(keys.len())
+    }
+
+    fn check_up(&self) -> Result<()> {
+        if self.is_up() {
+            Ok(())
+        } else {
+            Err(Error::Network {
+                layer: Some(Layer::Edge),
+                reason: format!("edge region {} is down", self.region),
+                source: None,
+            })
+        }
+    }
+
+    /// Serve this node over the wire protocol until the listener fails
+    pub async fn serve(self: Arc<Self>, listener: TcpListener) -> Result<()> {
+        wire::serve(listener, self).await
+    }
+}
+
+#[async_trait]
+impl CacheLayer for EdgeNode {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        self.check_up()?;
+        self.store.get(key).await
+    }
+
+    async fn set(&self, key: &str, value: Vec<u8>) -> Result<()> {
+        self.check_up()?;
+        self.store.set(key, value).await
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        self.invalidate(Invalidation::Key(key.to_string())).await.map(|_| ())
+    }
+
+    async fn keys(&self, pattern: &str) -> Result<Vec<String>> {
+        self.check_up()?;
+        self.store.keys(pattern).await
+    }
+
+    async fn get_versioned(&self, key: &str) -> Result<Option<Versioned>> {
+        self.check_up()?;
+        self.store.get_versioned(key).await
+    }
+
+    async fn set_versioned(&self, key: &str, value: Versioned) -> Result<Versioned> {
+        self.check_up()?;
+        self.store.set_versioned(key, value).await
+    }
+}
@@ -0,0 +1,98 @@
+//! Which regions hold a key
+
+use dashmap::DashMap;
+
+use core::pattern::{glob_matches, specificity};
+use core::{CacheStrategy, Consistency};
+
+use crate::region::{Region, Topology};
+
+/// Regions a key is written to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Placement {
+    /// Only the region the key was written from
+    Home,
+    /// The home region and the `n` regions nearest to it
+    Nearest(usize),
+    /// These regions, wherever the key was written from
+    Pinned(Vec<Region>),
+    /// Every region
+    Everywhere,
+}
+
+impl Placement {
+    /// The placement suited to a strategy's consistency
+    ///
+    /// Strongly consistent keys are written everywhere so every region reads
+    /// the latest write. Regionally and eventually consistent keys stay in
+    /// the region that wrote them and are copied to others as they are read
+    /// there; the next write invalidates those copies.
+    pub fn for_strategy(strategy: &dyn CacheStrategy) -> Self {
+        match strategy.consistency() {
+            Consistency::Strong => Placement::Everywhere,
+            Consistency::Regional | Consistency::Eventual => Placement::Home,
+        }
+    }
+
+    /// Regions to write a key written from `home` to, nearest first
+    pub fn targets(&self, home: &Region, regions: &[Region], topology: &Topology) -> Vec<Region> {
+        match self {
+            Placement::Home => regions.iter().filter(|r| *r == home).cloned().collect(),
+            Placement::Nearest(n) => topology.nearest(home, regions).into_iter().take(n + 1).collect(),
+            Placement::Pinned(pinned) => {
+                topology.nearest(home, regions.iter().filter(|r| pinned.contains(r)))
+            }
+            Placement::Everywhere => topology.nearest(home, regions),
+        }
+    }
+
+    /// Whether a region may keep a copy of a key it read from elsewhere
+    pub fn fills(&self, region: &Region) -> bool {
+        match self {
+            Placement::Pinned(pinned) => pinned.contains(region),
+            Placement::Home | Placement::Nearest(_) => true,
+            // Already written everywhere; a miss means the key is gone
+            Placement::Everywhere => false,
+        }
+    }
+}
+
+/// Placements by key pattern
+///
+/// A key is placed by the most specific matching pattern, and written only
+/// at home when none matches.
+#[derive(Default)]
+pub struct Placements {
+    rules: DashMap<String, Placement>,
+}
+
+impl Placements {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Place keys matching `pattern`
+    pub fn place(&self, pattern: impl Into<String>, placement: Placement) {
+        self.rules.insert(pattern.into(), placement);
+    }
+
+    /// Place keys matching `pattern` as suits `strategy`
+    pub fn place_for(&self, pattern: impl Into<String>, strategy: &dyn CacheStrategy) {
+        self.place(pattern, Placement::for_strategy(strategy));
+    }
+
+    /// Stop placing keys by `pattern`
+    pub fn remove(&self, pattern: &str) -> bool {
+        self.rules.remove(pattern).is_some()
+    }
+
+    /// The placement for a key
+    pub fn placement_for(&self, key: &str) -> Placement {
+        self.rules
+            .iter()
+            .filter(|r| r.key() == key || glob_matches(r.key(), key))
+            .max_by_key(|r| specificity(r.key()))
+            .map(|r| r.value().clone())
+            .unwrap_or(Placement::Home)
+    }
+}
@@ -0,0 +1,600 @@
+//! HTTP caching reverse proxy (RFC 9111)
+//!
+//! Responses are kept in a `Cache` as entries whose value is the body and
+//! whose metadata holds the status, header fields and the times the
+//! response was requested and received, from which its age is computed.
+//! A response that varies on request fields is kept under a secondary key
+//! built from those fields, next to an index entry naming them. Secondary
+//! keys include a generation drawn when the index is created, so deleting
+//! the index invalidates every variant stored under it.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use bytes::Bytes;
+use tokio::io::BufReader;
+use tokio::net::{TcpListener, TcpStream};
+
+use core::{Cache, CacheEntry, Error, Layer, Result};
+
+use crate::cache_control::{delta_seconds, CacheControl};
+use crate::http::{self, Headers, HttpRequest, HttpResponse};
+
+/// Statuses cacheable by default (RFC 9110 §15.1)
+const HEURISTIC: &[u16] = &[200, 203, 204, 206, 300, 301, 308, 404, 405, 410, 414, 501];
+
+/// Request fields that make a request conditional
+const CONDITIONALS: &[&str] = &["if-match", "if-none-match", "if-modified-since", "if-unmodified-since", "if-range"];
+
+/// Fields a 304 response repeats from the response it stands for (RFC 9110 §15.4.5)
+const NOT_MODIFIED_FIELDS: &[&str] = &[
+    "cache-control",
+    "content-location",
+    "date",
+    "etag",
+    "expires",
+    "last-modified",
+    "vary",
+    "age",
+];
+
+const STATUS: &str = "http.status";
+const HEADERS: &str = "http.headers";
+const REQUESTED: &str = "http.requested";
+const RECEIVED: &str = "http.received";
+const VARY: &str = "http.vary";
+const GENERATION: &str = "http.generation";
+
+/// Caching proxy configuration
+#[derive(Debug, Clone)]
+pub struct ProxyConfig {
+    /// Origin server address as `host:port`
+    pub origin: String,
+    /// Name reported in `Cache-Status` and `Via`
+    pub name: String,
+    /// Prefix of the keys responses are cached under
+    pub prefix: String,
+    /// Share of the time since `Last-Modified` that a response without an
+    /// explicit lifetime is considered fresh
+    pub heuristic_fraction: f64,
+    /// Longest heuristic freshness lifetime
+    pub max_heuristic: Duration,
+    /// How long stale responses are kept for revalidation
+    pub stale_retention: Duration,
+    pub origin_timeout: Duration,
+}
+
+impl ProxyConfig {
+    /// Defaults for proxying `origin`
+    pub fn new(origin: impl Into<String>) -> Self {
+        Self {
+            origin: origin.into(),
+            name: "stateless".to_string(),
+            prefix: "http:".to_string(),
+            heuristic_fraction: 0.1,
+            max_heuristic: Duration::from_secs(24 * 60 * 60),
+            stale_retention: Duration::from_secs(60 * 60),
+            origin_timeout: Duration::from_secs(30),
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        if !(0.0..=1.0).contains(&self.heuristic_fraction) {
+            return Err(Error::InvalidConfig(format!(
+                "heuristic fraction {} is not between 0 and 1",
+                self.heuristic_fraction
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// A response as kept in the cache
+#[derive(Debug, Clone)]
+struct Stored {
+    status: u16,
+    headers: Headers,
+    body: Bytes,
+    requested: SystemTime,
+    received: SystemTime,
+}
+
+impl Stored {
+    fn decode(entry: CacheEntry) -> Option<Self> {
+        let time = |name| {
+            let millis = entry.metadata.get(name)?.parse().ok()?;
+            Some(UNIX_EPOCH + Duration::from_millis(millis))
+        };
+        Some(Self {
+            status: entry.metadata.get(STATUS)?.parse().ok()?,
+            headers: serde_json::from_str(entry.metadata.get(HEADERS)?).ok()?,
+            requested: time(REQUESTED)?,
+            received: time(RECEIVED)?,
+            body: entry.value,
+        })
+    }
+
+    fn encode(&self, ttl: Duration) -> Result<CacheEntry> {
+        let millis = |t: SystemTime| t.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis().to_string();
+        let mut metadata = HashMap::new();
+        metadata.insert(STATUS.to_string(), self.status.to_string());
+        metadata.insert(HEADERS.to_string(), serde_json::to_string(&self.headers)?);
+        metadata.insert(REQUESTED.to_string(), millis(self.requested));
+        metadata.insert(RECEIVED.to_string(), millis(self.received));
+        Ok(CacheEntry {
+            value: self.body.clone(),
+            ttl: Some(ttl),
+            metadata,
+        })
+    }
+
+    fn control(&self) -> CacheControl {
+        CacheControl::from_headers(&self.headers)
+    }
+
+    fn date(&self, name: &str) -> Option<SystemTime> {
+        httpdate::parse_http_date(self.headers.get(name)?).ok()
+    }
+
+    /// Lowercased names of the request fields the response varies on
+    fn vary(&self) -> Vec<String> {
+        self.headers.list("vary").iter().map(|v| v.to_ascii_lowercase()).collect()
+    }
+
+    fn has_validators(&self) -> bool {
+        self.headers.contains("etag") || self.headers.contains("last-modified")
+    }
+
+    /// Freshness lifetime for a shared cache (RFC 9111 §4.2.1)
+    fn lifetime(&self, config: &ProxyConfig) -> Duration {
+        let control = self.control();
+        if let Some(lifetime) = control.s_maxage.or(control.max_age) {
+            return lifetime;
+        }
+        let date = self.date("date").unwrap_or(self.received);
+        if self.headers.contains("expires") {
+            // An invalid date means already expired
+            return self
+                .date("expires")
+                .and_then(|expires| expires.duration_since(date).ok())
+                .unwrap_or_default();
+        }
+        if !HEURISTIC.contains(&self.status) && !control.public {
+            return Duration::ZERO;
+        }
+        self.date("last-modified")
+            .and_then(|modified| date.duration_since(modified).ok())
+            .map(|since| since.mul_f64(config.heuristic_fraction).min(config.max_heuristic))
+            .unwrap_or_default()
+    }
+
+    /// Current age (RFC 9111 §4.2.3)
+    fn age(&self, now: SystemTime) -> Duration {
+        let age = self.headers.get("age").and_then(delta_seconds).unwrap_or_default();
+        let date = self.date("date").unwrap_or(self.received);
+        let apparent = self.received.duration_since(date).unwrap_or_default();
+        let delay = self.received.duration_since(self.requested).unwrap_or_default();
+        apparent.max(age + delay) + now.duration_since(self.received).unwrap_or_default()
+    }
+
+    /// Take the fields of a 304 response (RFC 9111 §4.3.4)
+    fn freshen(&mut self, response: &HttpResponse) {
+        for (name, _) in response.headers.iter() {
+            if !name.eq_ignore_ascii_case("content-length") {
+                self.headers.remove(name);
+            }
+        }
+        for (name, value) in response.headers.iter() {
+            if !name.eq_ignore_ascii_case("content-length") {
+                self.headers.append(name, value);
+            }
+        }
+    }
+}
+
+/// A caching reverse proxy in front of one origin server
+///
+/// Responses are kept with a TTL covering their freshness lifetime, plus
+/// `stale_retention` when they can be revalidated, so the cache drops them
+/// once they are of no further use. Over a `LayerCache` with metrics, each
+/// drop is counted as an expiration.
+pub struct HttpProxy {
+    cache: Arc<dyn Cache>,
+    config: ProxyConfig,
+}
+
+impl HttpProxy {
+    /// Proxy `config.origin`, keeping responses in `cache`
+    pub fn new(cache: Arc<dyn Cache>, config: ProxyConfig) -> Result<Self> {
+        config.validate()?;
+        Ok(Self { cache, config })
+    }
+
+    pub fn config(&self) -> &ProxyConfig {
+        &self.config
+    }
+
+    /// Forget the cached responses for `target` on `host`, every variant included
+    pub async fn purge(&self, host: &str, target: &str) -> Result<()> {
+        self.cache.delete(&self.key(host, target)).await
+    }
+
+    /// Answer a request from the cache or the origin
+    pub async fn handle(&self, mut request: HttpRequest) -> Result<HttpResponse> {
+        request.headers.strip_hop_by_hop();
+        let key = self.key(self.host(&request), &request.target);
+        if matches!(request.method.as_str(), "GET" | "HEAD") {
+            return self.lookup(&request, &key).await;
+        }
+
+        let response = self.forward(&request).await?;
+        // Unsafe methods invalidate what they may have changed (RFC 9111 §4.4)
+        if !request.is_safe() && response.is_success() {
+            let mut keys = vec![key];
+            for field in ["location", "content-location"] {
+                if let Some(target) = response.headers.get(field).and_then(|v| self.same_origin(&request, v)) {
+                    keys.push(self.key(self.host(&request), &target));
+                }
+            }
+            for key in keys {
+                if let Err(e) = self.cache.delete(&key).await {
+                    tracing::warn!(key, error = %e, "could not invalidate cached response");
+                }
+            }
+        }
+        let status = format!("fwd=method; fwd-status={}", response.status);
+        Ok(self.mark(response, &status))
+    }
+
+    async fn lookup(&self, request: &HttpRequest, key: &str) -> Result<HttpResponse> {
+        let asked = CacheControl::from_headers(&request.headers);
+        let (stored, miss) = self.find(request, key).await?;
+        let Some(stored) = stored else {
+            if asked.only_if_cached {
+                return Ok(self.mark(HttpResponse::new(504), "detail=only-if-cached"));
+            }
+            return self.fetch(request, key, miss).await;
+        };
+
+        let now = SystemTime::now();
+        let control = stored.control();
+        let (age, lifetime) = (stored.age(now), stored.lifetime(&self.config));
+        if !asked.no_cache && !control.no_cache && usable(&asked, &control, age, lifetime) {
+            return Ok(self.respond(request, stored, Some(age), "hit"));
+        }
+        if asked.only_if_cached {
+            return Ok(self.mark(HttpResponse::new(504), "detail=only-if-cached"));
+        }
+        let reason = if asked.no_cache { "request" } else { "stale" };
+        if !stored.has_validators() {
+            return self.fetch(request, key, reason).await;
+        }
+
+        // Revalidate the stored response (RFC 9111 §4.3.1)
+        let mut conditional = request.clone();
+        conditional.method = "GET".to_string();
+        for field in CONDITIONALS {
+            conditional.headers.remove(field);
+        }
+        if let Some(etag) = stored.headers.get("etag") {
+            conditional.headers.insert("If-None-Match", etag);
+        }
+        if let Some(modified) = stored.headers.get("last-modified") {
+            conditional.headers.insert("If-Modified-Since", modified);
+        }
+        let requested = SystemTime::now();
+        match self.forward(&conditional).await {
+            Ok(response) if response.status == 304 => {
+                let mut stored = stored;
+                stored.freshen(&response);
+                stored.requested = requested;
+                stored.received = SystemTime::now();
+                let age = stored.age(SystemTime::now());
+                self.store(request, key, &stored).await;
+                Ok(self.respond(request, stored, Some(age), &format!("fwd={}; fwd-status=304", reason)))
+            }
+            Ok(response) => self.complete(request, key, response, requested, reason).await,
+            // Stale responses may be served while the origin is unreachable (RFC 9111 §4.2.4)
+            Err(e) if e.is_retryable() && !asked.no_cache && !control.revalidate_when_stale() => {
+                tracing::warn!(key, error = %e, "origin unreachable, serving stale response");
+                Ok(self.respond(request, stored, Some(age), "hit; detail=stale-on-error"))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// The stored response for a request, or why there is none
+    async fn find(&self, request: &HttpRequest, key: &str) -> Result<(Option<Stored>, &'static str)> {
+        let Some(entry) = self.cache.get(key).await? else { return Ok((None, "uri-miss")) };
+        let Some(vary) = entry.metadata.get(VARY) else { return Ok((Stored::decode(entry), "uri-miss")) };
+        let names: Vec<_> = vary.split(',').map(str::to_string).collect();
+        let generation = entry.metadata.get(GENERATION).map(String::as_str).unwrap_or_default();
+        let Some(entry) = self.cache.get(&variant(key, generation, &names, request)).await? else {
+            return Ok((None, "vary-miss"));
+        };
+        Ok((Stored::decode(entry), "vary-miss"))
+    }
+
+    /// Get a full response from the origin, keeping it if allowed
+    async fn fetch(&self, request: &HttpRequest, key: &str, reason: &str) -> Result<HttpResponse> {
+        let mut outbound = request.clone();
+        // Fetch the full response so it can be kept, then answer conditionals here
+        if outbound.method == "GET" {
+            for field in CONDITIONALS {
+                outbound.headers.remove(field);
+            }
+        }
+        let requested = SystemTime::now();
+        let response = self.forward(&outbound).await?;
+        self.complete(request, key, response, requested, reason).await
+    }
+
+    async fn complete(
+        &self,
+        request: &HttpRequest,
+        key: &str,
+        response: HttpResponse,
+        requested: SystemTime,
+        reason: &str,
+    ) -> Result<HttpResponse> {
+        let stored = Stored {
+            status: response.status,
+            headers: response.headers,
+            body: response.body,
+            requested,
+            received: SystemTime::now(),
+        };
+        let mut status = format!("fwd={}; fwd-status={}", reason, stored.status);
+        if request.method == "GET" && self.storable(request, &stored) && self.store(request, key, &stored).await {
+            status.push_str("; stored");
+        }
+        Ok(self.respond(request, stored, None, &status))
+    }
+
+    /// Whether a shared cache may keep a response (RFC 9111 §3)
+    fn storable(&self, request: &HttpRequest, stored: &Stored) -> bool {
+        let asked = CacheControl::from_headers(&request.headers);
+        let control = stored.control();
+        if asked.no_store || control.no_store || control.private {
+            return false;
+        }
+        // Partial content is not combined, and `Vary: *` never matches
+        if matches!(stored.status, 100..=199 | 206) || request.headers.contains("range") {
+            return false;
+        }
+        if stored.vary().iter().any(|v| v == "*") {
+            return false;
+        }
+        if request.headers.contains("authorization")
+            && !(control.public || control.must_revalidate || control.s_maxage.is_some())
+        {
+            return false;
+        }
+        let explicit = control.public
+            || control.max_age.is_some()
+            || control.s_maxage.is_some()
+            || stored.headers.contains("expires");
+        explicit || HEURISTIC.contains(&stored.status)
+    }
+
+    /// Keep a response until it can no longer be served or revalidated
+    async fn store(&self, request: &HttpRequest, key: &str, stored: &Stored) -> bool {
+        let remaining = stored.lifetime(&self.config).saturating_sub(stored.age(SystemTime::now()));
+        let ttl = if stored.has_validators() {
+            remaining + self.config.stale_retention
+        } else {
+            remaining
+        };
+        if ttl.is_zero() {
+            return false;
+        }
+
+        let vary = stored.vary();
+        let result = async {
+            if vary.is_empty() {
+                return self.cache.set(key, stored.encode(ttl)?).await;
+            }
+            // Variants stored under a live index with the same fields stay reachable
+            let names = vary.join(",");
+            let generation = match self.cache.get(key).await? {
+                Some(index) if index.metadata.get(VARY) == Some(&names) => index.metadata.get(GENERATION).cloned(),
+                _ => None,
+            }
+            .unwrap_or_else(generation);
+            let mut index = CacheEntry {
+                value: Bytes::new(),
+                ttl: Some(ttl),
+                metadata: HashMap::new(),
+            };
+            index.metadata.insert(VARY.to_string(), names);
+            index.metadata.insert(GENERATION.to_string(), generation.clone());
+            self.cache.set(key, index).await?;
+            self.cache.set(&variant(key, &generation, &vary, request), stored.encode(ttl)?).await
+        };
+        match result.await {
+            Ok(()) => true,
+            Err(e) => {
+                tracing::warn!(key, error = %e, "could not cache response");
+                false
+            }
+        }
+    }
+
+    /// Build the response to send, answering the request's conditionals
+    fn respond(&self, request: &HttpRequest, stored: Stored, age: Option<Duration>, status: &str) -> HttpResponse {
+        let mut response = HttpResponse {
+            status: stored.status,
+            headers: stored.headers.clone(),
+            body: stored.body.clone(),
+        };
+        if let Some(age) = age {
+            response.headers.insert("Age", age.as_secs().to_string());
+        }
+        if stored.status == 200 && not_modified(request, &stored) {
+            response.status = 304;
+            response.body = Bytes::new();
+            response.headers = response
+                .headers
+                .iter()
+                .filter(|(name, _)| NOT_MODIFIED_FIELDS.iter().any(|f| name.eq_ignore_ascii_case(f)))
+                .collect();
+        } else if request.method == "HEAD" && !response.body.is_empty() {
+            response.headers.insert("Content-Length", response.body.len().to_string());
+            response.body = Bytes::new();
+        }
+        self.mark(response, status)
+    }
+
+    /// Report how the cache handled the request (RFC 9211)
+    fn mark(&self, mut response: HttpResponse, status: &str) -> HttpResponse {
+        response
+            .headers
+            .append("Cache-Status", format!("{}; {}", self.config.name, status));
+        response
+    }
+
+    async fn forward(&self, request: &HttpRequest) -> Result<HttpResponse> {
+        let mut outbound = request.clone();
+        outbound.headers.insert("Connection", "close");
+        outbound.headers.append("Via", format!("1.1 {}", self.config.name));
+        let exchange = async {
+            let stream = TcpStream::connect(&self.config.origin).await.map_err(|e| {
+                Error::network(Some(Layer::Edge), format!("connecting to origin {}", self.config.origin), e)
+            })?;
+            let _ = stream.set_nodelay(true);
+            let (read, mut write) = stream.into_split();
+            http::write_request(&mut write, &outbound).await?;
+            let mut response = http::read_response(&mut BufReader::new(read), &outbound.method).await?;
+            response.headers.strip_hop_by_hop();
+            Ok(response)
+        };
+        tokio::time::timeout(self.config.origin_timeout, exchange)
+            .await
+            .map_err(|_| Error::Timeout {
+                operation: format!("{} to origin", request.method),
+                key: Some(request.target.clone()),
+                layer: Some(Layer::Edge),
+                after: self.config.origin_timeout,
+            })?
+    }
+
+    fn host<'a>(&'a self, request: &'a HttpRequest) -> &'a str {
+        request.headers.get("host").unwrap_or(&self.config.origin)
+    }
+
+    fn key(&self, host: &str, target: &str) -> String {
+        format!("{}{}{}", self.config.prefix, host.to_ascii_lowercase(), target)
+    }
+
+    /// The target a `Location`-style field names, if on the request's host
+    fn same_origin(&self, request: &HttpRequest, value: &str) -> Option<String> {
+        if value.starts_with('/') {
+            return Some(value.to_string());
+        }
+        let rest = value.strip_prefix("http://").or_else(|| value.strip_prefix("https://"))?;
+        let (host, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+        host.eq_ignore_ascii_case(self.host(request))
+            .then(|| if path.is_empty() { "/".to_string() } else { path.to_string() })
+    }
+
+    /// Serve every accepted connection until the listener fails
+    pub async fn serve(self: Arc<Self>, listener: TcpListener) -> Result<()> {
+        loop {
+            let (stream, peer) = listener
+                .accept()
+                .await
+                .map_err(|e| Error::network(Some(Layer::Edge), "accepting connection", e))?;
+            let _ = stream.set_nodelay(true);
+            let proxy = self.clone();
+            tokio::spawn(async move {
+                if let Err(e) = proxy.serve_connection(stream).await {
+                    tracing::debug!(%peer, error = %e, "connection closed with error");
+                }
+            });
+        }
+    }
+
+    /// Serve requests on one connection until the peer closes it
+    pub async fn serve_connection(&self, stream: TcpStream) -> Result<()> {
+        let (read, mut write) = stream.into_split();
+        let mut reader = BufReader::new(read);
+        while let Some(request) = http::read_request(&mut reader).await? {
+            let (method, close) = (request.method.clone(), request.wants_close());
+            let response = match self.handle(request).await {
+                Ok(response) => response,
+                Err(e) => {
+                    tracing::warn!(error = %e, "could not reach origin");
+                    let status = if matches!(e, Error::Timeout { .. }) { 504 } else { 502 };
+                    self.mark(HttpResponse::new(status), "detail=origin-error")
+                }
+            };
+            http::write_response(&mut write, &response, &method).await?;
+            if close {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Whether a stored response may answer a request without revalidation (RFC 9111 §4.2)
+fn usable(asked: &CacheControl, control: &CacheControl, age: Duration, lifetime: Duration) -> bool {
+    if asked.max_age.is_some_and(|max_age| age > max_age) {
+        return false;
+    }
+    if asked.min_fresh.is_some_and(|min_fresh| lifetime < age + min_fresh) {
+        return false;
+    }
+    if age < lifetime {
+        return true;
+    }
+    if control.revalidate_when_stale() {
+        return false;
+    }
+    match asked.max_stale {
+        Some(None) => true,
+        Some(Some(max_stale)) => age - lifetime <= max_stale,
+        None => false,
+    }
+}
+
+/// Whether the request's conditionals hold for the stored response (RFC 9110 §13.2.2)
+fn not_modified(request: &HttpRequest, stored: &Stored) -> bool {
+    if let Some(tags) = request.headers.get_all("if-none-match") {
+        if tags.trim() == "*" {
+            return true;
+        }
+        let Some(etag) = stored.headers.get("etag") else { return false };
+        let weak = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+        return tags.split(',').any(|tag| weak(tag) == weak(etag));
+    }
+    let since = request
+        .headers
+        .get("if-modified-since")
+        .and_then(|since| httpdate::parse_http_date(since).ok());
+    match (since, stored.date("last-modified")) {
+        (Some(since), Some(modified)) => modified <= since,
+        _ => false,
+    }
+}
+
+/// A generation no earlier index of any proxy has used
+fn generation() -> String {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+    format!("{:x}.{:x}", nanos, NEXT.fetch_add(1, Ordering::Relaxed))
+}
+
+/// Key of the variant of `key` selected by the request's `names` fields
+fn variant(key: &str, generation: &str, names: &[String], request: &HttpRequest) -> String {
+    let fields: Vec<_> = names
+        .iter()
+        .map(|name| {
+            let value = request.headers.get_all(name).unwrap_or_default();
+            format!("{}={}", name, value.split_whitespace().collect::<Vec<_>>().join(" "))
+        })
+        .collect();
+    format!("{}#{}#{}", key, generation, fields.join("&"))
+}
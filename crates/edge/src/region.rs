@@ -0,0 +1,88 @@
+//! Regions and the latencies between them
+
+use std::collections::HashMap;
+use std::fmt;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// Identifier of an edge region, e.g. `us-east`
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Region(String);
+
+impl Region {
+    pub fn new(id: impl Into<String>) -> Self {
+        Self(id.into())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl From<&str> for Region {
+    fn from(id: &str) -> Self {
+        Self::new(id)
+    }
+}
+
+impl From<String> for Region {
+    fn from(id: String) -> Self {
+        Self(id)
+    }
+}
+
+impl From<&Region> for Region {
+    fn from(region: &Region) -> Self {
+        region.clone()
+    }
+}
+
+/// Round-trip latencies between regions
+///
+/// Links are symmetric. Regions without a link are treated as farther
+/// than any linked region and ordered by name among themselves.
+#[derive(Debug, Clone, Default)]
+pub struct Topology {
+    latencies: HashMap<(Region, Region), Duration>,
+}
+
+impl Topology {
+    /// A topology with no links
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the latency between two regions
+    pub fn link(mut self, a: impl Into<Region>, b: impl Into<Region>, latency: Duration) -> Self {
+        let (a, b) = (a.into(), b.into());
+        self.latencies.insert((a.clone(), b.clone()), latency);
+        self.latencies.insert((b, a), latency);
+        self
+    }
+
+    /// Latency from `from` to `to`, zero within a region
+    pub fn latency(&self, from: &Region, to: &Region) -> Option<Duration> {
+        if from == to {
+            return Some(Duration::ZERO);
+        }
+        self.latencies.get(&(from.clone(), to.clone())).copied()
+    }
+
+    /// `regions` ordered nearest first as seen from `from`
+    pub fn nearest<'a>(&self, from: &Region, regions: impl IntoIterator<Item = &'a Region>) -> Vec<Region> {
+        let mut regions: Vec<_> = regions.into_iter().cloned().collect();
+        regions.sort_by(|a, b| {
+            let distance = |r: &Region| self.latency(from, r).unwrap_or(Duration::MAX);
+            distance(a).cmp(&distance(b)).then_with(|| a.cmp(b))
+        });
+        regions
+    }
+}
@@ -1,120 +1,510 @@
 use proc_macro::TokenStream;
 use quote::quote;
-use syn::{parse_macro_input, DeriveInput, AttributeArgs};
+use syn::{parse_macro_input, DeriveInput, ItemFn, ItemStruct};
 
 /// Implements the cache manifest for a type
 #[proc_macro_attribute]
-pub fn cache_manifest(attr: TokenStream, item: TokenStream) -> TokenStream {
-    let _args = parse_macro_input!(attr as AttributeArgs);
-    let input = parse_macro_input!(item as DeriveInput);
-    
-    // TODO: Implement manifest parsing and validation
-    
+pub fn cache_manifest(_attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut input = parse_macro_input!(item as ItemStruct);
+
+    let args = match parse::ManifestArgs::from_struct(&mut input) {
+        Ok(args) => args,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    if let Err(e) = validate::validate_manifest(&args) {
+        return e.to_compile_error().into();
+    }
+
+    let generated = codegen::generate_manifest_impl(&args);
     quote! {
         #input
-        
-        // Generated implementation will go here
+
+        #generated
     }.into()
 }
 
 /// Implements cache attributes for functions
 #[proc_macro_attribute]
 pub fn cache(attr: TokenStream, item: TokenStream) -> TokenStream {
-    let _args = parse_macro_input!(attr as AttributeArgs);
-    let input = parse_macro_input!(item as DeriveInput);
-    
-    // TODO: Implement cache attribute parsing and validation
-    
+    let mut args = parse::CacheArgs::default();
+    let parser = syn::meta::parser(|meta| args.parse_meta(meta));
+    parse_macro_input!(attr with parser);
+    let input = parse_macro_input!(item as ItemFn);
+
+    if let Err(e) = validate::validate_cache_attrs(&args) {
+        return e.to_compile_error().into();
+    }
+
+    let generated = codegen::generate_cache_impl(&args);
     quote! {
         #input
-        
-        // Generated implementation will go here
+
+        #generated
     }.into()
 }
 
 /// Derives the CacheStrategy trait
 #[proc_macro_derive(CacheStrategy)]
 pub fn derive_cache_strategy(input: TokenStream) -> TokenStream {
-    let input = parse_macro_input!(input as DeriveInput);
-    
+    let _input = parse_macro_input!(input as DeriveInput);
+
     // TODO: Implement strategy trait derivation
-    
+
     quote! {
         // Generated implementation will go here
     }.into()
 }
 
 mod parse {
-    use syn::{parse::{Parse, ParseStream}, Result};
-    use syn::punctuated::Punctuated;
-    use syn::Token;
-    
+    use syn::meta::ParseNestedMeta;
+    use syn::{Expr, ExprArray, ExprLit, Ident, ItemStruct, Lit, LitInt, LitStr, Meta, Result, Visibility};
+
+    /// Helper attributes understood on manifest fields
+    const FIELD_ATTRIBUTES: &[&str] = &[
+        "owns",
+        "invalidates",
+        "client_primary",
+        "edge_primary",
+        "server_primary",
+        "fallback",
+        "strategy",
+        "max_size",
+        "max_keys",
+        "lifetime",
+        "streaming",
+        "chunk_size",
+        "warm_strategy",
+        "prefetch",
+        "backfill",
+        "with_offline",
+    ];
+
     /// Parses cache manifest attributes
     pub struct ManifestArgs {
+        pub name: Ident,
+        pub vis: Visibility,
         pub patterns: Vec<PatternDef>,
+    }
+
+    /// One manifest field and the pattern it owns
+    pub struct PatternDef {
+        pub field: Ident,
+        pub owns: Option<LitStr>,
+        pub invalidates: Vec<LitStr>,
         pub layer: Option<LayerDef>,
-        pub strategy: Option<StrategyDef>,
+        pub fallback: Option<LayerDef>,
+        pub strategies: Vec<StrategyDef>,
+        /// `#[max_size = "50MB"]` in bytes
+        pub max_bytes: Option<Quota>,
+        /// `#[max_keys = 10000]`
+        pub max_keys: Option<Quota>,
+        /// `#[with_offline]`: the pattern must allow client storage and no
+        /// strategy that forbids it. Only checked; `client::Client` queues
+        /// the writes, so no code is generated for it.
+        pub offline: Option<proc_macro2::Span>,
+    }
+
+    /// A layer named by `#[client_primary]` or `#[fallback = "edge"]`
+    pub struct LayerDef {
+        pub layer: core::Layer,
+        pub span: proc_macro2::Span,
+    }
+
+    /// A quota limit and the attribute that set it
+    pub struct Quota {
+        pub limit: usize,
+        pub span: proc_macro2::Span,
     }
-    
-    impl Parse for ManifestArgs {
-        fn parse(_input: ParseStream) -> Result<Self> {
-            // TODO: Implement attribute parsing
+
+    /// A strategy named by `#[strategy = "offline_first"]`
+    pub struct StrategyDef {
+        pub strategy: core::Strategy,
+        pub name: LitStr,
+    }
+
+    impl ManifestArgs {
+        /// Read the manifest from field attributes, removing them from the struct
+        pub fn from_struct(input: &mut ItemStruct) -> Result<Self> {
+            let mut patterns = Vec::new();
+            for field in input.fields.iter_mut() {
+                let ident = field.ident.clone().ok_or_else(|| {
+                    syn::Error::new_spanned(&*field, "cache manifest fields must be named")
+                })?;
+                let mut def = PatternDef {
+                    field: ident,
+                    owns: None,
+                    invalidates: Vec::new(),
+                    layer: None,
+                    fallback: None,
+                    strategies: Vec::new(),
+                    max_bytes: None,
+                    max_keys: None,
+                    offline: None,
+                };
+                for attr in &field.attrs {
+                    let path = attr.path();
+                    let Some(name) = FIELD_ATTRIBUTES.iter().find(|n| path.is_ident(n)) else {
+                        continue;
+                    };
+                    match *name {
+                        "owns" => def.owns = Some(string_value(&attr.meta)?),
+                        "invalidates" => def.invalidates.extend(string_values(&attr.meta)?),
+                        "client_primary" | "edge_primary" | "server_primary" => {
+                            if def.layer.is_some() {
+                                return Err(syn::Error::new_spanned(
+                                    attr,
+                                    "a field can only have one primary layer",
+                                ));
+                            }
+                            let layer = name.trim_end_matches("_primary");
+                            def.layer = Some(LayerDef {
+                                layer: core::Layer::from_name(layer).expect("known layer"),
+                                span: path.get_ident().map(Ident::span).unwrap_or_else(proc_macro2::Span::call_site),
+                            });
+                        }
+                        "fallback" => def.fallback = Some(layer_value(&attr.meta)?),
+                        "strategy" => def.strategies.push(strategy_value(&attr.meta)?),
+                        "max_size" => {
+                            let size = string_value(&attr.meta)?;
+                            let limit = core::parse_size(&size.value())
+                                .map_err(|e| syn::Error::new_spanned(&size, e.to_string()))?;
+                            def.max_bytes = Some(Quota { limit, span: size.span() });
+                        }
+                        "max_keys" => {
+                            let keys = int_value(&attr.meta)?;
+                            def.max_keys = Some(Quota { limit: keys.base10_parse()?, span: keys.span() });
+                        }
+                        "with_offline" => {
+                            attr.meta.require_path_only()?;
+                            def.offline = path.get_ident().map(Ident::span);
+                        }
+                        _ => {}
+                    }
+                }
+                field
+                    .attrs
+                    .retain(|attr| !FIELD_ATTRIBUTES.iter().any(|n| attr.path().is_ident(n)));
+                patterns.push(def);
+            }
             Ok(ManifestArgs {
-                patterns: vec![],
-                layer: None,
-                strategy: None,
+                name: input.ident.clone(),
+                vis: input.vis.clone(),
+                patterns,
             })
         }
     }
-    
+
     /// Parses cache operation attributes
+    #[derive(Default)]
     pub struct CacheArgs {
-        pub owns: Option<String>,
-        pub borrows: Vec<String>,
-        pub invalidates: Vec<String>,
-    }
-    
-    impl Parse for CacheArgs {
-        fn parse(_input: ParseStream) -> Result<Self> {
-            // TODO: Implement attribute parsing
-            Ok(CacheArgs {
-                owns: None,
-                borrows: vec![],
-                invalidates: vec![],
-            })
+        pub owns: Option<LitStr>,
+        pub borrows: Vec<LitStr>,
+        pub invalidates: Vec<LitStr>,
+        pub strategy: Option<StrategyDef>,
+        pub follows: Option<LitStr>,
+    }
+
+    impl CacheArgs {
+        /// Parse one `key = value` pair of `#[cache(...)]`
+        pub fn parse_meta(&mut self, meta: ParseNestedMeta) -> Result<()> {
+            if meta.path.is_ident("owns") {
+                self.owns = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("borrows") {
+                self.borrows.extend(nested_strings(&meta.value()?.parse()?)?);
+            } else if meta.path.is_ident("invalidates") {
+                self.invalidates.extend(nested_strings(&meta.value()?.parse()?)?);
+            } else if meta.path.is_ident("strategy") {
+                self.strategy = Some(strategy_def(meta.value()?.parse()?)?);
+            } else if meta.path.is_ident("follows") {
+                self.follows = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("lifetime") || meta.path.is_ident("exclusive") {
+                let _: LitStr = meta.value()?.parse()?;
+            } else {
+                return Err(meta.error("unknown cache attribute"));
+            }
+            Ok(())
+        }
+    }
+
+    fn string_value(meta: &Meta) -> Result<LitStr> {
+        match &meta.require_name_value()?.value {
+            Expr::Lit(ExprLit { lit: Lit::Str(s), .. }) => Ok(s.clone()),
+            other => Err(syn::Error::new_spanned(other, "expected a string literal")),
         }
     }
+
+    fn int_value(meta: &Meta) -> Result<LitInt> {
+        match &meta.require_name_value()?.value {
+            Expr::Lit(ExprLit { lit: Lit::Int(n), .. }) => Ok(n.clone()),
+            other => Err(syn::Error::new_spanned(other, "expected an integer literal")),
+        }
+    }
+
+    fn string_values(meta: &Meta) -> Result<Vec<LitStr>> {
+        nested_strings(&meta.require_name_value()?.value)
+    }
+
+    /// A string literal or an array of them
+    fn nested_strings(expr: &Expr) -> Result<Vec<LitStr>> {
+        match expr {
+            Expr::Lit(ExprLit { lit: Lit::Str(s), .. }) => Ok(vec![s.clone()]),
+            Expr::Array(ExprArray { elems, .. }) => {
+                let mut values = Vec::new();
+                for elem in elems {
+                    values.extend(nested_strings(elem)?);
+                }
+                Ok(values)
+            }
+            other => Err(syn::Error::new_spanned(other, "expected a string literal or an array of them")),
+        }
+    }
+
+    fn layer_value(meta: &Meta) -> Result<LayerDef> {
+        let name = string_value(meta)?;
+        let layer = core::Layer::from_name(&name.value()).ok_or_else(|| {
+            syn::Error::new_spanned(&name, "unknown layer, expected \"client\", \"edge\" or \"server\"")
+        })?;
+        Ok(LayerDef { layer, span: name.span() })
+    }
+
+    fn strategy_value(meta: &Meta) -> Result<StrategyDef> {
+        strategy_def(string_value(meta)?)
+    }
+
+    fn strategy_def(name: LitStr) -> Result<StrategyDef> {
+        let strategy = core::Strategy::from_name(&name.value()).ok_or_else(|| {
+            syn::Error::new_spanned(&name, format!("unknown strategy {:?}", name.value()))
+        })?;
+        Ok(StrategyDef { strategy, name })
+    }
 }
 
 mod validate {
+    use super::parse;
+    use core::{Layer, Ownership, OwnershipGraph, Strategy};
     use syn::Error;
-    
+
     /// Validates cache manifest structure
     pub fn validate_manifest(args: &parse::ManifestArgs) -> Result<(), Error> {
-        // TODO: Implement validation
+        let graph = OwnershipGraph::new();
+        for def in &args.patterns {
+            validate_strategies(def)?;
+
+            let Some(owns) = &def.owns else {
+                if let Some(quota) = def.max_bytes.as_ref().or(def.max_keys.as_ref()) {
+                    return Err(Error::new(quota.span, "a quota needs an #[owns] pattern to apply to"));
+                }
+                continue;
+            };
+            let layer = def.layer.as_ref().map(|l| l.layer).unwrap_or(Layer::Server);
+            graph
+                .add(Ownership::new(owns.value(), def.field.to_string(), layer))
+                .map_err(|e| Error::new_spanned(owns, e.to_string()))?;
+            for target in &def.invalidates {
+                graph.add_invalidation(&owns.value(), &target.value());
+            }
+        }
+        if let Some(cycle) = graph.dependency_cycle() {
+            return Err(Error::new_spanned(
+                &args.name,
+                format!("circular dependency: {}", cycle.join(" -> ")),
+            ));
+        }
         Ok(())
     }
-    
+
+    /// Strategies and layers on one field must be able to work together
+    fn validate_strategies(def: &parse::PatternDef) -> Result<(), Error> {
+        for (i, a) in def.strategies.iter().enumerate() {
+            for b in &def.strategies[..i] {
+                if !a.strategy.compatible_with(b.strategy) {
+                    return Err(Error::new_spanned(
+                        &a.name,
+                        format!("{:?} cannot be combined with {:?}", a.name.value(), b.name.value()),
+                    ));
+                }
+            }
+        }
+
+        let layers: Vec<Layer> = def.layer.iter().chain(&def.fallback).map(|l| l.layer).collect();
+        if let (Some(primary), Some(fallback)) = (&def.layer, &def.fallback) {
+            if primary.layer == fallback.layer {
+                return Err(Error::new(
+                    fallback.span,
+                    format!("fallback to the {} layer is unreachable, it is already primary", fallback.layer),
+                ));
+            }
+        }
+        if let Some(span) = def.offline {
+            if !layers.is_empty() && !layers.contains(&Layer::Client) {
+                return Err(Error::new(span, "#[with_offline] must include client storage"));
+            }
+            if let Some(strict) = def.strategies.iter().find(|s| !Strategy::ClientFirst.compatible_with(s.strategy)) {
+                return Err(Error::new_spanned(
+                    &strict.name,
+                    format!("{:?} cannot be combined with #[with_offline]", strict.name.value()),
+                ));
+            }
+        }
+        for def in &def.strategies {
+            let needs_client = def.strategy == Strategy::ClientFirst;
+            let forbids_client = !Strategy::ClientFirst.compatible_with(def.strategy);
+            if needs_client && !layers.is_empty() && !layers.contains(&Layer::Client) {
+                return Err(Error::new_spanned(
+                    &def.name,
+                    format!("{:?} must include client storage", def.name.value()),
+                ));
+            }
+            if forbids_client && layers.contains(&Layer::Client) {
+                return Err(Error::new_spanned(
+                    &def.name,
+                    format!("{:?} cannot be served from client storage", def.name.value()),
+                ));
+            }
+        }
+        Ok(())
+    }
+
     /// Validates cache attributes
     pub fn validate_cache_attrs(args: &parse::CacheArgs) -> Result<(), Error> {
-        // TODO: Implement validation
+        if let Some(owns) = &args.owns {
+            if let Some(borrow) = args.borrows.iter().find(|b| b.value() == owns.value()) {
+                return Err(Error::new_spanned(
+                    borrow,
+                    format!("{} cannot be both owned and borrowed", owns.value()),
+                ));
+            }
+        }
         Ok(())
     }
 }
 
 mod codegen {
-    use proc_macro2::TokenStream;
-    use quote::quote;
-    
+    use super::parse;
+    use core::Layer;
+    use proc_macro2::{Span, TokenStream};
+    use quote::{format_ident, quote};
+    use syn::Ident;
+
     /// Generates implementation for cache manifest
-    pub fn generate_manifest_impl(_args: &parse::ManifestArgs) -> TokenStream {
-        // TODO: Implement code generation
-        quote! {}
+    ///
+    /// Each owned field gets a `PatternToken` in a module named after the
+    /// manifest, e.g. `app_cache::UserData`, so typed layer handles can check
+    /// writes at compile time. `ownership()` builds the matching runtime graph
+    /// and `quotas()` the `#[max_size]` and `#[max_keys]` limits.
+    pub fn generate_manifest_impl(args: &parse::ManifestArgs) -> TokenStream {
+        let name = &args.name;
+        let vis = &args.vis;
+        let module = Ident::new(&snake_case(&name.to_string()), name.span());
+
+        let mut tokens = Vec::new();
+        let mut ownership = Vec::new();
+        let mut quotas = Vec::new();
+        for def in &args.patterns {
+            let Some(owns) = &def.owns else { continue };
+            let token = format_ident!("{}", pascal_case(&def.field.to_string()), span = def.field.span());
+            let owner = def.field.to_string();
+            let layer = def.layer.as_ref().map(|l| l.layer).unwrap_or(Layer::Server);
+            let kind = Ident::new(layer_kind(layer), Span::call_site());
+            let variant = kind.clone();
+            let doc = format!("Keys matching `{}`, owned on the {} layer", owns.value(), layer);
+
+            tokens.push(quote! {
+                #[doc = #doc]
+                #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+                pub struct #token;
+
+                impl ::stateless::typed::__private::Generated for #token {}
+
+                impl ::stateless::typed::PatternToken for #token {
+                    type Owner = ::stateless::typed::#kind;
+                    const PATTERN: &'static str = #owns;
+                    const OWNER: &'static str = #owner;
+                }
+            });
+            ownership.push(quote! {
+                graph
+                    .add(::stateless::Ownership::new(#owns, #owner, ::stateless::Layer::#variant))
+                    .expect("manifest patterns are validated at compile time");
+            });
+            for target in &def.invalidates {
+                ownership.push(quote! {
+                    graph.add_invalidation(#owns, #target);
+                });
+            }
+            if def.max_bytes.is_some() || def.max_keys.is_some() {
+                let max_bytes = def.max_bytes.as_ref().map(|q| q.limit);
+                let max_keys = def.max_keys.as_ref().map(|q| q.limit);
+                let max_bytes = max_bytes.map(|n| quote!(.max_bytes(#n)));
+                let max_keys = max_keys.map(|n| quote!(.max_keys(#n)));
+                quotas.push(quote! {
+                    ::stateless::QuotaPolicy::new(#owns) #max_bytes #max_keys
+                });
+            }
+        }
+
+        quote! {
+            #[allow(dead_code)]
+            #vis mod #module {
+                #(#tokens)*
+            }
+
+            #[allow(dead_code)]
+            impl #name {
+                /// Ownership declared by the manifest
+                #vis fn ownership() -> ::stateless::OwnershipGraph {
+                    let graph = ::stateless::OwnershipGraph::new();
+                    #(#ownership)*
+                    graph
+                }
+
+                /// Quotas declared by the manifest, one per pattern with limits
+                #vis fn quotas() -> ::std::vec::Vec<::stateless::QuotaPolicy> {
+                    ::std::vec![#(#quotas),*]
+                }
+            }
+        }
+    }
+
+    fn layer_kind(layer: Layer) -> &'static str {
+        match layer {
+            Layer::Client => "Client",
+            Layer::Edge => "Edge",
+            Layer::Server => "Server",
+        }
+    }
+
+    fn pascal_case(name: &str) -> String {
+        name.split('_')
+            .filter(|part| !part.is_empty())
+            .map(|part| {
+                let mut chars = part.chars();
+                chars
+                    .next()
+                    .map(|c| c.to_ascii_uppercase().to_string() + chars.as_str())
+                    .unwrap_or_default()
+            })
+            .collect()
     }
-    
+
+    fn snake_case(name: &str) -> String {
+        let mut snake = String::new();
+        for (i, c) in name.char_indices() {
+            if c.is_ascii_uppercase() {
+                if i > 0 {
+                    snake.push('_');
+                }
+                snake.push(c.to_ascii_lowercase());
+            } else {
+                snake.push(c);
+            }
+        }
+        snake
+    }
+
     /// Generates implementation for cache attributes
     pub fn generate_cache_impl(_args: &parse::CacheArgs) -> TokenStream {
         // TODO: Implement code generation
         quote! {}
     }
-} 
\ No newline at end of file
+}
@@ -0,0 +1,154 @@
+//! Health probes for server subsystems and the HTTP health endpoints
+
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use core::{ComponentHealth, HealthCheck, HealthChecker, HealthReport};
+use http_body_util::Full;
+use hyper::body::Incoming;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use parking_lot::Mutex;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+
+/// Handles of the per-shard maintenance tasks
+pub(crate) type MaintenanceTasks = Arc<Mutex<Vec<JoinHandle<()>>>>;
+
+/// Checks that every shard's maintenance task is still running
+pub(crate) struct ShardProbe {
+    pub(crate) num_shards: usize,
+    pub(crate) tasks: MaintenanceTasks,
+}
+
+#[async_trait]
+impl HealthCheck for ShardProbe {
+    async fn check(&self) -> ComponentHealth {
+        let tasks = self.tasks.lock();
+        if tasks.is_empty() {
+            return ComponentHealth::degraded("shards", "maintenance not started").critical();
+        }
+        let stopped = tasks.iter().filter(|t| t.is_finished()).count();
+        if stopped > 0 {
+            ComponentHealth::unhealthy(
+                "shards",
+                format!("{} of {} shard maintenance tasks stopped", stopped, self.num_shards),
+            )
+            .critical()
+        } else {
+            ComponentHealth::healthy("shards").critical()
+        }
+    }
+}
+
+/// Checks that the data directory accepts writes
+pub(crate) struct StorageProbe {
+    pub(crate) data_dir: PathBuf,
+}
+
+#[async_trait]
+impl HealthCheck for StorageProbe {
+    async fn check(&self) -> ComponentHealth {
+        let start = Instant::now();
+        let path = self.data_dir.join(".health");
+        let result = async {
+            tokio::fs::write(&path, b"ok").await?;
+            tokio::fs::remove_file(&path).await
+        }
+        .await;
+        let mut health = match result {
+            Ok(()) => ComponentHealth::healthy("storage"),
+            Err(e) => ComponentHealth::unhealthy(
+                "storage",
+                format!("{} is not writable: {}", self.data_dir.display(), e),
+            ),
+        };
+        health.latency = start.elapsed();
+        health.critical()
+    }
+}
+
+/// Checks that the listener accepts connections
+pub(crate) struct NetworkProbe {
+    pub(crate) listen_addr: SocketAddr,
+}
+
+#[async_trait]
+impl HealthCheck for NetworkProbe {
+    async fn check(&self) -> ComponentHealth {
+        let start = Instant::now();
+        let connect = TcpStream::connect(self.listen_addr);
+        let mut health = match tokio::time::timeout(Duration::from_secs(1), connect).await {
+            Ok(Ok(_)) => ComponentHealth::healthy("network"),
+            Ok(Err(e)) => ComponentHealth::unhealthy(
+                "network",
+                format!("{} is not accepting connections: {}", self.listen_addr, e),
+            ),
+            Err(_) => ComponentHealth::unhealthy(
+                "network",
+                format!("connecting to {} timed out", self.listen_addr),
+            ),
+        };
+        health.latency = start.elapsed();
+        health
+    }
+}
+
+/// Serve `/health`, `/health/live` and `/health/ready` until the listener fails
+pub async fn serve(health: Arc<HealthChecker>, listener: TcpListener) -> core::Result<()> {
+    loop {
+        let (stream, _) = listener
+            .accept()
+            .await
+            .map_err(|e| core::Error::network(None, "accepting health check", e))?;
+        let health = health.clone();
+        tokio::spawn(async move {
+            let service = service_fn(move |req: Request<Incoming>| {
+                let health = health.clone();
+                async move { Ok::<_, Infallible>(respond(&health, req.uri().path()).await) }
+            });
+            if let Err(e) = http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
+                tracing::debug!(error = %e, "health connection failed");
+            }
+        });
+    }
+}
+
+async fn respond(health: &HealthChecker, path: &str) -> Response<Full<Bytes>> {
+    let ok = |report: &HealthReport, passing: bool| {
+        let status = if passing {
+            StatusCode::OK
+        } else {
+            StatusCode::SERVICE_UNAVAILABLE
+        };
+        Response::builder()
+            .status(status)
+            .header("content-type", "application/json")
+            .body(Full::new(Bytes::from(report.to_json().to_string())))
+            .unwrap()
+    };
+    match path {
+        "/health" | "/health/ready" => {
+            let report = health.check().await;
+            ok(&report, report.is_ready())
+        }
+        "/health/live" => {
+            let report = health.check().await;
+            ok(&report, report.is_live())
+        }
+        _ => Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Full::new(Bytes::new()))
+            .unwrap(),
+    }
+}
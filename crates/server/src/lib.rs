@@ -4,6 +4,7 @@ mod shard;
 mod storage;
 mod network;
 mod protocol;
+mod health;
 
 use core::prelude::*;
 use std::sync::Arc;
@@ -28,6 +29,8 @@ pub struct NetworkConfig {
     pub listen_addr: std::net::SocketAddr,
     /// TLS configuration
     pub tls: Option<TlsConfig>,
+    /// Address for the HTTP health endpoints
+    pub health_addr: Option<std::net::SocketAddr>,
 }
 
 /// TLS configuration
@@ -45,6 +48,8 @@ pub struct Server {
     shards: Vec<Arc<shard::Shard>>,
     storage: Arc<storage::Storage>,
     network: Arc<network::Network>,
+    health: Arc<HealthChecker>,
+    maintenance: health::MaintenanceTasks,
 }
 
 impl Server {
@@ -64,19 +69,54 @@ impl Server {
         // Initialize network
         let network = Arc::new(network::Network::new(&config)?);
         
+        // Register subsystem health probes
+        let maintenance = health::MaintenanceTasks::default();
+        let health = Arc::new(HealthChecker::new());
+        health
+            .register(health::ShardProbe {
+                num_shards,
+                tasks: maintenance.clone(),
+            })
+            .register(health::StorageProbe {
+                data_dir: config.data_dir.clone(),
+            })
+            .register(health::NetworkProbe {
+                listen_addr: config.network.listen_addr,
+            });
+        
         Ok(Self {
             config,
             shards,
             storage,
             network,
+            health,
+            maintenance,
         })
     }
     
+    /// Health checker for the server's subsystems; cache layers can be registered on it
+    pub fn health(&self) -> &Arc<HealthChecker> {
+        &self.health
+    }
+    
+    /// Probe all subsystems and registered layers
+    pub async fn health_check(&self) -> HealthReport {
+        self.health.check().await
+    }
+    
     /// Start the server
     pub async fn run(&self) -> core::Result<()> {
         // Start background tasks
         self.start_background_tasks();
         
+        // Start health endpoints
+        if let Some(addr) = self.config.network.health_addr {
+            let listener = tokio::net::TcpListener::bind(addr)
+                .await
+                .map_err(|e| core::Error::network(None, format!("binding health endpoint {}", addr), e))?;
+            tokio::spawn(health::serve(self.health.clone(), listener));
+        }
+        
         // Start network server
         self.network.run(self.shards.clone()).await
     }
@@ -84,11 +124,12 @@ impl Server {
     /// Start background maintenance tasks
     fn start_background_tasks(&self) {
         // Shard maintenance
+        let mut maintenance = self.maintenance.lock();
         for shard in &self.shards {
             let shard = shard.clone();
-            tokio::spawn(async move {
+            maintenance.push(tokio::spawn(async move {
                 shard.run_maintenance().await;
-            });
+            }));
         }
         
         // Storage maintenance
@@ -100,6 +141,7 @@ impl Server {
 }
 
 // Re-exports
+pub use health::serve as serve_health;
 pub use shard::Shard;
 pub use storage::Storage;
 pub use network::Network;
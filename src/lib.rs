@@ -4,6 +4,8 @@
 //! applied to cache consistency and performance optimization.
 
 pub use core::{Cache, CacheEntry, Pattern, Strategy, Layer, Error, Result};
+pub use core::{Ownership, OwnershipGraph, LayerCoordinator, QuotaPolicy};
+pub use core::{typed, ClientLayer, EdgeLayer, ServerLayer, Key, PatternToken};
 pub use macros::{cache_manifest, cache, CacheStrategy};
 
 #[cfg(feature = "redis-compat")]
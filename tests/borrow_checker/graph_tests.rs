@@ -0,0 +1,74 @@
+use core::prelude::*;
+use core::{EdgeType, Ownership};
+
+fn manifest() -> OwnershipGraph {
+    let graph = OwnershipGraph::new();
+    graph.add(Ownership::new("user:*", "user_data", Layer::Edge)).unwrap();
+    graph.add(Ownership::new("profile:*", "profiles", Layer::Client)).unwrap();
+    graph.add(Ownership::new("feed:*", "feeds", Layer::Server)).unwrap();
+    graph.add_borrow("analytics", "user:*");
+    graph.add_borrow("search", "user:*:name");
+    graph.add_invalidation("user:*", "profile:*");
+    graph.add_invalidation("profile:*", "feed:*");
+    graph
+}
+
+#[test]
+fn test_duplicate_owner_conflicts() {
+    let graph = manifest();
+    assert!(matches!(
+        graph.add(Ownership::new("user:*", "other", Layer::Server)),
+        Err(Error::PatternConflict { .. })
+    ));
+}
+
+#[test]
+fn test_debug_ownership_for_key() {
+    let report = manifest().debug_ownership("user:123");
+    
+    assert_eq!(report.pattern.as_deref(), Some("user:*"));
+    assert_eq!(report.owner.as_deref(), Some("user_data"));
+    assert_eq!(report.layer, Some(Layer::Edge));
+    assert_eq!(report.borrowers, vec!["analytics".to_string()]);
+    // Invalidation cascades through profile:* to feed:*
+    assert_eq!(report.invalidates, vec!["feed:*".to_string(), "profile:*".to_string()]);
+    assert!(report.invalidated_by.is_empty());
+}
+
+#[test]
+fn test_debug_ownership_for_pattern() {
+    let report = manifest().debug_ownership("profile:*");
+    
+    assert_eq!(report.owner.as_deref(), Some("profiles"));
+    assert_eq!(report.invalidates, vec!["feed:*".to_string()]);
+    assert_eq!(report.invalidated_by, vec!["user:*".to_string()]);
+    assert!(report.to_string().contains("owner: profiles (profile:*) on client"));
+    
+    let unowned = manifest().debug_ownership("cart:1");
+    assert!(unowned.owner.is_none());
+    assert!(unowned.to_string().contains("owner: -"));
+}
+
+#[test]
+fn test_dot_export() {
+    let dot = manifest().to_dot();
+    
+    assert!(dot.starts_with("digraph ownership {"));
+    assert!(dot.contains("subgraph cluster_edge {"));
+    assert!(dot.contains("\"user_data\" -> \"user:*\" [label=\"owns\"];"));
+    assert!(dot.contains("\"analytics\" -> \"user:*\" [label=\"borrows\", style=dashed];"));
+    assert!(dot.contains("\"user:*\" -> \"profile:*\" [label=\"invalidates\", color=red];"));
+}
+
+#[test]
+fn test_json_export() {
+    let json = manifest().to_json();
+    
+    assert_eq!(json["nodes"].as_array().unwrap().len(), 3);
+    assert_eq!(json["nodes"][0]["pattern"], "feed:*");
+    assert_eq!(json["nodes"][0]["layer"], "server");
+    
+    let edges = json["edges"].as_array().unwrap();
+    assert_eq!(edges.len(), 4);
+    assert!(edges.iter().any(|e| e["from"] == "search" && e["type"] == EdgeType::Borrows.as_str()));
+}
@@ -0,0 +1,3 @@
+mod graph_tests;
+mod transfer_tests;
+mod typed_tests;
@@ -0,0 +1,176 @@
+use std::sync::Arc;
+use std::time::Duration;
+use async_trait::async_trait;
+use tokio::sync::Semaphore;
+use core::prelude::*;
+use core::layer::CacheLayer;
+use core::EdgeType;
+use crate::common::layer::MemoryLayer;
+
+/// A memory layer whose writes wait until the test lets them through
+struct GatedLayer {
+    inner: MemoryLayer,
+    gate: Semaphore,
+}
+
+impl GatedLayer {
+    fn closed() -> Self {
+        Self { inner: MemoryLayer::default(), gate: Semaphore::new(0) }
+    }
+}
+
+#[async_trait]
+impl CacheLayer for GatedLayer {
+    async fn get(&self, key: &str) -> core::Result<Option<Vec<u8>>> {
+        self.inner.get(key).await
+    }
+
+    async fn set(&self, key: &str, value: Vec<u8>) -> core::Result<()> {
+        self.gate.acquire().await.unwrap().forget();
+        self.inner.set(key, value).await
+    }
+
+    async fn delete(&self, key: &str) -> core::Result<()> {
+        self.inner.delete(key).await
+    }
+
+    async fn keys(&self, pattern: &str) -> core::Result<Vec<String>> {
+        self.inner.keys(pattern).await
+    }
+}
+
+fn manifest() -> Arc<OwnershipGraph> {
+    let graph = OwnershipGraph::new();
+    graph.add(Ownership::new("user:*", "edge_users", Layer::Edge)).unwrap();
+    Arc::new(graph)
+}
+
+#[tokio::test]
+async fn test_transfer_migrates_between_layers() {
+    let edge = Arc::new(MemoryLayer::default());
+    let server = Arc::new(MemoryLayer::default());
+    let coordinator = LayerCoordinator::new(manifest())
+        .with_layer(Layer::Edge, edge.clone())
+        .with_layer(Layer::Server, server.clone());
+
+    coordinator.set("user:1", b"ada".to_vec()).await.unwrap();
+    coordinator.set("user:2", b"bob".to_vec()).await.unwrap();
+    let migrated = coordinator
+        .transfer("edge_users", Ownership::new("user:*", "UserService", Layer::Server))
+        .await
+        .unwrap();
+
+    assert_eq!(migrated, 2);
+    assert_eq!(server.get("user:1").await.unwrap(), Some(b"ada".to_vec()));
+    assert!(edge.get("user:1").await.unwrap().is_none());
+    let owner = coordinator.check_owner("user:1").unwrap();
+    assert_eq!((owner.owner(), owner.layer()), ("UserService", Layer::Server));
+
+    coordinator.set("user:3", b"cy".to_vec()).await.unwrap();
+    assert!(server.get("user:3").await.unwrap().is_some());
+}
+
+#[tokio::test]
+async fn test_transfer_requires_current_owner() {
+    let coordinator = LayerCoordinator::new(manifest())
+        .with_layer(Layer::Edge, Arc::new(MemoryLayer::default()))
+        .with_layer(Layer::Server, Arc::new(MemoryLayer::default()));
+
+    assert!(matches!(
+        coordinator.transfer("someone", Ownership::new("user:*", "UserService", Layer::Server)).await,
+        Err(Error::PatternConflict { .. })
+    ));
+    assert!(coordinator
+        .transfer("edge_users", Ownership::new("order:*", "Orders", Layer::Server))
+        .await
+        .is_err());
+    assert_eq!(coordinator.check_owner("user:*").unwrap().owner(), "edge_users");
+}
+
+#[tokio::test]
+async fn test_transfer_drains_in_flight_writes() {
+    let edge = Arc::new(GatedLayer::closed());
+    let server = Arc::new(MemoryLayer::default());
+    let coordinator = Arc::new(
+        LayerCoordinator::new(manifest())
+            .with_layer(Layer::Edge, edge.clone())
+            .with_layer(Layer::Server, server.clone()),
+    );
+
+    let write = tokio::spawn({
+        let coordinator = coordinator.clone();
+        async move { coordinator.set("user:1", b"ada".to_vec()).await }
+    });
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    let transfer = tokio::spawn({
+        let coordinator = coordinator.clone();
+        async move {
+            coordinator
+                .transfer("edge_users", Ownership::new("user:*", "UserService", Layer::Server))
+                .await
+        }
+    });
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert!(!transfer.is_finished());
+
+    edge.gate.add_permits(1);
+    write.await.unwrap().unwrap();
+    assert_eq!(transfer.await.unwrap().unwrap(), 1);
+    assert_eq!(server.get("user:1").await.unwrap(), Some(b"ada".to_vec()));
+}
+
+#[tokio::test]
+async fn test_transfer_times_out_on_stuck_writes() {
+    let edge = Arc::new(GatedLayer::closed());
+    let coordinator = Arc::new(
+        LayerCoordinator::new(manifest())
+            .with_layer(Layer::Edge, edge.clone())
+            .with_layer(Layer::Server, Arc::new(MemoryLayer::default()))
+            .with_drain_timeout(Duration::from_millis(20)),
+    );
+
+    let write = tokio::spawn({
+        let coordinator = coordinator.clone();
+        async move { coordinator.set("user:1", b"ada".to_vec()).await }
+    });
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    let result = coordinator
+        .transfer("edge_users", Ownership::new("user:*", "UserService", Layer::Server))
+        .await;
+    assert!(matches!(result, Err(Error::Timeout { .. })));
+
+    edge.gate.add_permits(1);
+    write.await.unwrap().unwrap();
+    assert_eq!(coordinator.check_owner("user:*").unwrap().layer(), Layer::Edge);
+}
+
+#[tokio::test]
+async fn test_borrow_and_release() {
+    let graph = manifest();
+    graph.add_borrow("search", "user:*");
+    let coordinator = LayerCoordinator::new(graph.clone());
+
+    let first = coordinator.borrow_from("edge_users", "user:*", "analytics").unwrap();
+    let second = coordinator.borrow_from("edge_users", "user:*", "analytics").unwrap();
+    assert_eq!(first.owner(), "edge_users");
+    assert!(graph.has_edge("analytics", "user:*", EdgeType::Borrows));
+
+    coordinator.release_borrow(first);
+    assert!(graph.has_edge("analytics", "user:*", EdgeType::Borrows));
+    coordinator.release_borrow(second);
+    assert!(!graph.has_edge("analytics", "user:*", EdgeType::Borrows));
+
+    // Borrows declared in the manifest outlive runtime borrows
+    let borrow = coordinator.borrow_from("edge_users", "user:*", "search").unwrap();
+    coordinator.release_borrow(borrow);
+    assert!(graph.has_edge("search", "user:*", EdgeType::Borrows));
+
+    assert!(matches!(
+        coordinator.borrow_from("someone", "user:*", "analytics"),
+        Err(Error::PatternConflict { .. })
+    ));
+    assert!(matches!(
+        coordinator.borrow_from("edge_users", "user:*", "edge_users"),
+        Err(Error::InvalidBorrowing { .. })
+    ));
+}
@@ -0,0 +1,87 @@
+use std::sync::Arc;
+use core::prelude::*;
+use stateless::cache_manifest;
+use crate::common::layer::MemoryLayer;
+
+struct UserStrategy;
+struct ProductStrategy;
+
+#[allow(dead_code)]
+#[cache_manifest]
+struct AppCache {
+    #[client_primary]
+    #[owns = "user:*"]
+    #[invalidates = "feed:*"]
+    user_data: UserStrategy,
+
+    #[edge_primary]
+    #[owns = "products:*"]
+    product_cache: ProductStrategy,
+}
+
+fn coordinator() -> Arc<LayerCoordinator> {
+    Arc::new(
+        LayerCoordinator::new(Arc::new(AppCache::ownership()))
+            .with_layer(Layer::Client, Arc::new(MemoryLayer::default()))
+            .with_layer(Layer::Edge, Arc::new(MemoryLayer::default()))
+            .with_layer(Layer::Server, Arc::new(MemoryLayer::default())),
+    )
+}
+
+#[test]
+fn test_manifest_generates_tokens() {
+    assert_eq!(<app_cache::UserData as PatternToken>::PATTERN, "user:*");
+    assert_eq!(<app_cache::ProductCache as PatternToken>::OWNER, "product_cache");
+
+    let graph = AppCache::ownership();
+    assert_eq!(graph.owner_of("user:1").unwrap().layer(), Layer::Client);
+    assert_eq!(graph.owner_of("products:1").unwrap().layer(), Layer::Edge);
+    assert_eq!(graph.invalidation_targets("user:*"), vec!["feed:*".to_string()]);
+}
+
+#[tokio::test]
+async fn test_owner_handle_writes() {
+    let coordinator = coordinator();
+    let key = app_cache::UserData::key("user:123").unwrap();
+
+    // `coordinator.server().set(&key, ..)` does not compile: user:* is client-owned
+    coordinator.client().set(&key, b"ada".to_vec()).await.unwrap();
+    assert_eq!(coordinator.client().get(&key).await.unwrap(), Some(b"ada".to_vec()));
+    assert!(coordinator.server().get(&key).await.unwrap().is_none());
+
+    let product = app_cache::ProductCache::key("products:9").unwrap();
+    coordinator.edge().set(&product, b"lamp".to_vec()).await.unwrap();
+    coordinator.edge().delete(&product).await.unwrap();
+    assert!(coordinator.get("products:9").await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_keys_are_checked_against_patterns() {
+    assert!(matches!(
+        app_cache::UserData::key("products:1"),
+        Err(Error::LayerViolation { layer: Layer::Client, .. })
+    ));
+    assert_eq!(app_cache::UserData::key("user:7").unwrap().as_str(), "user:7");
+}
+
+#[tokio::test]
+async fn test_runtime_transfer_is_still_enforced() {
+    let coordinator = coordinator();
+    coordinator
+        .transfer("user_data", Ownership::new("user:*", "UserService", Layer::Server))
+        .await
+        .unwrap();
+
+    let key = app_cache::UserData::key("user:1").unwrap();
+    assert!(matches!(
+        coordinator.client().set(&key, b"ada".to_vec()).await,
+        Err(Error::LayerViolation { .. })
+    ));
+}
+
+#[test]
+fn test_layer_violations_fail_to_compile() {
+    // Writes through a handle whose layer does not own the key, and layers
+    // or pattern tokens defined outside a manifest
+    trybuild::TestCases::new().compile_fail("tests/ui/typed_*.rs");
+}
@@ -0,0 +1,133 @@
+use std::sync::{Arc, Mutex};
+use client::Client;
+use core::layer::CacheLayer;
+use core::prelude::*;
+use core::{ConflictLayer, MergeWith, RejectAndNotify};
+use crate::common::layer::MemoryLayer;
+
+fn server(conflicts: &Arc<Conflicts>) -> Arc<ConflictLayer<MemoryLayer>> {
+    Arc::new(ConflictLayer::new(MemoryLayer::default(), "server", conflicts.clone()))
+}
+
+#[tokio::test]
+async fn test_offline_edit_merged_on_reconnect() {
+    let conflicts = Arc::new(Conflicts::new());
+    conflicts.on_conflict("user:*:prefs", MergeWith::new(|conflict: &core::Conflict| {
+        [conflict.current.value.as_slice(), conflict.incoming.value.as_slice()].join(&b',')
+    }));
+    let server = server(&conflicts);
+    server.set("user:123:prefs", b"theme=dark".to_vec()).await.unwrap();
+
+    let client = Client::new(server.clone());
+    assert!(client.get("user:123:prefs").await.unwrap().is_some());
+    client.go_offline();
+    client.set("user:123:prefs", b"lang=fr".to_vec()).await.unwrap();
+    server.set("user:123:prefs", b"theme=light".to_vec()).await.unwrap();
+
+    let report = client.reconnect().await.unwrap();
+    assert_eq!((report.applied, report.resolved), (1, 1));
+    let merged = Some(b"theme=light,lang=fr".to_vec());
+    assert_eq!(server.get("user:123:prefs").await.unwrap(), merged);
+    assert_eq!(client.get("user:123:prefs").await.unwrap(), merged);
+
+    // The client now builds on the merged version
+    client.set("user:123:prefs", b"lang=de".to_vec()).await.unwrap();
+    assert_eq!(server.get("user:123:prefs").await.unwrap(), Some(b"lang=de".to_vec()));
+}
+
+#[tokio::test]
+async fn test_unconflicted_offline_edit_applies() {
+    let conflicts = Arc::new(Conflicts::new());
+    conflicts.on_conflict("user:*", RejectAndNotify::new(|_: &core::Conflict| {}));
+    let server = server(&conflicts);
+    server.set("user:123:prefs", b"theme=dark".to_vec()).await.unwrap();
+
+    let client = Client::new(server.clone());
+    client.get("user:123:prefs").await.unwrap();
+    client.go_offline();
+    client.set("user:123:prefs", b"theme=light".to_vec()).await.unwrap();
+
+    let report = client.reconnect().await.unwrap();
+    assert_eq!((report.applied, report.rejected, report.resolved), (1, 0, 0));
+    assert_eq!(server.get("user:123:prefs").await.unwrap(), Some(b"theme=light".to_vec()));
+}
+
+#[tokio::test]
+async fn test_rejected_conflict_is_reported() {
+    let conflicts = Arc::new(Conflicts::new());
+    let notified = Arc::new(Mutex::new(0));
+    let count = notified.clone();
+    conflicts.on_conflict("user:*", RejectAndNotify::new(move |_: &core::Conflict| {
+        *count.lock().unwrap() += 1;
+    }));
+    let server = server(&conflicts);
+    server.set("user:123:prefs", b"theme=dark".to_vec()).await.unwrap();
+
+    let client = Client::new(server.clone()).with_node("phone");
+    client.get("user:123:prefs").await.unwrap();
+    client.go_offline();
+    client.set("user:123:prefs", b"theme=blue".to_vec()).await.unwrap();
+    server.set("user:123:prefs", b"theme=light".to_vec()).await.unwrap();
+
+    let report = client.reconnect().await.unwrap();
+    assert_eq!((report.applied, report.rejected), (0, 1));
+    assert_eq!(*notified.lock().unwrap(), 1);
+    let rejections = client.take_rejections();
+    assert!(matches!(rejections[0].error, Error::Conflict { .. }));
+    assert_eq!(client.get("user:123:prefs").await.unwrap(), Some(b"theme=light".to_vec()));
+}
+
+#[tokio::test]
+async fn test_offline_increments_merge() {
+    let conflicts = Arc::new(Conflicts::new());
+    let server = server(&conflicts);
+    let edge = LayerCoordinator::new(Arc::new(OwnershipGraph::new())).with_layer(Layer::Server, server.clone());
+
+    let client = Client::new(server.clone());
+    assert_eq!(client.incr("page:home:likes", 1).await.unwrap(), 1);
+    client.go_offline();
+    assert_eq!(client.incr("page:home:likes", 2).await.unwrap(), 3);
+    assert_eq!(edge.incr("page:home:likes", "edge", 10).await.unwrap(), 11);
+
+    let report = client.reconnect().await.unwrap();
+    assert_eq!((report.applied, report.resolved), (1, 1));
+    assert_eq!(client.incr("page:home:likes", 0).await.unwrap(), 13);
+    assert_eq!(edge.incr("page:home:likes", "edge", 0).await.unwrap(), 13);
+}
+
+#[tokio::test]
+async fn test_offline_incr_needs_a_local_copy() {
+    let conflicts = Arc::new(Conflicts::new());
+    let server = server(&conflicts);
+    let edge = LayerCoordinator::new(Arc::new(OwnershipGraph::new())).with_layer(Layer::Server, server.clone());
+    edge.incr("page:home:likes", "edge", 5).await.unwrap();
+
+    let client = Client::new(server.clone()).with_node("phone");
+    client.go_offline();
+    let err = client.incr("page:home:likes", 1).await.unwrap_err();
+    assert!(matches!(err, Error::Network { .. }));
+    assert_eq!(client.pending(), 0);
+
+    client.reconnect().await.unwrap();
+    assert_eq!(client.incr("page:home:likes", 1).await.unwrap(), 6);
+}
+
+#[tokio::test]
+async fn test_default_nodes_count_separately() {
+    let conflicts = Arc::new(Conflicts::new());
+    let server = server(&conflicts);
+    let edge = LayerCoordinator::new(Arc::new(OwnershipGraph::new())).with_layer(Layer::Server, server.clone());
+
+    let phone = Client::new(server.clone());
+    let laptop = Client::new(server.clone());
+    assert_eq!(phone.incr("page:home:likes", 1).await.unwrap(), 1);
+    laptop.get("page:home:likes").await.unwrap();
+    phone.go_offline();
+    laptop.go_offline();
+    phone.incr("page:home:likes", 1).await.unwrap();
+    laptop.incr("page:home:likes", 1).await.unwrap();
+
+    phone.reconnect().await.unwrap();
+    laptop.reconnect().await.unwrap();
+    assert_eq!(edge.incr("page:home:likes", "edge", 0).await.unwrap(), 3);
+}
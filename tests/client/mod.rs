@@ -0,0 +1,5 @@
+mod conflict_tests;
+mod offline_tests;
+mod pool_tests;
+mod storage_tests;
+mod tracking_tests;
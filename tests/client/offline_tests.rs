@@ -0,0 +1,161 @@
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use async_trait::async_trait;
+use client::{Client, ClientConfig, Outbox, WriteOp};
+use core::layer::CacheLayer;
+use core::prelude::*;
+use core::SimNetwork;
+use crate::common::layer::MemoryLayer;
+use crate::common::test_dir;
+
+/// Upstream that owns `admin:*` elsewhere and records accepted writes in order
+#[derive(Default)]
+struct OwnerLayer {
+    inner: MemoryLayer,
+    accepted: Mutex<Vec<String>>,
+}
+
+impl OwnerLayer {
+    fn check(&self, key: &str) -> core::Result<()> {
+        if key.starts_with("admin:") {
+            return Err(Error::PatternConflict {
+                pattern: "admin:*".into(),
+                owner: "AdminService".into(),
+                requested_by: "client".into(),
+            });
+        }
+        self.accepted.lock().unwrap().push(key.to_string());
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl CacheLayer for OwnerLayer {
+    async fn get(&self, key: &str) -> core::Result<Option<Vec<u8>>> {
+        self.inner.get(key).await
+    }
+
+    async fn set(&self, key: &str, value: Vec<u8>) -> core::Result<()> {
+        self.check(key)?;
+        self.inner.set(key, value).await
+    }
+
+    async fn delete(&self, key: &str) -> core::Result<()> {
+        self.check(key)?;
+        self.inner.delete(key).await
+    }
+
+    async fn keys(&self, pattern: &str) -> core::Result<Vec<String>> {
+        self.inner.keys(pattern).await
+    }
+}
+
+#[tokio::test]
+async fn test_offline_writes_replay_in_order() {
+    let network = Arc::new(SimNetwork::new().with_local(Layer::Client));
+    let server = Arc::new(network.layer(Layer::Server));
+    let client = Client::new(server.clone());
+
+    network.simulate_offline(Layer::Client);
+    client.set("doc:1", b"draft".to_vec()).await.unwrap();
+    assert!(!client.is_online());
+    client.set("doc:2", b"notes".to_vec()).await.unwrap();
+    client.delete("doc:1").await.unwrap();
+    assert_eq!(client.get("doc:2").await.unwrap(), Some(b"notes".to_vec()));
+    assert_eq!(client.pending(), 3);
+
+    network.simulate_online(Layer::Client);
+    let report = client.reconnect().await.unwrap();
+    assert_eq!((report.applied, report.rejected, report.remaining), (3, 0, 0));
+    assert!(server.peek("doc:1").is_none());
+    assert_eq!(server.peek("doc:2"), Some(b"notes".to_vec()));
+}
+
+#[tokio::test]
+async fn test_rejected_writes_are_reported() {
+    let upstream = Arc::new(OwnerLayer::default());
+    let client = Client::new(upstream.clone());
+
+    client.go_offline();
+    client.set("doc:1", b"a".to_vec()).await.unwrap();
+    client.set("admin:1", b"root".to_vec()).await.unwrap();
+    client.set("doc:2", b"b".to_vec()).await.unwrap();
+
+    let report = client.reconnect().await.unwrap();
+    assert_eq!((report.applied, report.rejected), (2, 1));
+    assert_eq!(*upstream.accepted.lock().unwrap(), vec!["doc:1", "doc:2"]);
+
+    let rejections = client.take_rejections();
+    assert_eq!(rejections.len(), 1);
+    assert_eq!(rejections[0].op.key(), "admin:1");
+    assert!(matches!(rejections[0].error, Error::PatternConflict { .. }));
+    // The refused value no longer shadows upstream
+    assert!(client.get("admin:1").await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_outbox_survives_restart() {
+    let dir = test_dir();
+    let config = ClientConfig { data_dir: Some(dir.path().to_path_buf()), ..Default::default() };
+    let upstream = Arc::new(MemoryLayer::default());
+
+    {
+        let client = Client::open(config.clone(), upstream.clone()).unwrap();
+        client.go_offline();
+        client.set("doc:1", b"a".to_vec()).await.unwrap();
+        client.set("doc:2", b"b".to_vec()).await.unwrap();
+    }
+
+    let client = Client::open(config, upstream.clone()).unwrap();
+    assert_eq!(client.pending(), 2);
+    assert_eq!(client.reconnect().await.unwrap().applied, 2);
+    assert_eq!(upstream.get("doc:2").await.unwrap(), Some(b"b".to_vec()));
+    assert_eq!(Outbox::open(dir.path().join("outbox.log")).unwrap().len(), 0);
+}
+
+#[test]
+fn test_outbox_ignores_torn_writes() {
+    let dir = test_dir();
+    let path = dir.path().join("outbox.log");
+    let outbox = Outbox::open(&path).unwrap();
+    let first = outbox.push(WriteOp::Set { key: "a".into(), value: vec![1] }).unwrap();
+    outbox.push(WriteOp::Delete { key: "b".into() }).unwrap();
+    outbox.ack(first).unwrap();
+    drop(outbox);
+
+    let mut log = std::fs::OpenOptions::new().append(true).open(&path).unwrap();
+    write!(log, "{{\"record\":\"write\",\"seq\":3,\"op\":\"se").unwrap();
+    drop(log);
+
+    let outbox = Outbox::open(&path).unwrap();
+    let pending = outbox.pending();
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0].op, WriteOp::Delete { key: "b".into() });
+    assert_eq!(outbox.push(WriteOp::Delete { key: "c".into() }).unwrap(), 3);
+}
+
+#[test]
+fn test_outbox_refuses_corrupt_records() {
+    let dir = test_dir();
+    let path = dir.path().join("outbox.log");
+    let outbox = Outbox::open(&path).unwrap();
+    outbox.push(WriteOp::Delete { key: "a".into() }).unwrap();
+    drop(outbox);
+
+    let mut log = std::fs::OpenOptions::new().append(true).open(&path).unwrap();
+    writeln!(log, "not a record").unwrap();
+    drop(log);
+    let outbox = Outbox::open(&path).unwrap();
+    outbox.push(WriteOp::Delete { key: "b".into() }).unwrap();
+    drop(outbox);
+
+    // Corruption followed by later writes must not compact them away
+    let mut log = std::fs::OpenOptions::new().append(true).open(&path).unwrap();
+    writeln!(log, "not a record").unwrap();
+    writeln!(log, "{{\"record\":\"write\",\"seq\":3,\"op\":\"delete\",\"key\":\"c\"}}").unwrap();
+    drop(log);
+    let err = Outbox::open(&path).err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::Serialization);
+    assert!(err.to_string().contains("line 3"), "{}", err);
+    assert!(std::fs::read_to_string(&path).unwrap().contains("\"key\":\"c\""));
+}
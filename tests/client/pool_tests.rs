@@ -0,0 +1,185 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use async_trait::async_trait;
+use client::{Client, Pool, PoolConfig};
+use core::layer::CacheLayer;
+use core::prelude::*;
+use core::wire;
+use tokio::net::TcpListener;
+use tokio::sync::Semaphore;
+use tokio::task::JoinHandle;
+use crate::common::layer::MemoryLayer;
+
+/// Holds reads of `hold:*` keys until released and records every read
+struct GateLayer {
+    inner: MemoryLayer,
+    seen: Mutex<Vec<String>>,
+    gate: Semaphore,
+}
+
+impl GateLayer {
+    fn new() -> Self {
+        Self { inner: MemoryLayer::default(), seen: Mutex::new(Vec::new()), gate: Semaphore::new(0) }
+    }
+
+    fn seen(&self) -> Vec<String> {
+        self.seen.lock().unwrap().clone()
+    }
+
+    fn release(&self, n: usize) {
+        self.gate.add_permits(n);
+    }
+}
+
+#[async_trait]
+impl CacheLayer for GateLayer {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        self.seen.lock().unwrap().push(key.to_string());
+        if key.starts_with("hold:") {
+            self.gate.acquire().await.unwrap().forget();
+        }
+        self.inner.get(key).await
+    }
+
+    async fn set(&self, key: &str, value: Vec<u8>) -> Result<()> {
+        if key.starts_with("admin:") {
+            return Err(Error::PatternConflict {
+                pattern: "admin:*".into(),
+                owner: "server".into(),
+                requested_by: "client".into(),
+            });
+        }
+        self.inner.set(key, value).await
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        self.inner.delete(key).await
+    }
+
+    async fn keys(&self, pattern: &str) -> Result<Vec<String>> {
+        self.inner.keys(pattern).await
+    }
+}
+
+/// Serve `layer` on a local port, keeping each connection's task so tests can drop them
+async fn serve(layer: Arc<dyn CacheLayer>) -> (String, Arc<Mutex<Vec<JoinHandle<()>>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let connections = Arc::new(Mutex::new(Vec::new()));
+    let tasks = connections.clone();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let layer = layer.clone();
+            tasks.lock().unwrap().push(tokio::spawn(async move {
+                let _ = wire::serve_connection(stream, layer).await;
+            }));
+        }
+    });
+    (addr, connections)
+}
+
+async fn until(mut done: impl FnMut() -> bool) {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while !done() {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+    })
+    .await
+    .expect("condition not reached");
+}
+
+#[tokio::test]
+async fn test_pool_serves_client() {
+    let server = Arc::new(GateLayer::new());
+    let (addr, _) = serve(server.clone()).await;
+    let pool = Arc::new(Pool::connect(PoolConfig::new(addr)).await.unwrap());
+    assert_eq!(pool.stats().connections, 1);
+
+    pool.set("user:123:profile", b"Alice".to_vec()).await.unwrap();
+    assert_eq!(pool.keys("user:*").await.unwrap(), vec!["user:123:profile"]);
+    let err = pool.set("admin:config", b"x".to_vec()).await.unwrap_err();
+    assert!(matches!(err, Error::PatternConflict { ref owner, .. } if owner == "server"));
+
+    let client = Client::new(pool.clone());
+    assert_eq!(client.get("user:123:profile").await.unwrap(), Some(b"Alice".to_vec()));
+    client.delete("user:123:profile").await.unwrap();
+    assert_eq!(pool.get("user:123:profile").await.unwrap(), None);
+
+    let mut config = PoolConfig::new("127.0.0.1:1");
+    config.min_connections = 4;
+    config.max_connections = 2;
+    assert!(matches!(Pool::new(config), Err(Error::InvalidConfig(_))));
+}
+
+#[tokio::test]
+async fn test_requests_are_multiplexed() {
+    let server = Arc::new(GateLayer::new());
+    server.set("user:1", b"fast".to_vec()).await.unwrap();
+    let (addr, _) = serve(server.clone()).await;
+    let mut config = PoolConfig::new(addr);
+    config.max_connections = 1;
+    let pool = Arc::new(Pool::connect(config).await.unwrap());
+
+    let slow = tokio::spawn({
+        let pool = pool.clone();
+        async move { pool.get("hold:1").await }
+    });
+    until(|| server.seen().contains(&"hold:1".to_string())).await;
+
+    // Later requests on the same connection are answered while the first waits
+    let fast = futures::future::join_all((0..16).map(|_| pool.get("user:1"))).await;
+    assert!(fast.into_iter().all(|r| r.unwrap() == Some(b"fast".to_vec())));
+    assert_eq!(pool.stats().connections, 1);
+    assert_eq!(pool.stats().in_flight, 1);
+
+    server.release(1);
+    assert_eq!(slow.await.unwrap().unwrap(), None);
+}
+
+#[tokio::test]
+async fn test_pool_grows_to_max_then_queues_in_order() {
+    let server = Arc::new(GateLayer::new());
+    let (addr, _) = serve(server.clone()).await;
+    let mut config = PoolConfig::new(addr);
+    config.max_connections = 2;
+    config.max_in_flight = 1;
+    let pool = Arc::new(Pool::connect(config).await.unwrap());
+
+    let mut requests = Vec::new();
+    for i in 0..6 {
+        let request = pool.clone();
+        requests.push(tokio::spawn(async move { request.get(&format!("hold:{}", i)).await }));
+        // Each request reaches the server or queues before the next is made
+        until(|| server.seen().len() == (i + 1).min(2) && pool.stats().waiting == i.saturating_sub(1)).await;
+    }
+    assert_eq!(pool.stats().connections, 2);
+    assert_eq!(pool.stats().in_flight, 2);
+    assert_eq!(pool.stats().waiting, 4);
+
+    server.release(6);
+    for request in requests {
+        request.await.unwrap().unwrap();
+    }
+    let seen = server.seen();
+    assert_eq!(&seen[2..], ["hold:2", "hold:3", "hold:4", "hold:5"]);
+    assert_eq!(pool.stats().opened, 2);
+}
+
+#[tokio::test]
+async fn test_failed_connections_are_replaced() {
+    let server = Arc::new(GateLayer::new());
+    server.set("user:1", b"Alice".to_vec()).await.unwrap();
+    let (addr, connections) = serve(server.clone()).await;
+    let mut config = PoolConfig::new(addr);
+    config.min_connections = 2;
+    let pool = Pool::connect(config).await.unwrap();
+
+    for task in connections.lock().unwrap().drain(..) {
+        task.abort();
+    }
+    // The health check finds both connections dead and reopens the minimum
+    pool.check().await;
+    assert_eq!(pool.stats().connections, 2);
+    assert_eq!(pool.stats().closed, 2);
+    assert_eq!(pool.get("user:1").await.unwrap(), Some(b"Alice".to_vec()));
+}
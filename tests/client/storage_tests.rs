@@ -0,0 +1,106 @@
+use std::fs::OpenOptions;
+use std::io::{Seek, SeekFrom, Write};
+use std::sync::Arc;
+use client::{Client, ClientConfig, DiskStore};
+use core::layer::CacheLayer;
+use crate::common::layer::MemoryLayer;
+use crate::common::test_dir;
+
+#[tokio::test]
+async fn test_warm_cache_survives_restart() {
+    let dir = test_dir();
+    let config = ClientConfig { data_dir: Some(dir.path().to_path_buf()), ..Default::default() };
+    let upstream = Arc::new(MemoryLayer::default());
+    upstream.set("user:123:profile", b"Alice".to_vec()).await.unwrap();
+
+    {
+        let client = Client::open(config.clone(), upstream.clone()).unwrap();
+        assert!(client.get("user:123:profile").await.unwrap().is_some());
+        client.set("user:123:prefs", b"dark".to_vec()).await.unwrap();
+    }
+
+    let client = Client::open(config, upstream).unwrap();
+    client.go_offline();
+    assert_eq!(client.get("user:123:profile").await.unwrap(), Some(b"Alice".to_vec()));
+    assert_eq!(client.get("user:123:prefs").await.unwrap(), Some(b"dark".to_vec()));
+}
+
+#[tokio::test]
+async fn test_corrupt_records_are_discarded() {
+    let dir = test_dir();
+    let path = dir.path().join("cache.log");
+    {
+        let store = DiskStore::open(&path, 1 << 20).unwrap();
+        store.set("a", b"first".to_vec()).await.unwrap();
+        store.set("b", b"second".to_vec()).await.unwrap();
+    }
+    let mut log = OpenOptions::new().write(true).open(&path).unwrap();
+    log.seek(SeekFrom::End(-2)).unwrap();
+    log.write_all(b"??").unwrap();
+    drop(log);
+
+    let store = DiskStore::open(&path, 1 << 20).unwrap();
+    assert_eq!(store.get("a").await.unwrap(), Some(b"first".to_vec()));
+    assert_eq!(store.get("b").await.unwrap(), None);
+    assert!(store.stats().discarded > 0);
+
+    // The log was truncated, so new records are readable after the next open
+    store.set("c", b"third".to_vec()).await.unwrap();
+    drop(store);
+    let store = DiskStore::open(&path, 1 << 20).unwrap();
+    assert_eq!(store.get("c").await.unwrap(), Some(b"third".to_vec()));
+    assert_eq!(store.stats().discarded, 0);
+}
+
+#[tokio::test]
+async fn test_lru_eviction_bounds_disk_usage() {
+    let dir = test_dir();
+    let path = dir.path().join("cache.log");
+    let store = DiskStore::open(&path, 400).unwrap();
+    for i in 0..3 {
+        store.set(&format!("k{}", i), vec![0; 100]).await.unwrap();
+    }
+    store.get("k0").await.unwrap();
+    store.set("k3", vec![0; 100]).await.unwrap();
+
+    let mut keys = store.keys("*").await.unwrap();
+    keys.sort();
+    assert_eq!(keys, vec!["k0", "k2", "k3"]);
+    assert!(store.stats().live_bytes <= 400);
+    assert_eq!(store.stats().evicted, 1);
+    drop(store);
+
+    let store = DiskStore::open(&path, 400).unwrap();
+    assert_eq!(store.get("k1").await.unwrap(), None);
+    assert_eq!(store.len(), 3);
+}
+
+#[tokio::test]
+async fn test_log_is_compacted() {
+    let dir = test_dir();
+    let path = dir.path().join("cache.log");
+    let store = DiskStore::open(&path, 1 << 20).unwrap();
+    for i in 0..64u8 {
+        store.set("blob", vec![i; 64 * 1024]).await.unwrap();
+    }
+    let stats = store.stats();
+    assert!(stats.log_bytes < 2 << 20, "log grew to {} bytes", stats.log_bytes);
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), stats.log_bytes);
+    drop(store);
+
+    let store = DiskStore::open(&path, 1 << 20).unwrap();
+    assert_eq!(store.get("blob").await.unwrap(), Some(vec![63; 64 * 1024]));
+}
+
+#[tokio::test]
+async fn test_pending_writes_outlive_eviction() {
+    let dir = test_dir();
+    let config = ClientConfig { data_dir: Some(dir.path().to_path_buf()), max_cache_bytes: Some(64) };
+    let client = Client::open(config, Arc::new(MemoryLayer::default())).unwrap();
+    client.go_offline();
+
+    client.set("doc:1", vec![7; 128]).await.unwrap();
+    assert_eq!(client.get("doc:1").await.unwrap(), Some(vec![7; 128]));
+    client.delete("doc:1").await.unwrap();
+    assert_eq!(client.get("doc:1").await.unwrap(), None);
+}
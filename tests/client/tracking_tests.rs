@@ -0,0 +1,199 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use async_trait::async_trait;
+use client::{Client, ClientConfig, Pool, PoolConfig};
+use core::layer::CacheLayer;
+use core::prelude::*;
+use core::wire::Server;
+use core::{Invalidation, Tracking, TrackingLayer, TrackingMode};
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+use crate::common::layer::MemoryLayer;
+
+/// Counts reads reaching the server
+#[derive(Default)]
+struct CountingLayer {
+    inner: MemoryLayer,
+    reads: Arc<AtomicUsize>,
+}
+
+#[async_trait]
+impl CacheLayer for CountingLayer {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        self.reads.fetch_add(1, Ordering::SeqCst);
+        self.inner.get(key).await
+    }
+
+    async fn set(&self, key: &str, value: Vec<u8>) -> Result<()> {
+        self.inner.set(key, value).await
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        self.inner.delete(key).await
+    }
+
+    async fn keys(&self, pattern: &str) -> Result<Vec<String>> {
+        self.inner.keys(pattern).await
+    }
+}
+
+struct TestServer {
+    addr: String,
+    layer: Arc<TrackingLayer<CountingLayer>>,
+    reads: Arc<AtomicUsize>,
+    tracking: Arc<Tracking>,
+    connections: Arc<Mutex<Vec<JoinHandle<()>>>>,
+}
+
+impl TestServer {
+    async fn start() -> Self {
+        let tracking = Arc::new(Tracking::new());
+        let counting = CountingLayer::default();
+        let reads = counting.reads.clone();
+        let layer = Arc::new(TrackingLayer::new(counting, tracking.clone()));
+        let server = Arc::new(Server::new(layer.clone()).with_tracking(tracking.clone()));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let connections = Arc::new(Mutex::new(Vec::new()));
+        let tasks = connections.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let server = server.clone();
+                tasks.lock().unwrap().push(tokio::spawn(async move {
+                    let _ = server.serve_connection(stream).await;
+                }));
+            }
+        });
+        Self { addr, layer, reads, tracking, connections }
+    }
+
+    fn reads(&self) -> usize {
+        self.reads.load(Ordering::SeqCst)
+    }
+
+    async fn pool(&self, tracking: TrackingMode) -> Arc<Pool> {
+        let mut config = PoolConfig::new(self.addr.clone());
+        config.tracking = Some(tracking);
+        Arc::new(Pool::connect(config).await.unwrap())
+    }
+
+    async fn client(&self, tracking: TrackingMode) -> Client {
+        let pool = self.pool(tracking).await;
+        Client::new(pool.clone()).with_invalidations(pool.invalidations())
+    }
+}
+
+/// Read `key` until the client sees `expected`
+async fn eventually(client: &Client, key: &str, expected: &[u8]) {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while client.get(key).await.unwrap().as_deref() != Some(expected) {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+    })
+    .await
+    .expect("change never reached the client");
+}
+
+#[tokio::test]
+async fn test_near_cache_invalidated_on_change() {
+    let server = TestServer::start().await;
+    server.layer.set("user:1:profile", b"Alice".to_vec()).await.unwrap();
+    let client = server.client(TrackingMode::Default).await;
+
+    for _ in 0..3 {
+        assert_eq!(client.get("user:1:profile").await.unwrap(), Some(b"Alice".to_vec()));
+    }
+    assert_eq!(server.reads(), 1);
+    assert_eq!(server.tracking.tracked_keys(), 1);
+
+    server.layer.set("user:1:profile", b"Bob".to_vec()).await.unwrap();
+    eventually(&client, "user:1:profile", b"Bob").await;
+    assert_eq!(server.reads(), 2);
+}
+
+#[tokio::test]
+async fn test_broadcast_tracks_patterns_only() {
+    let server = TestServer::start().await;
+    server.layer.set("user:1", b"v1".to_vec()).await.unwrap();
+    server.layer.set("post:1", b"v1".to_vec()).await.unwrap();
+    let client = server.client(TrackingMode::Broadcast { patterns: vec!["user:*".into()] }).await;
+
+    client.get("user:1").await.unwrap();
+    client.get("post:1").await.unwrap();
+    assert_eq!(server.tracking.tracked_keys(), 0);
+
+    server.layer.set("post:1", b"v2".to_vec()).await.unwrap();
+    server.layer.set("user:1", b"v2".to_vec()).await.unwrap();
+    eventually(&client, "user:1", b"v2").await;
+    // Outside the broadcast patterns, the near copy is kept
+    assert_eq!(client.get("post:1").await.unwrap(), Some(b"v1".to_vec()));
+}
+
+#[tokio::test]
+async fn test_lost_connection_flushes_near_cache() {
+    let server = TestServer::start().await;
+    server.layer.set("user:1", b"v1".to_vec()).await.unwrap();
+    let client = server.client(TrackingMode::Default).await;
+    client.get("user:1").await.unwrap();
+
+    for task in server.connections.lock().unwrap().drain(..) {
+        task.abort();
+    }
+    // Made as the connections close, so it may never be pushed
+    server.layer.set("user:1", b"v2".to_vec()).await.unwrap();
+    eventually(&client, "user:1", b"v2").await;
+    assert_eq!(server.tracking.subscribers(), 1);
+}
+
+#[tokio::test]
+async fn test_dropped_connection_reports_loss() {
+    let server = TestServer::start().await;
+    let pool = server.pool(TrackingMode::Default).await;
+    let mut invalidations = pool.invalidations();
+
+    // The connection is dropped while its reader still waits on the socket
+    drop(pool);
+    assert_eq!(invalidations.recv().await.unwrap(), Invalidation::Pattern("*".into()));
+}
+
+#[tokio::test]
+async fn test_reopened_client_drops_untracked_copies() {
+    let server = TestServer::start().await;
+    server.layer.set("user:1", b"v1".to_vec()).await.unwrap();
+    let dir = crate::common::test_dir();
+    let config = ClientConfig { data_dir: Some(dir.path().to_path_buf()), ..Default::default() };
+    let pool = server.pool(TrackingMode::Default).await;
+    let client = Client::open(config.clone(), pool.clone()).unwrap().with_invalidations(pool.invalidations());
+    assert_eq!(client.get("user:1").await.unwrap(), Some(b"v1".to_vec()));
+    drop((client, pool));
+
+    // Changed while no connection tracked the key
+    server.layer.set("user:1", b"v2".to_vec()).await.unwrap();
+    let pool = server.pool(TrackingMode::Default).await;
+    let client = Client::open(config, pool.clone()).unwrap().with_invalidations(pool.invalidations());
+    assert_eq!(client.get("user:1").await.unwrap(), Some(b"v2".to_vec()));
+}
+
+#[tokio::test]
+async fn test_pending_writes_survive_invalidation() {
+    let server = TestServer::start().await;
+    let pool = server.pool(TrackingMode::Broadcast { patterns: vec!["user:*".into()] }).await;
+    let mut pushed = pool.invalidations();
+    let client = Client::new(pool.clone()).with_invalidations(pool.invalidations());
+
+    client.go_offline();
+    client.set("user:1", b"mine".to_vec()).await.unwrap();
+    server.layer.set("user:1", b"theirs".to_vec()).await.unwrap();
+    // Every receiver gets the push at once, so the client has it too
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while !pushed.recv().await.unwrap().covers("user:1") {}
+    })
+    .await
+    .expect("invalidation never pushed");
+    assert_eq!(client.get("user:1").await.unwrap(), Some(b"mine".to_vec()));
+
+    client.reconnect().await.unwrap();
+    eventually(&client, "user:1", b"mine").await;
+    assert_eq!(server.layer.get("user:1").await.unwrap(), Some(b"mine".to_vec()));
+}
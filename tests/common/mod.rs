@@ -57,4 +57,38 @@ pub mod data {
     pub fn random_bytes(len: usize) -> Vec<u8> {
         (0..len).map(|_| thread_rng().gen::<u8>()).collect()
     }
-} 
\ No newline at end of file
+} 
+/// In-memory `CacheLayer` for exercising layer wrappers
+pub mod layer {
+    use async_trait::async_trait;
+    use dashmap::DashMap;
+    
+    #[derive(Default)]
+    pub struct MemoryLayer {
+        pub entries: DashMap<String, Vec<u8>>,
+    }
+    
+    #[async_trait]
+    impl core::layer::CacheLayer for MemoryLayer {
+        async fn get(&self, key: &str) -> core::Result<Option<Vec<u8>>> {
+            Ok(self.entries.get(key).map(|v| v.clone()))
+        }
+        
+        async fn set(&self, key: &str, value: Vec<u8>) -> core::Result<()> {
+            self.entries.insert(key.to_string(), value);
+            Ok(())
+        }
+        
+        async fn delete(&self, key: &str) -> core::Result<()> {
+            self.entries.remove(key);
+            Ok(())
+        }
+        
+        async fn keys(&self, pattern: &str) -> core::Result<Vec<String>> {
+            Ok(self.entries.iter()
+                .filter(|e| core::pattern::glob_matches(pattern, e.key()))
+                .map(|e| e.key().clone())
+                .collect())
+        }
+    }
+}
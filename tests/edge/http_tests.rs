@@ -0,0 +1,353 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use async_trait::async_trait;
+use core::layer::CacheLayer;
+use core::prelude::*;
+use core::{Cache, CacheEntry, LayerCache};
+use dashmap::DashMap;
+use edge::http::{self, HttpRequest, HttpResponse};
+use edge::{CacheControl, EdgeNode, HttpProxy, ProxyConfig};
+use tokio::io::BufReader;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+use crate::common::layer::MemoryLayer;
+
+/// Cache honoring entry TTLs
+#[derive(Default)]
+struct MemoryCache {
+    entries: DashMap<String, (CacheEntry, Option<Instant>)>,
+}
+
+#[async_trait]
+impl Cache for MemoryCache {
+    async fn get(&self, key: &str) -> Result<Option<CacheEntry>> {
+        let live = |(_, expires): &(CacheEntry, Option<Instant>)| expires.is_none_or(|at| at > Instant::now());
+        Ok(self.entries.get(key).filter(|e| live(e)).map(|e| e.0.clone()))
+    }
+
+    async fn set(&self, key: &str, value: CacheEntry) -> Result<()> {
+        let expires = value.ttl.map(|ttl| Instant::now() + ttl);
+        self.entries.insert(key.to_string(), (value, expires));
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        self.entries.remove(key);
+        Ok(())
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool> {
+        Ok(self.get(key).await?.is_some())
+    }
+
+    async fn expire(&self, key: &str, ttl: Duration) -> Result<bool> {
+        Ok(self.entries.get_mut(key).map(|mut e| e.1 = Some(Instant::now() + ttl)).is_some())
+    }
+}
+
+type Handler = dyn Fn(&HttpRequest) -> HttpResponse + Send + Sync;
+
+/// Origin server answering with `handler` and counting requests
+struct Origin {
+    addr: String,
+    requests: Arc<AtomicUsize>,
+    task: JoinHandle<()>,
+}
+
+impl Origin {
+    async fn start(handler: impl Fn(&HttpRequest) -> HttpResponse + Send + Sync + 'static) -> Self {
+        let handler: Arc<Handler> = Arc::new(handler);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        let task = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let (handler, counter) = (handler.clone(), counter.clone());
+                tokio::spawn(async move {
+                    let (read, mut write) = stream.into_split();
+                    let mut reader = BufReader::new(read);
+                    while let Ok(Some(request)) = http::read_request(&mut reader).await {
+                        counter.fetch_add(1, Ordering::SeqCst);
+                        let response = handler(&request);
+                        if http::write_response(&mut write, &response, &request.method).await.is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+        Self { addr, requests, task }
+    }
+
+    fn requests(&self) -> usize {
+        self.requests.load(Ordering::SeqCst)
+    }
+
+    fn proxy(&self) -> HttpProxy {
+        HttpProxy::new(Arc::new(MemoryCache::default()), ProxyConfig::new(self.addr.clone())).unwrap()
+    }
+}
+
+fn cache_status(response: &HttpResponse) -> &str {
+    response.headers.get("cache-status").unwrap_or_default()
+}
+
+fn page(cache_control: &str) -> HttpResponse {
+    HttpResponse::new(200).with_header("Cache-Control", cache_control).with_body("page")
+}
+
+#[test]
+fn test_cache_control_parsing() {
+    let parsed = CacheControl::parse(r#"Public, max-age=60, no-cache="Set-Cookie, Cookie", s-maxage=x"#);
+    assert!(parsed.public && parsed.no_cache);
+    assert_eq!(parsed.max_age, Some(Duration::from_secs(60)));
+    // An invalid lifetime makes the response stale
+    assert_eq!(parsed.s_maxage, Some(Duration::ZERO));
+    assert!(parsed.revalidate_when_stale());
+
+    assert_eq!(CacheControl::parse("max-stale").max_stale, Some(None));
+    assert_eq!(CacheControl::parse("max-age=99999999999").max_age, Some(Duration::from_secs(1 << 31)));
+}
+
+#[tokio::test]
+async fn test_fresh_responses_served_from_cache() {
+    let origin = Origin::start(|request| match request.target.as_str() {
+        "/fresh" => page("public, max-age=60"),
+        "/private" => page("private, max-age=60"),
+        _ => page("no-store"),
+    })
+    .await;
+    let proxy = origin.proxy();
+
+    let first = proxy.handle(HttpRequest::get("/fresh")).await.unwrap();
+    assert_eq!(cache_status(&first), "stateless; fwd=uri-miss; fwd-status=200; stored");
+    let second = proxy.handle(HttpRequest::get("/fresh")).await.unwrap();
+    assert_eq!(second.body, first.body);
+    assert_eq!(cache_status(&second), "stateless; hit");
+    assert_eq!(second.headers.get("age"), Some("0"));
+    assert_eq!(origin.requests(), 1);
+
+    for target in ["/private", "/no-store"] {
+        proxy.handle(HttpRequest::get(target)).await.unwrap();
+        let again = proxy.handle(HttpRequest::get(target)).await.unwrap();
+        assert_eq!(cache_status(&again), "stateless; fwd=uri-miss; fwd-status=200");
+    }
+    assert_eq!(origin.requests(), 5);
+}
+
+#[tokio::test]
+async fn test_stale_responses_revalidated() {
+    let origin = Origin::start(|request| {
+        if request.headers.get("if-none-match") == Some("\"v1\"") {
+            return HttpResponse::new(304).with_header("Cache-Control", "max-age=60");
+        }
+        page("max-age=1").with_header("ETag", "\"v1\"")
+    })
+    .await;
+    let proxy = origin.proxy();
+
+    proxy.handle(HttpRequest::get("/page")).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    let revalidated = proxy.handle(HttpRequest::get("/page")).await.unwrap();
+    assert_eq!(revalidated.status, 200);
+    assert_eq!(revalidated.body, "page");
+    assert_eq!(cache_status(&revalidated), "stateless; fwd=stale; fwd-status=304");
+    assert_eq!(revalidated.headers.get("cache-control"), Some("max-age=60"));
+    assert_eq!(origin.requests(), 2);
+
+    // Freshened by the 304, and answering the client's own validator
+    let request = HttpRequest::get("/page").with_header("If-None-Match", "W/\"v1\"");
+    let not_modified = proxy.handle(request).await.unwrap();
+    assert_eq!(not_modified.status, 304);
+    assert!(not_modified.body.is_empty());
+    assert_eq!(not_modified.headers.get("etag"), Some("\"v1\""));
+    assert_eq!(origin.requests(), 2);
+
+    // A client asking for no-cache makes the proxy revalidate
+    let request = HttpRequest::get("/page").with_header("Cache-Control", "no-cache");
+    let forced = proxy.handle(request).await.unwrap();
+    assert_eq!(cache_status(&forced), "stateless; fwd=request; fwd-status=304");
+    assert_eq!(origin.requests(), 3);
+}
+
+#[tokio::test]
+async fn test_expires_and_last_modified() {
+    let now = SystemTime::now();
+    let origin = Origin::start(move |request| {
+        let date = httpdate::fmt_http_date(now);
+        match request.target.as_str() {
+            "/expired" => HttpResponse::new(200)
+                .with_header("Date", date)
+                .with_header("Expires", httpdate::fmt_http_date(now - Duration::from_secs(60))),
+            "/expires" => HttpResponse::new(200)
+                .with_header("Date", date)
+                .with_header("Expires", httpdate::fmt_http_date(now + Duration::from_secs(60))),
+            _ => HttpResponse::new(200)
+                .with_header("Date", date)
+                .with_header("Last-Modified", httpdate::fmt_http_date(now - Duration::from_secs(10 * 24 * 3600)))
+                .with_body("heuristic"),
+        }
+    })
+    .await;
+    let proxy = origin.proxy();
+
+    for target in ["/expired", "/expires", "/modified"] {
+        proxy.handle(HttpRequest::get(target)).await.unwrap();
+        proxy.handle(HttpRequest::get(target)).await.unwrap();
+    }
+    // Only the already expired response went back to the origin
+    assert_eq!(origin.requests(), 4);
+
+    let since = HttpRequest::get("/modified").with_header("If-Modified-Since", httpdate::fmt_http_date(now));
+    let response = proxy.handle(since).await.unwrap();
+    assert_eq!(response.status, 304);
+    let before = now - Duration::from_secs(30 * 24 * 3600);
+    let since = HttpRequest::get("/modified").with_header("If-Modified-Since", httpdate::fmt_http_date(before));
+    assert_eq!(proxy.handle(since).await.unwrap().body, "heuristic");
+    assert_eq!(origin.requests(), 4);
+}
+
+#[tokio::test]
+async fn test_vary_keeps_variants_apart() {
+    let origin = Origin::start(|request| {
+        let language = request.headers.get("accept-language").unwrap_or("en").to_string();
+        page("max-age=60").with_header("Vary", "Accept-Language").with_body(language)
+    })
+    .await;
+    let proxy = origin.proxy();
+    let get = |language: &str| HttpRequest::get("/page").with_header("Accept-Language", language);
+
+    assert_eq!(proxy.handle(get("en")).await.unwrap().body, "en");
+    let french = proxy.handle(get("fr")).await.unwrap();
+    assert_eq!(french.body, "fr");
+    assert_eq!(cache_status(&french), "stateless; fwd=vary-miss; fwd-status=200; stored");
+    assert_eq!(proxy.handle(get("en")).await.unwrap().body, "en");
+    assert_eq!(proxy.handle(get("fr")).await.unwrap().body, "fr");
+    assert_eq!(origin.requests(), 2);
+}
+
+#[tokio::test]
+async fn test_unsafe_methods_invalidate_every_variant() {
+    let version = Arc::new(AtomicUsize::new(1));
+    let current = version.clone();
+    let origin = Origin::start(move |request| {
+        if request.method == "POST" {
+            current.fetch_add(1, Ordering::SeqCst);
+            return HttpResponse::new(204);
+        }
+        let language = request.headers.get("accept-language").unwrap_or("en");
+        let body = format!("{} v{}", language, current.load(Ordering::SeqCst));
+        page("max-age=60").with_header("Vary", "Accept-Language").with_body(body)
+    })
+    .await;
+    let proxy = origin.proxy();
+    let get = |language: &str| HttpRequest::get("/page").with_header("Accept-Language", language);
+    proxy.handle(get("en")).await.unwrap();
+    proxy.handle(get("fr")).await.unwrap();
+
+    let mut post = HttpRequest::get("/page");
+    post.method = "POST".to_string();
+    proxy.handle(post).await.unwrap();
+    // Storing the new English variant must not bring back the old French one
+    assert_eq!(proxy.handle(get("en")).await.unwrap().body, "en v2");
+    assert_eq!(proxy.handle(get("fr")).await.unwrap().body, "fr v2");
+    assert_eq!(origin.requests(), 5);
+
+    proxy.purge(&origin.addr, "/page").await.unwrap();
+    let cached = get("fr").with_header("Cache-Control", "only-if-cached");
+    assert_eq!(proxy.handle(cached).await.unwrap().status, 504);
+    assert_eq!(version.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn test_unsafe_methods_invalidate() {
+    let origin = Origin::start(|request| match request.method.as_str() {
+        "POST" => HttpResponse::new(201).with_header("Location", "/pages/2"),
+        _ => page("max-age=60"),
+    })
+    .await;
+    let proxy = origin.proxy();
+    for target in ["/pages/1", "/pages/2", "/pages/3"] {
+        proxy.handle(HttpRequest::get(target)).await.unwrap();
+    }
+
+    let mut post = HttpRequest::get("/pages/1").with_body("update");
+    post.method = "POST".to_string();
+    let response = proxy.handle(post).await.unwrap();
+    assert_eq!(cache_status(&response), "stateless; fwd=method; fwd-status=201");
+
+    for target in ["/pages/1", "/pages/2", "/pages/3"] {
+        proxy.handle(HttpRequest::get(target)).await.unwrap();
+    }
+    assert_eq!(origin.requests(), 6);
+
+    proxy.purge(&origin.addr, "/pages/3").await.unwrap();
+    let request = HttpRequest::get("/pages/3").with_header("Cache-Control", "only-if-cached");
+    assert_eq!(proxy.handle(request).await.unwrap().status, 504);
+}
+
+#[tokio::test]
+async fn test_proxy_serves_over_tcp() {
+    let origin = Origin::start(|_| page("max-age=1").with_header("ETag", "\"v1\"")).await;
+    let proxy = Arc::new(origin.proxy());
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(proxy.clone().serve(listener));
+
+    let (read, mut write) = TcpStream::connect(addr).await.unwrap().into_split();
+    let mut reader = BufReader::new(read);
+    for expected in ["stateless; fwd=uri-miss; fwd-status=200; stored", "stateless; hit"] {
+        http::write_request(&mut write, &HttpRequest::get("/page")).await.unwrap();
+        let response = http::read_response(&mut reader, "GET").await.unwrap();
+        assert_eq!(response.body, "page");
+        assert_eq!(cache_status(&response), expected);
+    }
+    let head = HttpRequest { method: "HEAD".to_string(), ..HttpRequest::get("/page") };
+    http::write_request(&mut write, &head).await.unwrap();
+    let response = http::read_response(&mut reader, "HEAD").await.unwrap();
+    assert!(response.body.is_empty());
+    assert_eq!(response.headers.get("content-length"), Some("4"));
+
+    // Once stale, the response is still served while the origin is down
+    origin.task.abort();
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    http::write_request(&mut write, &HttpRequest::get("/page")).await.unwrap();
+    let response = http::read_response(&mut reader, "GET").await.unwrap();
+    assert_eq!(response.body, "page");
+    assert_eq!(cache_status(&response), "stateless; hit; detail=stale-on-error");
+    assert_eq!(origin.requests(), 1);
+}
+
+#[tokio::test]
+async fn test_dropped_responses_count_as_expirations() {
+    let origin = Origin::start(|_| page("max-age=1")).await;
+    let metrics = Arc::new(Metrics::new(["http:*"]));
+    let cache = LayerCache::new(MemoryLayer::default()).with_metrics(metrics.clone(), Layer::Edge);
+    let proxy = HttpProxy::new(Arc::new(cache), ProxyConfig::new(origin.addr.clone())).unwrap();
+
+    proxy.handle(HttpRequest::get("/page")).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    let response = proxy.handle(HttpRequest::get("/page")).await.unwrap();
+    assert_eq!(cache_status(&response), "stateless; fwd=uri-miss; fwd-status=200; stored");
+    assert_eq!(metrics.stats_for("http:*", Layer::Edge).expirations, 1);
+}
+
+#[tokio::test]
+async fn test_proxy_caches_in_an_edge_node() {
+    let origin = Origin::start(|_| page("max-age=1")).await;
+    let cache = Arc::new(LayerCache::new(EdgeNode::new("us-east")));
+    let proxy = HttpProxy::new(cache.clone(), ProxyConfig::new(origin.addr.clone())).unwrap();
+
+    proxy.handle(HttpRequest::get("/page")).await.unwrap();
+    let response = proxy.handle(HttpRequest::get("/page")).await.unwrap();
+    assert_eq!(cache_status(&response), "stateless; hit");
+    assert!(!cache.inner().keys("*").await.unwrap().is_empty());
+
+    // The node drops the response once its TTL runs out
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    let response = proxy.handle(HttpRequest::get("/page")).await.unwrap();
+    assert_eq!(cache_status(&response), "stateless; fwd=uri-miss; fwd-status=200; stored");
+    assert_eq!(origin.requests(), 2);
+}
@@ -0,0 +1,2 @@
+mod http_tests;
+mod region_tests;
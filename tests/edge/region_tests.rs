@@ -0,0 +1,217 @@
+use std::sync::Arc;
+use std::time::Duration;
+use async_trait::async_trait;
+use core::layer::CacheLayer;
+use core::prelude::*;
+use core::{ClientFirst, ConflictLayer, EdgeOptimized, GlobalConsistent, Timestamp, VersionVector};
+use edge::{EdgeCluster, EdgeNode, Placement, Region, Topology};
+use tokio::sync::{Notify, Semaphore};
+use crate::common::layer::MemoryLayer;
+
+fn topology() -> Topology {
+    Topology::new()
+        .link("us-east", "us-west", Duration::from_millis(60))
+        .link("us-east", "eu-west", Duration::from_millis(80))
+        .link("us-west", "eu-west", Duration::from_millis(140))
+}
+
+/// Upstream whose reads wait for a permit after looking up the value
+struct PausedLayer {
+    inner: MemoryLayer,
+    reading: Notify,
+    release: Semaphore,
+}
+
+#[async_trait]
+impl CacheLayer for PausedLayer {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let value = self.inner.get(key).await;
+        self.reading.notify_one();
+        self.release.acquire().await.unwrap().forget();
+        value
+    }
+
+    async fn set(&self, key: &str, value: Vec<u8>) -> Result<()> {
+        self.inner.set(key, value).await
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        self.inner.delete(key).await
+    }
+
+    async fn keys(&self, pattern: &str) -> Result<Vec<String>> {
+        self.inner.keys(pattern).await
+    }
+}
+
+/// Three in-memory edge nodes, serving `us-east` by default
+fn cluster() -> (EdgeCluster, [Arc<EdgeNode>; 3]) {
+    let nodes = ["us-east", "us-west", "eu-west"].map(|r| Arc::new(EdgeNode::new(r)));
+    let cluster = nodes
+        .iter()
+        .fold(EdgeCluster::new("us-east").with_topology(topology()), |c, n| c.with_node(n.clone()));
+    (cluster, nodes)
+}
+
+#[tokio::test]
+async fn test_reads_served_by_nearest_region() {
+    let (cache, [east, west, eu]) = cluster();
+    assert_eq!(
+        cache.nearest(&"us-west".into()),
+        vec![Region::new("us-west"), Region::new("us-east"), Region::new("eu-west")]
+    );
+
+    cache.set_from("eu-west", "content:page1", b"data".to_vec()).await.unwrap();
+    assert!(east.get("content:page1").await.unwrap().is_none());
+
+    let value = cache.get("content:page1").from_region("us-east").await.unwrap();
+    assert_eq!(value, Some(b"data".to_vec()));
+    // The reading region keeps a copy and serves the next read itself
+    let served = cache.get("content:page1").from_region("us-east").served().await.unwrap().unwrap();
+    assert_eq!(served.region, Some(Region::new("us-east")));
+
+    // us-west is nearer to us-east than to eu-west, and both hold the key now
+    let served = cache.get("content:page1").from_region("us-west").served().await.unwrap().unwrap();
+    assert_eq!(served.region, Some(Region::new("us-east")));
+    assert!(west.get("content:page1").await.unwrap().is_some());
+    assert!(eu.get("content:page1").await.unwrap().is_some());
+}
+
+#[tokio::test]
+async fn test_placement_follows_strategy() {
+    let (cache, [east, west, eu]) = cluster();
+    cache.placements().place_for("content:*", &EdgeOptimized::new());
+    cache.placements().place_for("price:*", &GlobalConsistent::new());
+
+    cache.set_from("us-west", "content:page1", b"data".to_vec()).await.unwrap();
+    assert!(west.get("content:page1").await.unwrap().is_some());
+    assert!(east.get("content:page1").await.unwrap().is_none());
+
+    cache.set_from("us-west", "price:1", b"10".to_vec()).await.unwrap();
+    for node in [&east, &west, &eu] {
+        assert_eq!(node.get("price:1").await.unwrap(), Some(b"10".to_vec()));
+    }
+
+    // Eventually consistent keys are copied to the regions that read them
+    cache.placements().place_for("draft:*", &ClientFirst::new());
+    cache.set_from("us-west", "draft:1", b"d".to_vec()).await.unwrap();
+    assert!(east.get("draft:1").await.unwrap().is_none());
+    cache.get("draft:1").from_region("us-east").await.unwrap();
+    assert!(east.get("draft:1").await.unwrap().is_some());
+
+    // Pinned keys never leave their regions, even when read elsewhere
+    cache.place("gdpr:*", Placement::Pinned(vec!["eu-west".into()]));
+    cache.set_from("us-east", "gdpr:user:1", b"eu".to_vec()).await.unwrap();
+    assert_eq!(cache.get("gdpr:user:1").await.unwrap(), Some(b"eu".to_vec()));
+    assert!(east.get("gdpr:user:1").await.unwrap().is_none());
+    assert_eq!(eu.keys("gdpr:*").await.unwrap(), vec!["gdpr:user:1".to_string()]);
+}
+
+#[tokio::test]
+async fn test_writes_invalidate_other_regions() {
+    let (cache, [east, _, eu]) = cluster();
+    cache.set_from("eu-west", "content:page1", b"v1".to_vec()).await.unwrap();
+    cache.get("content:page1").from_region("us-east").await.unwrap();
+    assert!(east.get("content:page1").await.unwrap().is_some());
+
+    cache.set_from("eu-west", "content:page1", b"v2".to_vec()).await.unwrap();
+    assert!(east.get("content:page1").await.unwrap().is_none());
+    assert_eq!(
+        cache.get("content:page1").from_region("us-east").await.unwrap(),
+        Some(b"v2".to_vec())
+    );
+    assert_eq!(eu.get("content:page1").await.unwrap(), Some(b"v2".to_vec()));
+}
+
+#[tokio::test]
+async fn test_region_scoped_invalidation() {
+    let (cache, [east, west, eu]) = cluster();
+    cache.place("content:*", Placement::Everywhere);
+    cache.set("content:page1", b"data".to_vec()).await.unwrap();
+    cache.set("content:page2", b"data".to_vec()).await.unwrap();
+
+    assert_eq!(cache.invalidate_in("eu-west", "content:*").await.unwrap(), 2);
+    assert!(eu.keys("content:*").await.unwrap().is_empty());
+    assert_eq!(east.keys("content:*").await.unwrap().len(), 2);
+    assert!(matches!(
+        cache.invalidate_in("ap-south", "content:*").await,
+        Err(Error::InvalidConfig(_))
+    ));
+
+    // Invalidations missed while down are applied before serving again
+    west.go_down();
+    assert_eq!(cache.invalidate("content:page1").await.unwrap(), 1);
+    assert!(west.get("content:page2").await.is_err());
+    west.come_up().await.unwrap();
+    assert!(west.get("content:page1").await.unwrap().is_none());
+    assert!(west.get("content:page2").await.unwrap().is_some());
+}
+
+#[tokio::test]
+async fn test_reads_skip_down_regions() {
+    let upstream = Arc::new(MemoryLayer::default());
+    upstream.set("content:page1", b"origin".to_vec()).await.unwrap();
+    let (cache, [east, west, eu]) = cluster();
+    let cache = cache.with_upstream(upstream.clone());
+
+    cache.set_from("us-west", "content:page2", b"data".to_vec()).await.unwrap();
+    west.go_down();
+    east.go_down();
+    let served = cache.get("content:page2").from_region("us-east").served().await.unwrap();
+    assert!(served.is_none());
+
+    // An upstream hit fills the reading region
+    let served = cache.get("content:page1").from_region("eu-west").served().await.unwrap().unwrap();
+    assert_eq!(served.value, b"origin".to_vec());
+    assert_eq!(served.region, None);
+    assert!(eu.get("content:page1").await.unwrap().is_some());
+
+    west.come_up().await.unwrap();
+    let served = cache.get("content:page2").from_region("us-east").served().await.unwrap().unwrap();
+    assert_eq!(served.region, Some(Region::new("us-west")));
+}
+
+#[tokio::test]
+async fn test_versioned_writes_reach_node_stores() {
+    let store = Arc::new(ConflictLayer::new(MemoryLayer::default(), "edge", Arc::new(Conflicts::new())));
+    let cache = EdgeCluster::new("us-east").with_node(Arc::new(EdgeNode::with_store("us-east", store.clone())));
+    let write = |value: &str, counter: u64| {
+        let mut vector = VersionVector::new();
+        vector.advance("client", counter);
+        let stamp = Timestamp { physical: counter, logical: 0, node: "client".into() };
+        Versioned::new(value.as_bytes().to_vec(), stamp, vector)
+    };
+
+    cache.set_versioned("content:page1", write("new", 2)).await.unwrap();
+    let stored = cache.set_versioned("content:page1", write("old", 1)).await.unwrap();
+    assert_eq!(stored.value, b"new".to_vec());
+    let read = cache.get_versioned("content:page1").await.unwrap().unwrap();
+    assert_eq!((read.value, read.vector.get("client")), (b"new".to_vec(), 2));
+}
+
+#[tokio::test]
+async fn test_fill_dropped_when_written_mid_read() {
+    let (cache, [east, _, eu]) = cluster();
+    let upstream = Arc::new(PausedLayer {
+        inner: MemoryLayer::default(),
+        reading: Notify::new(),
+        release: Semaphore::new(0),
+    });
+    upstream.inner.entries.insert("content:page1".into(), b"stale".to_vec());
+    let cache = Arc::new(cache.with_upstream(upstream.clone()));
+
+    let read = {
+        let cache = cache.clone();
+        tokio::spawn(async move { cache.get("content:page1").from_region("us-east").await.unwrap() })
+    };
+    // Upstream has answered; the write lands before the fill
+    upstream.reading.notified().await;
+    cache.set_from("eu-west", "content:page1", b"fresh".to_vec()).await.unwrap();
+    upstream.release.add_permits(1);
+
+    assert_eq!(read.await.unwrap(), Some(b"stale".to_vec()));
+    assert!(east.get("content:page1").await.unwrap().is_none());
+    assert_eq!(eu.get("content:page1").await.unwrap(), Some(b"fresh".to_vec()));
+    let value = cache.get("content:page1").from_region("us-east").await.unwrap();
+    assert_eq!(value, Some(b"fresh".to_vec()));
+}
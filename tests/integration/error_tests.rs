@@ -0,0 +1,73 @@
+use std::time::Duration;
+use core::prelude::*;
+use core::QuotaPolicy;
+
+#[test]
+fn test_errors_are_classified() {
+    let conflict = Error::PatternConflict {
+        pattern: "user:{id}".into(),
+        owner: "UserService".into(),
+        requested_by: "AdminService".into(),
+    };
+    assert_eq!(conflict.kind(), ErrorKind::Ownership);
+    assert_eq!(conflict.pattern(), Some("user:{id}"));
+    assert_eq!(conflict.owner(), Some("UserService"));
+    assert_eq!(conflict.key(), None);
+
+    let invalid = QuotaPolicy::new("user:*").max_size("12 parsecs").unwrap_err();
+    assert_eq!(invalid.kind(), ErrorKind::Config);
+}
+
+#[test]
+fn test_transient_errors_are_retryable() {
+    let timeout = Error::Timeout {
+        operation: "get".into(),
+        key: Some("user:1".into()),
+        layer: Some(Layer::Server),
+        after: Duration::from_millis(50),
+    };
+    assert!(timeout.is_retryable());
+    assert_eq!(timeout.key(), Some("user:1"));
+    assert_eq!(timeout.layer(), Some(Layer::Server));
+    assert_eq!(timeout.to_string(), "Timeout: get on user:1 did not complete within 50ms");
+    let drain = Error::Timeout { operation: "drain".into(), key: None, layer: None, after: Duration::from_secs(1) };
+    assert_eq!(drain.to_string(), "Timeout: drain did not complete within 1s");
+
+    let refused = std::io::Error::new(std::io::ErrorKind::ConnectionRefused, "refused");
+    let network = Error::network(Some(Layer::Edge), "connecting to edge", refused);
+    assert!(network.is_retryable());
+    assert!(std::error::Error::source(&network).is_some());
+
+    let version = Error::VersionConflict { key: "user:1".into(), expected: 3, actual: 4 };
+    assert!(version.is_retryable());
+    assert_eq!(version.kind(), ErrorKind::Conflict);
+}
+
+#[test]
+fn test_rule_violations_are_not_retryable() {
+    let borrow = Error::InvalidBorrowing {
+        key: "user:1".into(),
+        pattern: "user:{id}".into(),
+        owner: "UserService".into(),
+        borrower: "ProfileView".into(),
+    };
+    assert!(!borrow.is_retryable());
+    assert_eq!(borrow.owner(), Some("UserService"));
+    assert!(borrow.to_string().contains("ProfileView borrows user:{id} from UserService"));
+
+    let quota = Error::QuotaExceeded {
+        key: "session:1".into(),
+        pattern: "session:*".into(),
+        reason: "64 bytes do not fit".into(),
+    };
+    assert!(!quota.is_retryable());
+    assert_eq!(quota.kind(), ErrorKind::Capacity);
+    assert!(quota.to_string().contains("session:*"));
+}
+
+#[test]
+fn test_serde_errors_convert() {
+    let err: Error = serde_json::from_str::<serde_json::Value>("{").unwrap_err().into();
+    assert_eq!(err.kind(), ErrorKind::Serialization);
+    assert!(!err.is_retryable());
+}
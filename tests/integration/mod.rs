@@ -0,0 +1,3 @@
+mod error_tests;
+mod simulation_tests;
+mod tracing_tests;
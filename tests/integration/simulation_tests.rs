@@ -0,0 +1,114 @@
+use std::sync::Arc;
+use std::time::Duration;
+use core::layer::CacheLayer;
+use core::prelude::*;
+use core::{EdgeOptimized, LinkConditions, SimFailure, SimReport, Simulation};
+
+fn manifest() -> Arc<OwnershipGraph> {
+    let graph = OwnershipGraph::new();
+    graph.add(Ownership::new("user:*", "UserService", Layer::Server)).unwrap();
+    Arc::new(graph)
+}
+
+/// Readers backfilling the edge race a writer updating the server
+fn read_write_race(sim: &mut Simulation) -> std::result::Result<SimReport, SimFailure> {
+    let link = LinkConditions::new()
+        .latency(Duration::from_millis(5))
+        .jitter(Duration::from_millis(20));
+    sim.network().set_link(Layer::Server, Layer::Edge, link);
+    sim.network().set_link(Layer::Server, Layer::Client, link);
+
+    let coordinator = sim.coordinator(manifest()).with_strategy(EdgeOptimized::new());
+    let coordinator = Arc::new(futures::executor::block_on(coordinator).unwrap());
+    sim.check_invalidation_order(coordinator.bus().clone());
+    sim.check_convergence(coordinator.clone());
+
+    let writer = coordinator.clone();
+    sim.spawn("writer", async move {
+        for version in 0..2 {
+            writer.set("user:1", format!("v{}", version).into_bytes()).await?;
+        }
+        Ok(())
+    });
+    for reader in 0..3 {
+        let coordinator = coordinator.clone();
+        sim.spawn(format!("reader {}", reader), async move {
+            for _ in 0..3 {
+                coordinator.get("user:1").await?;
+            }
+            Ok(())
+        });
+    }
+    sim.run()
+}
+
+#[test]
+fn test_reads_and_writes_converge() {
+    let reports = Simulation::explore(0..500, read_write_race).unwrap_or_else(|failure| panic!("{}", failure));
+    assert_eq!(reports.len(), 500);
+}
+
+#[test]
+fn test_same_seed_replays_exactly() {
+    let first = read_write_race(&mut Simulation::new(7)).unwrap();
+    let second = read_write_race(&mut Simulation::new(7)).unwrap();
+    assert_eq!(first, second);
+    assert!(first.elapsed > Duration::ZERO);
+}
+
+#[test]
+fn test_failures_report_their_seed() {
+    let mut sim = Simulation::new(3);
+    let coordinator = Arc::new(sim.coordinator(manifest()));
+    sim.network().partition(Layer::Server, Layer::Edge);
+    let edge = sim.network().layer(Layer::Edge);
+    sim.invariant("edge stays empty", move || {
+        if edge.is_empty() {
+            Ok(())
+        } else {
+            Err("edge has entries".to_string())
+        }
+    });
+
+    let writer = coordinator.clone();
+    sim.spawn("writer", async move { writer.set("user:1", b"ada".to_vec()).await });
+    sim.run().unwrap();
+
+    sim.network().heal_all();
+    sim.spawn("backfill", async move {
+        coordinator.catch_up(Layer::Edge).await?;
+        coordinator.set_layer("session:1", b"s".to_vec(), Layer::Edge).await
+    });
+    let failure = sim.run().unwrap_err();
+    assert_eq!(failure.seed, 3);
+    assert!(failure.to_string().contains("STATELESS_SIM_SEED=3"), "{}", failure);
+}
+
+#[test]
+fn test_deadlocks_are_reported() {
+    let mut sim = Simulation::new(1);
+    let (_tx, rx) = tokio::sync::oneshot::channel::<()>();
+    sim.spawn("waiter", async move {
+        let _ = rx.await;
+        Ok(())
+    });
+    let failure = sim.run().unwrap_err();
+    assert!(failure.reason.contains("deadlock"), "{}", failure);
+}
+
+#[test]
+fn test_layer_keys_are_sorted() {
+    let mut sim = Simulation::new(5);
+    let server = sim.network().layer(Layer::Server);
+    let keys = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let found = keys.clone();
+    sim.spawn("lister", async move {
+        for id in [3, 1, 4, 2, 5] {
+            server.set(&format!("user:{}", id), vec![id]).await?;
+        }
+        *found.lock().unwrap() = server.keys("user:*").await?;
+        Ok(())
+    });
+    sim.run().unwrap();
+    assert_eq!(*keys.lock().unwrap(), ["user:1", "user:2", "user:3", "user:4", "user:5"]);
+}
@@ -0,0 +1,122 @@
+use std::sync::Arc;
+use core::prelude::*;
+use core::layer::CacheLayer;
+use core::otlp::OtlpLayer;
+use core::{Ownership, TraceConfig};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tracing_subscriber::layer::SubscriberExt;
+use crate::common::layer::MemoryLayer;
+
+fn ownership() -> Arc<OwnershipGraph> {
+    let graph = OwnershipGraph::new();
+    graph.add(Ownership::new("user:*", "user_data", Layer::Edge)).unwrap();
+    Arc::new(graph)
+}
+
+#[tokio::test]
+async fn test_trace_pattern_captures_access_log() {
+    let tracer = Tracer::new();
+    let layer = Traced::layer(MemoryLayer::default(), Layer::Edge)
+        .with_ownership(ownership())
+        .with_tracer(tracer.clone());
+    
+    let trace = tracer.trace_pattern("user:*");
+    assert_eq!(tracer.patterns(), vec!["user:*".to_string()]);
+    layer.set("user:1", b"alice".to_vec()).await.unwrap();
+    layer.get("user:1").await.unwrap();
+    layer.get("user:2").await.unwrap();
+    layer.get("product:1").await.unwrap();
+    
+    let records = trace.stop();
+    assert_eq!(records.len(), 3);
+    assert_eq!(records[0].op, "set");
+    assert_eq!(records[1].hit, Some(true));
+    assert_eq!(records[2].hit, Some(false));
+    assert_eq!(records[1].owner.as_deref(), Some("user_data"));
+    assert_eq!(records[1].layer, Some(Layer::Edge));
+    
+    // Stopping or dropping the handle unregisters the traced pattern
+    assert!(tracer.patterns().is_empty());
+    let trace = tracer.trace_pattern("user:*");
+    drop(trace);
+    assert!(tracer.patterns().is_empty());
+}
+
+#[tokio::test]
+async fn test_trace_pattern_sampling() {
+    let tracer = Tracer::new();
+    let layer = Traced::layer(MemoryLayer::default(), Layer::Client).with_tracer(tracer.clone());
+    
+    let trace = tracer.trace_pattern_with("k:*", TraceConfig { sample_every: 3, capacity: 2 });
+    for i in 0..9 {
+        layer.get(&format!("k:{}", i)).await.unwrap();
+    }
+    
+    assert_eq!(trace.seen(), 9);
+    let keys: Vec<_> = trace.records().into_iter().map(|r| r.key).collect();
+    assert_eq!(keys, vec!["k:3".to_string(), "k:6".to_string()]);
+}
+
+/// Minimal OTLP/HTTP collector stand-in returning the first request body
+async fn collector() -> (std::net::SocketAddr, tokio::task::JoinHandle<serde_json::Value>) {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let handle = tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut request = Vec::new();
+        let mut buf = [0u8; 4096];
+        let body_start = loop {
+            let n = stream.read(&mut buf).await.unwrap();
+            request.extend_from_slice(&buf[..n]);
+            if let Some(pos) = request.windows(4).position(|w| w == b"\r\n\r\n") {
+                break pos + 4;
+            }
+        };
+        let head = String::from_utf8_lossy(&request[..body_start]).to_string();
+        assert!(head.starts_with("POST /v1/traces"));
+        let length: usize = head.lines()
+            .find_map(|l| l.strip_prefix("Content-Length: "))
+            .unwrap()
+            .trim()
+            .parse()
+            .unwrap();
+        while request.len() < body_start + length {
+            let n = stream.read(&mut buf).await.unwrap();
+            request.extend_from_slice(&buf[..n]);
+        }
+        stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n").await.unwrap();
+        serde_json::from_slice(&request[body_start..body_start + length]).unwrap()
+    });
+    (addr, handle)
+}
+
+#[tokio::test]
+async fn test_otlp_export_to_local_collector() {
+    let (addr, received) = collector().await;
+    let otlp = OtlpLayer::new();
+    let exporter = otlp.exporter(addr, "stateless-test");
+    let subscriber = tracing_subscriber::registry().with(otlp);
+    let _guard = tracing::subscriber::set_default(subscriber);
+    
+    let layer = Traced::layer(MemoryLayer::default(), Layer::Server).with_ownership(ownership());
+    layer.set("user:1", b"alice".to_vec()).await.unwrap();
+    layer.get("user:1").await.unwrap();
+    
+    assert_eq!(exporter.flush().await.unwrap(), 2);
+    let body = received.await.unwrap();
+    
+    let resource = &body["resourceSpans"][0];
+    assert_eq!(resource["resource"]["attributes"][0]["value"]["stringValue"], "stateless-test");
+    let spans = resource["scopeSpans"][0]["spans"].as_array().unwrap();
+    assert_eq!(spans[0]["name"], "cache.set");
+    assert_eq!(spans[1]["name"], "cache.get");
+    
+    let attributes = spans[1]["attributes"].as_array().unwrap();
+    let attr = |name: &str| attributes.iter()
+        .find(|a| a["key"] == name)
+        .map(|a| a["value"]["stringValue"].clone());
+    assert_eq!(attr("key").unwrap(), "user:1");
+    assert_eq!(attr("pattern").unwrap(), "user:*");
+    assert_eq!(attr("owner").unwrap(), "user_data");
+    assert_eq!(attr("layer").unwrap(), "server");
+}
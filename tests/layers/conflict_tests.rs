@@ -0,0 +1,162 @@
+use std::sync::{Arc, Mutex};
+use core::layer::CacheLayer;
+use core::prelude::*;
+use core::{Causality, ConflictLayer, HybridClock, MergeWith, RejectAndNotify, Timestamp, VersionVector};
+use crate::common::layer::MemoryLayer;
+
+fn vector(counters: &[(&str, u64)]) -> VersionVector {
+    let mut vector = VersionVector::new();
+    for &(node, counter) in counters {
+        vector.advance(node, counter);
+    }
+    vector
+}
+
+fn write(value: &str, node: &str, physical: u64, vector: VersionVector) -> Versioned {
+    let stamp = Timestamp { physical, logical: 0, node: node.into() };
+    Versioned::new(value.as_bytes().to_vec(), stamp, vector)
+}
+
+#[test]
+fn test_version_vector_causality() {
+    let base = vector(&[("server", 1)]);
+    let mut client = base.clone();
+    client.increment("client");
+    let mut server = base.clone();
+    server.increment("server");
+
+    assert_eq!(client.compare(&base), Causality::After);
+    assert_eq!(base.compare(&server), Causality::Before);
+    assert_eq!(client.compare(&server), Causality::Concurrent);
+    assert_eq!(base.compare(&base.clone()), Causality::Equal);
+
+    client.merge(&server);
+    assert_eq!(client.compare(&server), Causality::After);
+    assert_eq!(client.get("server"), 2);
+}
+
+#[test]
+fn test_hybrid_clock_orders_after_observed() {
+    let clock = HybridClock::new("client");
+    let first = clock.now();
+    assert!(clock.now() > first);
+
+    let ahead = Timestamp { physical: first.physical + 60_000, logical: 7, node: "server".into() };
+    clock.observe(&ahead);
+    let next = clock.now();
+    assert!(next > ahead);
+    assert_eq!((next.physical, next.logical), (ahead.physical, 8));
+}
+
+#[tokio::test]
+async fn test_last_writer_wins_by_default() {
+    let layer = ConflictLayer::new(MemoryLayer::default(), "server", Arc::new(Conflicts::new()));
+    layer.set("user:123:prefs", b"dark".to_vec()).await.unwrap();
+    let base = layer.get_versioned("user:123:prefs").await.unwrap().unwrap();
+
+    // A server write after the stored one, and an offline write stamped before it
+    layer.set("user:123:prefs", b"light".to_vec()).await.unwrap();
+    let mut offline = base.vector.clone();
+    offline.increment("client");
+    let stored = layer
+        .set_versioned("user:123:prefs", write("blue", "client", base.stamp.physical, offline))
+        .await
+        .unwrap();
+    assert_eq!(stored.value, b"light".to_vec());
+
+    // Both versions are included, so the next write from the client wins
+    let mut next = stored.vector.clone();
+    next.increment("client");
+    let stored = layer
+        .set_versioned("user:123:prefs", write("green", "client", 0, next))
+        .await
+        .unwrap();
+    assert_eq!(stored.value, b"green".to_vec());
+    assert_eq!(layer.get("user:123:prefs").await.unwrap(), Some(b"green".to_vec()));
+}
+
+#[tokio::test]
+async fn test_stale_writes_are_dropped() {
+    let layer = ConflictLayer::new(MemoryLayer::default(), "server", Arc::new(Conflicts::new()));
+    let first = write("a", "client", 1, vector(&[("client", 1)]));
+    layer.set_versioned("doc:1", first.clone()).await.unwrap();
+    layer.set_versioned("doc:1", write("b", "client", 2, vector(&[("client", 2)]))).await.unwrap();
+
+    let stored = layer.set_versioned("doc:1", first).await.unwrap();
+    assert_eq!(stored.value, b"b".to_vec());
+}
+
+#[tokio::test]
+async fn test_on_conflict_merges_concurrent_writes() {
+    let conflicts = Arc::new(Conflicts::new());
+    let server = Arc::new(ConflictLayer::new(MemoryLayer::default(), "server", conflicts.clone()));
+    let coordinator = LayerCoordinator::new(Arc::new(OwnershipGraph::new()))
+        .with_layer(Layer::Server, server.clone())
+        .with_conflicts(conflicts);
+    coordinator.on_conflict("user:*:prefs", MergeWith::new(|conflict: &core::Conflict| {
+        let mut merged = conflict.current.value.clone();
+        merged.extend_from_slice(b"+");
+        merged.extend_from_slice(&conflict.incoming.value);
+        merged
+    }));
+
+    coordinator.set("user:123:prefs", b"dark".to_vec()).await.unwrap();
+    let base = server.get_versioned("user:123:prefs").await.unwrap().unwrap();
+    coordinator.set("user:123:prefs", b"compact".to_vec()).await.unwrap();
+
+    let mut offline = base.vector.clone();
+    offline.increment("client");
+    let stored = server
+        .set_versioned("user:123:prefs", write("large", "client", 0, offline.clone()))
+        .await
+        .unwrap();
+    assert_eq!(stored.value, b"compact+large".to_vec());
+    assert_eq!(stored.vector.compare(&offline), Causality::After);
+    assert_eq!(coordinator.get("user:123:prefs").await.unwrap(), Some(b"compact+large".to_vec()));
+}
+
+#[tokio::test]
+async fn test_reject_and_notify() {
+    let conflicts = Arc::new(Conflicts::new());
+    let notified = Arc::new(Mutex::new(Vec::new()));
+    let seen = notified.clone();
+    conflicts.on_conflict("user:*", RejectAndNotify::new(move |conflict: &core::Conflict| {
+        seen.lock().unwrap().push(conflict.key.clone());
+    }));
+    let layer = ConflictLayer::new(MemoryLayer::default(), "server", conflicts);
+
+    layer.set("user:123:prefs", b"dark".to_vec()).await.unwrap();
+    let result = layer
+        .set_versioned("user:123:prefs", write("blue", "client", u64::MAX, vector(&[("client", 1)])))
+        .await;
+
+    let err = result.unwrap_err();
+    assert!(matches!(err, Error::Conflict { .. }));
+    assert_eq!(err.kind(), ErrorKind::Conflict);
+    assert!(!err.is_retryable());
+    assert_eq!(*notified.lock().unwrap(), vec!["user:123:prefs"]);
+    assert_eq!(layer.get("user:123:prefs").await.unwrap(), Some(b"dark".to_vec()));
+}
+
+#[tokio::test]
+async fn test_delete_leaves_a_tombstone() {
+    let layer = ConflictLayer::new(MemoryLayer::default(), "server", Arc::new(Conflicts::new()));
+    layer.set("user:123:prefs", b"dark".to_vec()).await.unwrap();
+    let base = layer.get_versioned("user:123:prefs").await.unwrap().unwrap();
+    layer.delete("user:123:prefs").await.unwrap();
+    assert_eq!(layer.get_versioned("user:123:prefs").await.unwrap(), None);
+    assert!(layer.keys("user:*").await.unwrap().is_empty());
+
+    // An offline edit stamped before the delete does not bring the value back
+    let mut offline = base.vector.clone();
+    offline.increment("client");
+    let result = layer
+        .set_versioned("user:123:prefs", write("blue", "client", base.stamp.physical, offline))
+        .await;
+    assert!(matches!(result, Err(Error::Conflict { .. })));
+    assert_eq!(layer.get("user:123:prefs").await.unwrap(), None);
+
+    // A write that saw the delete applies
+    layer.set("user:123:prefs", b"light".to_vec()).await.unwrap();
+    assert_eq!(layer.get("user:123:prefs").await.unwrap(), Some(b"light".to_vec()));
+}
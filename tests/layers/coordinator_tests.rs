@@ -0,0 +1,171 @@
+use std::sync::Arc;
+use async_trait::async_trait;
+use core::layer::CacheLayer;
+use core::prelude::*;
+use core::{ClientFirst, Pinned};
+use tokio::sync::{Notify, Semaphore};
+use crate::common::layer::MemoryLayer;
+
+/// Holds reads until released, so a walk can be paused at the owner
+struct PausedLayer {
+    inner: MemoryLayer,
+    reading: Notify,
+    release: Semaphore,
+}
+
+impl PausedLayer {
+    fn new() -> Self {
+        Self { inner: MemoryLayer::default(), reading: Notify::new(), release: Semaphore::new(0) }
+    }
+}
+
+#[async_trait]
+impl CacheLayer for PausedLayer {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let value = self.inner.get(key).await;
+        self.reading.notify_one();
+        self.release.acquire().await.unwrap().forget();
+        value
+    }
+
+    async fn set(&self, key: &str, value: Vec<u8>) -> Result<()> {
+        self.inner.set(key, value).await
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        self.inner.delete(key).await
+    }
+
+    async fn keys(&self, pattern: &str) -> Result<Vec<String>> {
+        self.inner.keys(pattern).await
+    }
+}
+
+struct Tiers {
+    client: Arc<MemoryLayer>,
+    edge: Arc<MemoryLayer>,
+    server: Arc<MemoryLayer>,
+}
+
+fn coordinator(graph: OwnershipGraph) -> (LayerCoordinator, Tiers) {
+    let tiers = Tiers {
+        client: Arc::new(MemoryLayer::default()),
+        edge: Arc::new(MemoryLayer::default()),
+        server: Arc::new(MemoryLayer::default()),
+    };
+    let coordinator = LayerCoordinator::new(Arc::new(graph))
+        .with_layer(Layer::Server, tiers.server.clone())
+        .with_layer(Layer::Client, tiers.client.clone())
+        .with_layer(Layer::Edge, tiers.edge.clone());
+    (coordinator, tiers)
+}
+
+#[tokio::test]
+async fn test_tiered_read_backfills_strategy_layers() {
+    let (coordinator, tiers) = coordinator(OwnershipGraph::new());
+    let coordinator = coordinator
+        .with_strategy(ClientFirst::new().or_else(Pinned(Layer::Server)))
+        .await
+        .unwrap();
+    assert_eq!(coordinator.layers(), vec![Layer::Client, Layer::Edge, Layer::Server]);
+
+    tiers.server.entries.insert("user:1".into(), b"alice".to_vec());
+    let hit = coordinator.lookup("user:1").await.unwrap().unwrap();
+    assert_eq!(hit.layer, Layer::Server);
+    assert_eq!(hit.backfilled, vec![Layer::Client]);
+    assert!(!tiers.edge.entries.contains_key("user:1"));
+
+    let hit = coordinator.lookup("user:1").await.unwrap().unwrap();
+    assert_eq!(hit.layer, Layer::Client);
+    assert!(hit.backfilled.is_empty());
+    assert!(coordinator.get("user:2").await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_set_writes_owner_and_removes_copies() {
+    let graph = OwnershipGraph::new();
+    graph.add(Ownership::new("product:*", "Catalog", Layer::Edge)).unwrap();
+    let (coordinator, tiers) = coordinator(graph);
+
+    tiers.client.entries.insert("product:1".into(), b"stale".to_vec());
+    tiers.server.entries.insert("product:1".into(), b"stale".to_vec());
+    coordinator.set("product:1", b"fresh".to_vec()).await.unwrap();
+    assert_eq!(tiers.edge.entries.get("product:1").unwrap().as_slice(), b"fresh");
+    assert!(!tiers.client.entries.contains_key("product:1"));
+    assert!(!tiers.server.entries.contains_key("product:1"));
+
+    // Unowned keys follow the strategy
+    coordinator.set("misc:1", b"x".to_vec()).await.unwrap();
+    assert!(tiers.server.entries.contains_key("misc:1"));
+}
+
+#[tokio::test]
+async fn test_set_to_missing_owning_layer_fails() {
+    let graph = OwnershipGraph::new();
+    graph.add(Ownership::new("local:*", "App", Layer::Client)).unwrap();
+    let coordinator = LayerCoordinator::new(Arc::new(graph))
+        .with_layer(Layer::Server, Arc::new(MemoryLayer::default()));
+    let err = coordinator.set("local:1", vec![1]).await.unwrap_err();
+    assert!(matches!(err, Error::LayerViolation { layer: Layer::Client, .. }));
+}
+
+#[tokio::test]
+async fn test_concurrent_cold_reads_walk_once() {
+    let (coordinator, tiers) = coordinator(OwnershipGraph::new());
+    let coordinator = Arc::new(
+        coordinator
+            .with_strategy(Pinned(Layer::Client).or_else(Pinned(Layer::Server)))
+            .await
+            .unwrap(),
+    );
+    tiers.server.entries.insert("hot".into(), b"v".to_vec());
+
+    let reads: Vec<_> = (0..16)
+        .map(|_| {
+            let coordinator = coordinator.clone();
+            tokio::spawn(async move { coordinator.lookup("hot").await.unwrap().unwrap() })
+        })
+        .collect();
+    let mut from_server = 0;
+    for read in reads {
+        if read.await.unwrap().layer == Layer::Server {
+            from_server += 1;
+        }
+    }
+    assert_eq!(from_server, 1);
+}
+
+#[tokio::test]
+async fn test_walk_drops_backfill_invalidated_mid_read() {
+    let client = Arc::new(MemoryLayer::default());
+    let server = Arc::new(PausedLayer::new());
+    server.inner.entries.insert("team:1:members".into(), b"stale".to_vec());
+    let coordinator = Arc::new(
+        LayerCoordinator::new(Arc::new(OwnershipGraph::new()))
+            .with_layer(Layer::Client, client.clone())
+            .with_layer(Layer::Server, server.clone())
+            .with_strategy(Pinned(Layer::Client).or_else(Pinned(Layer::Server)))
+            .await
+            .unwrap(),
+    );
+
+    let read = {
+        let coordinator = coordinator.clone();
+        tokio::spawn(async move { coordinator.lookup("team:1:members").await.unwrap().unwrap() })
+    };
+    // The owner has answered; purge everywhere before the backfill lands
+    server.reading.notified().await;
+    coordinator.invalidate_pattern("team:*").await.unwrap();
+    server.release.add_permits(1);
+
+    let hit = read.await.unwrap();
+    assert_eq!(hit.layer, Layer::Server);
+    assert!(hit.backfilled.is_empty());
+    assert!(!client.entries.contains_key("team:1:members"));
+
+    // Without a concurrent invalidation the backfill is kept
+    server.inner.entries.insert("team:2:members".into(), b"fresh".to_vec());
+    server.release.add_permits(1);
+    let hit = coordinator.lookup("team:2:members").await.unwrap().unwrap();
+    assert_eq!(hit.backfilled, vec![Layer::Client]);
+}
@@ -0,0 +1,130 @@
+use std::sync::Arc;
+use core::layer::CacheLayer;
+use core::prelude::*;
+use core::{ConflictLayer, GCounter, LwwMap, LwwRegister, OrSet, PNCounter, Timestamp};
+use crate::common::layer::MemoryLayer;
+
+fn at(physical: u64, node: &str) -> Timestamp {
+    Timestamp { physical, logical: 0, node: node.into() }
+}
+
+fn merged<T: Crdt>(a: &T, b: &T) -> T {
+    let mut out = a.clone();
+    out.merge(b);
+    out
+}
+
+#[test]
+fn test_counters_converge() {
+    let mut edge = PNCounter::new();
+    let mut client = PNCounter::new();
+    edge.incr("edge", 5);
+    client.incr("client", 2);
+    client.incr("client", -1);
+
+    let a = merged(&edge, &client);
+    assert_eq!(a, merged(&client, &edge));
+    assert_eq!(a, merged(&a, &client));
+    assert_eq!(a.value(), 6);
+
+    let mut views = GCounter::new();
+    views.incr("edge", 3);
+    assert_eq!(merged(&views, &views).value(), 3);
+}
+
+#[test]
+fn test_or_set_add_wins() {
+    let mut base = OrSet::new();
+    base.add("server", "admin".to_string());
+    let mut edge = base.clone();
+    let mut client = base.clone();
+
+    edge.remove(&"admin".to_string());
+    client.add("client", "admin".to_string());
+    client.add("client", "editor".to_string());
+
+    let a = merged(&edge, &client);
+    assert_eq!(a, merged(&client, &edge));
+    assert_eq!(a.items().cloned().collect::<Vec<_>>(), vec!["admin", "editor"]);
+
+    // A remove that has seen every add sticks
+    let mut b = a.clone();
+    b.remove(&"admin".to_string());
+    assert!(!merged(&a, &b).contains(&"admin".to_string()));
+}
+
+#[test]
+fn test_lww_types() {
+    let mut edge = LwwRegister::new(b"v1".to_vec(), at(1, "edge"));
+    let client = LwwRegister::new(b"v2".to_vec(), at(2, "client"));
+    edge.merge(&client);
+    assert_eq!(edge.get(), &b"v2".to_vec());
+
+    let mut a = LwwMap::new();
+    a.set("theme".to_string(), "dark".to_string(), at(1, "client"));
+    let mut b = a.clone();
+    b.remove("theme".to_string(), at(2, "edge"));
+    a.set("lang".to_string(), "fr".to_string(), at(3, "client"));
+
+    let m = merged(&a, &b);
+    assert_eq!(m, merged(&b, &a));
+    assert_eq!(m.get(&"theme".to_string()), None);
+    assert_eq!(m.get(&"lang".to_string()), Some(&"fr".to_string()));
+    // The tombstone keeps a stale copy from restoring the entry
+    assert_eq!(merged(&m, &a).len(), 1);
+}
+
+#[test]
+fn test_cache_entry_holds_crdt() {
+    let mut counter = PNCounter::new();
+    counter.incr("edge", 4);
+    let entry = CacheEntry::crdt(&CrdtValue::PNCounter(counter.clone())).unwrap();
+    assert_eq!(entry.metadata.get("crdt").map(String::as_str), Some("pn_counter"));
+    assert_eq!(entry.as_crdt(), Some(CrdtValue::PNCounter(counter)));
+}
+
+#[tokio::test]
+async fn test_incr_converges_across_layers() {
+    let server = Arc::new(ConflictLayer::new(MemoryLayer::default(), "server", Arc::new(Conflicts::new())));
+    let graph = Arc::new(OwnershipGraph::new());
+    let edge = LayerCoordinator::new(graph.clone()).with_layer(Layer::Server, server.clone());
+    let client = LayerCoordinator::new(graph).with_layer(Layer::Server, server.clone());
+
+    edge.incr("page:home:views", "edge", 3).await.unwrap();
+    // A stale copy written without the edge's increments
+    let mut stale = PNCounter::new();
+    stale.incr("client", 2);
+    client.set("page:home:views", CrdtValue::PNCounter(stale).encode().unwrap()).await.unwrap();
+    assert_eq!(edge.incr("page:home:views", "edge", 1).await.unwrap(), 6);
+
+    let stored = server.get("page:home:views").await.unwrap().unwrap();
+    let counter = PNCounter::from_stored("page:home:views", Some(&stored)).unwrap();
+    assert_eq!(counter.value(), 6);
+
+    server.set("page:home:title", b"Home".to_vec()).await.unwrap();
+    assert!(matches!(
+        edge.incr("page:home:title", "edge", 1).await,
+        Err(Error::Serialization { .. })
+    ));
+}
+
+#[tokio::test]
+async fn test_replication_merges_crdts() {
+    let edge = Arc::new(ConflictLayer::new(MemoryLayer::default(), "edge", Arc::new(Conflicts::new())));
+    let server = Arc::new(MemoryLayer::default());
+    let coordinator = LayerCoordinator::new(Arc::new(OwnershipGraph::new()))
+        .with_layer(Layer::Edge, edge.clone())
+        .with_layer(Layer::Server, server.clone());
+
+    let mut tags = OrSet::new();
+    tags.add("edge", "new".to_string());
+    edge.set("post:1:tags", CrdtValue::OrSet(tags).encode().unwrap()).await.unwrap();
+    let mut tags = OrSet::new();
+    tags.add("server", "rust".to_string());
+    server.set("post:1:tags", CrdtValue::OrSet(tags).encode().unwrap()).await.unwrap();
+
+    assert_eq!(coordinator.replicate_to("post:*", Layer::Edge).await.unwrap(), 1);
+    let stored = edge.get("post:1:tags").await.unwrap().unwrap();
+    let Some(CrdtValue::OrSet(tags)) = CrdtValue::decode(&stored) else { panic!("not a set") };
+    assert_eq!(tags.items().cloned().collect::<Vec<_>>(), vec!["new", "rust"]);
+}
@@ -0,0 +1,101 @@
+use std::sync::Arc;
+use std::time::Duration;
+use async_trait::async_trait;
+use core::prelude::*;
+use core::layer::CacheLayer;
+use core::{ComponentHealth, LayerProbe, QuotaLayer};
+use crate::common::layer::MemoryLayer;
+
+/// A layer that rejects every operation
+struct BrokenLayer;
+
+fn down() -> Error {
+    Error::Network { layer: Some(Layer::Edge), reason: "down".into(), source: None }
+}
+
+#[async_trait]
+impl core::layer::CacheLayer for BrokenLayer {
+    async fn get(&self, _key: &str) -> core::Result<Option<Vec<u8>>> {
+        Err(down())
+    }
+    
+    async fn set(&self, _key: &str, _value: Vec<u8>) -> core::Result<()> {
+        Err(down())
+    }
+    
+    async fn delete(&self, _key: &str) -> core::Result<()> {
+        Err(down())
+    }
+    
+    async fn keys(&self, _pattern: &str) -> core::Result<Vec<String>> {
+        Ok(vec![])
+    }
+}
+
+struct Static(ComponentHealth);
+
+#[async_trait]
+impl HealthCheck for Static {
+    async fn check(&self) -> ComponentHealth {
+        self.0.clone()
+    }
+}
+
+#[tokio::test]
+async fn test_layer_probes() {
+    // A full layer is still healthy: probing only reads
+    let quotas = Arc::new(QuotaManager::new(vec![
+        QuotaPolicy::new("*").max_keys(1).on_overflow(OverflowAction::Reject),
+    ]));
+    let client = QuotaLayer::new(MemoryLayer::default(), quotas);
+    client.set("user:1", b"ada".to_vec()).await.unwrap();
+    let checker = HealthChecker::new();
+    checker
+        .register(LayerProbe::new(Layer::Client, Arc::new(client)))
+        .register(LayerProbe::new(Layer::Edge, Arc::new(BrokenLayer)));
+    
+    let report = checker.check().await;
+    assert_eq!(report.component("layer:client").unwrap().status, HealthStatus::Healthy);
+    assert_eq!(report.component("layer:edge").unwrap().status, HealthStatus::Unhealthy);
+    assert_eq!(report.status(), HealthStatus::Unhealthy);
+    assert!(!report.is_ready());
+    // Layers are not critical, so the process is still live
+    assert!(report.is_live());
+}
+
+#[tokio::test]
+async fn test_replication_lag_degrades_layer() {
+    let lag = Arc::new(|| Some(Duration::from_secs(5)));
+    let checker = HealthChecker::new();
+    checker.register(
+        LayerProbe::new(Layer::Edge, Arc::new(MemoryLayer::default()))
+            .with_replication_lag(lag, Duration::from_secs(1)),
+    );
+    
+    let report = checker.check().await;
+    let edge = report.component("layer:edge").unwrap();
+    assert_eq!(edge.status, HealthStatus::Degraded);
+    assert_eq!(edge.replication_lag, Some(Duration::from_secs(5)));
+    assert_eq!(report.degraded(), vec!["layer:edge"]);
+    assert!(report.is_ready());
+}
+
+#[tokio::test]
+async fn test_liveness_vs_readiness() {
+    let checker = HealthChecker::new();
+    checker
+        .register(Static(ComponentHealth::healthy("shards").critical()))
+        .register(Static(ComponentHealth::unhealthy("network", "not listening")));
+    
+    let report = checker.check().await;
+    assert!(report.is_live());
+    assert!(!report.is_ready());
+    
+    checker.register(Static(ComponentHealth::unhealthy("storage", "read-only").critical()));
+    let report = checker.check().await;
+    assert!(!report.is_live());
+    
+    let json = report.to_json();
+    assert_eq!(json["live"], false);
+    assert_eq!(json["components"][2]["message"], "read-only");
+}
@@ -0,0 +1,181 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use async_trait::async_trait;
+use core::prelude::*;
+use core::{CatchUp, GlobalConsistent, Invalidation, InvalidationBus};
+use crate::common::layer::MemoryLayer;
+
+/// A memory layer that can be cut off from the coordinator
+#[derive(Default)]
+struct PartitionableLayer {
+    inner: MemoryLayer,
+    down: AtomicBool,
+    /// Refuse deletes with a permanent error
+    read_only: AtomicBool,
+}
+
+impl PartitionableLayer {
+    fn check(&self) -> core::Result<()> {
+        if self.down.load(Ordering::SeqCst) {
+            Err(Error::Network { layer: Some(Layer::Edge), reason: "partitioned".into(), source: None })
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[async_trait]
+impl core::layer::CacheLayer for PartitionableLayer {
+    async fn get(&self, key: &str) -> core::Result<Option<Vec<u8>>> {
+        self.check()?;
+        self.inner.get(key).await
+    }
+
+    async fn set(&self, key: &str, value: Vec<u8>) -> core::Result<()> {
+        self.check()?;
+        self.inner.set(key, value).await
+    }
+
+    async fn delete(&self, key: &str) -> core::Result<()> {
+        self.check()?;
+        if self.read_only.load(Ordering::SeqCst) {
+            return Err(Error::InvalidConfig("read-only layer".into()));
+        }
+        self.inner.delete(key).await
+    }
+
+    async fn keys(&self, pattern: &str) -> core::Result<Vec<String>> {
+        self.check()?;
+        self.inner.keys(pattern).await
+    }
+}
+
+fn manifest() -> Arc<OwnershipGraph> {
+    let graph = OwnershipGraph::new();
+    graph.add(Ownership::new("user:{id}", "UserService", Layer::Server)).unwrap();
+    graph.add_invalidation("user:{id}", "profile:*");
+    Arc::new(graph)
+}
+
+#[tokio::test]
+async fn test_server_write_invalidates_edge_and_client() {
+    let graph = manifest();
+    let client = Arc::new(MemoryLayer::default());
+    let edge = Arc::new(MemoryLayer::default());
+    let coordinator = LayerCoordinator::new(graph.clone())
+        .with_layer(Layer::Client, client.clone())
+        .with_layer(Layer::Edge, edge.clone())
+        .with_layer(Layer::Server, Arc::new(MemoryLayer::default()))
+        .with_strategy(GlobalConsistent::new().with_ownership(graph))
+        .await
+        .unwrap();
+
+    client.entries.insert("user:1".into(), b"old".to_vec());
+    edge.entries.insert("user:1".into(), b"old".to_vec());
+    edge.entries.insert("profile:1".into(), b"old".to_vec());
+    coordinator.set("user:1", b"new".to_vec()).await.unwrap();
+
+    assert!(client.entries.is_empty());
+    assert!(edge.entries.is_empty());
+    assert_eq!(coordinator.get("user:1").await.unwrap().unwrap(), b"new");
+    assert_eq!(coordinator.bus().lag(Layer::Edge), Some(0));
+    assert_eq!(coordinator.bus().retained(), 0);
+}
+
+#[tokio::test]
+async fn test_partitioned_layer_replays_missed_invalidations() {
+    let edge = Arc::new(PartitionableLayer::default());
+    let coordinator = LayerCoordinator::new(manifest())
+        .with_layer(Layer::Edge, edge.clone())
+        .with_layer(Layer::Server, Arc::new(MemoryLayer::default()));
+    edge.inner.entries.insert("user:1".into(), b"old".to_vec());
+    edge.inner.entries.insert("user:2".into(), b"old".to_vec());
+
+    edge.down.store(true, Ordering::SeqCst);
+    coordinator.set("user:1", b"new".to_vec()).await.unwrap();
+    coordinator.set("user:2", b"new".to_vec()).await.unwrap();
+    assert!(!coordinator.bus().is_connected(Layer::Edge));
+    assert_eq!(coordinator.bus().lag(Layer::Edge), Some(2));
+    edge.down.store(false, Ordering::SeqCst);
+
+    // The stale edge is skipped until it catches up
+    let hit = coordinator.lookup("user:1").await.unwrap().unwrap();
+    assert_eq!(hit.layer, Layer::Server);
+
+    assert_eq!(coordinator.catch_up(Layer::Edge).await.unwrap(), 2);
+    assert!(edge.inner.entries.is_empty());
+    assert!(coordinator.bus().is_connected(Layer::Edge));
+    assert_eq!(coordinator.bus().lag(Layer::Edge), Some(0));
+}
+
+#[tokio::test]
+async fn test_failed_delivery_reaches_remaining_layers() {
+    let client = Arc::new(PartitionableLayer::default());
+    let edge = Arc::new(PartitionableLayer::default());
+    let coordinator = LayerCoordinator::new(manifest())
+        .with_layer(Layer::Client, client.clone())
+        .with_layer(Layer::Edge, edge.clone())
+        .with_layer(Layer::Server, Arc::new(MemoryLayer::default()));
+    client.inner.entries.insert("user:1".into(), b"old".to_vec());
+    edge.inner.entries.insert("user:1".into(), b"old".to_vec());
+
+    client.read_only.store(true, Ordering::SeqCst);
+    let err = coordinator.set("user:1", b"new".to_vec()).await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Config);
+    assert!(edge.inner.entries.is_empty());
+
+    edge.inner.entries.insert("user:2".into(), b"old".to_vec());
+    edge.read_only.store(true, Ordering::SeqCst);
+    client.inner.entries.insert("user:2".into(), b"old".to_vec());
+    let err = coordinator.set("user:2", b"new".to_vec()).await.unwrap_err();
+    let Error::Undelivered { errors } = &err else { panic!("{}", err) };
+    let layers: Vec<_> = errors.iter().map(|(layer, _)| *layer).collect();
+    assert_eq!(layers, vec![Layer::Client, Layer::Edge]);
+}
+
+#[tokio::test]
+async fn test_catch_up_names_missing_layer() {
+    let coordinator = LayerCoordinator::new(manifest()).with_layer(Layer::Server, Arc::new(MemoryLayer::default()));
+    let err = coordinator.catch_up(Layer::Edge).await.unwrap_err();
+    assert_eq!(err.to_string(), "Invalid configuration: the edge layer is not configured");
+}
+
+#[tokio::test]
+async fn test_layer_behind_retained_log_resyncs() {
+    let edge = Arc::new(PartitionableLayer::default());
+    let coordinator = LayerCoordinator::new(manifest())
+        .with_layer(Layer::Edge, edge.clone())
+        .with_layer(Layer::Server, Arc::new(MemoryLayer::default()))
+        .with_invalidation_log(2);
+    edge.inner.entries.insert("other:1".into(), b"old".to_vec());
+
+    edge.down.store(true, Ordering::SeqCst);
+    for i in 0..5 {
+        coordinator.set(&format!("user:{}", i), b"v".to_vec()).await.unwrap();
+    }
+    edge.down.store(false, Ordering::SeqCst);
+    assert!(matches!(coordinator.bus().pending(Layer::Edge), CatchUp::Resync { latest: 5 }));
+
+    coordinator.catch_up(Layer::Edge).await.unwrap();
+    assert!(edge.inner.entries.is_empty());
+    assert_eq!(coordinator.bus().acked(Layer::Edge), Some(5));
+}
+
+#[test]
+fn test_bus_sequences_and_acks() {
+    let bus = InvalidationBus::new();
+    bus.subscribe(Layer::Client);
+    bus.subscribe(Layer::Edge);
+    let first = bus.publish(Invalidation::Key("a".into()), Some(Layer::Edge));
+    let second = bus.publish(Invalidation::Pattern("b:*".into()), None);
+    assert_eq!((first.seq, second.seq), (1, 2));
+
+    bus.ack(Layer::Client, 2);
+    assert_eq!(bus.retained(), 2, "edge has not acknowledged yet");
+    match bus.pending(Layer::Edge) {
+        CatchUp::Replay(events) => assert_eq!(events, vec![first, second]),
+        other => panic!("unexpected {:?}", other),
+    }
+    bus.ack(Layer::Edge, 2);
+    assert_eq!(bus.retained(), 0);
+}
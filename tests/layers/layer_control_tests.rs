@@ -0,0 +1,101 @@
+use std::sync::Arc;
+use core::prelude::*;
+use core::layer::CacheLayer;
+use core::{EdgeOptimized, QuotaLayer};
+use crate::common::layer::MemoryLayer;
+
+fn coordinator(client: Arc<MemoryLayer>, edge: Arc<MemoryLayer>, server: Arc<MemoryLayer>) -> LayerCoordinator {
+    let graph = OwnershipGraph::new();
+    graph.add(Ownership::new("user:{id}", "UserService", Layer::Server)).unwrap();
+    LayerCoordinator::new(Arc::new(graph))
+        .with_layer(Layer::Client, client)
+        .with_layer(Layer::Edge, edge)
+        .with_layer(Layer::Server, server)
+}
+
+#[tokio::test]
+async fn test_set_layer_places_unowned_keys() {
+    let edge = Arc::new(MemoryLayer::default());
+    let server = Arc::new(MemoryLayer::default());
+    let coordinator = coordinator(Arc::default(), edge.clone(), server.clone());
+
+    coordinator.set_layer("session:1", b"a".to_vec(), Layer::Edge).await.unwrap();
+    assert_eq!(coordinator.get_from("session:1", Layer::Edge).await.unwrap(), Some(b"a".to_vec()));
+    assert!(server.get("session:1").await.unwrap().is_none());
+
+    // Later writes follow the explicit placement
+    coordinator.set("session:1", b"b".to_vec()).await.unwrap();
+    assert_eq!(edge.get("session:1").await.unwrap(), Some(b"b".to_vec()));
+    assert_eq!(coordinator.owning_layer("session:1", 1).await.unwrap(), Layer::Edge);
+}
+
+#[tokio::test]
+async fn test_set_layer_places_only_unowned_keys() {
+    let coordinator = coordinator(Arc::default(), Arc::default(), Arc::default());
+
+    // Typed handles write owned keys through set_layer; those follow the
+    // manifest and must not pile up as placements
+    coordinator.set_layer("user:1", b"a".to_vec(), Layer::Server).await.unwrap();
+    coordinator.set_layer("session:1", b"a".to_vec(), Layer::Edge).await.unwrap();
+    assert_eq!(coordinator.placement("user:1"), None);
+    assert_eq!(coordinator.placement("session:1"), Some(Layer::Edge));
+}
+
+#[tokio::test]
+async fn test_placement_respects_manifest() {
+    let coordinator = coordinator(Arc::default(), Arc::default(), Arc::default());
+
+    assert!(matches!(
+        coordinator.set_layer("user:1", b"a".to_vec(), Layer::Client).await,
+        Err(Error::LayerViolation { layer: Layer::Client, .. })
+    ));
+    assert!(coordinator.pin_to("user:*", Layer::Edge).is_err());
+    coordinator.set_layer("user:1", b"a".to_vec(), Layer::Server).await.unwrap();
+
+    coordinator.pin_to("feed:*", Layer::Edge).unwrap();
+    assert!(coordinator.set_layer("feed:1", b"a".to_vec(), Layer::Server).await.is_err());
+    assert_eq!(coordinator.owning_layer("feed:1", 1).await.unwrap(), Layer::Edge);
+    assert_eq!(coordinator.unpin("feed:*"), Some(Layer::Edge));
+    assert!(!coordinator.pins().is_pinned("feed:1"));
+}
+
+#[tokio::test]
+async fn test_replicate_to_copies_from_owner() {
+    let edge = Arc::new(MemoryLayer::default());
+    let server = Arc::new(MemoryLayer::default());
+    let coordinator = coordinator(Arc::default(), edge.clone(), server.clone())
+        .with_strategy(EdgeOptimized::new())
+        .await
+        .unwrap();
+
+    coordinator.set("user:1", b"ada".to_vec()).await.unwrap();
+    coordinator.set("user:2", b"bob".to_vec()).await.unwrap();
+    assert_eq!(coordinator.replicate_to("user:*", Layer::Edge).await.unwrap(), 2);
+    assert_eq!(edge.get("user:2").await.unwrap(), Some(b"bob".to_vec()));
+
+    // Replicas are invalidated by the owner's writes
+    coordinator.set("user:2", b"bea".to_vec()).await.unwrap();
+    assert!(edge.get("user:2").await.unwrap().is_none());
+    assert_eq!(coordinator.replicate_to("user:*", Layer::Server).await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn test_pinned_keys_survive_eviction() {
+    let pins = Arc::new(Pins::new());
+    let quotas = Arc::new(
+        QuotaManager::new(vec![QuotaPolicy::new("feed:*").max_keys(2)]).with_pins(pins.clone()),
+    );
+    let edge = Arc::new(QuotaLayer::new(MemoryLayer::default(), quotas));
+    let coordinator = LayerCoordinator::new(Arc::new(OwnershipGraph::new()))
+        .with_layer(Layer::Edge, edge.clone())
+        .with_pins(pins);
+
+    coordinator.pin_to("feed:home", Layer::Edge).unwrap();
+    coordinator.set("feed:home", b"h".to_vec()).await.unwrap();
+    coordinator.set_layer("feed:1", b"1".to_vec(), Layer::Edge).await.unwrap();
+    coordinator.set_layer("feed:2", b"2".to_vec(), Layer::Edge).await.unwrap();
+
+    assert!(edge.get("feed:home").await.unwrap().is_some());
+    assert!(edge.get("feed:1").await.unwrap().is_none());
+    assert!(edge.get("feed:2").await.unwrap().is_some());
+}
@@ -0,0 +1,133 @@
+use std::sync::Arc;
+use std::time::Duration;
+use core::prelude::*;
+use core::layer::CacheLayer;
+use core::{QuotaLayer, Histogram, LayerCache};
+use crate::common::layer::MemoryLayer;
+
+#[tokio::test]
+async fn test_hits_and_misses_by_pattern_and_layer() {
+    let metrics = Arc::new(Metrics::new(["user:*", "user:*:profile"]));
+    let client = MetricsLayer::new(MemoryLayer::default(), Layer::Client, metrics.clone());
+    let server = MetricsLayer::new(MemoryLayer::default(), Layer::Server, metrics.clone());
+    
+    client.set("user:1:profile", b"alice".to_vec()).await.unwrap();
+    client.get("user:1:profile").await.unwrap();
+    client.get("user:2:profile").await.unwrap();
+    server.get("user:1").await.unwrap();
+    server.get("product:1").await.unwrap();
+    
+    let profile = metrics.stats("user:*:profile");
+    assert_eq!((profile.hits, profile.misses), (1, 1));
+    assert_eq!(profile.hit_ratio(), 0.5);
+    assert_eq!(profile.get_latency.count(), 2);
+    assert_eq!(profile.set_latency.count(), 1);
+    
+    assert_eq!(metrics.stats_for("user:*", Layer::Server).misses, 1);
+    assert_eq!(metrics.stats_for("user:*", Layer::Client).misses, 0);
+    assert_eq!(metrics.stats("*").misses, 1);
+}
+
+#[tokio::test]
+async fn test_quota_evictions_are_counted() {
+    let metrics = Arc::new(Metrics::new(["session:*"]));
+    let quotas = Arc::new(QuotaManager::new(vec![QuotaPolicy::new("session:*").max_keys(1)]));
+    let layer = QuotaLayer::new(MemoryLayer::default(), quotas)
+        .with_metrics(metrics.clone(), Layer::Edge);
+    
+    layer.set("session:1", vec![1]).await.unwrap();
+    layer.set("session:2", vec![2]).await.unwrap();
+    
+    assert_eq!(metrics.stats_for("session:*", Layer::Edge).evictions, 1);
+}
+
+#[tokio::test]
+async fn test_invalidations_are_counted() {
+    let metrics = Arc::new(Metrics::new(["user:*"]));
+    let edge = Arc::new(MemoryLayer::default());
+    let coordinator = LayerCoordinator::new(Arc::new(OwnershipGraph::new()))
+        .with_layer(Layer::Edge, edge.clone())
+        .with_metrics(metrics.clone());
+    edge.entries.insert("user:1".into(), vec![1]);
+    edge.entries.insert("user:2".into(), vec![2]);
+    coordinator.invalidate_pattern("user:*").await.unwrap();
+    assert_eq!(metrics.stats_for("user:*", Layer::Edge).invalidations, 2);
+
+    let namespaces = Namespaces::new(Arc::new(MemoryLayer::default()));
+    let acme = namespaces
+        .create(NamespaceConfig::new("acme").metrics(metrics.clone(), Layer::Server))
+        .unwrap();
+    acme.set("user:1", vec![1]).await.unwrap();
+    acme.invalidate_pattern("user:*").await.unwrap();
+    assert_eq!(metrics.stats_for("user:*", Layer::Server).invalidations, 1);
+}
+
+#[tokio::test]
+async fn test_expirations_are_counted() {
+    let metrics = Arc::new(Metrics::new(["session:*"]));
+    let cache = LayerCache::new(MemoryLayer::default()).with_metrics(metrics.clone(), Layer::Client);
+    let entry = |ttl| CacheEntry { value: "token".into(), ttl, metadata: Default::default() };
+    cache.set("session:1", entry(Some(Duration::ZERO))).await.unwrap();
+    cache.set("session:2", entry(None)).await.unwrap();
+
+    assert!(cache.get("session:1").await.unwrap().is_none());
+    assert!(!cache.exists("session:1").await.unwrap());
+    assert!(cache.get("session:2").await.unwrap().is_some());
+    assert!(!cache.inner().entries.contains_key("session:1"));
+    assert_eq!(metrics.stats_for("session:*", Layer::Client).expirations, 1);
+    assert!(metrics
+        .to_prometheus()
+        .contains("stateless_cache_expirations_total{pattern=\"session:*\",layer=\"client\"} 1"));
+}
+
+#[test]
+fn test_histogram_quantiles() {
+    let histogram = Histogram::default();
+    for _ in 0..9 {
+        histogram.observe(Duration::from_micros(80));
+    }
+    histogram.observe(Duration::from_millis(20));
+    
+    let snapshot = histogram.snapshot();
+    assert_eq!(snapshot.count(), 10);
+    assert_eq!(snapshot.quantile(0.5), Some(Duration::from_micros(100)));
+    assert_eq!(snapshot.quantile(0.99), Some(Duration::from_millis(25)));
+}
+
+#[test]
+fn test_prometheus_exposition() {
+    let metrics = Metrics::new(["user:*"]);
+    metrics.record_hit("user:1", Layer::Edge, Duration::from_micros(40));
+    metrics.record_invalidation("user:1", Layer::Edge);
+    
+    let text = metrics.to_prometheus();
+    assert!(text.contains("# TYPE stateless_cache_hits_total counter"));
+    assert!(text.contains("stateless_cache_hits_total{pattern=\"user:*\",layer=\"edge\"} 1"));
+    assert!(text.contains("stateless_cache_invalidations_total{pattern=\"user:*\",layer=\"edge\"} 1"));
+    assert!(text.contains(
+        "stateless_cache_latency_seconds_bucket{pattern=\"user:*\",layer=\"edge\",op=\"get\",le=\"+Inf\"} 1"
+    ));
+    assert!(text.contains(
+        "stateless_cache_latency_seconds_count{pattern=\"user:*\",layer=\"edge\",op=\"get\"} 1"
+    ));
+}
+
+#[tokio::test]
+async fn test_prometheus_scrape_endpoint() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    
+    let metrics = Arc::new(Metrics::new(["user:*"]));
+    metrics.record_miss("user:1", Layer::Client, Duration::from_micros(10));
+    
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(metrics.clone().serve(listener));
+    
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    stream.write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n").await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    
+    assert!(response.starts_with("HTTP/1.1 200 OK"));
+    assert!(response.contains("stateless_cache_misses_total{pattern=\"user:*\",layer=\"client\"} 1"));
+}
@@ -0,0 +1,11 @@
+mod conflict_tests;
+mod coordinator_tests;
+mod crdt_tests;
+mod health_tests;
+mod invalidation_tests;
+mod layer_control_tests;
+mod metrics_tests;
+mod namespace_tests;
+mod quota_tests;
+mod sim_tests;
+mod tracking_tests;
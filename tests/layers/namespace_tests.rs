@@ -0,0 +1,102 @@
+use std::sync::Arc;
+use core::prelude::*;
+use crate::common::layer::MemoryLayer;
+
+fn registry() -> Namespaces {
+    Namespaces::new(Arc::new(MemoryLayer::default()))
+}
+
+#[tokio::test]
+async fn test_tenant_isolation() {
+    let namespaces = registry();
+    let acme = namespaces.create(NamespaceConfig::new("acme")).unwrap();
+    let globex = namespaces.create(NamespaceConfig::new("globex")).unwrap();
+    
+    acme.set("user:1", b"alice".to_vec()).await.unwrap();
+    globex.set("user:1", b"bob".to_vec()).await.unwrap();
+    
+    assert_eq!(acme.get("user:1").await.unwrap(), Some(b"alice".to_vec()));
+    assert_eq!(globex.get("user:1").await.unwrap(), Some(b"bob".to_vec()));
+    
+    // Even a catch-all pattern stays inside the tenant
+    assert_eq!(acme.get_pattern("*").await.unwrap(), vec!["user:1".to_string()]);
+    
+    assert_eq!(acme.invalidate_pattern("*").await.unwrap(), 1);
+    assert!(acme.get("user:1").await.unwrap().is_none());
+    assert!(globex.get("user:1").await.unwrap().is_some());
+}
+
+#[tokio::test]
+async fn test_tenant_memory_quota() {
+    let namespaces = registry();
+    let tenant = namespaces
+        .create(NamespaceConfig::new("small").max_bytes(8))
+        .unwrap();
+    
+    tenant.set("a", vec![0; 5]).await.unwrap();
+    assert!(matches!(
+        tenant.set("b", vec![0; 5]).await,
+        Err(Error::QuotaExceeded { .. })
+    ));
+    
+    let stats = tenant.stats();
+    assert_eq!(stats.bytes, 5);
+    assert_eq!(stats.keys, 1);
+    assert_eq!(stats.writes, 1);
+}
+
+#[tokio::test]
+async fn test_rejected_write_evicts_nothing() {
+    let namespaces = registry();
+    let tenant = namespaces
+        .create(NamespaceConfig::new("acme").max_bytes(10).quota(QuotaPolicy::new("user:*").max_keys(1)))
+        .unwrap();
+    tenant.set("user:1", vec![0; 4]).await.unwrap();
+    tenant.set("other:1", vec![0; 5]).await.unwrap();
+
+    // Evicting user:1 would not make room for 8 more bytes
+    assert!(matches!(
+        tenant.set("user:2", vec![0; 8]).await,
+        Err(Error::QuotaExceeded { .. })
+    ));
+    assert!(tenant.get("user:1").await.unwrap().is_some());
+    assert!(tenant.set("user:1", vec![0; 9]).await.is_err());
+    assert_eq!(tenant.get("user:1").await.unwrap(), Some(vec![0; 4]));
+
+    assert_eq!((tenant.usage()[0].keys, tenant.usage()[0].bytes), (1, 4));
+    let stats = tenant.stats();
+    assert_eq!((stats.keys, stats.bytes), (2, 9));
+}
+
+#[tokio::test]
+async fn test_tenant_pattern_quota_and_stats() {
+    let namespaces = registry();
+    let tenant = namespaces
+        .create(NamespaceConfig::new("acme").quota(QuotaPolicy::new("session:*").max_keys(1)))
+        .unwrap();
+    
+    tenant.set("session:1", vec![1]).await.unwrap();
+    tenant.set("session:2", vec![2]).await.unwrap();
+    
+    assert!(tenant.get("session:1").await.unwrap().is_none());
+    assert!(tenant.get("session:2").await.unwrap().is_some());
+    assert_eq!(tenant.usage()[0].keys, 1);
+    
+    let stats = tenant.stats();
+    assert_eq!((stats.hits, stats.misses), (1, 1));
+    assert_eq!(stats.keys, 1);
+}
+
+#[tokio::test]
+async fn test_namespace_lifecycle() {
+    let namespaces = registry();
+    assert!(namespaces.create(NamespaceConfig::new("bad/name")).is_err());
+    
+    let tenant = namespaces.create(NamespaceConfig::new("acme")).unwrap();
+    assert!(namespaces.create(NamespaceConfig::new("acme")).is_err());
+    tenant.set("k", vec![1]).await.unwrap();
+    
+    namespaces.remove("acme").await.unwrap();
+    assert!(namespaces.get("acme").is_none());
+    assert!(tenant.get("k").await.unwrap().is_none());
+}
@@ -0,0 +1,157 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use core::prelude::*;
+use core::layer::CacheLayer;
+use core::{ConflictLayer, QuotaLayer, Timestamp, VersionVector};
+use crate::common::layer::MemoryLayer;
+
+#[test]
+fn test_parse_max_size() {
+    let policy = QuotaPolicy::new("products:*").max_size("50MB").unwrap();
+    assert_eq!(policy.max_bytes, Some(50 * 1024 * 1024));
+    assert!(QuotaPolicy::new("products:*").max_size("50XB").is_err());
+}
+
+#[tokio::test]
+async fn test_evict_within_pattern() {
+    let quotas = Arc::new(QuotaManager::new(vec![
+        QuotaPolicy::new("user:*").max_keys(2),
+    ]));
+    let layer = QuotaLayer::new(MemoryLayer::default(), quotas.clone());
+    
+    layer.set("user:1", vec![0; 4]).await.unwrap();
+    layer.set("user:2", vec![0; 4]).await.unwrap();
+    layer.set("user:3", vec![0; 4]).await.unwrap();
+    layer.set("product:1", vec![0; 4]).await.unwrap();
+    
+    assert!(layer.get("user:1").await.unwrap().is_none());
+    assert!(layer.get("user:3").await.unwrap().is_some());
+    
+    let usage = quotas.usage("user:*").unwrap();
+    assert_eq!(usage.keys, 2);
+    assert_eq!(usage.bytes, 8);
+}
+
+#[tokio::test]
+async fn test_reject_over_quota() {
+    let quotas = Arc::new(QuotaManager::new(vec![
+        QuotaPolicy::new("blob:*").max_bytes(10).on_overflow(OverflowAction::Reject),
+    ]));
+    let layer = QuotaLayer::new(MemoryLayer::default(), quotas.clone());
+    
+    layer.set("blob:a", vec![0; 6]).await.unwrap();
+    assert!(matches!(
+        layer.set("blob:b", vec![0; 6]).await,
+        Err(Error::QuotaExceeded { .. })
+    ));
+    
+    // Overwriting an existing key only counts the difference
+    layer.set("blob:a", vec![0; 10]).await.unwrap();
+    assert_eq!(quotas.usage("blob:*").unwrap().bytes, 10);
+}
+
+#[tokio::test]
+async fn test_spill_to_lower_tier() {
+    let quotas = Arc::new(QuotaManager::new(vec![
+        QuotaPolicy::new("video:*").max_keys(1).on_overflow(OverflowAction::Spill),
+    ]));
+    let layer = QuotaLayer::new(MemoryLayer::default(), quotas.clone())
+        .with_spill(MemoryLayer::default());
+    
+    layer.set("video:1", vec![1]).await.unwrap();
+    layer.set("video:2", vec![2]).await.unwrap();
+    
+    assert_eq!(layer.get("video:2").await.unwrap(), Some(vec![2]));
+    assert_eq!(quotas.usage("video:*").unwrap().keys, 1);
+}
+
+#[test]
+fn test_most_specific_policy_wins() {
+    let quotas = QuotaManager::new(vec![
+        QuotaPolicy::new("user:*").max_keys(100),
+        QuotaPolicy::new("user:*:avatar").max_keys(1),
+    ]);
+    
+    assert_eq!(quotas.policy_for("user:1:avatar").unwrap().pattern, "user:*:avatar");
+    assert_eq!(quotas.policy_for("user:1:name").unwrap().pattern, "user:*");
+    assert!(quotas.policy_for("product:1").is_none());
+}
+
+/// A layer whose writes fail once it is marked full
+struct FullLayer {
+    inner: MemoryLayer,
+    full: Arc<AtomicBool>,
+}
+
+#[async_trait::async_trait]
+impl CacheLayer for FullLayer {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        self.inner.get(key).await
+    }
+
+    async fn set(&self, key: &str, value: Vec<u8>) -> Result<()> {
+        if self.full.load(Ordering::SeqCst) {
+            return Err(Error::Capacity { layer: Layer::Edge, reason: "full".into() });
+        }
+        self.inner.set(key, value).await
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        self.inner.delete(key).await
+    }
+
+    async fn keys(&self, pattern: &str) -> Result<Vec<String>> {
+        self.inner.keys(pattern).await
+    }
+}
+
+#[tokio::test]
+async fn test_failed_write_keeps_previous_usage() {
+    let quotas = Arc::new(QuotaManager::new(vec![QuotaPolicy::new("user:*").max_bytes(100)]));
+    let full = Arc::new(AtomicBool::new(false));
+    let layer = QuotaLayer::new(FullLayer { inner: MemoryLayer::default(), full: full.clone() }, quotas.clone());
+    layer.set("user:1", vec![0; 3]).await.unwrap();
+
+    full.store(true, Ordering::SeqCst);
+    assert!(layer.set("user:1", vec![0; 5]).await.is_err());
+    assert!(layer.set("user:2", vec![0; 5]).await.is_err());
+
+    let usage = quotas.usage("user:*").unwrap();
+    assert_eq!((usage.bytes, usage.keys), (3, 1));
+}
+
+#[test]
+fn test_pinned_victims_leave_usage_intact() {
+    let pins = Arc::new(Pins::new());
+    pins.pin("user:*", Layer::Server);
+    let quotas = QuotaManager::new(vec![QuotaPolicy::new("user:*").max_bytes(8)]).with_pins(pins);
+    quotas.admit("user:1", 4).unwrap();
+    quotas.admit("user:2", 4).unwrap();
+
+    assert!(matches!(quotas.admit("user:1", 6), Err(Error::QuotaExceeded { .. })));
+    assert!(matches!(quotas.admit("user:3", 1), Err(Error::QuotaExceeded { .. })));
+    let usage = quotas.usage("user:*").unwrap();
+    assert_eq!((usage.bytes, usage.keys), (8, 2));
+    assert_eq!(quotas.size_of("user:1"), Some(4));
+}
+
+#[tokio::test]
+async fn test_versioned_writes_reach_the_inner_layer() {
+    let quotas = Arc::new(QuotaManager::new(vec![QuotaPolicy::new("doc:*").max_bytes(100)]));
+    let inner = ConflictLayer::new(MemoryLayer::default(), "server", Arc::new(Conflicts::new()));
+    let layer = QuotaLayer::new(inner, quotas.clone());
+    let write = |value: &str, counter: u64| {
+        let mut vector = VersionVector::new();
+        vector.advance("client", counter);
+        let stamp = Timestamp { physical: counter, logical: 0, node: "client".into() };
+        Versioned::new(value.as_bytes().to_vec(), stamp, vector)
+    };
+
+    layer.set_versioned("doc:1", write("aaaa", 1)).await.unwrap();
+    layer.set_versioned("doc:1", write("bb", 2)).await.unwrap();
+    // The stale write is dropped by the conflict layer, and usage follows what it kept
+    let stored = layer.set_versioned("doc:1", write("aaaa", 1)).await.unwrap();
+    assert_eq!(stored.value, b"bb".to_vec());
+    assert_eq!(layer.get_versioned("doc:1").await.unwrap().unwrap().vector.get("client"), 2);
+    assert_eq!(quotas.usage("doc:*").unwrap().bytes, 2);
+}
@@ -0,0 +1,100 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use core::prelude::*;
+use core::layer::CacheLayer;
+use core::{LinkConditions, SimNetwork};
+
+fn coordinator(network: &Arc<SimNetwork>) -> LayerCoordinator {
+    let graph = OwnershipGraph::new();
+    graph.add(Ownership::new("user:*", "UserService", Layer::Server)).unwrap();
+    LayerCoordinator::new(Arc::new(graph))
+        .with_layer(Layer::Client, Arc::new(network.layer(Layer::Client)))
+        .with_layer(Layer::Edge, Arc::new(network.layer(Layer::Edge)))
+        .with_layer(Layer::Server, Arc::new(network.layer(Layer::Server)))
+}
+
+#[tokio::test]
+async fn test_latency_is_applied_per_link() {
+    let network = Arc::new(
+        SimNetwork::new()
+            .with_link(Layer::Client, Layer::Server, LinkConditions::new().latency(Duration::from_millis(30))),
+    );
+    let server = network.link(Layer::Client, Layer::Server);
+    let edge = network.link(Layer::Client, Layer::Edge);
+
+    let start = Instant::now();
+    edge.set("a", vec![1]).await.unwrap();
+    assert!(start.elapsed() < Duration::from_millis(30));
+    server.set("a", vec![1]).await.unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(30));
+    assert_eq!(network.delivered(), 2);
+}
+
+#[tokio::test]
+async fn test_packet_loss_is_seeded() {
+    async fn lost(seed: u64) -> u64 {
+        let network = Arc::new(
+            SimNetwork::new()
+                .with_seed(seed)
+                .with_default_link(LinkConditions::new().loss(0.3)),
+        );
+        let edge = network.link(Layer::Client, Layer::Edge);
+        for i in 0..100 {
+            let _ = edge.set(&format!("k:{}", i), vec![]).await;
+        }
+        network.dropped()
+    }
+
+    let dropped = lost(42).await;
+    assert_eq!(dropped, lost(42).await);
+    assert!(dropped > 10 && dropped < 60, "{}", dropped);
+}
+
+#[tokio::test]
+async fn test_partition_is_skipped_until_healed() {
+    let network = Arc::new(SimNetwork::new());
+    let coordinator = coordinator(&network);
+    let edge = network.layer(Layer::Edge);
+
+    coordinator.set("user:1", b"ada".to_vec()).await.unwrap();
+    assert!(coordinator.lookup("user:1").await.unwrap().is_some());
+
+    network.partition(Layer::Server, Layer::Edge);
+    assert!(matches!(edge.get("user:1").await, Err(Error::Network { .. })));
+    coordinator.set("user:1", b"bea".to_vec()).await.unwrap();
+    assert_eq!(coordinator.get("user:1").await.unwrap(), Some(b"bea".to_vec()));
+
+    network.heal(Layer::Server, Layer::Edge);
+    coordinator.catch_up(Layer::Edge).await.unwrap();
+    assert_eq!(coordinator.get("user:1").await.unwrap(), Some(b"bea".to_vec()));
+}
+
+#[tokio::test]
+async fn test_offline_client_keeps_local_data() {
+    let network = Arc::new(SimNetwork::new().with_local(Layer::Client));
+    let client = network.layer(Layer::Client);
+    let server = network.layer(Layer::Server);
+    client.set("doc:1", b"draft".to_vec()).await.unwrap();
+
+    network.simulate_offline(Layer::Client);
+    assert_eq!(client.get("doc:1").await.unwrap(), Some(b"draft".to_vec()));
+    assert!(server.set("doc:1", b"draft".to_vec()).await.is_err());
+
+    network.simulate_online(Layer::Client);
+    server.set("doc:1", b"draft".to_vec()).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_crash_loses_data() {
+    let network = Arc::new(SimNetwork::new());
+    let edge = network.layer(Layer::Edge);
+    edge.set("a", vec![1]).await.unwrap();
+
+    network.crash(Layer::Edge);
+    assert!(!network.is_up(Layer::Edge));
+    assert!(edge.get("a").await.unwrap_err().is_retryable());
+
+    network.restart(Layer::Edge);
+    assert!(edge.is_empty());
+    assert!(edge.get("a").await.unwrap().is_none());
+}
@@ -0,0 +1,56 @@
+use std::sync::Arc;
+use core::layer::CacheLayer;
+use core::{Tracking, TrackingLayer, TrackingMode};
+use crate::common::layer::MemoryLayer;
+
+#[tokio::test]
+async fn test_default_mode_reports_reads_once() {
+    let tracking = Arc::new(Tracking::new());
+    let layer = TrackingLayer::new(MemoryLayer::default(), tracking.clone());
+    let mut client = tracking.subscribe(TrackingMode::Default);
+
+    tracking.remember(client.id(), "user:1");
+    layer.set("user:2", b"x".to_vec()).await.unwrap();
+    layer.set("user:1", b"x".to_vec()).await.unwrap();
+    assert_eq!(client.recv().await, Some(vec!["user:1".to_string()]));
+    assert_eq!(tracking.tracked_keys(), 0);
+
+    // Not read again, so the next change goes unreported
+    layer.set("user:1", b"y".to_vec()).await.unwrap();
+    tracking.remember(client.id(), "user:1");
+    layer.delete("user:1").await.unwrap();
+    assert_eq!(client.recv().await, Some(vec!["user:1".to_string()]));
+
+    drop(client);
+    assert_eq!(tracking.subscribers(), 0);
+}
+
+#[tokio::test]
+async fn test_broadcast_mode_matches_patterns() {
+    let tracking = Arc::new(Tracking::new());
+    let layer = TrackingLayer::new(MemoryLayer::default(), tracking.clone());
+    let mut client = tracking.subscribe(TrackingMode::Broadcast { patterns: vec!["user:*".into()] });
+
+    tracking.remember(client.id(), "post:1");
+    assert_eq!(tracking.tracked_keys(), 0);
+    for key in ["user:1", "post:1", "user:2"] {
+        layer.set(key, b"x".to_vec()).await.unwrap();
+    }
+    assert_eq!(client.recv().await, Some(vec!["user:1".to_string()]));
+    assert_eq!(client.recv().await, Some(vec!["user:2".to_string()]));
+}
+
+#[tokio::test]
+async fn test_tracking_table_is_bounded() {
+    let tracking = Arc::new(Tracking::with_max_keys(2));
+    let mut client = tracking.subscribe(TrackingMode::Default);
+    for key in ["a", "b", "c"] {
+        tracking.remember(client.id(), key);
+    }
+    assert_eq!(tracking.tracked_keys(), 2);
+    let evicted = client.recv().await.unwrap();
+    assert_eq!(evicted.len(), 1);
+
+    drop(client);
+    assert_eq!(tracking.tracked_keys(), 0);
+}
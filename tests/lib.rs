@@ -1,10 +1,14 @@
 mod common;
 mod borrow_checker;
-mod patterns;
 mod layers;
 mod strategies;
-mod redis;
 mod integration;
+#[cfg(feature = "client")]
+mod client;
+#[cfg(feature = "edge")]
+mod edge;
+#[cfg(feature = "server")]
+mod server;
 
 #[cfg(test)]
 mod tests {
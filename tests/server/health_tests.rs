@@ -0,0 +1,81 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+use async_trait::async_trait;
+use core::prelude::*;
+use core::ComponentHealth;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+struct Static(ComponentHealth);
+
+#[async_trait]
+impl HealthCheck for Static {
+    async fn check(&self) -> ComponentHealth {
+        self.0.clone()
+    }
+}
+
+async fn endpoints(checker: HealthChecker) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(server::serve_health(Arc::new(checker), listener));
+    addr
+}
+
+/// Status code and body of a GET
+async fn get(addr: SocketAddr, path: &str) -> (u16, String) {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    let request = format!("GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n", path);
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    let status = response[9..12].parse().unwrap();
+    let body = response.split_once("\r\n\r\n").map(|(_, body)| body.to_string()).unwrap_or_default();
+    (status, body)
+}
+
+#[tokio::test]
+async fn test_health_endpoints_when_healthy() {
+    let checker = HealthChecker::new();
+    checker.register(Static(ComponentHealth::healthy("shards").critical()));
+    let addr = endpoints(checker).await;
+
+    for path in ["/health", "/health/live", "/health/ready"] {
+        let (status, body) = get(addr, path).await;
+        assert_eq!(status, 200, "{}", path);
+        let report: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(report["status"], "healthy");
+        assert_eq!(report["components"][0]["name"], "shards");
+    }
+    assert_eq!(get(addr, "/metrics").await.0, 404);
+}
+
+#[tokio::test]
+async fn test_unready_server_is_still_live() {
+    let checker = HealthChecker::new();
+    checker
+        .register(Static(ComponentHealth::healthy("shards").critical()))
+        .register(Static(ComponentHealth::unhealthy("network", "not listening")));
+    let addr = endpoints(checker).await;
+
+    assert_eq!(get(addr, "/health").await.0, 503);
+    let (status, body) = get(addr, "/health/ready").await;
+    assert_eq!(status, 503);
+    let report: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(report["ready"], false);
+    assert_eq!(report["components"][1]["message"], "not listening");
+
+    let (status, body) = get(addr, "/health/live").await;
+    assert_eq!(status, 200);
+    assert_eq!(serde_json::from_str::<serde_json::Value>(&body).unwrap()["live"], true);
+}
+
+#[tokio::test]
+async fn test_failing_critical_component_is_not_live() {
+    let checker = HealthChecker::new();
+    checker.register(Static(ComponentHealth::unhealthy("storage", "read-only").critical()));
+    let addr = endpoints(checker).await;
+
+    assert_eq!(get(addr, "/health/live").await.0, 503);
+    assert_eq!(get(addr, "/health/ready").await.0, 503);
+}
@@ -0,0 +1,47 @@
+use std::sync::Arc;
+use std::time::Duration;
+use core::prelude::*;
+use core::{ClientFirst, EdgeOptimized, GlobalConsistent, RedisCompatible};
+
+fn manifest() -> Arc<OwnershipGraph> {
+    let graph = OwnershipGraph::new();
+    graph.add(Ownership::new("user:{id}", "UserService", Layer::Server)).unwrap();
+    graph.add_invalidation("user:{id}", "team:{id}:members");
+    graph.add_invalidation("team:{id}:members", "dashboard:*");
+    Arc::new(graph)
+}
+
+#[tokio::test]
+async fn test_builtin_locations() {
+    assert_eq!(Strategy::ClientFirst.determine_location("user:1").await.unwrap(), Layer::Client);
+    assert_eq!(Strategy::EdgeOptimized.determine_location("user:1").await.unwrap(), Layer::Edge);
+    assert_eq!(Strategy::GlobalConsistent.determine_location("user:1").await.unwrap(), Layer::Server);
+    assert_eq!(Strategy::RedisCompatible.determine_location("user:1").await.unwrap(), Layer::Server);
+}
+
+#[tokio::test]
+async fn test_global_consistent_invalidates_dependents() {
+    let strategy = GlobalConsistent::new().with_ownership(manifest());
+    let purged = strategy.handle_invalidation("user:{id}").await.unwrap();
+    assert_eq!(purged, vec!["user:{id}", "dashboard:*", "team:{id}:members"]);
+    assert_eq!(strategy.consistency(), Consistency::Strong);
+
+    let client = ClientFirst::new().with_ownership(manifest());
+    assert_eq!(client.handle_invalidation("user:{id}").await.unwrap().len(), 3);
+}
+
+#[tokio::test]
+async fn test_edge_optimized_relies_on_ttl() {
+    let strategy = EdgeOptimized::new().with_ttl(Duration::from_secs(30));
+    assert!(strategy.handle_invalidation("product:*").await.unwrap().is_empty());
+    assert_eq!(strategy.ttl("product:1"), Some(Duration::from_secs(30)));
+    assert_eq!(strategy.consistency(), Consistency::Regional);
+}
+
+#[tokio::test]
+async fn test_redis_compatible_ignores_dependencies() {
+    let strategy = RedisCompatible::new();
+    assert_eq!(strategy.handle_invalidation("user:*").await.unwrap(), vec!["user:*"]);
+    assert_eq!(strategy.ttl("user:1"), None);
+    assert_eq!(Strategy::RedisCompatible.name(), "RedisCompatible");
+}
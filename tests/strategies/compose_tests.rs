@@ -0,0 +1,82 @@
+use core::prelude::*;
+use core::{BySize, ClientFirst, EdgeOptimized, GlobalConsistent, Pinned, Route, Weighted};
+
+#[tokio::test]
+async fn test_fallback_reads_primary_then_fallback() {
+    // #[client_primary] #[fallback = "edge"]
+    let strategy = ClientFirst::new().or_else(Pinned(Layer::Edge));
+    assert_eq!(strategy.determine_location("user:1").await.unwrap(), Layer::Client);
+    assert_eq!(strategy.locations("user:1").await.unwrap(), vec![Layer::Client, Layer::Edge]);
+    assert_eq!(strategy.children().len(), 2);
+}
+
+#[tokio::test]
+async fn test_route_picks_most_specific_pattern() {
+    let strategy = Route::new(GlobalConsistent::new())
+        .route("product:*", EdgeOptimized::new())
+        .route("product:{id}:stock", Strategy::RedisCompatible);
+    assert_eq!(strategy.determine_location("user:1").await.unwrap(), Layer::Server);
+    assert_eq!(strategy.determine_location("product:7").await.unwrap(), Layer::Edge);
+    assert_eq!(strategy.strategy_for("product:7:stock").name(), "RedisCompatible");
+    assert_eq!(strategy.consistency(), Consistency::Regional);
+    assert!(strategy.validate().is_ok());
+
+    let duplicate = Route::new(Pinned(Layer::Server))
+        .route("user:*", Pinned(Layer::Client))
+        .route("user:*", Pinned(Layer::Edge));
+    let err = duplicate.validate().unwrap_err();
+    assert!(matches!(err, Error::StrategyError { .. }));
+    assert_eq!(err.pattern(), Some("user:*"));
+}
+
+#[tokio::test]
+async fn test_route_invalidation_spans_routes() {
+    let strategy = Route::new(EdgeOptimized::new())
+        .route("product:*", EdgeOptimized::new())
+        .route("product:{id}:stock", Strategy::RedisCompatible)
+        .route("user:*", Strategy::RedisCompatible);
+    assert_eq!(strategy.handle_invalidation("product:*").await.unwrap(), vec!["product:*".to_string()]);
+    assert_eq!(strategy.handle_invalidation("*").await.unwrap(), vec!["*".to_string()]);
+    assert!(strategy.handle_invalidation("cart:*").await.unwrap().is_empty());
+
+    let strategy = Route::new(GlobalConsistent::new()).route("product:*", Strategy::RedisCompatible);
+    assert_eq!(strategy.handle_invalidation("*").await.unwrap(), vec!["*".to_string()]);
+
+    // The default only handles patterns reaching keys no route covers
+    let strategy = Route::new(Strategy::RedisCompatible)
+        .route("user:*", EdgeOptimized::new())
+        .route("team:{id}", EdgeOptimized::new());
+    assert!(strategy.handle_invalidation("user:1:*").await.unwrap().is_empty());
+    assert!(strategy.handle_invalidation("team:1").await.unwrap().is_empty());
+    assert_eq!(strategy.handle_invalidation("team:*").await.unwrap(), vec!["team:*".to_string()]);
+    assert_eq!(strategy.handle_invalidation("*").await.unwrap(), vec!["*".to_string()]);
+}
+
+#[tokio::test]
+async fn test_large_values_go_elsewhere() {
+    let strategy: BySize = Pinned(Layer::Edge).above_size(1024, Pinned(Layer::Server));
+    assert_eq!(strategy.determine_location_sized("img:1", 100).await.unwrap(), Layer::Edge);
+    assert_eq!(strategy.determine_location_sized("img:1", 4096).await.unwrap(), Layer::Server);
+    assert_eq!(strategy.locations("img:1").await.unwrap(), vec![Layer::Edge, Layer::Server]);
+}
+
+#[tokio::test]
+async fn test_weighted_split_is_stable() {
+    let strategy = Weighted::new()
+        .with(3, Pinned(Layer::Edge))
+        .with(1, Pinned(Layer::Server));
+    let mut edge = 0;
+    for i in 0..1000 {
+        let key = format!("session:{}", i);
+        let layer = strategy.determine_location(&key).await.unwrap();
+        assert_eq!(strategy.determine_location(&key).await.unwrap(), layer);
+        if layer == Layer::Edge {
+            edge += 1;
+        }
+    }
+    assert!((650..850).contains(&edge), "edge received {}", edge);
+
+    let empty = Weighted::new().with(0, Pinned(Layer::Edge));
+    assert!(empty.validate().is_err());
+    assert!(empty.determine_location("k").await.is_err());
+}
@@ -0,0 +1,3 @@
+mod builtin_tests;
+mod compose_tests;
+mod validation_tests;
@@ -0,0 +1,161 @@
+use std::sync::Arc;
+use core::prelude::*;
+use core::{ClientFirst, GlobalConsistent, EdgeOptimized, Pinned, Route, StrategyValidator};
+use crate::common::layer::MemoryLayer;
+
+fn manifest() -> OwnershipGraph {
+    let graph = OwnershipGraph::new();
+    graph.add(Ownership::new("user:*", "UserService", Layer::Client)).unwrap();
+    graph.add(Ownership::new("product:*", "Catalog", Layer::Edge)).unwrap();
+    graph
+}
+
+#[tokio::test]
+async fn test_offline_and_real_time_cannot_compose() {
+    assert!(!Strategy::from_name("offline_first").unwrap()
+        .compatible_with(Strategy::from_name("real_time").unwrap()));
+
+    let graph = manifest();
+    let invalid = ClientFirst::new().or_else(GlobalConsistent::new());
+    let result = StrategyValidator::new(&graph).strategy("user:*", &invalid).validate().await;
+    assert!(matches!(result, Err(Error::StrategyError { ref strategy, .. }) if strategy == "Fallback"));
+
+    // Disjoint keys may use either
+    let routed = Route::new(GlobalConsistent::new()).route("user:*", ClientFirst::new());
+    assert!(StrategyValidator::new(&graph).strategy("user:*", &routed).validate().await.is_ok());
+
+    let nested = Route::new(Pinned(Layer::Edge))
+        .route("user:*", ClientFirst::new().or_else(Strategy::RedisCompatible));
+    assert!(StrategyValidator::new(&graph).strategy("user:*", &nested).validate().await.is_err());
+}
+
+#[tokio::test]
+async fn test_unreachable_layers_are_reported() {
+    let graph = manifest();
+    let edge = EdgeOptimized::new();
+    let problems = StrategyValidator::new(&graph)
+        .with_layers(&[Layer::Client, Layer::Server])
+        .strategy("product:*", &edge)
+        .problems()
+        .await;
+    // The strategy places data on the edge, and the manifest owns product:* there
+    assert_eq!(problems.len(), 2);
+    assert!(problems.iter().all(|e| e.kind() == ErrorKind::Strategy));
+    assert!(problems.iter().all(|e| e.pattern() == Some("product:*") && e.layer() == Some(Layer::Edge)));
+}
+
+#[tokio::test]
+async fn test_strategy_must_use_owning_layer() {
+    let graph = manifest();
+    let server = Strategy::GlobalConsistent;
+    let err = StrategyValidator::new(&graph)
+        .strategy("user:*", &server)
+        .validate()
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("owned by UserService on the client layer"));
+}
+
+#[tokio::test]
+async fn test_circular_invalidation_is_reported() {
+    let graph = manifest();
+    graph.add_invalidation("user:*", "team:*");
+    graph.add_invalidation("team:*", "org:*");
+    assert!(StrategyValidator::new(&graph).validate().await.is_ok());
+
+    graph.add_invalidation("org:*", "user:*");
+    let err = StrategyValidator::new(&graph).validate().await.unwrap_err();
+    assert!(err.to_string().contains("circular dependency"), "{}", err);
+}
+
+#[tokio::test]
+async fn test_coordinator_validates_its_strategy() {
+    let coordinator = || {
+        LayerCoordinator::new(Arc::new(manifest()))
+            .with_layer(Layer::Client, Arc::new(MemoryLayer::default()))
+            .with_layer(Layer::Edge, Arc::new(MemoryLayer::default()))
+            .with_layer(Layer::Server, Arc::new(MemoryLayer::default()))
+    };
+    let invalid = ClientFirst::new().or_else(GlobalConsistent::new());
+    let err = coordinator().with_strategy(invalid).await.err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::Strategy);
+    assert!(coordinator().with_strategy(ClientFirst::new()).await.is_ok());
+
+    // The edge layer the strategy places data on is not configured
+    let partial = LayerCoordinator::new(Arc::new(OwnershipGraph::new()))
+        .with_layer(Layer::Server, Arc::new(MemoryLayer::default()));
+    let err = partial.with_strategy(EdgeOptimized::new()).await.err().unwrap();
+    assert!(err.to_string().contains("not deployed"), "{}", err);
+}
+
+#[tokio::test]
+async fn test_overlapping_one_way_chain_is_not_a_cycle() {
+    let graph = manifest();
+    graph.add_invalidation("a:*", "a:*:b");
+    assert_eq!(graph.dependency_cycle(), None);
+    assert!(StrategyValidator::new(&graph).validate().await.is_ok());
+
+    graph.add_invalidation("user:*", "user:*:sessions");
+    graph.add_invalidation("user:*:sessions", "team:*");
+    assert_eq!(graph.dependency_cycle(), None);
+}
+
+#[test]
+fn test_compatible_manifest_compiles() {
+    use stateless::{cache, cache_manifest};
+
+    struct UserStrategy;
+    struct ProfileStrategy;
+
+    #[cache_manifest]
+    struct TestCache {
+        #[client_primary]
+        #[fallback = "edge"]
+        #[strategy = "offline_first"]
+        #[owns = "user:*"]
+        user_data: UserStrategy,
+
+        #[server_primary]
+        #[strategy = "real_time"]
+        #[owns = "profile:*"]
+        profile_data: ProfileStrategy,
+    }
+
+    // Mixing "offline_first" and "real_time" on one field, a fallback to the
+    // primary layer, or a circular #[invalidates] chain fails to compile
+    #[cache(strategy = "real_time", owns = "profile:*")]
+    async fn update_profile() {}
+}
+
+#[test]
+fn test_manifest_quotas() {
+    use stateless::cache_manifest;
+
+    struct SessionStrategy;
+    struct ProductStrategy;
+
+    #[cache_manifest]
+    struct QuotaCache {
+        #[edge_primary]
+        #[owns = "session:*"]
+        #[max_size = "50MB"]
+        #[max_keys = 10000]
+        sessions: SessionStrategy,
+
+        #[owns = "product:*"]
+        products: ProductStrategy,
+    }
+
+    let quotas = QuotaCache::quotas();
+    assert_eq!(quotas.len(), 1);
+    assert_eq!(quotas[0].pattern, "session:*");
+    assert_eq!(quotas[0].max_bytes, Some(50 << 20));
+    assert_eq!(quotas[0].max_keys, Some(10000));
+}
+
+#[test]
+fn test_manifest_errors_fail_to_compile() {
+    // A size that does not parse, a quota without an #[owns] pattern,
+    // incompatible strategies, an unreachable fallback or an invalidation cycle
+    trybuild::TestCases::new().compile_fail("tests/ui/manifest_*.rs");
+}
@@ -0,0 +1,12 @@
+use stateless::cache_manifest;
+
+struct SessionStrategy;
+
+#[cache_manifest]
+struct AppCache {
+    #[owns = "session:*"]
+    #[max_size = "50 parsecs"]
+    sessions: SessionStrategy,
+}
+
+fn main() {}
@@ -0,0 +1,14 @@
+use stateless::cache_manifest;
+
+struct UserStrategy;
+
+#[cache_manifest]
+struct AppCache {
+    #[client_primary]
+    #[strategy = "offline_first"]
+    #[strategy = "real_time"]
+    #[owns = "user:*"]
+    user_data: UserStrategy,
+}
+
+fn main() {}
@@ -0,0 +1,17 @@
+use stateless::cache_manifest;
+
+struct UserStrategy;
+struct TeamStrategy;
+
+#[cache_manifest]
+struct AppCache {
+    #[owns = "user:*"]
+    #[invalidates = "team:*"]
+    users: UserStrategy,
+
+    #[owns = "team:*"]
+    #[invalidates = "user:*"]
+    teams: TeamStrategy,
+}
+
+fn main() {}
@@ -0,0 +1,11 @@
+use stateless::cache_manifest;
+
+struct SessionStrategy;
+
+#[cache_manifest]
+struct AppCache {
+    #[max_keys = 100]
+    sessions: SessionStrategy,
+}
+
+fn main() {}
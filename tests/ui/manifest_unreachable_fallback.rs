@@ -0,0 +1,13 @@
+use stateless::cache_manifest;
+
+struct ProductStrategy;
+
+#[cache_manifest]
+struct AppCache {
+    #[edge_primary]
+    #[fallback = "edge"]
+    #[owns = "product:*"]
+    products: ProductStrategy,
+}
+
+fn main() {}
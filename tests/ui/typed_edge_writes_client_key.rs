@@ -0,0 +1,17 @@
+use stateless::typed::Key;
+use stateless::{cache_manifest, EdgeLayer};
+
+struct UserStrategy;
+
+#[cache_manifest]
+struct AppCache {
+    #[client_primary]
+    #[owns = "user:*"]
+    user_data: UserStrategy,
+}
+
+async fn write(edge: EdgeLayer, key: Key<app_cache::UserData>) {
+    edge.set(&key, b"ada".to_vec()).await.unwrap();
+}
+
+fn main() {}
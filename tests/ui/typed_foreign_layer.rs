@@ -0,0 +1,10 @@
+use stateless::typed::LayerKind;
+use stateless::Layer;
+
+enum Cdn {}
+
+impl LayerKind for Cdn {
+    const LAYER: Layer = Layer::Edge;
+}
+
+fn main() {}
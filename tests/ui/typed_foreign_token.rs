@@ -0,0 +1,11 @@
+use stateless::typed::{Client, PatternToken};
+
+struct Session;
+
+impl PatternToken for Session {
+    type Owner = Client;
+    const PATTERN: &'static str = "session:*";
+    const OWNER: &'static str = "Sessions";
+}
+
+fn main() {}
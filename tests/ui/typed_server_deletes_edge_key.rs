@@ -0,0 +1,17 @@
+use stateless::typed::Key;
+use stateless::{cache_manifest, ServerLayer};
+
+struct ProductStrategy;
+
+#[cache_manifest]
+struct AppCache {
+    #[edge_primary]
+    #[owns = "products:*"]
+    product_cache: ProductStrategy,
+}
+
+async fn remove(server: ServerLayer, key: Key<app_cache::ProductCache>) {
+    server.delete(&key).await.unwrap();
+}
+
+fn main() {}